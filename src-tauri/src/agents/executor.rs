@@ -1 +1,85 @@
-// Agent executor implementation - placeholder for Phase 3
\ No newline at end of file
+use crate::database::budgets;
+use crate::integrations::generation::DEFAULT_MAX_TOKENS;
+use crate::database::models::{Agent, AgentRun, JsonMap};
+use crate::database::usage::{UsageContext, UsageSource};
+use crate::database::Database;
+use crate::integrations::anthropic::{AnthropicClient, AnthropicMessage, AnthropicRequest, SystemPrompt};
+use crate::integrations::auth::AuthProvider;
+use crate::integrations::models::DEFAULT_MODEL;
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use std::sync::Arc;
+
+// Sent as the user turn of runs started without input
+const NO_INPUT_PROMPT: &str = "Run your task.";
+
+/// Creates a run of the agent and executes it in the background. API usage
+/// is attributed to the run, and to `hook_id` when a hook started it.
+pub async fn start_run(
+    db: &Arc<Database>,
+    auth: Arc<dyn AuthProvider>,
+    agent_id: &str,
+    input_data: Option<&JsonMap>,
+    hook_id: Option<&str>,
+) -> Result<AgentRun> {
+    let agent = db.agents().get(agent_id).await?.ok_or_else(|| anyhow!("Agent not found"))?;
+    let run = db.runs().create(agent_id, input_data).await?;
+
+    let mut context = UsageContext::agent(&agent.id, &run.id);
+    if let Some(hook_id) = hook_id {
+        context.source = UsageSource::Hook;
+        context.hook_id = Some(hook_id.to_string());
+    }
+
+    // Don't start runs that would spend from an exhausted budget; the run is
+    // kept as failed so the user can see why it did not execute
+    if let Err(e) = budgets::enforce_budgets(db.pool(), &context).await {
+        db.runs().fail(&run.id, &e.to_string()).await?;
+        return Err(e);
+    }
+
+    let client = AnthropicClient::with_auth(auth, None).with_usage_tracking(Arc::clone(db), context);
+    let db = Arc::clone(db);
+    let started = run.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = execute(&db, &client, &agent, &started).await {
+            log::error!("Agent run {} failed: {}", started.id, e);
+        }
+    });
+
+    Ok(run)
+}
+
+/// Sends the agent's system prompt and the run's input to the model and
+/// completes the run with the reply, or fails it with the error.
+pub async fn execute(db: &Database, client: &AnthropicClient, agent: &Agent, run: &AgentRun) -> Result<String> {
+    let config = &agent.model_config;
+    let request = AnthropicRequest {
+        model: config.get("model").and_then(|model| model.as_str()).unwrap_or(DEFAULT_MODEL).to_string(),
+        max_tokens: config
+            .get("max_tokens")
+            .and_then(|value| value.as_u64())
+            .and_then(|value| u32::try_from(value).ok())
+            .unwrap_or(DEFAULT_MAX_TOKENS),
+        messages: vec![AnthropicMessage::text(
+            "user",
+            run.input_data.clone().unwrap_or_else(|| NO_INPUT_PROMPT.to_string()),
+        )],
+        temperature: config.get("temperature").and_then(|value| value.as_f64()).map(|value| value as f32),
+        system: Some(SystemPrompt::Text(agent.system_prompt.clone())),
+        tools: None,
+    };
+
+    match client.send_message(request).await {
+        Ok(response) => {
+            let text: String = response.content.iter().map(|block| block.text.as_str()).collect();
+            let output = JsonMap::from([("text".to_string(), json!(text))]);
+            db.runs().complete(&run.id, &output).await?;
+            Ok(text)
+        }
+        Err(e) => {
+            db.runs().fail(&run.id, &e.to_string()).await?;
+            Err(e)
+        }
+    }
+}
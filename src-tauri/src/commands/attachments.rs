@@ -0,0 +1,42 @@
+use crate::database::Database;
+use crate::integrations::attachments::{AttachmentRef, AttachmentStore, GcReport, GC_GRACE_PERIOD};
+use base64::{engine::general_purpose, Engine as _};
+use tauri::State;
+use std::sync::Arc;
+
+/// Stores the file at `file_path` for sending with a message.
+#[tauri::command]
+pub async fn add_attachment(
+    store: State<'_, Arc<AttachmentStore>>,
+    file_path: String,
+) -> Result<AttachmentRef, String> {
+    store
+        .put_file(std::path::Path::new(&file_path))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Stores base64 `data`, such as a pasted screenshot, for sending with a message.
+#[tauri::command]
+pub async fn add_attachment_data(
+    store: State<'_, Arc<AttachmentStore>>,
+    name: String,
+    data: String,
+) -> Result<AttachmentRef, String> {
+    let bytes = general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|e| format!("Invalid attachment data: {}", e))?;
+
+    store.put(&name, &bytes).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn collect_attachment_garbage(
+    db: State<'_, Arc<Database>>,
+    store: State<'_, Arc<AttachmentStore>>,
+) -> Result<GcReport, String> {
+    store
+        .collect_garbage(db.pool(), GC_GRACE_PERIOD)
+        .await
+        .map_err(|e| e.to_string())
+}
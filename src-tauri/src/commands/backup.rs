@@ -0,0 +1,109 @@
+use crate::commands::settings;
+use crate::database::Database;
+use crate::utils::backup::{self, BackupInfo, BackupManifest};
+use crate::utils::config;
+use crate::utils::crypto;
+use chrono::Utc;
+use std::path::Path;
+use std::sync::Arc;
+use tauri::{AppHandle, State};
+
+// Scheduled backups unless configured otherwise: daily, keeping a week
+const DEFAULT_INTERVAL_HOURS: i64 = 24;
+const DEFAULT_RETENTION: usize = 7;
+
+// Passphrase for backups nobody supplied one for: the local one when the
+// user turned on `backup.encrypt`
+async fn default_passphrase(db: &Database) -> Result<Option<String>, String> {
+    let encrypt = settings::load_settings(db)
+        .await?
+        .get("backup.encrypt")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+    Ok(encrypt.then(crypto::local_passphrase))
+}
+
+/// Takes a scheduled backup if `backup.autoBackup` is on and the newest
+/// backup is older than `backup.intervalHours`, then deletes all but the
+/// newest `backup.retention` backups. Returns the backup taken, if any.
+pub async fn run_scheduled_backup(db: &Database) -> Result<Option<BackupInfo>, String> {
+    let stored = settings::load_settings(db).await?;
+    if !stored.get("backup.autoBackup").and_then(|value| value.as_bool()).unwrap_or(true) {
+        return Ok(None);
+    }
+    let interval = stored
+        .get("backup.intervalHours")
+        .and_then(|value| value.as_i64())
+        .filter(|hours| *hours > 0)
+        .unwrap_or(DEFAULT_INTERVAL_HOURS);
+    let retention = stored
+        .get("backup.retention")
+        .and_then(|value| value.as_u64())
+        .map_or(DEFAULT_RETENTION, |keep| keep.max(1) as usize);
+
+    let data_dir = config::get_data_dir().map_err(|e| e.to_string())?;
+    let dir = backup::backups_dir(&data_dir);
+    let latest = backup::list_backups(&dir).map_err(|e| e.to_string())?.into_iter().next();
+    if latest.is_some_and(|latest| Utc::now() - latest.created_at < chrono::Duration::hours(interval)) {
+        return Ok(None);
+    }
+
+    let passphrase = default_passphrase(db).await?;
+    let info = backup::create_backup(db.pool(), &data_dir, &dir, passphrase.as_deref())
+        .await
+        .map_err(|e| format!("Failed to create backup: {}", e))?;
+    let pruned = backup::prune_backups(&dir, retention).map_err(|e| e.to_string())?;
+    if pruned > 0 {
+        log::info!("Deleted {} old backups", pruned);
+    }
+    Ok(Some(info))
+}
+
+/// Backs up the database, settings and attachments into the backups folder.
+/// The archive is encrypted with `passphrase` when one is given, or with the
+/// local key when `backup.encrypt` is on.
+#[tauri::command]
+pub async fn create_backup(
+    db: State<'_, Arc<Database>>,
+    passphrase: Option<String>,
+) -> Result<BackupInfo, String> {
+    let passphrase = match passphrase.filter(|passphrase| !passphrase.is_empty()) {
+        Some(passphrase) => Some(passphrase),
+        None => default_passphrase(&db).await?,
+    };
+
+    let data_dir = config::get_data_dir().map_err(|e| e.to_string())?;
+    backup::create_backup(db.pool(), &data_dir, &backup::backups_dir(&data_dir), passphrase.as_deref())
+        .await
+        .map_err(|e| format!("Failed to create backup: {}", e))
+}
+
+#[tauri::command]
+pub async fn list_backups() -> Result<Vec<BackupInfo>, String> {
+    let data_dir = config::get_data_dir().map_err(|e| e.to_string())?;
+    backup::list_backups(&backup::backups_dir(&data_dir)).map_err(|e| e.to_string())
+}
+
+/// Verifies the backup at `path` and restarts the app to restore it. The
+/// current data is backed up first so the restore can be undone.
+#[tauri::command]
+pub async fn restore_backup(
+    app: AppHandle,
+    db: State<'_, Arc<Database>>,
+    path: String,
+    passphrase: Option<String>,
+) -> Result<BackupManifest, String> {
+    let data_dir = config::get_data_dir().map_err(|e| e.to_string())?;
+    let manifest = backup::stage_restore(Path::new(&path), passphrase.as_deref(), &data_dir)
+        .await
+        .map_err(|e| format!("Cannot restore backup: {}", e))?;
+
+    let current = default_passphrase(&db).await?;
+    if let Err(e) = backup::create_backup(db.pool(), &data_dir, &backup::backups_dir(&data_dir), current.as_deref()).await {
+        let _ = backup::discard_pending_restore(&data_dir);
+        return Err(format!("Failed to back up current data before restoring: {}", e));
+    }
+
+    log::info!("Restarting to restore backup from {}", manifest.created_at);
+    app.restart()
+}
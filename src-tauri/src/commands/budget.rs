@@ -0,0 +1,175 @@
+use crate::database::{Database, models::*};
+use crate::database::budgets::{self, BudgetStatus};
+use tauri::State;
+use std::sync::Arc;
+use chrono::Utc;
+
+async fn fetch_budget(db: &Database, budget_id: &str) -> Result<Budget, String> {
+    sqlx::query_as::<_, Budget>("SELECT * FROM budgets WHERE id = ?")
+        .bind(budget_id)
+        .fetch_optional(db.pool())
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Budget not found".to_string())
+}
+
+#[tauri::command]
+pub async fn get_budgets(
+    db: State<'_, Arc<Database>>,
+) -> Result<Vec<Budget>, String> {
+    let budgets = sqlx::query_as::<_, Budget>(
+        "SELECT * FROM budgets ORDER BY created_at DESC",
+    )
+    .fetch_all(db.pool())
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(budgets)
+}
+
+#[tauri::command]
+pub async fn create_budget(
+    db: State<'_, Arc<Database>>,
+    request: CreateBudgetRequest,
+) -> Result<Budget, String> {
+    let mut budget = Budget::new(
+        request.name,
+        request.scope,
+        request.scope_id,
+        request.period,
+        request.limit_usd,
+    );
+    if let Some(warn_threshold) = request.warn_threshold {
+        budget.warn_threshold = warn_threshold;
+    }
+    budgets::validate_budget(&budget)?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO budgets (id, name, scope, scope_id, period, limit_usd, warn_threshold, enabled, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&budget.id)
+    .bind(&budget.name)
+    .bind(&budget.scope)
+    .bind(&budget.scope_id)
+    .bind(&budget.period)
+    .bind(budget.limit_usd)
+    .bind(budget.warn_threshold)
+    .bind(budget.enabled)
+    .bind(budget.created_at)
+    .bind(budget.updated_at)
+    .execute(db.pool())
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(budget)
+}
+
+#[tauri::command]
+pub async fn update_budget(
+    db: State<'_, Arc<Database>>,
+    budget_id: String,
+    request: UpdateBudgetRequest,
+) -> Result<Budget, String> {
+    let mut budget = fetch_budget(&db, &budget_id).await?;
+    if let Some(name) = request.name {
+        budget.name = name;
+    }
+    if let Some(limit_usd) = request.limit_usd {
+        budget.limit_usd = limit_usd;
+    }
+    if let Some(warn_threshold) = request.warn_threshold {
+        budget.warn_threshold = warn_threshold;
+    }
+    if let Some(enabled) = request.enabled {
+        budget.enabled = enabled;
+    }
+    budget.updated_at = Utc::now();
+    budgets::validate_budget(&budget)?;
+
+    sqlx::query(
+        r#"
+        UPDATE budgets
+        SET name = ?, limit_usd = ?, warn_threshold = ?, enabled = ?, updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(&budget.name)
+    .bind(budget.limit_usd)
+    .bind(budget.warn_threshold)
+    .bind(budget.enabled)
+    .bind(budget.updated_at)
+    .bind(&budget.id)
+    .execute(db.pool())
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(budget)
+}
+
+#[tauri::command]
+pub async fn delete_budget(
+    db: State<'_, Arc<Database>>,
+    budget_id: String,
+) -> Result<bool, String> {
+    let result = sqlx::query("DELETE FROM budgets WHERE id = ?")
+        .bind(budget_id)
+        .execute(db.pool())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[tauri::command]
+pub async fn get_budget_status(
+    db: State<'_, Arc<Database>>,
+) -> Result<Vec<BudgetStatus>, String> {
+    let budgets = sqlx::query_as::<_, Budget>(
+        "SELECT * FROM budgets WHERE enabled = TRUE ORDER BY created_at DESC",
+    )
+    .fetch_all(db.pool())
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut statuses = Vec::with_capacity(budgets.len());
+    for budget in &budgets {
+        statuses.push(
+            budgets::budget_status(db.pool(), budget)
+                .await
+                .map_err(|e| e.to_string())?,
+        );
+    }
+
+    Ok(statuses)
+}
+
+#[tauri::command]
+pub async fn get_budget_events(
+    db: State<'_, Arc<Database>>,
+    budget_id: Option<String>,
+    kind: Option<String>,
+    limit: Option<i32>,
+) -> Result<Vec<BudgetEvent>, String> {
+    let events = sqlx::query_as::<_, BudgetEvent>(
+        r#"
+        SELECT * FROM budget_events
+        WHERE (? IS NULL OR budget_id = ?)
+          AND (? IS NULL OR kind = ?)
+        ORDER BY created_at DESC
+        LIMIT ?
+        "#,
+    )
+    .bind(&budget_id)
+    .bind(&budget_id)
+    .bind(&kind)
+    .bind(&kind)
+    .bind(limit.unwrap_or(100))
+    .fetch_all(db.pool())
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(events)
+}
@@ -1,228 +1,462 @@
-use crate::database::models::*;
-use crate::integrations::anthropic::{AnthropicClient, AnthropicRequest, AnthropicMessage};
-use crate::commands::settings;
-use std::fs;
-use std::path::Path;
-use chrono::Utc;
-
-fn get_chats_file_path() -> Result<String, String> {
-    let app_data_dir = dirs::data_dir()
-        .unwrap_or_else(|| std::env::temp_dir())
-        .join("cloddo");
-    
-    // Create directory if it doesn't exist
-    if !app_data_dir.exists() {
-        std::fs::create_dir_all(&app_data_dir)
-            .map_err(|e| format!("Failed to create app data directory: {}", e))?;
-    }
-    
-    Ok(app_data_dir.join("chats.json").to_string_lossy().to_string())
+use crate::database::{Database, models::*};
+use crate::database::budgets::{self, BudgetExceeded, BUDGET_EXCEEDED};
+use crate::integrations::generation::{self, EffectiveGenerationSettings, GenerationSettings};
+use crate::database::knowledge;
+use crate::database::repositories::ChatFilter;
+use crate::database::sessions;
+use crate::database::summaries;
+use crate::database::usage::UsageContext;
+use crate::integrations::anthropic::{
+    AnthropicClient, AnthropicRequest, AnthropicMessage, MessageContent, RequestBlock, SystemBlock, SystemPrompt,
+};
+use crate::integrations::attachments::{self, AttachmentRef, AttachmentStore};
+use crate::integrations::auth::{ApiKeyAuth, AuthProvider, OAuthAuth};
+use crate::integrations::filesystem::estimate_tokens;
+use crate::integrations::models::{self, ModelInfo, ModelRegistry};
+use crate::integrations::prompt_cache;
+use crate::integrations::oauth::OAuthManager;
+use crate::commands::{settings, CommandError, CommandResult};
+use crate::utils::credentials;
+use std::sync::Arc;
+use sqlx::types::Json;
+use tauri::{AppHandle, Emitter, State};
+
+/// Event emitted when a chat's title or summary changes in the background.
+pub const CHAT_UPDATED_EVENT: &str = "chat-updated";
+
+// Output limits for the titling and summary requests
+const TITLE_MAX_TOKENS: u32 = 32;
+const SUMMARY_MAX_TOKENS: u32 = 1024;
+
+async fn project_generation_settings(db: &Database, project_id: Option<&str>) -> CommandResult<GenerationSettings> {
+    let Some(project_id) = project_id else {
+        return Ok(GenerationSettings::default());
+    };
+
+    let project = db.projects().get(project_id).await?;
+    Ok(GenerationSettings::from_metadata(project.as_ref().and_then(|project| project.metadata.as_deref()))?)
 }
 
-fn load_chats() -> Result<Vec<Chat>, String> {
-    let path = get_chats_file_path()?;
-    if Path::new(&path).exists() {
-        let content = fs::read_to_string(&path)
-            .map_err(|e| format!("Failed to read chats: {}", e))?;
-        let chats: Vec<Chat> = serde_json::from_str(&content)
-            .map_err(|e| format!("Failed to parse chats: {}", e))?;
-        Ok(chats)
-    } else {
-        Ok(Vec::new())
-    }
+async fn resolve_generation_settings(
+    db: &Database,
+    models: &ModelRegistry,
+    chat: &Chat,
+    overrides: &GenerationSettings,
+) -> CommandResult<EffectiveGenerationSettings> {
+    let project = project_generation_settings(db, chat.project_id.as_deref()).await?;
+    let global = GenerationSettings::from_global_settings(&settings::load_settings(db).await?);
+
+    Ok(generation::resolve(overrides, &project, &global, models)?)
 }
 
-fn save_chats(chats: &Vec<Chat>) -> Result<(), String> {
-    let path = get_chats_file_path()?;
-    let json_content = serde_json::to_string_pretty(chats)
-        .map_err(|e| format!("Failed to serialize chats: {}", e))?;
-    fs::write(&path, json_content)
-        .map_err(|e| format!("Failed to write chats: {}", e))?;
-    Ok(())
+/// Settings `chat` would use without its own overrides.
+async fn inherited_generation_settings(
+    db: &Database,
+    models: &ModelRegistry,
+    chat: &Chat,
+) -> CommandResult<EffectiveGenerationSettings> {
+    resolve_generation_settings(db, models, chat, &GenerationSettings::default()).await
 }
 
-#[tauri::command]
-pub async fn get_chats(
-    _session_id: Option<String>,
-    _project_id: Option<String>,
-    _folder_path: Option<String>,
-    _limit: Option<i32>,
-    _offset: Option<i32>,
-) -> Result<Vec<Chat>, String> {
-    let chats = load_chats()?;
-    Ok(chats)
+async fn effective_generation_settings(
+    db: &Database,
+    models: &ModelRegistry,
+    chat: &Chat,
+) -> CommandResult<EffectiveGenerationSettings> {
+    let overrides = GenerationSettings::from_metadata(chat.metadata.as_deref())?;
+    resolve_generation_settings(db, models, chat, &overrides).await
 }
 
-#[tauri::command]
-pub async fn get_chat_by_id(chat_id: String) -> Result<Option<Chat>, String> {
-    let chats = load_chats()?;
-    let chat = chats.into_iter().find(|c| c.id == chat_id);
-    Ok(chat)
+/// Chats, newest activity first. `folder_path` matches the folder and all
+/// of its subfolders; `tag` matches chats carrying the tag of that name.
+pub async fn list(db: &Database, filter: &ChatFilter) -> CommandResult<Vec<Chat>> {
+    Ok(db.chats().list(filter).await?)
 }
 
-#[tauri::command]
-pub async fn create_chat(request: CreateChatRequest) -> Result<Chat, String> {
-    let mut chats = load_chats()?;
-    
-    let now = Utc::now();
-    let chat = Chat {
-        id: format!("chat_{}", now.timestamp()),
-        session_id: request.session_id,
-        project_id: request.project_id,
-        title: request.title,
-        folder_path: request.folder_path,
-        is_favorite: false,
-        last_activity: now,
-        created_at: now,
-        updated_at: now,
-        metadata: Some("{}".to_string()),
+pub async fn get(db: &Database, chat_id: &str) -> CommandResult<Option<Chat>> {
+    Ok(db.chats().get(chat_id).await?)
+}
+
+pub async fn create(db: &Database, request: CreateChatRequest) -> CommandResult<Chat> {
+    sessions::require_open(db.pool(), &request.session_id).await?;
+
+    // Only chats left with the default title get a generated one
+    let title = match request.title.trim() {
+        "" => summaries::DEFAULT_TITLE.to_string(),
+        title => title.to_string(),
     };
-    
-    chats.push(chat.clone());
-    save_chats(&chats)?;
+    let mut chat = Chat::new(request.session_id, title);
+    chat.auto_title = chat.title == summaries::DEFAULT_TITLE;
+    chat.project_id = request.project_id;
+    chat.folder_path = request.folder_path;
+    chat.metadata = Some(Json(JsonMap::new()));
+
+    db.chats().insert(&chat, false).await?;
     
     Ok(chat)
 }
 
-#[tauri::command]
-pub async fn update_chat(chat_id: String, request: UpdateChatRequest) -> Result<Chat, String> {
-    let mut chats = load_chats()?;
-    
-    let mut updated_chat = None;
-    
-    for chat in chats.iter_mut() {
-        if chat.id == chat_id {
-            if let Some(title) = request.title {
-                chat.title = title;
-            }
-            if let Some(is_favorite) = request.is_favorite {
-                chat.is_favorite = is_favorite;
-            }
-            if let Some(folder_path) = request.folder_path {
-                chat.folder_path = Some(folder_path);
-            }
-            let now = Utc::now();
-            chat.updated_at = now;
-            chat.last_activity = now;
-            updated_chat = Some(chat.clone());
-            break;
-        }
-    }
-    
-    if let Some(chat) = updated_chat {
-        save_chats(&chats)?;
-        Ok(chat)
-    } else {
-        Err("Chat not found".to_string())
+pub async fn update(
+    db: &Database,
+    models: &ModelRegistry,
+    chat_id: &str,
+    request: UpdateChatRequest,
+) -> CommandResult<Chat> {
+    if let Some(metadata) = &request.metadata {
+        let overrides = GenerationSettings::from_metadata(Some(metadata))?;
+        let chat = db.chats().get(chat_id).await?.ok_or("Chat not found")?;
+        let inherited = inherited_generation_settings(db, models, &chat).await?;
+        overrides.validate(models, &inherited.model)?;
     }
+
+    db.chats()
+        .update(chat_id, &request)
+        .await?
+        .ok_or_else(|| CommandError::from("Chat not found"))
 }
 
-#[tauri::command]
-pub async fn delete_chat(chat_id: String) -> Result<bool, String> {
-    let mut chats = load_chats()?;
-    let initial_len = chats.len();
-    chats.retain(|c| c.id != chat_id);
-    
-    if chats.len() < initial_len {
-        save_chats(&chats)?;
-        Ok(true)
-    } else {
-        Ok(false)
-    }
+/// Moves a chat to the trash, from which `restore_chat` brings it back.
+pub async fn delete(db: &Database, chat_id: &str) -> CommandResult<bool> {
+    Ok(db.chats().move_to_trash(chat_id).await?)
 }
 
-#[tauri::command]
-pub async fn get_messages(
-    chat_id: String,
-    _limit: Option<i32>,
-    _offset: Option<i32>,
-) -> Result<Vec<Message>, String> {
+/// Model, token limit, temperature and system prompt the chat's next
+/// message will be sent with, and where each of them comes from.
+pub async fn generation_settings(
+    db: &Database,
+    models: &ModelRegistry,
+    chat_id: &str,
+) -> CommandResult<EffectiveGenerationSettings> {
+    let chat = db.chats().get(chat_id).await?.ok_or("Chat not found")?;
+    effective_generation_settings(db, models, &chat).await
+}
+
+/// Replaces the chat's overrides. Fields left unset are inherited from the
+/// project and global settings; an empty `settings` clears all overrides.
+pub async fn set_generation_settings(
+    db: &Database,
+    models: &ModelRegistry,
+    chat_id: &str,
+    settings: GenerationSettings,
+) -> CommandResult<EffectiveGenerationSettings> {
+    let mut chat = db.chats().get(chat_id).await?.ok_or("Chat not found")?;
+
+    let inherited = inherited_generation_settings(db, models, &chat).await?;
+    settings.validate(models, &inherited.model)?;
+
+    let metadata = settings.merge_into_metadata(chat.metadata.as_deref())?;
+    db.chats().set_metadata(&chat.id, &metadata).await?;
+    chat.metadata = Some(Json(metadata));
+
+    effective_generation_settings(db, models, &chat).await
+}
+
+/// The branch the chat is showing, skipping `offset` messages and returning
+/// at most `limit`. Other branches are reached through `siblings` and
+/// `select_branch`.
+pub async fn messages(
+    db: &Database,
+    chat_id: &str,
+    limit: Option<i32>,
+    offset: Option<i32>,
+) -> CommandResult<Vec<Message>> {
     // Validate chat_id parameter
     if chat_id.is_empty() {
-        return Err("Chat ID cannot be empty. Please select a valid chat to view messages.".to_string());
+        return Err("Chat ID cannot be empty. Please select a valid chat to view messages.".into());
     }
     
     // Check if the chat exists
-    let chats = load_chats()?;
-    let chat_exists = chats.iter().any(|c| c.id == chat_id);
-    
-    if !chat_exists {
-        return Err(format!("Chat with ID '{}' not found. Please select a valid chat.", chat_id));
+    if db.chats().get(chat_id).await?.is_none() {
+        return Err(format!("Chat with ID '{}' not found. Please select a valid chat.", chat_id).into());
     }
     
-    // For now, return empty messages - will implement message storage when needed
-    // This prevents the error while allowing the UI to work correctly
-    Ok(Vec::new())
+    let messages = db.messages().active_path(chat_id).await?;
+
+    let offset = offset.unwrap_or(0).max(0) as usize;
+    let limit = limit.filter(|limit| *limit >= 0).map_or(usize::MAX, |limit| limit as usize);
+    Ok(messages.into_iter().skip(offset).take(limit).collect())
 }
 
-async fn get_api_key_from_settings() -> Result<String, String> {
-    let settings = settings::get_settings().await?;
-    
-    // Debug: Log all settings for troubleshooting
-    log::info!("All stored settings: {:?}", settings);
-    log::info!("Looking for api.anthropicApiKey in settings...");
+/// The message and the alternatives to it, oldest first.
+pub async fn siblings(db: &Database, message_id: &str) -> CommandResult<Vec<Message>> {
+    Ok(db.messages().siblings(message_id).await?)
+}
+
+/// Shows the branch through `message_id`, continuing with the newest reply
+/// below it, and returns that branch.
+pub async fn select_branch(db: &Database, message_id: &str) -> CommandResult<Vec<Message>> {
+    Ok(db.messages().switch_branch(message_id).await?)
+}
+
+async fn get_api_key_from_settings() -> CommandResult<String> {
+    log::info!("Looking for api.anthropicApiKey in the credential store...");
     
-    // Try to get the API key from settings first
-    let api_key = if let Some(key) = settings.get("api.anthropicApiKey").and_then(|v| v.as_str()).filter(|s| !s.is_empty()) {
-        log::info!("Found api.anthropicApiKey in settings");
-        key.to_string()
+    // Try to get the API key from the credential store first
+    let api_key = if let Some(key) = settings::get_secret("api.anthropicApiKey").await? {
+        log::info!("Found api.anthropicApiKey in the credential store");
+        key
     } else {
-        log::info!("API key not found in settings, checking ANTHROPIC_API_KEY environment variable...");
+        log::info!("API key not found in the credential store, checking ANTHROPIC_API_KEY environment variable...");
         std::env::var("ANTHROPIC_API_KEY").map_err(|_| {
-            log::error!("API key not found in settings or environment. Available settings keys: {:?}", 
-                settings.keys().collect::<Vec<_>>());
+            log::error!("API key not found in the credential store or environment");
             "API key not found. Please ensure you have saved a valid Anthropic API key in Settings > API Configuration.".to_string()
         })?
     };
     
     if api_key.is_empty() {
-        return Err("API key is empty. Please configure your Anthropic API key in settings first.".to_string());
+        return Err("API key is empty. Please configure your Anthropic API key in settings first.".into());
     }
     
     // Validate basic API key format - Anthropic uses sk-ant-api03- or sk-ant-api04- format
     if !api_key.starts_with("sk-ant-api03-") && !api_key.starts_with("sk-ant-api04-") {
-        return Err("Invalid API key format. Anthropic API keys must start with 'sk-ant-api03-' or 'sk-ant-api04-'. Please check your API key in settings.".to_string());
+        return Err("Invalid API key format. Anthropic API keys must start with 'sk-ant-api03-' or 'sk-ant-api04-'. Please check your API key in settings.".into());
     }
     
     if api_key.len() < 90 {
-        return Err("API key appears to be too short (expected ~95+ characters). Please verify your API key in settings.".to_string());
+        return Err("API key appears to be too short (expected ~95+ characters). Please verify your API key in settings.".into());
     }
     
     // Log API key format for debugging (but not the actual key)
-    log::info!("API key found. Length: {}, starts with: {}", 
-        api_key.len(), 
-        if api_key.len() >= 8 { &api_key[..8] } else { "too short" }
-    );
+    log::info!("API key found. Length: {}", api_key.len());
     
     Ok(api_key.to_string())
 }
 
-#[tauri::command]
-pub async fn send_claude_message(request: CreateMessageRequest) -> Result<Message, String> {
-    // Get API key from settings
-    let api_key = get_api_key_from_settings().await?;
+/// Builds the auth provider for the method selected in settings.
+pub async fn resolve_auth_provider(
+    db: &Database,
+    oauth: &Arc<OAuthManager>,
+) -> CommandResult<Arc<dyn AuthProvider>> {
+    let stored = settings::load_settings(db).await?;
+    let auth_method = stored
+        .get("api.authMethod")
+        .and_then(|v| v.as_str())
+        .unwrap_or("api_key");
+
+    if auth_method == "oauth" {
+        if settings::get_secret("api.anthropicOAuthToken").await?.is_none() {
+            return Err("Not signed in. Please sign in with your Anthropic account in Settings > API Configuration.".into());
+        }
+        let store = credentials::default_store().await?;
+        return Ok(Arc::new(OAuthAuth::new(Arc::clone(oauth), store)));
+    }
+
+    Ok(Arc::new(ApiKeyAuth::new(get_api_key_from_settings().await?)))
+}
+
+/// Converts a stored message for the API, inlining its attachments ahead of
+/// the text.
+async fn to_anthropic_message(store: &AttachmentStore, model: &ModelInfo, message: Message) -> CommandResult<AnthropicMessage> {
+    let attachments = AttachmentRef::from_metadata(message.metadata.as_deref());
+    if attachments.is_empty() {
+        return Ok(AnthropicMessage::text(message.role.as_str(), message.content));
+    }
+
+    let mut blocks = store.to_blocks(&attachments, model).await?;
+    if !message.content.trim().is_empty() {
+        blocks.push(RequestBlock::text(message.content));
+    }
+
+    Ok(AnthropicMessage {
+        role: message.role.as_str().to_string(),
+        content: MessageContent::Blocks(blocks),
+    })
+}
+
+/// Checks attachments for a new user turn before it is stored, returning
+/// them with the type and size of the stored files.
+async fn check_new_attachments(
+    db: &Database,
+    models: &ModelRegistry,
+    store: &AttachmentStore,
+    chat: &Chat,
+    new_attachments: &[AttachmentRef],
+) -> CommandResult<Vec<AttachmentRef>> {
+    if new_attachments.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut resolved = Vec::with_capacity(new_attachments.len());
+    for attachment in new_attachments {
+        resolved.push(store.resolve(attachment).await?);
+    }
+
+    let generation = effective_generation_settings(db, models, chat).await?;
+    attachments::validate_attachments(&resolved, &models.require(&generation.model)?)?;
+    Ok(resolved)
+}
+
+/// Sends a single prompt and returns the text of the reply.
+async fn complete(client: &AnthropicClient, model: &str, prompt: String, max_tokens: u32) -> CommandResult<String> {
+    let request = AnthropicRequest {
+        model: model.to_string(),
+        max_tokens,
+        messages: vec![AnthropicMessage::text("user", prompt)],
+        temperature: Some(0.0),
+        system: None,
+        tools: None,
+    };
+
+    let response = client.send_message(request).await?;
+    Ok(response
+        .content
+        .first()
+        .map(|block| block.text.trim().to_string())
+        .unwrap_or_default())
+}
+
+/// Titles the chat after its first exchange and keeps the summary of its
+/// active branch current, as far as settings allow. Uses the small model
+/// from `chat.summaryModel` and emits `chat-updated` when anything changed.
+async fn maintain_chat(
+    app: &AppHandle,
+    db: Arc<Database>,
+    oauth: &Arc<OAuthManager>,
+    chat_id: &str,
+) -> CommandResult<()> {
+    let stored = settings::load_settings(&db).await?;
+    let enabled = |key: &str| stored.get(key).and_then(|value| value.as_bool()).unwrap_or(true);
+    let model = stored
+        .get("chat.summaryModel")
+        .and_then(|value| value.as_str())
+        .unwrap_or(models::LIGHTWEIGHT_MODEL)
+        .to_string();
+
+    let Some(chat) = db.chats().get(chat_id).await? else {
+        return Ok(());
+    };
+    let path = db.messages().active_path(chat_id).await?;
+
+    let question = path.iter().find(|message| message.role == Role::User);
+    let answer = path.iter().find(|message| message.role == Role::Assistant);
+    let title_due = enabled("chat.autoTitle") && chat.auto_title && question.is_some() && answer.is_some();
+    let summary_plan = enabled("chat.autoSummary")
+        .then(|| summaries::plan_summary(&chat, &path))
+        .flatten();
+    if !title_due && summary_plan.is_none() {
+        return Ok(());
+    }
+
+    let client = AnthropicClient::with_auth(resolve_auth_provider(&db, oauth).await?, None)
+        .with_usage_tracking(Arc::clone(&db), UsageContext::chat(&chat));
+    let mut updated = false;
+
+    if let (true, Some(question), Some(answer)) = (title_due, question, answer) {
+        let prompt = summaries::title_prompt(&question.content, &answer.content);
+        if let Some(title) = summaries::clean_title(&complete(&client, &model, prompt, TITLE_MAX_TOKENS).await?) {
+            updated |= summaries::store_title(db.pool(), chat_id, &title).await?;
+        }
+    }
+
+    if let Some(plan) = summary_plan {
+        let prompt = summaries::summary_prompt(plan.previous.as_deref(), &plan.messages);
+        let summary = complete(&client, &model, prompt, SUMMARY_MAX_TOKENS).await?;
+        if !summary.is_empty() {
+            summaries::store_summary(db.pool(), chat_id, &summary, &plan.through_message_id).await?;
+            updated = true;
+        }
+    }
+
+    if updated {
+        if let Some(chat) = db.chats().get(chat_id).await? {
+            if let Err(e) = app.emit(CHAT_UPDATED_EVENT, chat) {
+                log::error!("Failed to emit {} event: {}", CHAT_UPDATED_EVENT, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Asks Claude to answer the last message of `path` and stores the reply as
+/// its child. Failed replies are returned for display without being stored.
+async fn generate_reply(
+    app: &AppHandle,
+    db: &Arc<Database>,
+    oauth: &Arc<OAuthManager>,
+    models: &ModelRegistry,
+    attachment_store: &AttachmentStore,
+    chat: &Chat,
+    path: Vec<Message>,
+) -> CommandResult<Message> {
+    let parent_id = path
+        .last()
+        .map(|message| message.id.clone())
+        .ok_or("There is no message to reply to.")?;
+
+    let generation = effective_generation_settings(db, models, chat).await?;
+    let model = models.require(&generation.model)?;
+
+    // Project documents follow the system prompt in their own block
+    let mut system_blocks = Vec::new();
+    if !generation.system_prompt.is_empty() {
+        system_blocks.push(SystemBlock::text(generation.system_prompt.clone()));
+    }
+    let mut knowledge_warning = None;
+    if let Some(project_id) = &chat.project_id {
+        if let Some(project_knowledge) = knowledge::load_knowledge(db.pool(), project_id).await? {
+            let budget = knowledge::check_budget(project_knowledge.total_tokens, &model, generation.max_tokens);
+            if let Some(warning) = &budget.warning {
+                log::warn!("{}", warning);
+            }
+            knowledge_warning = budget.warning;
+            system_blocks.push(SystemBlock::text(project_knowledge.text));
+        }
+    }
+
+    // Branches too long for the context window start from their summary
+    let system_tokens: i64 = system_blocks.iter().map(|block| estimate_tokens(&block.text)).sum();
+    let history_budget = model.context_window as i64 - generation.max_tokens as i64 - system_tokens;
+    let (summary, path) = summaries::compress_history(chat, path, history_budget);
+    if let Some(summary) = summary {
+        system_blocks.push(SystemBlock::text(format!(
+            "Summary of the earlier part of this conversation:\n<summary>\n{}\n</summary>",
+            summary
+        )));
+    }
+
+    // Resolve credentials for the configured auth method
+    let auth = resolve_auth_provider(db, oauth).await?;
     
-    // Create Anthropic client
-    let client = AnthropicClient::new(api_key, None);
+    // Create Anthropic client, attributing usage to this chat
+    let client = AnthropicClient::with_auth(auth, None)
+        .with_usage_tracking(Arc::clone(db), UsageContext::chat(chat));
+
+    // Only the branch being answered is sent. New attachments were checked
+    // against the model already; older ones it can't read are described
+    let mut messages = Vec::with_capacity(path.len());
+    for message in path {
+        if message.role == Role::System {
+            continue;
+        }
+        messages.push(to_anthropic_message(attachment_store, &model, message).await?);
+    }
     
     // Build the request for Claude API
-    let anthropic_request = AnthropicRequest {
-        model: "claude-3-5-sonnet-20241022".to_string(),
-        max_tokens: 4096,
-        messages: vec![AnthropicMessage {
-            role: request.role.clone(),
-            content: request.content.clone(),
-        }],
-        temperature: Some(0.7),
-        system: Some("You are Claude, a helpful AI assistant created by Anthropic. You are running in Cloddo, a desktop application alternative to Claude Desktop.".to_string()),
+    let mut anthropic_request = AnthropicRequest {
+        model: generation.model,
+        max_tokens: generation.max_tokens,
+        messages,
+        temperature: Some(generation.temperature as f32),
+        system: (!system_blocks.is_empty()).then_some(SystemPrompt::Blocks(system_blocks)),
+        tools: None,
     };
+
+    let prompt_caching = settings::load_settings(db)
+        .await?
+        .get("api.promptCaching")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(true);
+    if prompt_caching {
+        prompt_cache::apply_cache_breakpoints(&mut anthropic_request);
+    }
     
     // Send message to Claude API
     log::info!("🚀 Sending message to Claude API...");
     match client.send_message(anthropic_request).await {
         Ok(response) => {
-            log::info!("✅ Received response from Claude API: {} input tokens, {} output tokens", 
-                response.usage.input_tokens, response.usage.output_tokens);
+            log::info!("✅ Received response from Claude API: {} input tokens, {} output tokens, {} cache write tokens, {} cache read tokens", 
+                response.usage.input_tokens, response.usage.output_tokens,
+                response.usage.cache_creation_input_tokens, response.usage.cache_read_input_tokens);
             
             let content = response.content.first()
                 .map(|block| block.text.clone())
@@ -230,43 +464,267 @@ pub async fn send_claude_message(request: CreateMessageRequest) -> Result<Messag
             
             log::info!("📝 Response content length: {}", content.len());
             
-            let assistant_message = Message {
-                id: format!("msg_{}", Utc::now().timestamp()),
-                chat_id: request.chat_id,
-                role: "assistant".to_string(),
-                content,
-                created_at: Utc::now(),
-                metadata: Some(format!(r#"{{"input_tokens": {}, "output_tokens": {}}}"#, 
-                    response.usage.input_tokens, response.usage.output_tokens)),
-            };
+            let mut assistant_message = Message::new(chat.id.clone(), Role::Assistant, content);
+            assistant_message.parent_id = Some(parent_id);
+            assistant_message.metadata = Some(json_map([
+                ("model", serde_json::json!(response.model)),
+                ("input_tokens", serde_json::json!(response.usage.input_tokens)),
+                ("output_tokens", serde_json::json!(response.usage.output_tokens)),
+                ("cache_creation_input_tokens", serde_json::json!(response.usage.cache_creation_input_tokens)),
+                ("cache_read_input_tokens", serde_json::json!(response.usage.cache_read_input_tokens)),
+                ("knowledge_warning", serde_json::json!(knowledge_warning)),
+            ]));
+
+            db.messages().insert(&assistant_message).await?;
+
+            let (app, db, oauth, chat_id) = (app.clone(), Arc::clone(db), Arc::clone(oauth), chat.id.clone());
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = maintain_chat(&app, db, &oauth, &chat_id).await {
+                    log::warn!("Failed to update title or summary of chat {}: {}", chat_id, e);
+                }
+            });
             
             Ok(assistant_message)
         },
         Err(e) => {
             // Log detailed error for debugging
             log::error!("Claude API request failed: {}", e);
-            
-            // Provide specific error messages based on error type
-            let error_content = if e.to_string().contains("401") || e.to_string().contains("authentication") {
-                "Authentication failed. Please check your Anthropic API key in settings and ensure it's valid.".to_string()
-            } else if e.to_string().contains("429") {
-                "Rate limit exceeded. Please wait a moment and try again.".to_string()
-            } else if e.to_string().contains("network") || e.to_string().contains("connection") {
-                "Network connection error. Please check your internet connection and try again.".to_string()
-            } else {
-                format!("API request failed: {}. Please check your API key configuration in settings.", e)
-            };
-            
-            let error_message = Message {
-                id: format!("msg_{}", Utc::now().timestamp()),
-                chat_id: request.chat_id,
-                role: "assistant".to_string(),
-                content: error_content,
-                created_at: Utc::now(),
-                metadata: Some(format!(r#"{{"error": true, "error_details": "{}"}}"#, e)),
-            };
-            
-            Ok(error_message)
+            Ok(error_reply(chat, Some(parent_id), &e, knowledge_warning))
         }
     }
-}
\ No newline at end of file
+}
+
+/// A reply explaining why Claude could not answer. Returned for display
+/// only; failed replies are not stored.
+fn error_reply(chat: &Chat, parent_id: Option<String>, e: &anyhow::Error, knowledge_warning: Option<String>) -> Message {
+    // Provide specific error messages based on error type
+    let budget_exceeded = e.downcast_ref::<BudgetExceeded>();
+    let error_content = if let Some(exceeded) = budget_exceeded {
+        format!(
+            "The {} budget \"{}\" of ${:.2} has been reached. Raise the limit in settings or wait for the next period.",
+            exceeded.period, exceeded.budget_name, exceeded.limit_usd
+        )
+    } else if e.to_string().contains("401") || e.to_string().contains("authentication") {
+        "Authentication failed. Please check your Anthropic API key in settings and ensure it's valid.".to_string()
+    } else if e.to_string().contains("429") {
+        "Rate limit exceeded. Please wait a moment and try again.".to_string()
+    } else if e.to_string().contains("network") || e.to_string().contains("connection") {
+        "Network connection error. Please check your internet connection and try again.".to_string()
+    } else {
+        format!("API request failed: {}. Please check your API key configuration in settings.", e)
+    };
+
+    let mut error_message = Message::new(chat.id.clone(), Role::Assistant, error_content);
+    error_message.parent_id = parent_id;
+    error_message.metadata = Some(json_map([
+        ("error", serde_json::json!(true)),
+        ("error_code", serde_json::json!(budget_exceeded.map(|_| BUDGET_EXCEEDED))),
+        ("knowledge_warning", serde_json::json!(knowledge_warning)),
+        ("error_details", serde_json::json!(e.to_string())),
+    ]));
+    error_message
+}
+
+#[tauri::command]
+pub async fn send_claude_message(
+    app: AppHandle,
+    db: State<'_, Arc<Database>>,
+    oauth: State<'_, Arc<OAuthManager>>,
+    models: State<'_, Arc<ModelRegistry>>,
+    attachment_store: State<'_, Arc<AttachmentStore>>,
+    request: CreateMessageRequest,
+) -> CommandResult<Message> {
+    let chat = db.chats().get(&request.chat_id)
+        .await?
+        .ok_or_else(|| format!("Chat with ID '{}' not found. Please select a valid chat.", request.chat_id))?;
+
+    let new_attachments = check_new_attachments(&db, &models, &attachment_store, &chat, &request.attachments).await?;
+
+    // The new turn continues the branch the chat is showing
+    let mut path = db.messages().active_path(&chat.id).await?;
+
+    // A send a budget blocks must not leave the turn behind without a reply
+    if let Err(e) = budgets::enforce_budgets(db.pool(), &UsageContext::chat(&chat)).await {
+        return Ok(error_reply(&chat, path.last().map(|message| message.id.clone()), &e, None));
+    }
+
+    let mut user_message = Message::new(chat.id.clone(), request.role, request.content.clone());
+    user_message.parent_id = path.last().map(|message| message.id.clone());
+    if !new_attachments.is_empty() {
+        user_message.metadata = Some(json_map([(attachments::ATTACHMENTS_METADATA_KEY, serde_json::json!(new_attachments))]));
+    }
+    db.messages().insert(&user_message).await?;
+    path.push(user_message);
+
+    generate_reply(&app, &db, &oauth, &models, &attachment_store, &chat, path).await
+}
+
+/// Forks a new branch from an earlier user turn: the edited text becomes a
+/// sibling of the original and Claude answers it. `attachments` replaces the
+/// original's attachments when given.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)] // managed state plus the arguments the frontend sends
+pub async fn edit_message(
+    app: AppHandle,
+    db: State<'_, Arc<Database>>,
+    oauth: State<'_, Arc<OAuthManager>>,
+    models: State<'_, Arc<ModelRegistry>>,
+    attachment_store: State<'_, Arc<AttachmentStore>>,
+    message_id: String,
+    content: String,
+    attachments: Option<Vec<AttachmentRef>>,
+) -> CommandResult<Message> {
+    let original = db.messages().get(&message_id)
+        .await?
+        .ok_or("Message not found")?;
+    if original.role != Role::User {
+        return Err("Only your own messages can be edited. Regenerate a reply instead.".into());
+    }
+    let chat = db.chats().get(&original.chat_id)
+        .await?
+        .ok_or("Chat not found")?;
+
+    let attachments = match attachments {
+        Some(attachments) => check_new_attachments(&db, &models, &attachment_store, &chat, &attachments).await?,
+        None => AttachmentRef::from_metadata(original.metadata.as_deref()),
+    };
+
+    if let Err(e) = budgets::enforce_budgets(db.pool(), &UsageContext::chat(&chat)).await {
+        return Ok(error_reply(&chat, original.parent_id.clone(), &e, None));
+    }
+
+    let mut edited = Message::new(chat.id.clone(), original.role, content);
+    edited.parent_id = original.parent_id.clone();
+    let mut metadata = vec![("edited_from", serde_json::json!(original.id))];
+    if !attachments.is_empty() {
+        metadata.push((attachments::ATTACHMENTS_METADATA_KEY, serde_json::json!(attachments)));
+    }
+    edited.metadata = Some(json_map(metadata));
+    db.messages().insert(&edited).await?;
+
+    let path = db.messages().path_to(&edited.id).await?;
+    generate_reply(&app, &db, &oauth, &models, &attachment_store, &chat, path).await
+}
+
+/// Asks Claude for another answer. For a reply, the new answer becomes its
+/// sibling; for a user message left without a reply, it becomes the reply.
+#[tauri::command]
+pub async fn regenerate_message(
+    app: AppHandle,
+    db: State<'_, Arc<Database>>,
+    oauth: State<'_, Arc<OAuthManager>>,
+    models: State<'_, Arc<ModelRegistry>>,
+    attachment_store: State<'_, Arc<AttachmentStore>>,
+    message_id: String,
+) -> CommandResult<Message> {
+    let message = db.messages().get(&message_id)
+        .await?
+        .ok_or("Message not found")?;
+    let chat = db.chats().get(&message.chat_id)
+        .await?
+        .ok_or("Chat not found")?;
+
+    let prompt_id = match message.role {
+        Role::Assistant => message
+            .parent_id
+            .ok_or("This reply has no message to answer.")?,
+        Role::User => message.id,
+        Role::System => return Err("Only chat turns can be regenerated.".into()),
+    };
+
+    let path = db.messages().path_to(&prompt_id).await?;
+    generate_reply(&app, &db, &oauth, &models, &attachment_store, &chat, path).await
+}
+
+#[tauri::command]
+pub async fn get_chats(
+    db: State<'_, Arc<Database>>,
+    session_id: Option<String>,
+    project_id: Option<String>,
+    folder_path: Option<String>,
+    tag: Option<String>,
+    limit: Option<i32>,
+    offset: Option<i32>,
+) -> CommandResult<Vec<Chat>> {
+    let filter = ChatFilter { session_id, project_id, folder_path, tag, limit, offset };
+    list(&db, &filter).await
+}
+
+#[tauri::command]
+pub async fn get_chat_by_id(
+    db: State<'_, Arc<Database>>,
+    chat_id: String,
+) -> CommandResult<Option<Chat>> {
+    get(&db, &chat_id).await
+}
+
+#[tauri::command]
+pub async fn create_chat(
+    db: State<'_, Arc<Database>>,
+    request: CreateChatRequest,
+) -> CommandResult<Chat> {
+    create(&db, request).await
+}
+
+#[tauri::command]
+pub async fn update_chat(
+    db: State<'_, Arc<Database>>,
+    models: State<'_, Arc<ModelRegistry>>,
+    chat_id: String,
+    request: UpdateChatRequest,
+) -> CommandResult<Chat> {
+    update(&db, &models, &chat_id, request).await
+}
+
+#[tauri::command]
+pub async fn delete_chat(
+    db: State<'_, Arc<Database>>,
+    chat_id: String,
+) -> CommandResult<bool> {
+    delete(&db, &chat_id).await
+}
+
+#[tauri::command]
+pub async fn get_chat_generation_settings(
+    db: State<'_, Arc<Database>>,
+    models: State<'_, Arc<ModelRegistry>>,
+    chat_id: String,
+) -> CommandResult<EffectiveGenerationSettings> {
+    generation_settings(&db, &models, &chat_id).await
+}
+
+#[tauri::command]
+pub async fn set_chat_generation_settings(
+    db: State<'_, Arc<Database>>,
+    models: State<'_, Arc<ModelRegistry>>,
+    chat_id: String,
+    settings: GenerationSettings,
+) -> CommandResult<EffectiveGenerationSettings> {
+    set_generation_settings(&db, &models, &chat_id, settings).await
+}
+
+#[tauri::command]
+pub async fn get_messages(
+    db: State<'_, Arc<Database>>,
+    chat_id: String,
+    limit: Option<i32>,
+    offset: Option<i32>,
+) -> CommandResult<Vec<Message>> {
+    messages(&db, &chat_id, limit, offset).await
+}
+
+#[tauri::command]
+pub async fn get_message_siblings(
+    db: State<'_, Arc<Database>>,
+    message_id: String,
+) -> CommandResult<Vec<Message>> {
+    siblings(&db, &message_id).await
+}
+
+#[tauri::command]
+pub async fn switch_branch(
+    db: State<'_, Arc<Database>>,
+    message_id: String,
+) -> CommandResult<Vec<Message>> {
+    select_branch(&db, &message_id).await
+}
@@ -0,0 +1,80 @@
+use crate::database::{Database, models::*};
+use crate::database::collections;
+use tauri::State;
+use std::sync::Arc;
+use chrono::Utc;
+
+#[tauri::command]
+pub async fn get_collections(
+    db: State<'_, Arc<Database>>,
+) -> Result<Vec<Collection>, String> {
+    collections::list(db.pool()).await.map_err(|e| e.to_string())
+}
+
+/// Saves a smart collection. Fails with the position of the problem when
+/// the query does not parse.
+#[tauri::command]
+pub async fn create_collection(
+    db: State<'_, Arc<Database>>,
+    name: String,
+    query: String,
+) -> Result<Collection, String> {
+    collections::create(db.pool(), &name, &query)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_collection(
+    db: State<'_, Arc<Database>>,
+    collection_id: String,
+    name: Option<String>,
+    query: Option<String>,
+) -> Result<Collection, String> {
+    collections::update(db.pool(), &collection_id, name.as_deref(), query.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_collection(
+    db: State<'_, Arc<Database>>,
+    collection_id: String,
+) -> Result<bool, String> {
+    collections::delete(db.pool(), &collection_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Chats currently matching a saved collection, most recently active first.
+#[tauri::command]
+pub async fn get_collection_chats(
+    db: State<'_, Arc<Database>>,
+    collection_id: String,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Vec<Chat>, String> {
+    let collection = collections::get(db.pool(), &collection_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Collection not found".to_string())?;
+    let expr = collections::parse(&collection.query).map_err(|e| e.to_string())?;
+
+    collections::evaluate(db.pool(), &expr, Utc::now(), limit, offset)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Chats matching a query that is not saved yet, for editing a collection.
+#[tauri::command]
+pub async fn preview_collection(
+    db: State<'_, Arc<Database>>,
+    query: String,
+    limit: Option<i64>,
+) -> Result<Vec<Chat>, String> {
+    let expr = collections::parse(&query).map_err(|e| e.to_string())?;
+
+    collections::evaluate(db.pool(), &expr, Utc::now(), limit, None)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,87 @@
+use crate::database::Database;
+use crate::database::export::{self, ExportBundle, ExportFormat};
+use crate::integrations::attachments::AttachmentStore;
+use crate::utils::render;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::{AppHandle, State};
+use tauri_plugin_dialog::DialogExt;
+
+/// Asks where to save the export. Returns `None` when the user cancels.
+async fn choose_save_path(app: &AppHandle, default_name: String, format: ExportFormat) -> Result<Option<PathBuf>, String> {
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .file()
+        .set_title("Export")
+        .set_file_name(default_name)
+        .add_filter(format.label(), &[format.extension()])
+        .save_file(move |path| {
+            let _ = sender.send(path);
+        });
+
+    match receiver.await.map_err(|_| "The save dialog was closed unexpectedly".to_string())? {
+        Some(path) => Ok(Some(path.into_path().map_err(|e| e.to_string())?)),
+        None => Ok(None),
+    }
+}
+
+async fn write_export(
+    app: &AppHandle,
+    store: &AttachmentStore,
+    mut bundle: ExportBundle,
+    default_name: String,
+    format: ExportFormat,
+) -> Result<Option<String>, String> {
+    let Some(path) = choose_save_path(app, default_name, format).await? else {
+        return Ok(None);
+    };
+
+    // Markdown only names attachments; the other formats carry them
+    if format != ExportFormat::Markdown {
+        bundle.embed_attachments(store).await.map_err(|e| e.to_string())?;
+    }
+    let content = render::render(format, &bundle).map_err(|e| e.to_string())?;
+    tokio::fs::write(&path, content)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    log::info!("Exported {} chats to {}", bundle.chats.len(), path.display());
+    Ok(Some(path.to_string_lossy().to_string()))
+}
+
+/// Saves a chat as Markdown, JSON or HTML to a location the user picks.
+/// Returns the path written, or `None` if the user cancelled.
+#[tauri::command]
+pub async fn export_chat(
+    app: AppHandle,
+    db: State<'_, Arc<Database>>,
+    attachment_store: State<'_, Arc<AttachmentStore>>,
+    chat_id: String,
+    format: ExportFormat,
+) -> Result<Option<String>, String> {
+    let chat = export::load_chat(db.pool(), &chat_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Chat not found".to_string())?;
+
+    let default_name = export::file_name(&chat.chat.title, format);
+    write_export(&app, &attachment_store, ExportBundle::new(None, vec![chat]), default_name, format).await
+}
+
+/// Saves every chat of a project into a single file.
+#[tauri::command]
+pub async fn export_project(
+    app: AppHandle,
+    db: State<'_, Arc<Database>>,
+    attachment_store: State<'_, Arc<AttachmentStore>>,
+    project_id: String,
+    format: ExportFormat,
+) -> Result<Option<String>, String> {
+    let (project, chats) = export::load_project(db.pool(), &project_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Project not found".to_string())?;
+
+    let default_name = export::file_name(&project.name, format);
+    write_export(&app, &attachment_store, ExportBundle::new(Some(project), chats), default_name, format).await
+}
@@ -0,0 +1,71 @@
+use crate::database::{Database, models::*};
+use crate::database::folders;
+use tauri::State;
+use std::sync::Arc;
+
+/// Every folder with its full path and chat count, ordered by path.
+#[tauri::command]
+pub async fn get_folders(
+    db: State<'_, Arc<Database>>,
+) -> Result<Vec<Folder>, String> {
+    folders::list(db.pool()).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_folder(
+    db: State<'_, Arc<Database>>,
+    name: String,
+    parent_id: Option<String>,
+) -> Result<Folder, String> {
+    folders::create(db.pool(), &name, parent_id.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn rename_folder(
+    db: State<'_, Arc<Database>>,
+    folder_id: String,
+    name: String,
+) -> Result<Folder, String> {
+    folders::rename(db.pool(), &folder_id, &name)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Moves a folder with everything in it under `parent_id`, or to the top
+/// level when `None`.
+#[tauri::command]
+pub async fn move_folder(
+    db: State<'_, Arc<Database>>,
+    folder_id: String,
+    parent_id: Option<String>,
+) -> Result<Folder, String> {
+    folders::move_folder(db.pool(), &folder_id, parent_id.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Deletes a folder and its subfolders, moving their chats to its parent.
+#[tauri::command]
+pub async fn delete_folder(
+    db: State<'_, Arc<Database>>,
+    folder_id: String,
+) -> Result<bool, String> {
+    folders::delete(db.pool(), &folder_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Puts chats into `folder_id`, or at the top level when `None`. Returns how
+/// many chats were moved.
+#[tauri::command]
+pub async fn move_chats_to_folder(
+    db: State<'_, Arc<Database>>,
+    chat_ids: Vec<String>,
+    folder_id: Option<String>,
+) -> Result<u64, String> {
+    folders::move_chats(db.pool(), &chat_ids, folder_id.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
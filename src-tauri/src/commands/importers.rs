@@ -0,0 +1,43 @@
+use crate::database::Database;
+use crate::database::importers::{self, ImportSource, ImportSummary};
+use crate::database::sessions;
+use std::sync::Arc;
+use tauri::{AppHandle, State};
+use tauri_plugin_dialog::DialogExt;
+
+/// Imports the `conversations.json` of a claude.ai or ChatGPT data export
+/// the user picks, as chats in `session_id`. The format is recognized from
+/// the file unless `source` is given. Returns `None` if the user cancelled.
+#[tauri::command]
+pub async fn import_conversations(
+    app: AppHandle,
+    db: State<'_, Arc<Database>>,
+    session_id: String,
+    project_id: Option<String>,
+    source: Option<ImportSource>,
+) -> Result<Option<ImportSummary>, String> {
+    sessions::require_open(db.pool(), &session_id).await.map_err(|e| e.to_string())?;
+
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .file()
+        .set_title("Import conversations")
+        .add_filter("Conversations export", &["json"])
+        .pick_file(move |path| {
+            let _ = sender.send(path);
+        });
+
+    let Some(path) = receiver.await.map_err(|_| "The open dialog was closed unexpectedly".to_string())? else {
+        return Ok(None);
+    };
+    let path = path.into_path().map_err(|e| e.to_string())?;
+    let json = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let summary = importers::import_export(db.pool(), &json, source, &session_id, project_id.as_deref())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(Some(summary))
+}
@@ -1,9 +1,25 @@
+pub mod error;
+pub mod session;
 pub mod chat;
 pub mod project;
+pub mod folder;
+pub mod tag;
+pub mod collection;
 pub mod settings;
 pub mod agent;
 pub mod workflow;
 pub mod oauth;
+pub mod usage;
+pub mod budget;
+pub mod models;
+pub mod attachments;
+pub mod search;
+pub mod export;
+pub mod importers;
+pub mod backup;
+pub mod trash;
+
+pub use error::{CommandError, CommandResult};
 
 // Re-export common types
 pub use crate::database::models::*;
\ No newline at end of file
@@ -0,0 +1,40 @@
+use crate::commands::chat::resolve_auth_provider;
+use crate::commands::CommandResult;
+use crate::database::Database;
+use crate::integrations::anthropic::AnthropicClient;
+use crate::integrations::models::{ModelInfo, ModelRegistry};
+use crate::integrations::oauth::OAuthManager;
+use tauri::State;
+use std::sync::Arc;
+
+#[tauri::command]
+pub async fn get_models(
+    models: State<'_, Arc<ModelRegistry>>,
+) -> Result<Vec<ModelInfo>, String> {
+    Ok(models.list())
+}
+
+#[tauri::command]
+pub async fn get_model(
+    models: State<'_, Arc<ModelRegistry>>,
+    model_id: String,
+) -> Result<ModelInfo, String> {
+    models.require(&model_id)
+}
+
+/// Re-fetches the model list from the API and updates the local cache.
+#[tauri::command]
+pub async fn refresh_models(
+    db: State<'_, Arc<Database>>,
+    models: State<'_, Arc<ModelRegistry>>,
+    oauth: State<'_, Arc<OAuthManager>>,
+) -> CommandResult<Vec<ModelInfo>> {
+    let auth = resolve_auth_provider(&db, oauth.inner()).await?;
+    let client = AnthropicClient::with_auth(auth, None);
+
+    let models = models
+        .refresh(&client)
+        .await
+        .map_err(|e| format!("Failed to refresh models: {}", e))?;
+    Ok(models)
+}
@@ -1,52 +1,67 @@
-use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use ring::digest;
-use base64::{Engine as _, engine::general_purpose};
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct OAuthTokenResponse {
-    pub access_token: String,
-    pub refresh_token: String,
-    pub token_type: String,
-    pub expires_in: i64,
-    pub scope: String,
-}
+use serde::Serialize;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, State};
+use crate::integrations::oauth::OAuthManager;
+use crate::utils::credentials;
+
+pub use crate::integrations::oauth::{OAuthError, OAuthTokenResponse};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct OAuthError {
-    pub error: String,
-    pub error_description: String,
+/// Event emitted when a sign-in started with `initiate_oauth_flow` finishes.
+pub const OAUTH_COMPLETED_EVENT: &str = "oauth-completed";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OAuthCompletedPayload {
+    pub success: bool,
+    pub error: Option<String>,
 }
 
-// Anthropic OAuth configuration
-const ANTHROPIC_AUTH_URL: &str = "https://console.anthropic.com/oauth/authorize";
-const ANTHROPIC_TOKEN_URL: &str = "https://api.anthropic.com/v1/oauth/token";
-const REDIRECT_URI: &str = "http://localhost:8080/oauth/callback";
+/// Returns the port of the running callback server, starting one if needed.
+/// The server emits `oauth-completed` once the redirect has been handled.
+async fn ensure_callback_server(
+    app: &AppHandle,
+    oauth: &Arc<OAuthManager>,
+) -> Result<u16, String> {
+    if let Some(port) = oauth.active_port() {
+        return Ok(port);
+    }
+
+    let store = credentials::default_store().await?;
+    let server = oauth.start_callback_server(store).await?;
+    let port = server.port;
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let result = server
+            .completion
+            .await
+            .unwrap_or_else(|e| Err(format!("OAuth callback server crashed: {}", e)));
+
+        let payload = match result {
+            Ok(_) => {
+                log::info!("OAuth sign-in completed");
+                OAuthCompletedPayload { success: true, error: None }
+            }
+            Err(e) => {
+                log::error!("OAuth sign-in failed: {}", e);
+                OAuthCompletedPayload { success: false, error: Some(e) }
+            }
+        };
+
+        if let Err(e) = app.emit(OAUTH_COMPLETED_EVENT, payload) {
+            log::error!("Failed to emit {} event: {}", OAUTH_COMPLETED_EVENT, e);
+        }
+    });
+
+    Ok(port)
+}
 
 #[tauri::command]
-pub async fn initiate_oauth_flow() -> Result<String, String> {
-    // Use Claude Code's official client ID for desktop applications
-    let client_id = "9d1c250a-e61b-44d9-88ed-5944d1962f5e";
-    let state = format!("state_{}", chrono::Utc::now().timestamp());
-    
-    // Generate PKCE parameters
-    let code_verifier = generate_code_verifier();
-    let code_challenge = generate_code_challenge(&code_verifier);
-    
-    // Store code_verifier for later use in token exchange
-    // In a real app, this should be stored securely
-    log::info!("Generated PKCE code_verifier (first 20 chars): {}", &code_verifier[..20]);
-    
-    // Build proper OAuth URL with PKCE parameters (no scope)
-    let oauth_url = format!(
-        "{}?response_type=code&client_id={}&redirect_uri={}&state={}&code_challenge={}&code_challenge_method=S256",
-        ANTHROPIC_AUTH_URL,
-        urlencoding::encode(client_id),
-        urlencoding::encode(REDIRECT_URI),
-        urlencoding::encode(&state),
-        urlencoding::encode(&code_challenge)
-    );
+pub async fn initiate_oauth_flow(
+    app: AppHandle,
+    oauth: State<'_, Arc<OAuthManager>>,
+) -> Result<String, String> {
+    let port = ensure_callback_server(&app, oauth.inner()).await?;
+    let oauth_url = oauth.authorization_url(port);
     
     log::info!("Generated OAuth URL with PKCE for Claude authentication");
     Ok(oauth_url)
@@ -54,76 +69,34 @@ pub async fn initiate_oauth_flow() -> Result<String, String> {
 
 #[tauri::command]
 pub async fn exchange_oauth_code(
+    oauth: State<'_, Arc<OAuthManager>>,
     code: String,
-    _state: String,
+    state: String,
 ) -> Result<OAuthTokenResponse, String> {
-    let client = reqwest::Client::new();
-    
-    let client_id = get_client_id();
-    let client_secret = get_client_secret();
-    
-    let mut params = HashMap::new();
-    params.insert("grant_type", "authorization_code");
-    params.insert("code", &code);
-    params.insert("redirect_uri", REDIRECT_URI);
-    params.insert("client_id", &client_id);
-    params.insert("client_secret", &client_secret);
-    
-    let response = client
-        .post(ANTHROPIC_TOKEN_URL)
-        .form(&params)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to exchange OAuth code: {}", e))?;
-        
-    if response.status().is_success() {
-        let token_response: OAuthTokenResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse token response: {}", e))?;
-        Ok(token_response)
-    } else {
-        let error: OAuthError = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse error response: {}", e))?;
-        Err(format!("OAuth error: {}", error.error_description))
-    }
+    let store = credentials::default_store().await?;
+    oauth.exchange_code(&code, &state, store.as_ref()).await
 }
 
 #[tauri::command]
-pub async fn refresh_oauth_token(refresh_token: String) -> Result<OAuthTokenResponse, String> {
-    let client = reqwest::Client::new();
-    
-    let client_id = get_client_id();
-    let client_secret = get_client_secret();
-    
-    let mut params = HashMap::new();
-    params.insert("grant_type", "refresh_token");
-    params.insert("refresh_token", &refresh_token);
-    params.insert("client_id", &client_id);
-    params.insert("client_secret", &client_secret);
-    
-    let response = client
-        .post(ANTHROPIC_TOKEN_URL)
-        .form(&params)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to refresh OAuth token: {}", e))?;
-        
-    if response.status().is_success() {
-        let token_response: OAuthTokenResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse refresh response: {}", e))?;
-        Ok(token_response)
-    } else {
-        let error: OAuthError = response
-            .json()
+pub async fn refresh_oauth_token(
+    oauth: State<'_, Arc<OAuthManager>>,
+    refresh_token: Option<String>,
+) -> Result<OAuthTokenResponse, String> {
+    let store = credentials::default_store().await?;
+    // Shares the lock API clients refresh under, so a refresh token is never
+    // used after another refresh has rotated it
+    let _guard = oauth.refresh_lock().lock().await;
+
+    let refresh_token = match refresh_token.filter(|token| !token.is_empty()) {
+        Some(token) => token,
+        None => store
+            .get("api.anthropicRefreshToken")
             .await
-            .map_err(|e| format!("Failed to parse error response: {}", e))?;
-        Err(format!("OAuth refresh error: {}", error.error_description))
-    }
+            .map_err(|e| format!("Failed to read refresh token: {}", e))?
+            .ok_or_else(|| "No OAuth refresh token stored. Please sign in again.".to_string())?,
+    };
+
+    oauth.refresh(&refresh_token, store.as_ref()).await
 }
 
 #[tauri::command]
@@ -142,43 +115,6 @@ pub async fn validate_oauth_token(access_token: String) -> Result<bool, String>
     Ok(response.status().is_success())
 }
 
-// PKCE helper functions
-fn generate_code_verifier() -> String {
-    use rand::Rng;
-    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~";
-    let mut rng = rand::thread_rng();
-    
-    (0..128)
-        .map(|_| {
-            let idx = rng.gen_range(0..CHARSET.len());
-            CHARSET[idx] as char
-        })
-        .collect()
-}
-
-fn generate_code_challenge(verifier: &str) -> String {
-    let digest = digest::digest(&digest::SHA256, verifier.as_bytes());
-    general_purpose::URL_SAFE_NO_PAD.encode(digest.as_ref())
-}
-
-// Helper functions for OAuth configuration
-fn get_client_id() -> String {
-    std::env::var("ANTHROPIC_CLIENT_ID")
-        .unwrap_or_else(|_| {
-            log::error!("ANTHROPIC_CLIENT_ID environment variable not set. OAuth will not work without proper credentials.");
-            // Return a clearly invalid client ID that will trigger proper error handling
-            "INVALID_CLIENT_ID_NOT_CONFIGURED".to_string()
-        })
-}
-
-fn get_client_secret() -> String {
-    std::env::var("ANTHROPIC_CLIENT_SECRET")
-        .unwrap_or_else(|_| {
-            log::error!("ANTHROPIC_CLIENT_SECRET environment variable not set. OAuth will not work without proper credentials.");
-            "INVALID_CLIENT_SECRET_NOT_CONFIGURED".to_string()
-        })
-}
-
 #[tauri::command]
 pub async fn open_url(url: String) -> Result<(), String> {
     use std::process::Command;
@@ -211,19 +147,9 @@ pub async fn open_url(url: String) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub async fn start_oauth_server() -> Result<u16, String> {
-    // Start a local server to handle the OAuth callback
-    // This is a simplified version - in production you'd want a more robust server
-    use std::net::TcpListener;
-    
-    let listener = TcpListener::bind("127.0.0.1:0")
-        .map_err(|e| format!("Failed to bind OAuth callback server: {}", e))?;
-    
-    let port = listener.local_addr()
-        .map_err(|e| format!("Failed to get server port: {}", e))?
-        .port();
-    
-    // In a real implementation, you'd spawn a server here to handle the callback
-    // For now, we'll just return the port
-    Ok(port)
-}
\ No newline at end of file
+pub async fn start_oauth_server(
+    app: AppHandle,
+    oauth: State<'_, Arc<OAuthManager>>,
+) -> Result<u16, String> {
+    ensure_callback_server(&app, oauth.inner()).await
+}
@@ -1,92 +1,168 @@
+use crate::commands::{CommandError, CommandResult};
 use crate::database::{Database, models::*};
+use crate::integrations::generation::{self, GenerationSettings};
+use crate::database::knowledge::{self, KnowledgeBudget};
+use crate::integrations::filesystem;
+use crate::commands::settings;
+use crate::integrations::models::{ModelRegistry, DEFAULT_MODEL};
 use tauri::State;
+use sqlx::types::Json;
+use std::path::Path;
 use std::sync::Arc;
-use anyhow::Result;
 use chrono::Utc;
 
-#[tauri::command]
-pub async fn get_projects(
-    db: State<'_, Arc<Database>>,
-) -> Result<Vec<Project>, String> {
-    let projects = sqlx::query_as::<_, Project>(
-        "SELECT * FROM projects ORDER BY created_at DESC",
-    )
-    .fetch_all(db.pool())
-    .await
-    .map_err(|e| e.to_string())?;
+async fn fetch_project(db: &Database, project_id: &str) -> CommandResult<Project> {
+    db.projects()
+        .get(project_id)
+        .await?
+        .ok_or_else(|| CommandError::from("Project not found"))
+}
 
-    Ok(projects)
+pub async fn list(db: &Database) -> CommandResult<Vec<Project>> {
+    Ok(db.projects().list().await?)
 }
 
-#[tauri::command]
-pub async fn create_project(
-    db: State<'_, Arc<Database>>,
-    request: CreateProjectRequest,
-) -> Result<Project, String> {
+pub async fn create(db: &Database, request: CreateProjectRequest) -> CommandResult<Project> {
     let mut project = Project::new(request.name);
     project.description = request.description;
     project.color = request.color;
 
-    sqlx::query(
-        r#"
-        INSERT INTO projects (id, name, description, color, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, ?)
-        "#,
-    )
-    .bind(&project.id)
-    .bind(&project.name)
-    .bind(&project.description)
-    .bind(&project.color)
-    .bind(project.created_at)
-    .bind(project.updated_at)
-    .execute(db.pool())
-    .await
-    .map_err(|e| e.to_string())?;
+    db.projects().create(&project).await?;
+    Ok(project)
+}
+
+pub async fn update(db: &Database, project_id: &str, request: CreateProjectRequest) -> CommandResult<Project> {
+    db.projects()
+        .update(project_id, &request)
+        .await?
+        .ok_or_else(|| CommandError::from("Project not found"))
+}
+
+/// Replaces the generation settings inherited by the project's chats.
+pub async fn set_generation_settings(
+    db: &Database,
+    models: &ModelRegistry,
+    project_id: &str,
+    settings: GenerationSettings,
+) -> CommandResult<Project> {
+    let mut project = fetch_project(db, project_id).await?;
+
+    let global = GenerationSettings::from_global_settings(&settings::load_settings(db).await?);
+    settings.validate(models, global.model.as_deref().unwrap_or(DEFAULT_MODEL))?;
+
+    let metadata = settings.merge_into_metadata(project.metadata.as_deref())?;
+    db.projects().set_metadata(&project.id, &metadata).await?;
+    project.metadata = Some(Json(metadata));
+    project.updated_at = Utc::now();
 
     Ok(project)
 }
 
+/// Moves a project to the trash. Its chats stay where they are, and leave the
+/// project when it is purged.
+pub async fn delete(db: &Database, project_id: &str) -> CommandResult<bool> {
+    Ok(db.projects().move_to_trash(project_id).await?)
+}
+
+/// Attaches the file at `file_path` to the project as knowledge.
+pub async fn add_document(db: &Database, project_id: &str, file_path: &Path) -> CommandResult<ProjectDocument> {
+    let document = filesystem::read_document(file_path).await?;
+    Ok(db.projects().add_document(project_id, document).await?)
+}
+
+pub async fn documents(db: &Database, project_id: &str) -> CommandResult<Vec<ProjectDocument>> {
+    Ok(db.projects().documents(project_id).await?)
+}
+
+pub async fn delete_document(db: &Database, document_id: &str) -> CommandResult<bool> {
+    Ok(db.projects().delete_document(document_id).await?)
+}
+
+/// Total size of the project's knowledge and whether it fits the context
+/// window of the model its chats use by default.
+pub async fn knowledge(db: &Database, models: &ModelRegistry, project_id: &str) -> CommandResult<KnowledgeBudget> {
+    let project = fetch_project(db, project_id).await?;
+    let overrides = GenerationSettings::from_metadata(project.metadata.as_deref())?;
+    let global = GenerationSettings::from_global_settings(&settings::load_settings(db).await?);
+    let effective = generation::resolve(&GenerationSettings::default(), &overrides, &global, models)?;
+    let model = models.require(&effective.model)?;
+
+    let total_tokens = db.projects().knowledge_tokens(project_id).await?;
+    Ok(knowledge::check_budget(total_tokens, &model, effective.max_tokens))
+}
+
+#[tauri::command]
+pub async fn get_projects(
+    db: State<'_, Arc<Database>>,
+) -> CommandResult<Vec<Project>> {
+    list(&db).await
+}
+
+#[tauri::command]
+pub async fn create_project(
+    db: State<'_, Arc<Database>>,
+    request: CreateProjectRequest,
+) -> CommandResult<Project> {
+    create(&db, request).await
+}
+
 #[tauri::command]
 pub async fn update_project(
     db: State<'_, Arc<Database>>,
     project_id: String,
     request: CreateProjectRequest,
-) -> Result<Project, String> {
-    sqlx::query(
-        r#"
-        UPDATE projects 
-        SET name = ?, description = ?, color = ?, updated_at = ?
-        WHERE id = ?
-        "#,
-    )
-    .bind(&request.name)
-    .bind(&request.description)
-    .bind(&request.color)
-    .bind(Utc::now())
-    .bind(&project_id)
-    .execute(db.pool())
-    .await
-    .map_err(|e| e.to_string())?;
-
-    let project = sqlx::query_as::<_, Project>("SELECT * FROM projects WHERE id = ?")
-        .bind(project_id)
-        .fetch_one(db.pool())
-        .await
-        .map_err(|e| e.to_string())?;
+) -> CommandResult<Project> {
+    update(&db, &project_id, request).await
+}
 
-    Ok(project)
+#[tauri::command]
+pub async fn set_project_generation_settings(
+    db: State<'_, Arc<Database>>,
+    models: State<'_, Arc<ModelRegistry>>,
+    project_id: String,
+    settings: GenerationSettings,
+) -> CommandResult<Project> {
+    set_generation_settings(&db, &models, &project_id, settings).await
 }
 
 #[tauri::command]
 pub async fn delete_project(
     db: State<'_, Arc<Database>>,
     project_id: String,
-) -> Result<bool, String> {
-    let result = sqlx::query("DELETE FROM projects WHERE id = ?")
-        .bind(project_id)
-        .execute(db.pool())
-        .await
-        .map_err(|e| e.to_string())?;
-
-    Ok(result.rows_affected() > 0)
-}
\ No newline at end of file
+) -> CommandResult<bool> {
+    delete(&db, &project_id).await
+}
+
+#[tauri::command]
+pub async fn add_project_document(
+    db: State<'_, Arc<Database>>,
+    project_id: String,
+    file_path: String,
+) -> CommandResult<ProjectDocument> {
+    add_document(&db, &project_id, Path::new(&file_path)).await
+}
+
+#[tauri::command]
+pub async fn get_project_documents(
+    db: State<'_, Arc<Database>>,
+    project_id: String,
+) -> CommandResult<Vec<ProjectDocument>> {
+    documents(&db, &project_id).await
+}
+
+#[tauri::command]
+pub async fn delete_project_document(
+    db: State<'_, Arc<Database>>,
+    document_id: String,
+) -> CommandResult<bool> {
+    delete_document(&db, &document_id).await
+}
+
+#[tauri::command]
+pub async fn get_project_knowledge(
+    db: State<'_, Arc<Database>>,
+    models: State<'_, Arc<ModelRegistry>>,
+    project_id: String,
+) -> CommandResult<KnowledgeBudget> {
+    knowledge(&db, &models, &project_id).await
+}
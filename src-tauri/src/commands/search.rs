@@ -0,0 +1,25 @@
+use crate::database::Database;
+use crate::database::search::{self, SearchFilter, SearchIndexStats, SearchResult};
+use tauri::State;
+use std::sync::Arc;
+
+/// Searches message content and chat titles, best matches first.
+#[tauri::command]
+pub async fn search_messages(
+    db: State<'_, Arc<Database>>,
+    query: String,
+    filter: Option<SearchFilter>,
+) -> Result<Vec<SearchResult>, String> {
+    search::search(db.pool(), &query, &filter.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn rebuild_search_index(
+    db: State<'_, Arc<Database>>,
+) -> Result<SearchIndexStats, String> {
+    search::rebuild_index(db.pool())
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,103 @@
+use crate::database::{Database, models::*};
+use crate::database::sessions;
+use tauri::State;
+use std::sync::Arc;
+
+#[tauri::command]
+pub async fn get_user_profile(
+    db: State<'_, Arc<Database>>,
+) -> Result<UserProfile, String> {
+    sessions::profile(db.pool()).await.map_err(|e| e.to_string())
+}
+
+/// Merges `preferences` into the profile's preferences. Keys set to `null`
+/// are removed.
+#[tauri::command]
+pub async fn update_user_profile(
+    db: State<'_, Arc<Database>>,
+    preferences: serde_json::Value,
+) -> Result<UserProfile, String> {
+    sessions::update_preferences(db.pool(), &preferences)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Sessions by most recent activity. Archived sessions are left out unless
+/// `include_archived` is set.
+#[tauri::command]
+pub async fn get_sessions(
+    db: State<'_, Arc<Database>>,
+    include_archived: Option<bool>,
+) -> Result<Vec<Session>, String> {
+    sessions::list(db.pool(), include_archived.unwrap_or(false))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_session(
+    db: State<'_, Arc<Database>>,
+    session_id: String,
+) -> Result<Option<Session>, String> {
+    sessions::get(db.pool(), &session_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_session(
+    db: State<'_, Arc<Database>>,
+    title: String,
+) -> Result<Session, String> {
+    sessions::create(db.pool(), &title).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn rename_session(
+    db: State<'_, Arc<Database>>,
+    session_id: String,
+    title: String,
+) -> Result<Session, String> {
+    sessions::rename(db.pool(), &session_id, &title)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Hides a session from the list. No chats can be added to it until it is
+/// unarchived.
+#[tauri::command]
+pub async fn archive_session(
+    db: State<'_, Arc<Database>>,
+    session_id: String,
+) -> Result<Session, String> {
+    sessions::set_archived(db.pool(), &session_id, true)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn unarchive_session(
+    db: State<'_, Arc<Database>>,
+    session_id: String,
+) -> Result<Session, String> {
+    sessions::set_archived(db.pool(), &session_id, false)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Session to open on launch: the one last switched to, else the most
+/// recently active one, else a new one.
+#[tauri::command]
+pub async fn get_active_session(
+    db: State<'_, Arc<Database>>,
+) -> Result<Session, String> {
+    sessions::ensure_active(db.pool()).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn switch_session(
+    db: State<'_, Arc<Database>>,
+    session_id: String,
+) -> Result<Session, String> {
+    sessions::switch(db.pool(), &session_id)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -1,63 +1,198 @@
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::sync::Arc;
+use crate::database::settings as schema;
+use crate::database::Database;
 use crate::integrations::anthropic::AnthropicClient;
+use crate::utils::config;
+use crate::utils::credentials::{self, CredentialStore};
+use crate::utils::crypto::{self, SecureStorage};
+use tauri::{AppHandle, Emitter, State};
 
-fn get_settings_file_path() -> Result<String, String> {
-    let app_data_dir = dirs::data_dir()
-        .unwrap_or_else(|| std::env::temp_dir())
-        .join("cloddo");
-    
-    // Create directory if it doesn't exist
-    if !app_data_dir.exists() {
-        std::fs::create_dir_all(&app_data_dir)
-            .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+/// Event emitted with the settings an update changed and their new values.
+pub const SETTINGS_CHANGED_EVENT: &str = "settings-changed";
+
+/// Value returned by `get_settings` in place of a stored secret. Sending it
+/// back through `update_settings` keeps the stored value unchanged.
+pub const REDACTED_SECRET: &str = "********";
+
+// Prefix of secrets encrypted inside settings.json by earlier versions
+const LEGACY_ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+fn legacy_storage() -> Result<SecureStorage, String> {
+    let data_dir = config::get_data_dir()
+        .map_err(|e| format!("Failed to resolve data directory: {}", e))?;
+    let salt = crypto::load_or_create_salt(&data_dir.join("secure.salt"))
+        .map_err(|e| format!("Failed to load encryption salt: {}", e))?;
+    SecureStorage::new(&crypto::local_passphrase(), &salt)
+        .map_err(|e| format!("Failed to initialize secure storage: {}", e))
+}
+
+/// Moves secrets left in `settings.json` by older versions, whether plain
+/// text or encrypted in place, into the credential store.
+async fn migrate_file_secrets(
+    settings: &mut HashMap<String, serde_json::Value>,
+    store: &dyn CredentialStore,
+) -> Result<(), String> {
+    let mut legacy: Option<SecureStorage> = None;
+
+    for key in schema::secret_keys() {
+        let Some(value) = settings.remove(key) else {
+            continue;
+        };
+
+        let Some(value) = value.as_str().filter(|v| !v.is_empty()) else {
+            continue;
+        };
+
+        let plaintext = match value.strip_prefix(LEGACY_ENCRYPTED_PREFIX) {
+            Some(ciphertext) => {
+                if legacy.is_none() {
+                    legacy = Some(legacy_storage()?);
+                }
+                match legacy.as_ref().map(|storage| storage.decrypt(ciphertext)) {
+                    Some(Ok(plaintext)) => plaintext,
+                    _ => {
+                        log::error!("Failed to decrypt {} from settings.json, dropping it", key);
+                        continue;
+                    }
+                }
+            }
+            None => value.to_string(),
+        };
+
+        store
+            .set(key, &plaintext)
+            .await
+            .map_err(|e| format!("Failed to store {}: {}", key, e))?;
+        log::info!("Moved {} from settings.json to the {} credential store", key, store.backend_name());
     }
-    
-    Ok(app_data_dir.join("settings.json").to_string_lossy().to_string())
+
+    Ok(())
 }
 
-#[tauri::command]
-pub async fn get_settings() -> Result<HashMap<String, serde_json::Value>, String> {
-    let settings_path = get_settings_file_path()?;
-    
-    if Path::new(&settings_path).exists() {
-        let content = fs::read_to_string(&settings_path)
-            .map_err(|e| format!("Failed to read settings: {}", e))?;
-        
-        let settings: HashMap<String, serde_json::Value> = serde_json::from_str(&content)
-            .map_err(|e| format!("Failed to parse settings: {}", e))?;
-        
-        Ok(settings)
-    } else {
-        // Return default settings
-        let mut defaults = HashMap::new();
-        defaults.insert("api.anthropicApiKey".to_string(), serde_json::Value::String("".to_string()));
-        defaults.insert("api.defaultModel".to_string(), serde_json::Value::String("claude-3-5-sonnet-20241022".to_string()));
-        defaults.insert("api.authMethod".to_string(), serde_json::Value::String("api_key".to_string()));
-        Ok(defaults)
+/// Moves the settings saved to `settings.json` by earlier versions into the
+/// database and the credential store, then renames the file so this only
+/// happens once.
+pub async fn import_legacy_settings(db: &Database) -> Result<usize, String> {
+    let data_dir = config::get_data_dir().map_err(|e| e.to_string())?;
+    let path = data_dir.join("settings.json");
+    if !path.exists() {
+        return Ok(0);
     }
+
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read settings: {}", e))?;
+    let mut settings: HashMap<String, serde_json::Value> = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse settings: {}", e))?;
+
+    let store = credentials::default_store().await?;
+    migrate_file_secrets(&mut settings, store.as_ref()).await?;
+    let imported = schema::import(db.settings(), &settings)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    fs::rename(&path, data_dir.join("settings.json.imported"))
+        .map_err(|e| format!("Failed to rename imported settings file: {}", e))?;
+
+    log::info!("Imported {} settings from settings.json", imported);
+    Ok(imported)
+}
+
+/// Reads a single secret from the credential store, treating empty as unset.
+pub async fn get_secret(key: &str) -> Result<Option<String>, String> {
+    let store = credentials::default_store().await?;
+    let value = store
+        .get(key)
+        .await
+        .map_err(|e| format!("Failed to read {} from credential store: {}", key, e))?;
+    Ok(value.filter(|v| !v.is_empty()))
 }
 
+/// Loads settings with secrets resolved from the credential store, for use by
+/// backend code only.
+pub async fn load_settings(db: &Database) -> Result<HashMap<String, serde_json::Value>, String> {
+    let mut settings = schema::load(db.settings()).await.map_err(|e| e.to_string())?;
+
+    let store = credentials::default_store().await?;
+    for key in schema::secret_keys() {
+        let value = store
+            .get(key)
+            .await
+            .map_err(|e| format!("Failed to read {} from credential store: {}", key, e))?;
+        if let Some(value) = value {
+            settings.insert(key.to_string(), serde_json::Value::String(value));
+        }
+    }
+
+    Ok(settings)
+}
+
+// Secrets as the webview sees them: the placeholder when one is stored
+fn redact_secrets(settings: &mut HashMap<String, serde_json::Value>) {
+    for key in schema::secret_keys() {
+        if settings.get(key).and_then(|v| v.as_str()).is_some_and(|v| !v.is_empty()) {
+            settings.insert(key.to_string(), serde_json::Value::String(REDACTED_SECRET.to_string()));
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_settings(
+    db: State<'_, Arc<Database>>,
+) -> Result<HashMap<String, serde_json::Value>, String> {
+    let mut settings = load_settings(&db).await?;
+    redact_secrets(&mut settings);
+    Ok(settings)
+}
+
+/// Changes the given settings and leaves the rest as they are; `null` resets
+/// a setting to its default. Unknown keys and values of the wrong type are
+/// rejected before anything is stored. Emits `settings-changed` and returns
+/// whether any value changed.
 #[tauri::command]
 pub async fn update_settings(
-    settings: HashMap<String, serde_json::Value>,
+    app: AppHandle,
+    db: State<'_, Arc<Database>>,
+    mut settings: HashMap<String, serde_json::Value>,
 ) -> Result<bool, String> {
-    let settings_path = get_settings_file_path()?;
-    
-    let json_content = serde_json::to_string_pretty(&settings)
-        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
-    
-    fs::write(&settings_path, json_content)
-        .map_err(|e| format!("Failed to write settings: {}", e))?;
-    
+    schema::validate_changes(&settings).map_err(|e| e.to_string())?;
+
+    // The webview only knows stored secrets as the placeholder
+    let secrets: Vec<_> = schema::secret_keys()
+        .filter_map(|key| settings.remove(key).map(|value| (key, value)))
+        .filter(|(_, value)| value.as_str() != Some(REDACTED_SECRET))
+        .collect();
+    let store = credentials::default_store().await?;
+
+    // Secrets can't be rolled back, so they are written once the rest is stored
+    let mut changed = schema::update(db.settings(), &settings).await.map_err(|e| e.to_string())?;
+    for (key, value) in secrets {
+        let result = match value.as_str() {
+            Some(secret) if !secret.is_empty() => store.set(key, secret).await,
+            _ => store.delete(key).await,
+        };
+        result.map_err(|e| format!("Failed to update {} in credential store: {}", key, e))?;
+        changed.insert(key.to_string(), value);
+    }
+
+    if changed.is_empty() {
+        return Ok(false);
+    }
+
+    redact_secrets(&mut changed);
+    if let Err(e) = app.emit(SETTINGS_CHANGED_EVENT, &changed) {
+        log::error!("Failed to emit {} event: {}", SETTINGS_CHANGED_EVENT, e);
+    }
     Ok(true)
 }
 
 #[tauri::command]
-pub async fn validate_api_key(api_key: String) -> Result<bool, String> {
+pub async fn validate_api_key(
+    db: State<'_, Arc<Database>>,
+    mut api_key: String,
+) -> Result<bool, String> {
     log::info!("🔑 validate_api_key called with key length: {}", api_key.len());
-    log::info!("🔑 Key starts with: {}", if api_key.len() >= 15 { &api_key[..15] } else { &api_key });
     
     if api_key.is_empty() {
         log::error!("❌ API key is empty");
@@ -65,13 +200,15 @@ pub async fn validate_api_key(api_key: String) -> Result<bool, String> {
     }
     
     // Also check what's currently stored for comparison
-    match get_settings().await {
+    match load_settings(&db).await {
         Ok(stored_settings) => {
             if let Some(stored_key) = stored_settings.get("api.anthropicApiKey").and_then(|v| v.as_str()) {
-                log::info!("🔍 Currently stored API key: length={}, starts_with={}", 
-                    stored_key.len(),
-                    if stored_key.len() >= 15 { &stored_key[..15] } else { stored_key }
-                );
+                log::info!("🔍 Currently stored API key: length={}", stored_key.len());
+
+                // The settings form only holds the placeholder until the user types a new key
+                if api_key == REDACTED_SECRET {
+                    api_key = stored_key.to_string();
+                }
                 log::info!("🔍 Keys match? {}", stored_key == api_key);
             } else {
                 log::info!("🔍 No API key currently stored");
@@ -98,8 +235,11 @@ pub async fn validate_api_key(api_key: String) -> Result<bool, String> {
 }
 
 #[tauri::command]
-pub async fn debug_settings() -> Result<String, String> {
-    let settings = get_settings().await?;
+pub async fn debug_settings(
+    db: State<'_, Arc<Database>>,
+) -> Result<String, String> {
+    let mut settings = load_settings(&db).await?;
+    redact_secrets(&mut settings);
     log::info!("Debug settings called, found: {:?}", settings);
     Ok(format!("Settings: {:?}", settings))
 }
\ No newline at end of file
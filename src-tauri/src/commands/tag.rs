@@ -0,0 +1,64 @@
+use crate::database::{Database, models::*};
+use crate::database::tags;
+use tauri::State;
+use std::sync::Arc;
+
+/// Every tag with the number of chats carrying it.
+#[tauri::command]
+pub async fn get_tags(
+    db: State<'_, Arc<Database>>,
+) -> Result<Vec<Tag>, String> {
+    tags::list(db.pool()).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_tag(
+    db: State<'_, Arc<Database>>,
+    name: String,
+    color: Option<String>,
+) -> Result<Tag, String> {
+    tags::create(db.pool(), &name, color.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Renames or recolors a tag; fields left out stay as they are.
+#[tauri::command]
+pub async fn update_tag(
+    db: State<'_, Arc<Database>>,
+    tag_id: String,
+    name: Option<String>,
+    color: Option<String>,
+) -> Result<Tag, String> {
+    tags::update(db.pool(), &tag_id, name.as_deref(), color.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_tag(
+    db: State<'_, Arc<Database>>,
+    tag_id: String,
+) -> Result<bool, String> {
+    tags::delete(db.pool(), &tag_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_chat_tags(
+    db: State<'_, Arc<Database>>,
+    chat_id: String,
+) -> Result<Vec<Tag>, String> {
+    tags::for_chat(db.pool(), &chat_id).await.map_err(|e| e.to_string())
+}
+
+/// Replaces a chat's tags, creating tags that do not exist yet.
+#[tauri::command]
+pub async fn set_chat_tags(
+    db: State<'_, Arc<Database>>,
+    chat_id: String,
+    tags: Vec<String>,
+) -> Result<Vec<Tag>, String> {
+    tags::set_for_chat(db.pool(), &chat_id, &tags)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,84 @@
+use crate::commands::settings;
+use crate::database::{Database, models::*};
+use crate::database::trash::{self, PurgeReport, TrashKind};
+use crate::integrations::attachments::{AttachmentStore, GC_GRACE_PERIOD};
+use chrono::{Duration, Utc};
+use tauri::State;
+use std::sync::Arc;
+
+// Removes attachments only the purged messages referred to
+async fn collect_attachments(db: &Database, attachment_store: &AttachmentStore) -> Result<(), String> {
+    attachment_store
+        .collect_garbage(db.pool(), GC_GRACE_PERIOD)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Permanently deletes items that have been in the trash for longer than
+/// `trash.retentionDays`, then the attachments nothing refers to anymore.
+pub async fn run_scheduled_purge(db: &Database, attachment_store: &AttachmentStore) -> Result<PurgeReport, String> {
+    let retention_days = settings::load_settings(db)
+        .await?
+        .get("trash.retentionDays")
+        .and_then(|value| value.as_i64())
+        .unwrap_or(trash::DEFAULT_RETENTION_DAYS)
+        .max(0);
+
+    let report = trash::purge(db.pool(), Some(Utc::now() - Duration::days(retention_days)))
+        .await
+        .map_err(|e| e.to_string())?;
+    if report != PurgeReport::default() {
+        collect_attachments(db, attachment_store).await?;
+    }
+    Ok(report)
+}
+
+/// Trashed chats, projects and agents, most recently deleted first.
+#[tauri::command]
+pub async fn get_trash(
+    db: State<'_, Arc<Database>>,
+) -> Result<Vec<TrashItem>, String> {
+    trash::list(db.pool()).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn restore_chat(
+    db: State<'_, Arc<Database>>,
+    chat_id: String,
+) -> Result<bool, String> {
+    trash::restore(db.pool(), TrashKind::Chat, &chat_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn restore_project(
+    db: State<'_, Arc<Database>>,
+    project_id: String,
+) -> Result<bool, String> {
+    trash::restore(db.pool(), TrashKind::Project, &project_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn restore_agent(
+    db: State<'_, Arc<Database>>,
+    agent_id: String,
+) -> Result<bool, String> {
+    trash::restore(db.pool(), TrashKind::Agent, &agent_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Permanently deletes everything in the trash.
+#[tauri::command]
+pub async fn empty_trash(
+    db: State<'_, Arc<Database>>,
+    attachment_store: State<'_, Arc<AttachmentStore>>,
+) -> Result<PurgeReport, String> {
+    let report = trash::purge(db.pool(), None).await.map_err(|e| e.to_string())?;
+    collect_attachments(&db, &attachment_store).await?;
+    Ok(report)
+}
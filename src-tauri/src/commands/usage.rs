@@ -0,0 +1,46 @@
+use crate::database::Database;
+use crate::database::usage::{self, UsageFilter, UsageGrouping, UsageSummary};
+use tauri::State;
+use std::sync::Arc;
+
+async fn summarize(
+    db: &Database,
+    grouping: UsageGrouping,
+    filter: Option<UsageFilter>,
+) -> Result<Vec<UsageSummary>, String> {
+    usage::summarize_usage(db.pool(), grouping, &filter.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_usage_by_day(
+    db: State<'_, Arc<Database>>,
+    filter: Option<UsageFilter>,
+) -> Result<Vec<UsageSummary>, String> {
+    summarize(&db, UsageGrouping::Day, filter).await
+}
+
+#[tauri::command]
+pub async fn get_usage_by_model(
+    db: State<'_, Arc<Database>>,
+    filter: Option<UsageFilter>,
+) -> Result<Vec<UsageSummary>, String> {
+    summarize(&db, UsageGrouping::Model, filter).await
+}
+
+#[tauri::command]
+pub async fn get_usage_by_project(
+    db: State<'_, Arc<Database>>,
+    filter: Option<UsageFilter>,
+) -> Result<Vec<UsageSummary>, String> {
+    summarize(&db, UsageGrouping::Project, filter).await
+}
+
+#[tauri::command]
+pub async fn get_usage_by_agent(
+    db: State<'_, Arc<Database>>,
+    filter: Option<UsageFilter>,
+) -> Result<Vec<UsageSummary>, String> {
+    summarize(&db, UsageGrouping::Agent, filter).await
+}
@@ -1,28 +1,18 @@
+use crate::agents::executor;
+use crate::commands::{chat, CommandError, CommandResult};
 use crate::database::{Database, models::*};
+use crate::integrations::oauth::OAuthManager;
 use tauri::State;
 use std::sync::Arc;
-use anyhow::Result;
-use chrono::Utc;
 
-#[tauri::command]
-pub async fn get_hooks(
-    db: State<'_, Arc<Database>>,
-) -> Result<Vec<Hook>, String> {
-    let hooks = sqlx::query_as::<_, Hook>(
-        "SELECT * FROM hooks ORDER BY created_at DESC",
-    )
-    .fetch_all(db.pool())
-    .await
-    .map_err(|e| e.to_string())?;
-
-    Ok(hooks)
+// Action of hooks that start an agent run
+const TRIGGER_AGENT_ACTION: &str = "trigger_agent";
+
+pub async fn list(db: &Database) -> CommandResult<Vec<Hook>> {
+    Ok(db.hooks().list().await?)
 }
 
-#[tauri::command]
-pub async fn create_hook(
-    db: State<'_, Arc<Database>>,
-    request: CreateHookRequest,
-) -> Result<Hook, String> {
+pub async fn create(db: &Database, request: CreateHookRequest) -> CommandResult<Hook> {
     let mut hook = Hook::new(
         request.name,
         request.trigger_type,
@@ -32,98 +22,100 @@ pub async fn create_hook(
     );
     hook.description = request.description;
 
-    sqlx::query(
-        r#"
-        INSERT INTO hooks (id, name, description, trigger_type, trigger_config, action_type, action_config, enabled, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-        "#,
-    )
-    .bind(&hook.id)
-    .bind(&hook.name)
-    .bind(&hook.description)
-    .bind(&hook.trigger_type)
-    .bind(&hook.trigger_config)
-    .bind(&hook.action_type)
-    .bind(&hook.action_config)
-    .bind(hook.enabled)
-    .bind(hook.created_at)
-    .bind(hook.updated_at)
-    .execute(db.pool())
-    .await
-    .map_err(|e| e.to_string())?;
-
+    db.hooks().create(&hook).await?;
     Ok(hook)
 }
 
+pub async fn update(db: &Database, hook_id: &str, request: CreateHookRequest) -> CommandResult<Hook> {
+    db.hooks()
+        .update(hook_id, &request)
+        .await?
+        .ok_or_else(|| CommandError::from("Hook not found"))
+}
+
+pub async fn delete(db: &Database, hook_id: &str) -> CommandResult<bool> {
+    Ok(db.hooks().delete(hook_id).await?)
+}
+
+/// Runs the hook's action. `trigger_agent` hooks start a run of the agent
+/// named in their config, with the trigger data added to its input.
+pub async fn trigger(
+    db: &Arc<Database>,
+    oauth: &Arc<OAuthManager>,
+    hook_id: &str,
+    trigger_data: Option<JsonMap>,
+) -> CommandResult<String> {
+    let hook = db.hooks().get(hook_id).await?.ok_or("Hook not found")?;
+
+    if !hook.enabled {
+        return Err("Hook is disabled".into());
+    }
+
+    log::info!("Hook triggered: {} ({})", hook.name, hook.id);
+
+    if hook.action_type == TRIGGER_AGENT_ACTION {
+        let config: serde_json::Value = serde_json::from_str(&hook.action_config)
+            .map_err(|e| format!("Invalid hook action config: {}", e))?;
+        let agent_id = config
+            .pointer("/agent/agentId")
+            .and_then(|value| value.as_str())
+            .ok_or("Hook does not name an agent to run")?;
+
+        let mut input: JsonMap = config
+            .pointer("/agent/inputData")
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+            .unwrap_or_default();
+        if let Some(trigger_data) = trigger_data {
+            input.insert("trigger".to_string(), serde_json::json!(trigger_data));
+        }
+
+        let auth = chat::resolve_auth_provider(db, oauth).await?;
+        let run = executor::start_run(db, auth, agent_id, Some(&input), Some(&hook.id)).await?;
+        return Ok(format!("Hook {} started agent run {}", hook.name, run.id));
+    }
+
+    // TODO: Implement the other hook actions
+    Ok(format!("Hook {} triggered successfully", hook.name))
+}
+
+#[tauri::command]
+pub async fn get_hooks(
+    db: State<'_, Arc<Database>>,
+) -> CommandResult<Vec<Hook>> {
+    list(&db).await
+}
+
+#[tauri::command]
+pub async fn create_hook(
+    db: State<'_, Arc<Database>>,
+    request: CreateHookRequest,
+) -> CommandResult<Hook> {
+    create(&db, request).await
+}
+
 #[tauri::command]
 pub async fn update_hook(
     db: State<'_, Arc<Database>>,
     hook_id: String,
     request: CreateHookRequest,
-) -> Result<Hook, String> {
-    sqlx::query(
-        r#"
-        UPDATE hooks 
-        SET name = ?, description = ?, trigger_type = ?, trigger_config = ?, action_type = ?, action_config = ?, updated_at = ?
-        WHERE id = ?
-        "#,
-    )
-    .bind(&request.name)
-    .bind(&request.description)
-    .bind(&request.trigger_type)
-    .bind(serde_json::to_string(&request.trigger_config).unwrap_or_default())
-    .bind(&request.action_type)
-    .bind(serde_json::to_string(&request.action_config).unwrap_or_default())
-    .bind(Utc::now())
-    .bind(&hook_id)
-    .execute(db.pool())
-    .await
-    .map_err(|e| e.to_string())?;
-
-    let hook = sqlx::query_as::<_, Hook>("SELECT * FROM hooks WHERE id = ?")
-        .bind(hook_id)
-        .fetch_one(db.pool())
-        .await
-        .map_err(|e| e.to_string())?;
-
-    Ok(hook)
+) -> CommandResult<Hook> {
+    update(&db, &hook_id, request).await
 }
 
 #[tauri::command]
 pub async fn delete_hook(
     db: State<'_, Arc<Database>>,
     hook_id: String,
-) -> Result<bool, String> {
-    let result = sqlx::query("DELETE FROM hooks WHERE id = ?")
-        .bind(hook_id)
-        .execute(db.pool())
-        .await
-        .map_err(|e| e.to_string())?;
-
-    Ok(result.rows_affected() > 0)
+) -> CommandResult<bool> {
+    delete(&db, &hook_id).await
 }
 
 #[tauri::command]
 pub async fn trigger_hook(
     db: State<'_, Arc<Database>>,
+    oauth: State<'_, Arc<OAuthManager>>,
     hook_id: String,
-    trigger_data: Option<std::collections::HashMap<String, serde_json::Value>>,
-) -> Result<String, String> {
-    // Get the hook
-    let hook = sqlx::query_as::<_, Hook>("SELECT * FROM hooks WHERE id = ?")
-        .bind(&hook_id)
-        .fetch_optional(db.pool())
-        .await
-        .map_err(|e| e.to_string())?
-        .ok_or_else(|| "Hook not found".to_string())?;
-
-    if !hook.enabled {
-        return Err("Hook is disabled".to_string());
-    }
-
-    // TODO: Implement actual hook execution logic
-    // For now, just log the trigger
-    log::info!("Hook triggered: {} ({})", hook.name, hook.id);
-    
-    Ok(format!("Hook {} triggered successfully", hook.name))
-}
\ No newline at end of file
+    trigger_data: Option<JsonMap>,
+) -> CommandResult<String> {
+    trigger(&db, &oauth, &hook_id, trigger_data).await
+}
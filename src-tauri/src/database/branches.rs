@@ -0,0 +1,109 @@
+use sqlx::SqlitePool;
+use anyhow::Result;
+use crate::database::models::Message;
+
+/// Messages from the first turn down to `leaf_id`, oldest first.
+pub async fn path_to(pool: &SqlitePool, leaf_id: &str) -> Result<Vec<Message>> {
+    let messages = sqlx::query_as::<_, Message>(
+        r#"
+        WITH RECURSIVE path(id, parent_id, depth) AS (
+            SELECT id, parent_id, 0 FROM messages WHERE id = ?
+            UNION ALL
+            SELECT messages.id, messages.parent_id, path.depth + 1
+            FROM messages JOIN path ON messages.id = path.parent_id
+        )
+        SELECT messages.* FROM messages JOIN path ON messages.id = path.id
+        ORDER BY path.depth DESC
+        "#,
+    )
+    .bind(leaf_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(messages)
+}
+
+/// The branch `chat_id` currently shows, oldest first. Chats whose active
+/// message was deleted show the branch ending in their newest message.
+pub async fn active_path(pool: &SqlitePool, chat_id: &str) -> Result<Vec<Message>> {
+    let leaf: Option<Option<String>> = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(
+            (SELECT id FROM messages WHERE id = chats.active_message_id),
+            (SELECT id FROM messages WHERE chat_id = chats.id ORDER BY created_at DESC, rowid DESC LIMIT 1)
+        )
+        FROM chats WHERE id = ?
+        "#,
+    )
+    .bind(chat_id)
+    .fetch_optional(pool)
+    .await?;
+
+    match leaf.flatten() {
+        Some(leaf_id) => path_to(pool, &leaf_id).await,
+        None => Ok(Vec::new()),
+    }
+}
+
+/// `message_id` and the other replies to the same turn, oldest first.
+pub async fn siblings(pool: &SqlitePool, message_id: &str) -> Result<Vec<Message>> {
+    let messages = sqlx::query_as::<_, Message>(
+        r#"
+        SELECT siblings.* FROM messages AS message
+        JOIN messages AS siblings
+          ON siblings.chat_id = message.chat_id AND siblings.parent_id IS message.parent_id
+        WHERE message.id = ?
+        ORDER BY siblings.created_at ASC, siblings.rowid ASC
+        "#,
+    )
+    .bind(message_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(messages)
+}
+
+/// The end of the branch below `message_id`, following the newest reply at
+/// every turn.
+pub async fn latest_leaf(pool: &SqlitePool, message_id: &str) -> Result<String> {
+    let leaf: String = sqlx::query_scalar(
+        r#"
+        WITH RECURSIVE descent(id, depth) AS (
+            SELECT ?, 0
+            UNION ALL
+            SELECT (
+                SELECT child.id FROM messages AS child
+                WHERE child.parent_id = descent.id
+                ORDER BY child.created_at DESC, child.rowid DESC
+                LIMIT 1
+            ), descent.depth + 1
+            FROM descent WHERE descent.id IS NOT NULL
+        )
+        SELECT id FROM descent WHERE id IS NOT NULL ORDER BY depth DESC LIMIT 1
+        "#,
+    )
+    .bind(message_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(leaf)
+}
+
+/// Makes the branch through `message_id` the one its chat shows and returns
+/// that branch.
+pub async fn switch_branch(pool: &SqlitePool, message_id: &str) -> Result<Vec<Message>> {
+    let chat_id: String = sqlx::query_scalar("SELECT chat_id FROM messages WHERE id = ?")
+        .bind(message_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Message not found"))?;
+
+    let leaf_id = latest_leaf(pool, message_id).await?;
+    sqlx::query("UPDATE chats SET active_message_id = ? WHERE id = ?")
+        .bind(&leaf_id)
+        .bind(&chat_id)
+        .execute(pool)
+        .await?;
+
+    path_to(pool, &leaf_id).await
+}
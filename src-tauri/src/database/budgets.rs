@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use chrono::{Datelike, NaiveDate, Utc};
+use uuid::Uuid;
+use anyhow::Result;
+use std::fmt;
+use crate::database::models::Budget;
+use crate::database::usage::UsageContext;
+
+/// Error code reported when a request is refused because a budget is spent.
+pub const BUDGET_EXCEEDED: &str = "budget_exceeded";
+
+/// A request was blocked because `budget` has no money left this period.
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetExceeded {
+    pub budget_id: String,
+    pub budget_name: String,
+    pub period: String,
+    pub spent: f64,
+    pub limit_usd: f64,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} budget '{}' of ${:.2} reached (${:.2} spent)",
+            BUDGET_EXCEEDED, self.period, self.budget_name, self.limit_usd, self.spent
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetState {
+    Ok,
+    Warning,
+    Exceeded,
+}
+
+/// Spending against a budget in its current period.
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetStatus {
+    pub budget: Budget,
+    pub period_start: String,
+    pub spent: f64,
+    pub fraction: f64,
+    pub state: BudgetState,
+}
+
+/// Validates a budget definition before it is stored.
+pub fn validate_budget(budget: &Budget) -> Result<(), String> {
+    match budget.scope.as_str() {
+        "global" if budget.scope_id.is_some() => {
+            return Err("Global budgets cannot have a scope ID".to_string())
+        }
+        "project" | "agent" if budget.scope_id.is_none() => {
+            return Err(format!("A {} budget needs a scope ID", budget.scope))
+        }
+        "global" | "project" | "agent" => {}
+        other => return Err(format!("Unknown budget scope '{}'", other)),
+    }
+    if !matches!(budget.period.as_str(), "daily" | "monthly") {
+        return Err(format!("Unknown budget period '{}'", budget.period));
+    }
+    if !budget.limit_usd.is_finite() || budget.limit_usd < 0.0 {
+        return Err("Budget limit must be a non-negative amount".to_string());
+    }
+    if !(budget.warn_threshold > 0.0 && budget.warn_threshold <= 1.0) {
+        return Err("Warning threshold must be between 0 and 1".to_string());
+    }
+    Ok(())
+}
+
+/// First day of the period containing `today`.
+pub fn period_start(period: &str, today: NaiveDate) -> NaiveDate {
+    match period {
+        "monthly" => today.with_day(1).unwrap_or(today),
+        _ => today,
+    }
+}
+
+async fn spent_since(pool: &SqlitePool, budget: &Budget, since: &str) -> Result<f64> {
+    let column = match budget.scope.as_str() {
+        "project" => Some("project_id"),
+        "agent" => Some("agent_id"),
+        _ => None,
+    };
+
+    let spent = match column {
+        Some(column) => {
+            sqlx::query_scalar::<_, f64>(&format!(
+                "SELECT COALESCE(SUM(cost), 0.0) FROM api_usage WHERE date >= ? AND {} = ?",
+                column
+            ))
+            .bind(since)
+            .bind(&budget.scope_id)
+            .fetch_one(pool)
+            .await?
+        }
+        None => {
+            sqlx::query_scalar::<_, f64>("SELECT COALESCE(SUM(cost), 0.0) FROM api_usage WHERE date >= ?")
+                .bind(since)
+                .fetch_one(pool)
+                .await?
+        }
+    };
+
+    Ok(spent)
+}
+
+/// Current spending against `budget`.
+pub async fn budget_status(pool: &SqlitePool, budget: &Budget) -> Result<BudgetStatus> {
+    let start = period_start(&budget.period, Utc::now().date_naive())
+        .format("%Y-%m-%d")
+        .to_string();
+    let spent = spent_since(pool, budget, &start).await?;
+
+    let fraction = if budget.limit_usd > 0.0 {
+        spent / budget.limit_usd
+    } else {
+        // A zero budget allows nothing
+        1.0
+    };
+    let state = if fraction >= 1.0 {
+        BudgetState::Exceeded
+    } else if fraction >= budget.warn_threshold {
+        BudgetState::Warning
+    } else {
+        BudgetState::Ok
+    };
+
+    Ok(BudgetStatus {
+        budget: budget.clone(),
+        period_start: start,
+        spent,
+        fraction,
+        state,
+    })
+}
+
+/// Enabled budgets covering a call made in `context`.
+async fn applicable_budgets(pool: &SqlitePool, context: &UsageContext) -> Result<Vec<Budget>> {
+    let budgets = sqlx::query_as::<_, Budget>(
+        r#"
+        SELECT * FROM budgets
+        WHERE enabled = TRUE
+          AND (scope = 'global'
+               OR (scope = 'project' AND scope_id = ?)
+               OR (scope = 'agent' AND scope_id = ?))
+        "#,
+    )
+    .bind(&context.project_id)
+    .bind(&context.agent_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(budgets)
+}
+
+async fn record_event(
+    pool: &SqlitePool,
+    status: &BudgetStatus,
+    kind: &str,
+    context: &UsageContext,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO budget_events (id, budget_id, kind, period_start, spent, limit_usd, source,
+                                   session_id, chat_id, project_id, agent_id, agent_run_id, hook_id, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&status.budget.id)
+    .bind(kind)
+    .bind(&status.period_start)
+    .bind(status.spent)
+    .bind(status.budget.limit_usd)
+    .bind(context.source.as_str())
+    .bind(&context.session_id)
+    .bind(&context.chat_id)
+    .bind(&context.project_id)
+    .bind(&context.agent_id)
+    .bind(&context.agent_run_id)
+    .bind(&context.hook_id)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn warned_this_period(pool: &SqlitePool, status: &BudgetStatus) -> Result<bool> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM budget_events WHERE budget_id = ? AND kind = 'warning' AND period_start = ?",
+    )
+    .bind(&status.budget.id)
+    .bind(&status.period_start)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count > 0)
+}
+
+/// Checks every budget covering `context` before an API call.
+///
+/// Fails with [`BudgetExceeded`] when any of them is spent, recording the
+/// blocked attempt. Budgets past their warning threshold are logged and get
+/// one `warning` event per period. Returns the statuses that were checked.
+pub async fn enforce_budgets(pool: &SqlitePool, context: &UsageContext) -> Result<Vec<BudgetStatus>> {
+    let mut statuses = Vec::new();
+    for budget in applicable_budgets(pool, context).await? {
+        statuses.push(budget_status(pool, &budget).await?);
+    }
+
+    if let Some(status) = statuses.iter().find(|status| status.state == BudgetState::Exceeded) {
+        record_event(pool, status, "blocked", context).await?;
+        let error = BudgetExceeded {
+            budget_id: status.budget.id.clone(),
+            budget_name: status.budget.name.clone(),
+            period: status.budget.period.clone(),
+            spent: status.spent,
+            limit_usd: status.budget.limit_usd,
+        };
+        log::warn!("Blocked {} request: {}", context.source.as_str(), error);
+        return Err(error.into());
+    }
+
+    for status in statuses.iter().filter(|status| status.state == BudgetState::Warning) {
+        if !warned_this_period(pool, status).await? {
+            log::warn!(
+                "Budget '{}' is at {:.0}% of its {} limit",
+                status.budget.name,
+                status.fraction * 100.0,
+                status.budget.period
+            );
+            record_event(pool, status, "warning", context).await?;
+        }
+    }
+
+    Ok(statuses)
+}
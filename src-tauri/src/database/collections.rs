@@ -0,0 +1,631 @@
+use crate::database::{folders, tags};
+use crate::database::models::{Chat, Collection};
+use crate::database::search::{DEFAULT_SEARCH_LIMIT, MAX_SEARCH_LIMIT};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use uuid::Uuid;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// A parsed collection query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// Every item must match; empty matches all chats.
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Filter(Filter),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Words in message content or the title; `phrase` when quoted.
+    Text { text: String, phrase: bool },
+    Title(String),
+    Favorite,
+    Imported,
+    Project(String),
+    Folder(String),
+    Tag(String),
+    Date(DateField, DateValue),
+    Count(CountField, Comparison, i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateField {
+    Created,
+    Updated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateValue {
+    /// Within this long before now.
+    Within(Duration),
+    /// Since the start of the current day, week, month or year (UTC).
+    Current(Period),
+    Date(Comparison, NaiveDate),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountField {
+    Tokens,
+    Messages,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn sql(&self) -> &'static str {
+        match self {
+            Comparison::Eq => " = ",
+            Comparison::Lt => " < ",
+            Comparison::Le => " <= ",
+            Comparison::Gt => " > ",
+            Comparison::Ge => " >= ",
+        }
+    }
+
+    // Splits a leading operator off `value`
+    fn split(value: &str) -> (Option<Comparison>, &str) {
+        for (prefix, comparison) in [
+            (">=", Comparison::Ge),
+            ("<=", Comparison::Le),
+            (">", Comparison::Gt),
+            ("<", Comparison::Lt),
+            ("=", Comparison::Eq),
+        ] {
+            if let Some(rest) = value.strip_prefix(prefix) {
+                return (Some(comparison), rest);
+            }
+        }
+        (None, value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Or,
+    And,
+    Not,
+    Term { field: Option<String>, value: String, quoted: bool },
+}
+
+// Fields a `field:value` term may name. Other words with a colon, such as
+// `TODO:` or `https://…`, are searched for as text
+const FIELDS: &[&str] = &["is", "title", "project", "folder", "tag", "created", "updated", "tokens", "messages"];
+
+// Deepest nesting of parentheses and negations a query may use
+const MAX_NESTING: usize = 32;
+
+fn is_field(name: &str) -> bool {
+    FIELDS.contains(&name.to_lowercase().as_str())
+}
+
+fn error_at(position: usize, message: impl std::fmt::Display) -> anyhow::Error {
+    anyhow::anyhow!("{} at position {}", message, position + 1)
+}
+
+// Reads a quoted string starting at the quote `chars[start]`. Returns it and
+// the index after the closing quote
+fn read_quoted(chars: &[(usize, char)], start: usize) -> Result<(String, usize)> {
+    let mut value = String::new();
+    let mut index = start + 1;
+    while index < chars.len() {
+        if chars[index].1 == '"' {
+            return Ok((value, index + 1));
+        }
+        value.push(chars[index].1);
+        index += 1;
+    }
+    Err(error_at(chars[start].0, "Unclosed quote"))
+}
+
+fn tokenize(query: &str) -> Result<Vec<(usize, Token)>> {
+    let chars: Vec<(usize, char)> = query.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let (position, c) = chars[index];
+        if c.is_whitespace() {
+            index += 1;
+            continue;
+        }
+        match c {
+            '(' => {
+                tokens.push((position, Token::Open));
+                index += 1;
+            }
+            ')' => {
+                tokens.push((position, Token::Close));
+                index += 1;
+            }
+            '-' if chars.get(index + 1).is_some_and(|(_, next)| !next.is_whitespace()) => {
+                tokens.push((position, Token::Not));
+                index += 1;
+            }
+            '"' => {
+                let (value, next) = read_quoted(&chars, index)?;
+                tokens.push((position, Token::Term { field: None, value, quoted: true }));
+                index = next;
+            }
+            _ => {
+                let mut word = String::new();
+                while index < chars.len() && !chars[index].1.is_whitespace() && !matches!(chars[index].1, '(' | ')') {
+                    // field:"quoted value"
+                    if chars[index].1 == '"' && word.strip_suffix(':').is_some_and(is_field) {
+                        break;
+                    }
+                    word.push(chars[index].1);
+                    index += 1;
+                }
+
+                let token = match word.as_str() {
+                    "OR" => Token::Or,
+                    "AND" => Token::And,
+                    "NOT" => Token::Not,
+                    _ => match word.split_once(':') {
+                        Some((field, value)) if is_field(field) => {
+                            let (value, quoted) = if value.is_empty() && chars.get(index).is_some_and(|(_, c)| *c == '"') {
+                                let (value, next) = read_quoted(&chars, index)?;
+                                index = next;
+                                (value, true)
+                            } else {
+                                (value.to_string(), false)
+                            };
+                            Token::Term { field: Some(field.to_lowercase()), value, quoted }
+                        }
+                        _ => Token::Term { field: None, value: word, quoted: false },
+                    },
+                };
+                tokens.push((position, token));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_date(value: &str, position: usize) -> Result<DateValue> {
+    let (comparison, rest) = Comparison::split(value);
+    if let Ok(date) = NaiveDate::parse_from_str(rest, "%Y-%m-%d") {
+        return Ok(DateValue::Date(comparison.unwrap_or(Comparison::Eq), date));
+    }
+    if comparison.is_some() {
+        return Err(error_at(position, format!("Expected a YYYY-MM-DD date after the comparison in '{}'", value)));
+    }
+
+    let period = match value {
+        "today" => Some(Period::Day),
+        "week" => Some(Period::Week),
+        "month" => Some(Period::Month),
+        "year" => Some(Period::Year),
+        _ => None,
+    };
+    if let Some(period) = period {
+        return Ok(DateValue::Current(period));
+    }
+
+    // Longer spans than this are surely typos
+    let span = value
+        .char_indices()
+        .last()
+        .and_then(|(split, unit)| Some((value[..split].parse::<i64>().ok()?, unit)))
+        .filter(|(amount, _)| (0..=100_000).contains(amount));
+    match span {
+        Some((amount, 'h')) => Ok(DateValue::Within(Duration::hours(amount))),
+        Some((amount, 'd')) => Ok(DateValue::Within(Duration::days(amount))),
+        Some((amount, 'w')) => Ok(DateValue::Within(Duration::weeks(amount))),
+        _ => Err(error_at(position, format!("Cannot read '{}' as a date", value))),
+    }
+}
+
+fn parse_count(value: &str, position: usize) -> Result<(Comparison, i64)> {
+    let (comparison, rest) = Comparison::split(value);
+    let number = match rest.strip_suffix(['k', 'K']) {
+        Some(thousands) => thousands.parse::<i64>().ok().and_then(|n| n.checked_mul(1000)),
+        None => rest.parse::<i64>().ok(),
+    };
+    let number = number.ok_or_else(|| error_at(position, format!("Cannot read '{}' as a number", value)))?;
+    Ok((comparison.unwrap_or(Comparison::Eq), number))
+}
+
+fn parse_filter(field: Option<&str>, value: String, quoted: bool, position: usize) -> Result<Filter> {
+    let Some(field) = field else {
+        return Ok(Filter::Text { text: value, phrase: quoted });
+    };
+    if value.is_empty() {
+        return Err(error_at(position, format!("'{}:' needs a value", field)));
+    }
+
+    Ok(match field {
+        "is" => match value.to_lowercase().as_str() {
+            "favorite" | "favourite" | "starred" => Filter::Favorite,
+            "imported" => Filter::Imported,
+            _ => return Err(error_at(position, format!("Unknown 'is:{}'", value))),
+        },
+        "title" => Filter::Title(value),
+        "project" => Filter::Project(value),
+        "folder" => Filter::Folder(value),
+        "tag" => Filter::Tag(value),
+        "created" => Filter::Date(DateField::Created, parse_date(&value, position)?),
+        "updated" => Filter::Date(DateField::Updated, parse_date(&value, position)?),
+        "tokens" => {
+            let (comparison, number) = parse_count(&value, position)?;
+            Filter::Count(CountField::Tokens, comparison, number)
+        }
+        "messages" => {
+            let (comparison, number) = parse_count(&value, position)?;
+            Filter::Count(CountField::Messages, comparison, number)
+        }
+        _ => Filter::Text { text: format!("{}:{}", field, value), phrase: quoted },
+    })
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    end: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.index).map_or(self.end, |(position, _)| *position)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut items = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.index += 1;
+            items.push(self.parse_and()?);
+        }
+        Ok(if items.len() == 1 { items.remove(0) } else { Expr::Or(items) })
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Close) | Some(Token::Or) => break,
+                Some(Token::And) => self.index += 1,
+                Some(_) => items.push(self.parse_unary()?),
+            }
+        }
+        match items.len() {
+            0 => Err(error_at(self.position(), "Expected a search term")),
+            1 => Ok(items.remove(0)),
+            _ => Ok(Expr::And(items)),
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        let position = self.position();
+        let Some((_, token)) = self.tokens.get(self.index).cloned() else {
+            return Err(error_at(position, "Expected a search term"));
+        };
+        self.index += 1;
+
+        if matches!(token, Token::Not | Token::Open) && self.depth >= MAX_NESTING {
+            return Err(error_at(position, "Query is nested too deeply"));
+        }
+        match token {
+            Token::Not => {
+                self.depth += 1;
+                let inner = self.parse_unary()?;
+                self.depth -= 1;
+                Ok(Expr::Not(Box::new(inner)))
+            }
+            Token::Open => {
+                self.depth += 1;
+                let inner = self.parse_or()?;
+                self.depth -= 1;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(error_at(self.position(), "Expected ')'"));
+                }
+                self.index += 1;
+                Ok(inner)
+            }
+            Token::Term { field, value, quoted } => {
+                Ok(Expr::Filter(parse_filter(field.as_deref(), value, quoted, position)?))
+            }
+            Token::Close | Token::Or | Token::And => Err(error_at(position, "Expected a search term")),
+        }
+    }
+}
+
+/// Parses a collection query. An empty query matches every chat.
+///
+/// A query is a list of terms that must all match, for example
+/// `is:favorite project:"Billing" updated:week migration`:
+///
+/// - a bare word or `"quoted phrase"` matches message content or the title,
+///   as does a word with a colon that names no field, such as `TODO:`
+/// - `is:favorite`, `is:imported`
+/// - `title:`, `project:` (name or id), `folder:` (with subfolders), `tag:`
+/// - `created:` and `updated:` take `today`, `week`, `month`, `year`, a
+///   span such as `12h`, `7d` or `2w` (meaning within it), or a
+///   `YYYY-MM-DD` date, optionally after `>`, `>=`, `<` or `<=`
+/// - `tokens:` (API tokens spent in the chat) and `messages:` take a number,
+///   optionally after a comparison; `10k` is 10000
+///
+/// Terms combine with `OR`, are negated with `-` or `NOT`, and group with
+/// parentheses.
+pub fn parse(query: &str) -> Result<Expr> {
+    let tokens = tokenize(query)?;
+    if tokens.is_empty() {
+        return Ok(Expr::And(Vec::new()));
+    }
+
+    let mut parser = Parser { tokens, index: 0, end: query.len(), depth: 0 };
+    let expr = parser.parse_or()?;
+    if parser.index < parser.tokens.len() {
+        return Err(error_at(parser.position(), "Unexpected ')'"));
+    }
+    Ok(expr)
+}
+
+fn like_pattern(text: &str) -> String {
+    format!("%{}%", text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"))
+}
+
+// FTS5 query for literal text: a quoted phrase, or words where the last one
+// may be a prefix
+fn match_query(text: &str, phrase: bool) -> String {
+    let quoted = format!("\"{}\"", text.replace('"', "\"\""));
+    if phrase { quoted } else { format!("{}*", quoted) }
+}
+
+fn start_of(period: Period, now: DateTime<Utc>) -> DateTime<Utc> {
+    let today = now.date_naive();
+    let start = match period {
+        Period::Day => today,
+        Period::Week => today - Duration::days(today.weekday().num_days_from_monday() as i64),
+        Period::Month => today.with_day(1).unwrap_or(today),
+        Period::Year => today.with_ordinal(1).unwrap_or(today),
+    };
+    start.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+}
+
+// Folder ids of the `folder:` paths in a query, `None` for paths that don't
+// exist
+type FolderIds = HashMap<String, Option<String>>;
+
+fn folder_paths<'a>(expr: &'a Expr, paths: &mut Vec<&'a str>) {
+    match expr {
+        Expr::And(items) | Expr::Or(items) => items.iter().for_each(|item| folder_paths(item, paths)),
+        Expr::Not(inner) => folder_paths(inner, paths),
+        Expr::Filter(Filter::Folder(path)) => paths.push(path),
+        Expr::Filter(_) => {}
+    }
+}
+
+fn push_filter(query: &mut QueryBuilder<'_, Sqlite>, filter: &Filter, folder_ids: &FolderIds, now: DateTime<Utc>) {
+    match filter {
+        Filter::Text { text, phrase } => {
+            let match_query = match_query(text, *phrase);
+            query
+                .push("(c.id IN (SELECT chat_id FROM message_search WHERE message_search MATCH ")
+                .push_bind(match_query.clone())
+                .push(") OR c.id IN (SELECT chat_id FROM chat_search WHERE chat_search MATCH ")
+                .push_bind(match_query)
+                .push("))");
+        }
+        Filter::Title(text) => {
+            query
+                .push("c.title LIKE ")
+                .push_bind(like_pattern(text))
+                .push(" ESCAPE '\\'");
+        }
+        Filter::Favorite => {
+            query.push("c.is_favorite");
+        }
+        Filter::Imported => {
+            query.push("c.source IS NOT NULL");
+        }
+        Filter::Project(project) => {
+            query
+                .push("c.project_id IN (SELECT id FROM projects WHERE id = ")
+                .push_bind(project.clone())
+                .push(" OR name = ")
+                .push_bind(project.clone())
+                .push(" COLLATE NOCASE)");
+        }
+        Filter::Folder(path) => match folder_ids.get(path).cloned().flatten() {
+            Some(folder_id) => folders::push_in_subtree(query, "c.folder_id", folder_id),
+            None => {
+                query.push("1 = 0");
+            }
+        },
+        Filter::Tag(tag) => tags::push_has_tag(query, "c.id", tag.clone()),
+        Filter::Date(field, value) => {
+            let column = match field {
+                DateField::Created => "c.created_at",
+                DateField::Updated => "c.last_activity",
+            };
+            let since = match value {
+                DateValue::Within(span) => now - *span,
+                DateValue::Current(period) => start_of(*period, now),
+                DateValue::Date(comparison, date) => {
+                    query
+                        .push(format!("date({})", column))
+                        .push(comparison.sql())
+                        .push_bind(date.format("%Y-%m-%d").to_string());
+                    return;
+                }
+            };
+            // julianday reads both SQLite's and chrono's timestamp formats
+            query
+                .push(format!("julianday({}) >= julianday(", column))
+                .push_bind(since.format("%Y-%m-%d %H:%M:%S").to_string())
+                .push(")");
+        }
+        Filter::Count(field, comparison, number) => {
+            query.push(match field {
+                CountField::Tokens => {
+                    "(SELECT COALESCE(SUM(u.input_tokens + u.output_tokens + u.cache_creation_input_tokens \
+                     + u.cache_read_input_tokens), 0) FROM api_usage u WHERE u.chat_id = c.id)"
+                }
+                CountField::Messages => "(SELECT COUNT(*) FROM messages m WHERE m.chat_id = c.id)",
+            });
+            query.push(comparison.sql()).push_bind(*number);
+        }
+    }
+}
+
+fn push_expr(query: &mut QueryBuilder<'_, Sqlite>, expr: &Expr, folder_ids: &FolderIds, now: DateTime<Utc>) {
+    match expr {
+        Expr::And(items) | Expr::Or(items) if items.is_empty() => {
+            query.push(if matches!(expr, Expr::And(_)) { "1 = 1" } else { "1 = 0" });
+        }
+        Expr::And(items) | Expr::Or(items) => {
+            let separator = if matches!(expr, Expr::And(_)) { " AND " } else { " OR " };
+            query.push("(");
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    query.push(separator);
+                }
+                push_expr(query, item, folder_ids, now);
+            }
+            query.push(")");
+        }
+        Expr::Not(inner) => {
+            query.push("NOT (");
+            push_expr(query, inner, folder_ids, now);
+            query.push(")");
+        }
+        Expr::Filter(filter) => push_filter(query, filter, folder_ids, now),
+    }
+}
+
+/// Chats matching `expr`, most recently active first. Relative dates are
+/// measured from `now`.
+pub async fn evaluate(
+    pool: &SqlitePool,
+    expr: &Expr,
+    now: DateTime<Utc>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Vec<Chat>> {
+    let mut paths = Vec::new();
+    folder_paths(expr, &mut paths);
+    let mut folder_ids = FolderIds::new();
+    if !paths.is_empty() {
+        let mut conn = pool.acquire().await?;
+        for path in paths {
+            let folder_id = folders::find_by_path(&mut conn, path).await?;
+            folder_ids.insert(path.to_string(), folder_id);
+        }
+    }
+
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT c.* FROM chats c WHERE c.deleted_at IS NULL AND (");
+    push_expr(&mut query, expr, &folder_ids, now);
+    query
+        .push(") ORDER BY c.last_activity DESC LIMIT ")
+        .push_bind(limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT))
+        .push(" OFFSET ")
+        .push_bind(offset.unwrap_or(0).max(0));
+
+    let chats = query.build_query_as::<Chat>().fetch_all(pool).await?;
+    Ok(chats)
+}
+
+fn validate(name: &str, query: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("Collection name cannot be empty"));
+    }
+    parse(query)?;
+    Ok(name.to_string())
+}
+
+pub async fn list(pool: &SqlitePool) -> Result<Vec<Collection>> {
+    let collections = sqlx::query_as::<_, Collection>("SELECT * FROM collections ORDER BY name COLLATE NOCASE")
+        .fetch_all(pool)
+        .await?;
+    Ok(collections)
+}
+
+pub async fn get(pool: &SqlitePool, collection_id: &str) -> Result<Option<Collection>> {
+    let collection = sqlx::query_as::<_, Collection>("SELECT * FROM collections WHERE id = ?")
+        .bind(collection_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(collection)
+}
+
+/// Saves a collection. The query must parse.
+pub async fn create(pool: &SqlitePool, name: &str, query: &str) -> Result<Collection> {
+    let name = validate(name, query)?;
+    let now = Utc::now();
+    let collection = Collection {
+        id: Uuid::new_v4().to_string(),
+        name,
+        query: query.trim().to_string(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    sqlx::query("INSERT INTO collections (id, name, query, created_at, updated_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(&collection.id)
+        .bind(&collection.name)
+        .bind(&collection.query)
+        .bind(collection.created_at)
+        .bind(collection.updated_at)
+        .execute(pool)
+        .await?;
+
+    Ok(collection)
+}
+
+pub async fn update(pool: &SqlitePool, collection_id: &str, name: Option<&str>, query: Option<&str>) -> Result<Collection> {
+    let mut collection = get(pool, collection_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Collection not found"))?;
+    if let Some(name) = name {
+        collection.name = name.to_string();
+    }
+    if let Some(query) = query {
+        collection.query = query.trim().to_string();
+    }
+    collection.name = validate(&collection.name, &collection.query)?;
+    collection.updated_at = Utc::now();
+
+    sqlx::query("UPDATE collections SET name = ?, query = ?, updated_at = ? WHERE id = ?")
+        .bind(&collection.name)
+        .bind(&collection.query)
+        .bind(collection.updated_at)
+        .bind(collection_id)
+        .execute(pool)
+        .await?;
+
+    Ok(collection)
+}
+
+pub async fn delete(pool: &SqlitePool, collection_id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM collections WHERE id = ?")
+        .bind(collection_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
@@ -1,4 +1,4 @@
-use sqlx::{SqlitePool, sqlite::SqliteConnectOptions};
+use sqlx::{SqlitePool, sqlite::{SqliteConnectOptions, SqlitePoolOptions}};
 use std::str::FromStr;
 use anyhow::Result;
 
@@ -33,7 +33,14 @@ pub async fn create_pool(options: ConnectionOptions) -> Result<SqlitePool> {
         .pragma("temp_store", "memory")
         .pragma("mmap_size", "268435456"); // 256 MB
 
-    let pool = SqlitePool::connect_with(connect_options).await?;
+    let pool = SqlitePoolOptions::new()
+        .max_connections(options.max_connections)
+        .min_connections(options.min_connections)
+        .acquire_timeout(options.acquire_timeout)
+        .idle_timeout(options.idle_timeout)
+        .max_lifetime(options.max_lifetime)
+        .connect_with(connect_options)
+        .await?;
 
     log::info!("Database connection pool created successfully");
     Ok(pool)
@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use chrono::{DateTime, Utc};
+use anyhow::Result;
+use base64::{engine::general_purpose, Engine as _};
+use std::collections::{BTreeMap, HashMap};
+use crate::database::models::{Chat, Message, Project};
+use crate::integrations::attachments::{AttachmentRef, AttachmentStore};
+
+/// Identifies Cloddo's JSON exports, and the version of their layout.
+pub const EXPORT_FORMAT_NAME: &str = "cloddo-export";
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Html => "html",
+        }
+    }
+
+    /// Name of the file type in the save dialog.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Json => "JSON",
+            ExportFormat::Html => "HTML",
+        }
+    }
+}
+
+/// A chat with every message on every branch, oldest first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatExport {
+    pub chat: Chat,
+    pub messages: Vec<Message>,
+}
+
+impl ChatExport {
+    /// The branch the chat shows, oldest first.
+    pub fn active_branch(&self) -> Vec<&Message> {
+        let by_id: HashMap<&str, &Message> = self
+            .messages
+            .iter()
+            .map(|message| (message.id.as_str(), message))
+            .collect();
+
+        let mut current = self
+            .chat
+            .active_message_id
+            .as_deref()
+            .and_then(|id| by_id.get(id).copied())
+            .or_else(|| self.messages.last());
+
+        let mut branch = Vec::new();
+        while let Some(message) = current {
+            branch.push(message);
+            current = message.parent_id.as_deref().and_then(|id| by_id.get(id).copied());
+        }
+        branch.reverse();
+        branch
+    }
+}
+
+/// An attached file embedded in an export.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedAttachment {
+    pub name: String,
+    pub mime_type: String,
+    /// Base64 of the file's contents.
+    pub data: String,
+}
+
+/// Everything one export file holds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportBundle {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub project: Option<Project>,
+    pub chats: Vec<ChatExport>,
+    /// Attached files by content hash.
+    #[serde(default)]
+    pub attachments: BTreeMap<String, ExportedAttachment>,
+}
+
+impl ExportBundle {
+    pub fn new(project: Option<Project>, chats: Vec<ChatExport>) -> Self {
+        Self {
+            format: EXPORT_FORMAT_NAME.to_string(),
+            version: EXPORT_FORMAT_VERSION,
+            exported_at: Utc::now(),
+            project,
+            chats,
+            attachments: BTreeMap::new(),
+        }
+    }
+
+    /// Embeds the files the chats' messages refer to. Files missing from the
+    /// store are left out.
+    pub async fn embed_attachments(&mut self, store: &AttachmentStore) -> Result<()> {
+        let references: Vec<AttachmentRef> = self
+            .chats
+            .iter()
+            .flat_map(|chat| &chat.messages)
+            .flat_map(|message| AttachmentRef::from_metadata(message.metadata.as_deref()))
+            .collect();
+
+        for reference in references {
+            if self.attachments.contains_key(&reference.hash) {
+                continue;
+            }
+            match store.read(&reference.hash).await {
+                Ok(bytes) => {
+                    self.attachments.insert(
+                        reference.hash.clone(),
+                        ExportedAttachment {
+                            name: reference.name,
+                            mime_type: reference.mime_type,
+                            data: general_purpose::STANDARD.encode(bytes),
+                        },
+                    );
+                }
+                Err(e) => log::warn!("Leaving {} out of the export: {}", reference.name, e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn load_messages(pool: &SqlitePool, chat: Chat) -> Result<ChatExport> {
+    let messages = sqlx::query_as::<_, Message>(
+        "SELECT * FROM messages WHERE chat_id = ? ORDER BY created_at ASC, rowid ASC",
+    )
+    .bind(&chat.id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(ChatExport { chat, messages })
+}
+
+pub async fn load_chat(pool: &SqlitePool, chat_id: &str) -> Result<Option<ChatExport>> {
+    let chat = sqlx::query_as::<_, Chat>("SELECT * FROM chats WHERE id = ?")
+        .bind(chat_id)
+        .fetch_optional(pool)
+        .await?;
+
+    match chat {
+        Some(chat) => Ok(Some(load_messages(pool, chat).await?)),
+        None => Ok(None),
+    }
+}
+
+/// Loads `project_id` and its chats, oldest chat first.
+pub async fn load_project(pool: &SqlitePool, project_id: &str) -> Result<Option<(Project, Vec<ChatExport>)>> {
+    let Some(project) = sqlx::query_as::<_, Project>("SELECT * FROM projects WHERE id = ?")
+        .bind(project_id)
+        .fetch_optional(pool)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let chats = sqlx::query_as::<_, Chat>("SELECT * FROM chats WHERE project_id = ? AND deleted_at IS NULL ORDER BY created_at ASC")
+        .bind(project_id)
+        .fetch_all(pool)
+        .await?;
+
+    let mut exports = Vec::with_capacity(chats.len());
+    for chat in chats {
+        exports.push(load_messages(pool, chat).await?);
+    }
+    Ok(Some((project, exports)))
+}
+
+/// A file name for `title` that is safe on every platform.
+pub fn file_name(title: &str, format: ExportFormat) -> String {
+    let stem: String = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let stem = stem.trim();
+    let stem = if stem.is_empty() { "chat" } else { stem };
+    format!("{}.{}", stem.chars().take(100).collect::<String>(), format.extension())
+}
@@ -0,0 +1,312 @@
+use crate::database::models::Folder;
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+use chrono::Utc;
+use uuid::Uuid;
+use anyhow::Result;
+
+/// Splits a folder path such as `"Work / Clients/"` into its folder names,
+/// dropping empty ones.
+pub fn path_segments(path: &str) -> Vec<&str> {
+    path.split('/').map(str::trim).filter(|name| !name.is_empty()).collect()
+}
+
+fn validate_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("Folder name cannot be empty"));
+    }
+    if name.contains('/') {
+        return Err(anyhow::anyhow!("Folder names cannot contain '/'"));
+    }
+    Ok(name.to_string())
+}
+
+// Turns a unique index violation into a readable error
+fn name_taken(e: sqlx::Error, name: &str) -> anyhow::Error {
+    match &e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            anyhow::anyhow!("A folder named '{}' already exists there", name)
+        }
+        _ => e.into(),
+    }
+}
+
+/// Pushes a condition that `column` is `folder_id` or one of its subfolders.
+pub fn push_subtree_filter(query: &mut QueryBuilder<'_, Sqlite>, column: &str, folder_id: String) {
+    query.push(" AND ");
+    push_in_subtree(query, column, folder_id);
+}
+
+/// The condition of [`push_subtree_filter`] on its own, for queries that
+/// combine conditions themselves.
+pub fn push_in_subtree(query: &mut QueryBuilder<'_, Sqlite>, column: &str, folder_id: String) {
+    query
+        .push(format!("{} IN (WITH RECURSIVE subtree(id) AS (SELECT ", column))
+        .push_bind(folder_id)
+        .push(" UNION ALL SELECT f.id FROM folders f JOIN subtree ON f.parent_id = subtree.id) SELECT id FROM subtree)");
+}
+
+/// Full path of a folder, its ancestors' names joined with `/`.
+pub async fn folder_path(conn: &mut SqliteConnection, folder_id: &str) -> Result<Option<String>> {
+    let path = sqlx::query_scalar(
+        r#"
+        WITH RECURSIVE up(id, parent_id, path) AS (
+            SELECT id, parent_id, name FROM folders WHERE id = ?
+            UNION ALL
+            SELECT f.id, f.parent_id, f.name || '/' || up.path
+            FROM folders f JOIN up ON f.id = up.parent_id
+        )
+        SELECT path FROM up WHERE parent_id IS NULL
+        "#,
+    )
+    .bind(folder_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(path)
+}
+
+/// Folder at `path`, if every folder along it exists.
+pub async fn find_by_path(conn: &mut SqliteConnection, path: &str) -> Result<Option<String>> {
+    let mut parent_id: Option<String> = None;
+    for name in path_segments(path) {
+        let id: Option<String> = sqlx::query_scalar("SELECT id FROM folders WHERE parent_id IS ? AND name = ?")
+            .bind(&parent_id)
+            .bind(name)
+            .fetch_optional(&mut *conn)
+            .await?;
+        match id {
+            Some(id) => parent_id = Some(id),
+            None => return Ok(None),
+        }
+    }
+    Ok(parent_id)
+}
+
+/// Folder at `path`, creating the folders along it that are missing.
+/// Returns `None` for an empty path, which is the top level.
+pub async fn ensure_path(conn: &mut SqliteConnection, path: &str) -> Result<Option<String>> {
+    let mut parent_id: Option<String> = None;
+    for name in path_segments(path) {
+        let existing: Option<String> = sqlx::query_scalar("SELECT id FROM folders WHERE parent_id IS ? AND name = ?")
+            .bind(&parent_id)
+            .bind(name)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = Uuid::new_v4().to_string();
+                let now = Utc::now();
+                sqlx::query("INSERT INTO folders (id, parent_id, name, created_at, updated_at) VALUES (?, ?, ?, ?, ?)")
+                    .bind(&id)
+                    .bind(&parent_id)
+                    .bind(name)
+                    .bind(now)
+                    .bind(now)
+                    .execute(&mut *conn)
+                    .await?;
+                id
+            }
+        };
+        parent_id = Some(id);
+    }
+    Ok(parent_id)
+}
+
+// Rewrites `folder_path` of the chats in `folder_id`'s subtree after the
+// folder was renamed or moved
+async fn refresh_chat_paths(conn: &mut SqliteConnection, folder_id: &str) -> Result<()> {
+    let path = folder_path(conn, folder_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Folder not found"))?;
+
+    sqlx::query(
+        r#"
+        WITH RECURSIVE tree(id, path) AS (
+            SELECT ?, ?
+            UNION ALL
+            SELECT f.id, tree.path || '/' || f.name FROM folders f JOIN tree ON f.parent_id = tree.id
+        )
+        UPDATE chats
+        SET folder_path = (SELECT path FROM tree WHERE tree.id = chats.folder_id)
+        WHERE folder_id IN (SELECT id FROM tree)
+        "#,
+    )
+    .bind(folder_id)
+    .bind(path)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Every folder with its path and the number of chats directly in it,
+/// ordered by path.
+pub async fn list(pool: &SqlitePool) -> Result<Vec<Folder>> {
+    let folders = sqlx::query_as::<_, Folder>(
+        r#"
+        WITH RECURSIVE tree(id, path) AS (
+            SELECT id, name FROM folders WHERE parent_id IS NULL
+            UNION ALL
+            SELECT f.id, tree.path || '/' || f.name FROM folders f JOIN tree ON f.parent_id = tree.id
+        )
+        SELECT f.id, f.parent_id, f.name, tree.path,
+               (SELECT COUNT(*) FROM chats c WHERE c.folder_id = f.id AND c.deleted_at IS NULL) AS chat_count,
+               f.created_at, f.updated_at
+        FROM folders f JOIN tree ON tree.id = f.id
+        ORDER BY tree.path
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(folders)
+}
+
+pub async fn get(pool: &SqlitePool, folder_id: &str) -> Result<Option<Folder>> {
+    Ok(list(pool).await?.into_iter().find(|folder| folder.id == folder_id))
+}
+
+async fn get_required(pool: &SqlitePool, folder_id: &str) -> Result<Folder> {
+    get(pool, folder_id).await?.ok_or_else(|| anyhow::anyhow!("Folder not found"))
+}
+
+pub async fn create(pool: &SqlitePool, name: &str, parent_id: Option<&str>) -> Result<Folder> {
+    let name = validate_name(name)?;
+    if let Some(parent_id) = parent_id {
+        get_required(pool, parent_id).await?;
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    sqlx::query("INSERT INTO folders (id, parent_id, name, created_at, updated_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(&id)
+        .bind(parent_id)
+        .bind(&name)
+        .bind(now)
+        .bind(now)
+        .execute(pool)
+        .await
+        .map_err(|e| name_taken(e, &name))?;
+
+    get_required(pool, &id).await
+}
+
+pub async fn rename(pool: &SqlitePool, folder_id: &str, name: &str) -> Result<Folder> {
+    let name = validate_name(name)?;
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query("UPDATE folders SET name = ?, updated_at = ? WHERE id = ?")
+        .bind(&name)
+        .bind(Utc::now())
+        .bind(folder_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| name_taken(e, &name))?;
+    if result.rows_affected() == 0 {
+        return Err(anyhow::anyhow!("Folder not found"));
+    }
+    refresh_chat_paths(&mut tx, folder_id).await?;
+
+    tx.commit().await?;
+    get_required(pool, folder_id).await
+}
+
+/// Moves a folder, with its subfolders and chats, under `parent_id`, or to
+/// the top level when `None`.
+pub async fn move_folder(pool: &SqlitePool, folder_id: &str, parent_id: Option<&str>) -> Result<Folder> {
+    let folder = get_required(pool, folder_id).await?;
+    let mut tx = pool.begin().await?;
+
+    if let Some(parent_id) = parent_id {
+        let mut query = QueryBuilder::new("SELECT COUNT(*) FROM folders WHERE id = ");
+        query.push_bind(parent_id);
+        push_subtree_filter(&mut query, "id", folder_id.to_string());
+        let inside: i64 = query.build_query_scalar().fetch_one(&mut *tx).await?;
+        if inside > 0 {
+            return Err(anyhow::anyhow!("Cannot move a folder into itself"));
+        }
+
+        let exists: Option<String> = sqlx::query_scalar("SELECT id FROM folders WHERE id = ?")
+            .bind(parent_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if exists.is_none() {
+            return Err(anyhow::anyhow!("Folder not found"));
+        }
+    }
+
+    sqlx::query("UPDATE folders SET parent_id = ?, updated_at = ? WHERE id = ?")
+        .bind(parent_id)
+        .bind(Utc::now())
+        .bind(folder_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| name_taken(e, &folder.name))?;
+    refresh_chat_paths(&mut tx, folder_id).await?;
+
+    tx.commit().await?;
+    get_required(pool, folder_id).await
+}
+
+/// Deletes a folder and its subfolders. Their chats move to the deleted
+/// folder's parent, or to the top level.
+pub async fn delete(pool: &SqlitePool, folder_id: &str) -> Result<bool> {
+    let Some(folder) = get(pool, folder_id).await? else {
+        return Ok(false);
+    };
+    let mut tx = pool.begin().await?;
+
+    let parent_path = match &folder.parent_id {
+        Some(parent_id) => folder_path(&mut tx, parent_id).await?,
+        None => None,
+    };
+    let mut query = QueryBuilder::new("UPDATE chats SET folder_id = ");
+    query
+        .push_bind(folder.parent_id.clone())
+        .push(", folder_path = ")
+        .push_bind(parent_path)
+        .push(" WHERE 1 = 1");
+    push_subtree_filter(&mut query, "folder_id", folder_id.to_string());
+    query.build().execute(&mut *tx).await?;
+
+    // Subfolders go with it through ON DELETE CASCADE
+    sqlx::query("DELETE FROM folders WHERE id = ?")
+        .bind(folder_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Puts chats into `folder_id`, or at the top level when `None`.
+pub async fn move_chats(pool: &SqlitePool, chat_ids: &[String], folder_id: Option<&str>) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+
+    let path = match folder_id {
+        Some(folder_id) => Some(
+            folder_path(&mut tx, folder_id)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Folder not found"))?,
+        ),
+        None => None,
+    };
+
+    let mut moved = 0;
+    for chat_id in chat_ids {
+        moved += sqlx::query("UPDATE chats SET folder_id = ?, folder_path = ?, updated_at = ? WHERE id = ?")
+            .bind(folder_id)
+            .bind(&path)
+            .bind(Utc::now())
+            .bind(chat_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+    }
+
+    tx.commit().await?;
+    Ok(moved)
+}
@@ -0,0 +1,495 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use chrono::{DateTime, TimeZone, Utc};
+use uuid::Uuid;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use crate::database::models::Role;
+
+// Parent of the first message in claude.ai exports
+const CLAUDE_ROOT_PARENT: &str = "00000000-0000-4000-8000-000000000000";
+
+/// Where an export file came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportSource {
+    /// The `conversations.json` of a claude.ai data export.
+    ClaudeAi,
+    /// ChatGPT-style `conversations.json` with a message tree per conversation.
+    Chatgpt,
+}
+
+impl ImportSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportSource::ClaudeAi => "claude_ai",
+            ImportSource::Chatgpt => "chatgpt",
+        }
+    }
+}
+
+/// A message read from an export, linked to its parent by source ids.
+#[derive(Debug, Clone)]
+pub struct ImportedMessage {
+    pub source_id: String,
+    pub parent_source_id: Option<String>,
+    pub role: Role,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub metadata: Option<Value>,
+}
+
+/// A conversation read from an export. Parents come before their replies.
+#[derive(Debug, Clone)]
+pub struct ImportedConversation {
+    pub source_id: String,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub messages: Vec<ImportedMessage>,
+    /// Last message of the branch that was showing, if the export says.
+    pub current_source_id: Option<String>,
+}
+
+/// A conversation that could not be imported.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportIssue {
+    pub source_id: Option<String>,
+    pub title: Option<String>,
+    pub reason: String,
+}
+
+/// What an import did.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportSummary {
+    pub source: ImportSource,
+    pub conversations_found: usize,
+    pub chats_imported: usize,
+    /// Conversations imported before, left as they are.
+    pub chats_skipped: usize,
+    pub messages_imported: usize,
+    pub issues: Vec<ImportIssue>,
+}
+
+/// Recognizes an export by the shape of its first conversation.
+pub fn detect_source(conversations: &[Value]) -> Option<ImportSource> {
+    let first = conversations.first()?;
+    if first.get("chat_messages").is_some() {
+        Some(ImportSource::ClaudeAi)
+    } else if first.get("mapping").is_some() {
+        Some(ImportSource::Chatgpt)
+    } else {
+        None
+    }
+}
+
+fn parse_rfc3339(value: Option<&Value>) -> Option<DateTime<Utc>> {
+    value
+        .and_then(Value::as_str)
+        .and_then(|text| DateTime::parse_from_rfc3339(text).ok())
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
+
+fn parse_unix(value: Option<&Value>) -> Option<DateTime<Utc>> {
+    let seconds = value.and_then(Value::as_f64)?;
+    Utc.timestamp_millis_opt((seconds * 1000.0) as i64).single()
+}
+
+fn string(value: Option<&Value>) -> Option<String> {
+    value.and_then(Value::as_str).map(str::to_string)
+}
+
+fn title_or_default(title: Option<String>) -> String {
+    title
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| "Untitled".to_string())
+}
+
+/// Reorders `messages` so every parent comes before its replies, keeping
+/// the original order otherwise. Messages whose parent is missing become
+/// roots.
+fn parents_first(messages: Vec<ImportedMessage>) -> Vec<ImportedMessage> {
+    let ids: HashSet<String> = messages.iter().map(|message| message.source_id.clone()).collect();
+    let mut pending: Vec<ImportedMessage> = messages
+        .into_iter()
+        .map(|mut message| {
+            if message.parent_source_id.as_ref().is_some_and(|parent| !ids.contains(parent)) {
+                message.parent_source_id = None;
+            }
+            message
+        })
+        .collect();
+
+    let mut placed = HashSet::new();
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let before = pending.len();
+        let mut waiting = Vec::new();
+        for message in pending {
+            let ready = message.parent_source_id.as_ref().map_or(true, |parent| placed.contains(parent));
+            if ready {
+                placed.insert(message.source_id.clone());
+                ordered.push(message);
+            } else {
+                waiting.push(message);
+            }
+        }
+        // Cycles cannot be placed; cut them loose
+        if waiting.len() == before {
+            for message in &mut waiting {
+                message.parent_source_id = None;
+            }
+        }
+        pending = waiting;
+    }
+    ordered
+}
+
+fn parse_claude(conversation: &Value) -> Result<ImportedConversation, String> {
+    let source_id = string(conversation.get("uuid")).ok_or("Conversation has no uuid")?;
+    let created_at = parse_rfc3339(conversation.get("created_at")).ok_or("Conversation has no creation time")?;
+    let updated_at = parse_rfc3339(conversation.get("updated_at")).unwrap_or(created_at);
+    let entries = conversation
+        .get("chat_messages")
+        .and_then(Value::as_array)
+        .ok_or("Conversation has no messages")?;
+
+    // Skipped entries are remembered so their replies can attach to the
+    // nearest kept ancestor instead of starting a new tree
+    let mut messages = Vec::new();
+    let mut parents: HashMap<String, Option<String>> = HashMap::new();
+    let mut previous: Option<String> = None;
+    for entry in entries {
+        let Some(id) = string(entry.get("uuid")) else { continue };
+        // Exports without parent links are a single branch
+        let parent = match entry.get("parent_message_uuid") {
+            Some(parent) => string(Some(parent)).filter(|parent| parent != CLAUDE_ROOT_PARENT),
+            None => previous.clone(),
+        };
+        parents.insert(id.clone(), parent.clone());
+
+        let role = match entry.get("sender").and_then(Value::as_str) {
+            Some("human") => Role::User,
+            Some("assistant") => Role::Assistant,
+            _ => continue,
+        };
+
+        // Newer exports keep the text in content blocks
+        let mut content = string(entry.get("text")).unwrap_or_default();
+        if content.trim().is_empty() {
+            content = entry
+                .get("content")
+                .and_then(Value::as_array)
+                .map(|blocks| {
+                    blocks
+                        .iter()
+                        .filter(|block| block.get("type").and_then(Value::as_str) == Some("text"))
+                        .filter_map(|block| block.get("text").and_then(Value::as_str))
+                        .collect::<Vec<_>>()
+                        .join("\n\n")
+                })
+                .unwrap_or_default();
+        }
+
+        let attachments: Vec<Value> = entry
+            .get("attachments")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .map(|attachment| {
+                serde_json::json!({
+                    "name": attachment.get("file_name"),
+                    "mime_type": attachment.get("file_type"),
+                    "size_bytes": attachment.get("file_size"),
+                    "extracted_content": attachment.get("extracted_content"),
+                })
+            })
+            .collect();
+        if content.trim().is_empty() && attachments.is_empty() {
+            continue;
+        }
+
+        messages.push(ImportedMessage {
+            source_id: id.clone(),
+            parent_source_id: parent,
+            role,
+            content,
+            created_at: parse_rfc3339(entry.get("created_at")).unwrap_or(created_at),
+            metadata: (!attachments.is_empty()).then(|| serde_json::json!({ "imported_attachments": attachments })),
+        });
+        previous = Some(id);
+    }
+
+    let kept: HashSet<String> = messages.iter().map(|message| message.source_id.clone()).collect();
+    let nearest_kept = |start: Option<String>| {
+        let mut current = start;
+        let mut steps = 0;
+        while let Some(id) = current.as_ref().filter(|id| !kept.contains(*id) && steps <= parents.len()) {
+            current = parents.get(id).cloned().flatten();
+            steps += 1;
+        }
+        current.filter(|id| kept.contains(id))
+    };
+    for message in &mut messages {
+        message.parent_source_id = nearest_kept(message.parent_source_id.take());
+    }
+
+    Ok(ImportedConversation {
+        source_id,
+        title: title_or_default(string(conversation.get("name"))),
+        created_at,
+        updated_at,
+        current_source_id: nearest_kept(string(conversation.get("current_leaf_message_uuid"))),
+        messages: parents_first(messages),
+    })
+}
+
+fn parse_chatgpt(conversation: &Value) -> Result<ImportedConversation, String> {
+    let source_id = string(conversation.get("conversation_id"))
+        .or_else(|| string(conversation.get("id")))
+        .ok_or("Conversation has no id")?;
+    let created_at = parse_unix(conversation.get("create_time")).ok_or("Conversation has no creation time")?;
+    let updated_at = parse_unix(conversation.get("update_time")).unwrap_or(created_at);
+    let mapping = conversation
+        .get("mapping")
+        .and_then(Value::as_object)
+        .ok_or("Conversation has no messages")?;
+
+    // Only visible user and assistant text becomes messages; everything else
+    // is skipped and its replies attach to the nearest kept ancestor
+    let mut kept = HashMap::new();
+    for (node_id, node) in mapping {
+        let Some(message) = node.get("message").filter(|message| !message.is_null()) else { continue };
+        let role = match message.pointer("/author/role").and_then(Value::as_str) {
+            Some("user") => Role::User,
+            Some("assistant") => Role::Assistant,
+            _ => continue,
+        };
+        if message.pointer("/metadata/is_visually_hidden_from_conversation").and_then(Value::as_bool) == Some(true) {
+            continue;
+        }
+        let content = message
+            .pointer("/content/parts")
+            .and_then(Value::as_array)
+            .map(|parts| parts.iter().filter_map(Value::as_str).collect::<Vec<_>>().join("\n\n"))
+            .unwrap_or_default();
+        if content.trim().is_empty() {
+            continue;
+        }
+
+        kept.insert(
+            node_id.clone(),
+            ImportedMessage {
+                source_id: node_id.clone(),
+                parent_source_id: None,
+                role,
+                content,
+                created_at: parse_unix(message.get("create_time")).unwrap_or(created_at),
+                metadata: None,
+            },
+        );
+    }
+
+    let nearest_kept = |start: Option<&str>| {
+        let mut current = start.map(str::to_string);
+        let mut steps = 0;
+        while let Some(id) = current {
+            if kept.contains_key(&id) || steps > mapping.len() {
+                return kept.contains_key(&id).then_some(id);
+            }
+            current = mapping.get(&id).and_then(|node| string(node.get("parent")));
+            steps += 1;
+        }
+        None
+    };
+
+    let mut messages: Vec<ImportedMessage> = kept
+        .values()
+        .cloned()
+        .map(|mut message| {
+            let parent = mapping
+                .get(&message.source_id)
+                .and_then(|node| node.get("parent"))
+                .and_then(Value::as_str);
+            message.parent_source_id = nearest_kept(parent);
+            message
+        })
+        .collect();
+    messages.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.source_id.cmp(&b.source_id)));
+
+    Ok(ImportedConversation {
+        source_id,
+        title: title_or_default(string(conversation.get("title"))),
+        created_at,
+        updated_at,
+        current_source_id: nearest_kept(conversation.get("current_node").and_then(Value::as_str)),
+        messages: parents_first(messages),
+    })
+}
+
+/// Parses an export file, recognizing its format unless `source` is given.
+/// Conversations that cannot be read are reported rather than failing the
+/// whole file.
+pub fn parse_export(
+    json: &str,
+    source: Option<ImportSource>,
+) -> Result<(ImportSource, Vec<ImportedConversation>, Vec<ImportIssue>)> {
+    let value: Value = serde_json::from_str(json)?;
+    let conversations = value
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Expected a list of conversations"))?;
+    let source = source
+        .or_else(|| detect_source(conversations))
+        .ok_or_else(|| anyhow::anyhow!("Not a claude.ai or ChatGPT conversations export"))?;
+
+    let mut parsed = Vec::new();
+    let mut issues = Vec::new();
+    for conversation in conversations {
+        let result = match source {
+            ImportSource::ClaudeAi => parse_claude(conversation),
+            ImportSource::Chatgpt => parse_chatgpt(conversation),
+        };
+        match result {
+            Ok(conversation) => parsed.push(conversation),
+            Err(reason) => issues.push(ImportIssue {
+                source_id: string(conversation.get("uuid"))
+                    .or_else(|| string(conversation.get("conversation_id")))
+                    .or_else(|| string(conversation.get("id"))),
+                title: string(conversation.get("name")).or_else(|| string(conversation.get("title"))),
+                reason,
+            }),
+        }
+    }
+
+    Ok((source, parsed, issues))
+}
+
+// Stores one conversation as a chat. Returns the number of messages stored,
+// or `None` if it was imported before
+async fn import_conversation(
+    pool: &SqlitePool,
+    source: ImportSource,
+    session_id: &str,
+    project_id: Option<&str>,
+    conversation: &ImportedConversation,
+) -> Result<Option<usize>> {
+    let mut tx = pool.begin().await?;
+
+    let existing: Option<String> = sqlx::query_scalar("SELECT id FROM chats WHERE source = ? AND source_id = ?")
+        .bind(source.as_str())
+        .bind(&conversation.source_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if existing.is_some() {
+        return Ok(None);
+    }
+
+    let ids: HashMap<&str, String> = conversation
+        .messages
+        .iter()
+        .map(|message| (message.source_id.as_str(), Uuid::new_v4().to_string()))
+        .collect();
+    let last_activity = conversation
+        .messages
+        .iter()
+        .map(|message| message.created_at)
+        .max()
+        .unwrap_or(conversation.updated_at);
+    let active_message_id = conversation
+        .current_source_id
+        .as_deref()
+        .or_else(|| conversation.messages.last().map(|message| message.source_id.as_str()))
+        .and_then(|source_id| ids.get(source_id).cloned());
+
+    let chat_id = Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"
+        INSERT INTO chats (id, session_id, project_id, title, is_favorite, last_activity, created_at, updated_at,
+                           metadata, active_message_id, auto_title, source, source_id)
+        VALUES (?, ?, ?, ?, FALSE, ?, ?, ?, '{}', ?, FALSE, ?, ?)
+        "#,
+    )
+    .bind(&chat_id)
+    .bind(session_id)
+    .bind(project_id)
+    .bind(&conversation.title)
+    .bind(last_activity)
+    .bind(conversation.created_at)
+    .bind(conversation.updated_at)
+    .bind(&active_message_id)
+    .bind(source.as_str())
+    .bind(&conversation.source_id)
+    .execute(&mut *tx)
+    .await?;
+
+    for message in &conversation.messages {
+        let mut metadata = message.metadata.clone().unwrap_or_else(|| serde_json::json!({}));
+        metadata["import"] = serde_json::json!({ "source": source.as_str(), "source_id": message.source_id });
+
+        sqlx::query(
+            r#"
+            INSERT INTO messages (id, chat_id, parent_id, role, content, metadata, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&ids[message.source_id.as_str()])
+        .bind(&chat_id)
+        .bind(message.parent_source_id.as_deref().map(|parent| &ids[parent]))
+        .bind(message.role)
+        .bind(&message.content)
+        .bind(metadata.to_string())
+        .bind(message.created_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(Some(conversation.messages.len()))
+}
+
+/// Imports an export file's conversations as chats in `session_id`,
+/// skipping conversations imported before.
+pub async fn import_export(
+    pool: &SqlitePool,
+    json: &str,
+    source: Option<ImportSource>,
+    session_id: &str,
+    project_id: Option<&str>,
+) -> Result<ImportSummary> {
+    let (source, conversations, issues) = parse_export(json, source)?;
+    let mut summary = ImportSummary {
+        source,
+        conversations_found: conversations.len() + issues.len(),
+        chats_imported: 0,
+        chats_skipped: 0,
+        messages_imported: 0,
+        issues,
+    };
+
+    for conversation in &conversations {
+        match import_conversation(pool, source, session_id, project_id, conversation).await {
+            Ok(Some(messages)) => {
+                summary.chats_imported += 1;
+                summary.messages_imported += messages;
+            }
+            Ok(None) => summary.chats_skipped += 1,
+            Err(e) => summary.issues.push(ImportIssue {
+                source_id: Some(conversation.source_id.clone()),
+                title: Some(conversation.title.clone()),
+                reason: e.to_string(),
+            }),
+        }
+    }
+
+    log::info!(
+        "Imported {} of {} {} conversations ({} already imported, {} failed)",
+        summary.chats_imported,
+        summary.conversations_found,
+        source.as_str(),
+        summary.chats_skipped,
+        summary.issues.len()
+    );
+    Ok(summary)
+}
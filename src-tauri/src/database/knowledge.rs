@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use chrono::Utc;
+use uuid::Uuid;
+use anyhow::Result;
+use crate::database::models::ProjectDocument;
+use crate::integrations::filesystem::{estimate_tokens, ExtractedDocument};
+use crate::integrations::models::ModelInfo;
+
+/// Attaches `document` to the project. Attaching a file the project already
+/// has returns the existing document.
+pub async fn add_document(
+    pool: &SqlitePool,
+    project_id: &str,
+    document: ExtractedDocument,
+) -> Result<ProjectDocument> {
+    let existing = sqlx::query_as::<_, ProjectDocument>(
+        "SELECT * FROM project_documents WHERE project_id = ? AND content_hash = ?",
+    )
+    .bind(project_id)
+    .bind(&document.content_hash)
+    .fetch_optional(pool)
+    .await?;
+    if let Some(existing) = existing {
+        return Ok(existing);
+    }
+
+    let now = Utc::now();
+    let record = ProjectDocument {
+        id: Uuid::new_v4().to_string(),
+        project_id: project_id.to_string(),
+        token_count: estimate_tokens(&document.text),
+        name: document.name,
+        mime_type: document.mime_type,
+        size_bytes: document.size_bytes,
+        content_hash: document.content_hash,
+        extracted_text: document.text,
+        created_at: now,
+        updated_at: now,
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO project_documents (id, project_id, name, mime_type, size_bytes, content_hash,
+                                       extracted_text, token_count, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&record.id)
+    .bind(&record.project_id)
+    .bind(&record.name)
+    .bind(&record.mime_type)
+    .bind(record.size_bytes)
+    .bind(&record.content_hash)
+    .bind(&record.extracted_text)
+    .bind(record.token_count)
+    .bind(record.created_at)
+    .bind(record.updated_at)
+    .execute(pool)
+    .await?;
+
+    Ok(record)
+}
+
+pub async fn list_documents(pool: &SqlitePool, project_id: &str) -> Result<Vec<ProjectDocument>> {
+    let documents = sqlx::query_as::<_, ProjectDocument>(
+        "SELECT * FROM project_documents WHERE project_id = ? ORDER BY created_at ASC",
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(documents)
+}
+
+/// Formats documents the way Claude expects long-form context: one
+/// `<document>` per file with its source and content.
+pub fn format_documents(documents: &[ProjectDocument]) -> String {
+    let mut text = String::from("The user attached these documents to the project:\n<documents>\n");
+    for (index, document) in documents.iter().enumerate() {
+        text.push_str(&format!(
+            "<document index=\"{}\">\n<source>{}</source>\n<document_content>\n{}\n</document_content>\n</document>\n",
+            index + 1,
+            document.name,
+            document.extracted_text.trim_end()
+        ));
+    }
+    text.push_str("</documents>");
+    text
+}
+
+/// A project's documents rendered as system context.
+#[derive(Debug, Clone)]
+pub struct ProjectKnowledge {
+    pub document_count: usize,
+    pub total_tokens: i64,
+    pub text: String,
+}
+
+/// Loads the knowledge for `project_id`, or `None` when it has no documents.
+pub async fn load_knowledge(pool: &SqlitePool, project_id: &str) -> Result<Option<ProjectKnowledge>> {
+    let documents = list_documents(pool, project_id).await?;
+    if documents.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(ProjectKnowledge {
+        document_count: documents.len(),
+        total_tokens: documents.iter().map(|document| document.token_count).sum(),
+        text: format_documents(&documents),
+    }))
+}
+
+/// How a project's knowledge fits into a model's context window.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeBudget {
+    pub model: String,
+    pub total_tokens: i64,
+    pub context_budget: i64,
+    pub over_budget: bool,
+    pub warning: Option<String>,
+}
+
+/// Compares `total_tokens` of knowledge against the room `model` leaves
+/// once `max_tokens` is reserved for the reply.
+pub fn check_budget(total_tokens: i64, model: &ModelInfo, max_tokens: u32) -> KnowledgeBudget {
+    let context_budget = model.context_window as i64 - max_tokens as i64;
+    let over_budget = total_tokens > context_budget;
+    let warning = over_budget.then(|| {
+        format!(
+            "Project knowledge is about {} tokens, more than the {} tokens {} has room for. Remove documents or pick a model with a larger context window.",
+            total_tokens, context_budget, model.display_name
+        )
+    });
+
+    KnowledgeBudget {
+        model: model.id.clone(),
+        total_tokens,
+        context_budget,
+        over_budget,
+        warning,
+    }
+}
@@ -0,0 +1,32 @@
+use crate::database::models::Chat;
+use crate::database::Database;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// File earlier versions kept every chat in.
+pub const CHATS_FILE: &str = "chats.json";
+
+/// Imports the chats an earlier version saved to `chats.json` in `data_dir`,
+/// then renames the file so the import only runs once. Chats already in the
+/// database are left alone, so an interrupted import can simply run again.
+pub async fn import_chats(db: &Database, data_dir: &Path) -> Result<usize> {
+    let path = data_dir.join(CHATS_FILE);
+    if !path.exists() {
+        return Ok(0);
+    }
+
+    let content = fs::read_to_string(&path).context("Failed to read chats")?;
+    let chats: Vec<Chat> = serde_json::from_str(&content).context("Failed to parse chats")?;
+
+    for chat in &chats {
+        db.ensure_session(&chat.session_id).await?;
+        db.chats().insert(chat, true).await?;
+    }
+
+    fs::rename(&path, data_dir.join(format!("{}.imported", CHATS_FILE)))
+        .context("Failed to rename imported chats file")?;
+
+    log::info!("Imported {} chats from {}", chats.len(), CHATS_FILE);
+    Ok(chats.len())
+}
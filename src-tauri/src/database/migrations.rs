@@ -1,6 +1,9 @@
-use sqlx::SqlitePool;
+use sqlx::{Connection, Row, SqliteConnection, SqlitePool};
 use anyhow::Result;
 
+/// Schema version recorded in `PRAGMA user_version` once all migrations ran.
+pub const SCHEMA_VERSION: i64 = 14;
+
 pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
     log::info!("Running database migrations...");
 
@@ -190,6 +193,51 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
     // Create indexes for better performance
     create_indexes(pool).await?;
 
+    // Apply versioned schema changes on top of the base tables
+    let version = schema_version(pool).await?;
+    if version < 2 {
+        migrate_to_v2(pool).await?;
+    }
+    if version < 3 {
+        migrate_to_v3(pool).await?;
+    }
+    if version < 4 {
+        migrate_to_v4(pool).await?;
+    }
+    if version < 5 {
+        migrate_to_v5(pool).await?;
+    }
+    if version < 6 {
+        migrate_to_v6(pool).await?;
+    }
+    if version < 7 {
+        migrate_to_v7(pool).await?;
+    }
+    if version < 8 {
+        migrate_to_v8(pool).await?;
+    }
+    if version < 9 {
+        migrate_to_v9(pool).await?;
+    }
+    if version < 10 {
+        migrate_to_v10(pool).await?;
+    }
+    if version < 11 {
+        migrate_to_v11(pool).await?;
+    }
+    if version < 12 {
+        migrate_to_v12(pool).await?;
+    }
+    if version < 13 {
+        migrate_to_v13(pool).await?;
+    }
+    if version < 14 {
+        migrate_to_v14(pool).await?;
+    }
+    if version < SCHEMA_VERSION {
+        log::info!("Database schema upgraded from version {} to {}", version, SCHEMA_VERSION);
+    }
+
     log::info!("Database migrations completed successfully");
     Ok(())
 }
@@ -235,4 +283,707 @@ async fn create_indexes(pool: &SqlitePool) -> Result<()> {
 
     log::info!("Database indexes created successfully");
     Ok(())
-}
\ No newline at end of file
+}
+
+pub async fn schema_version(pool: &SqlitePool) -> Result<i64> {
+    let row = sqlx::query("PRAGMA user_version").fetch_one(pool).await?;
+    Ok(row.get::<i64, _>(0))
+}
+
+// Records that the schema reached `version`. Each migration calls this in its
+// own transaction, so one that fails part way is run again from its start
+async fn set_schema_version(conn: &mut SqliteConnection, version: i64) -> Result<()> {
+    sqlx::query(&format!("PRAGMA user_version = {}", version))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// v2: chats track last activity, API usage is recorded per call and rolled
+// up into session_analytics once per session and day
+async fn migrate_to_v2(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("ALTER TABLE chats ADD COLUMN last_activity TIMESTAMP")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE chats SET last_activity = COALESCE(updated_at, created_at)")
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS api_usage (
+            id TEXT PRIMARY KEY,
+            source TEXT NOT NULL CHECK (source IN ('chat', 'agent', 'hook')),
+            session_id TEXT,
+            chat_id TEXT,
+            project_id TEXT,
+            agent_id TEXT,
+            agent_run_id TEXT,
+            hook_id TEXT,
+            model TEXT NOT NULL,
+            input_tokens INTEGER NOT NULL DEFAULT 0,
+            output_tokens INTEGER NOT NULL DEFAULT 0,
+            cache_creation_input_tokens INTEGER NOT NULL DEFAULT 0,
+            cache_read_input_tokens INTEGER NOT NULL DEFAULT 0,
+            cost REAL NOT NULL DEFAULT 0.0,
+            date DATE NOT NULL,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let indexes = vec![
+        "CREATE INDEX IF NOT EXISTS idx_chats_last_activity ON chats(last_activity)",
+        "CREATE INDEX IF NOT EXISTS idx_api_usage_date ON api_usage(date)",
+        "CREATE INDEX IF NOT EXISTS idx_api_usage_session_id ON api_usage(session_id)",
+        "CREATE INDEX IF NOT EXISTS idx_api_usage_project_id ON api_usage(project_id)",
+        "CREATE INDEX IF NOT EXISTS idx_api_usage_agent_id ON api_usage(agent_id)",
+        "CREATE INDEX IF NOT EXISTS idx_api_usage_model ON api_usage(model)",
+        // One analytics row per session and day, updated in place
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_session_analytics_session_date ON session_analytics(session_id, date)",
+    ];
+
+    for index_sql in indexes {
+        sqlx::query(index_sql).execute(&mut *tx).await?;
+    }
+
+    set_schema_version(&mut tx, 2).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// v3: spending budgets and a log of the warnings and blocked requests they
+// produced
+async fn migrate_to_v3(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS budgets (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            scope TEXT NOT NULL CHECK (scope IN ('global', 'project', 'agent')),
+            scope_id TEXT,
+            period TEXT NOT NULL CHECK (period IN ('daily', 'monthly')),
+            limit_usd REAL NOT NULL CHECK (limit_usd >= 0),
+            warn_threshold REAL NOT NULL DEFAULT 0.8 CHECK (warn_threshold > 0 AND warn_threshold <= 1),
+            enabled BOOLEAN DEFAULT TRUE,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            CHECK ((scope = 'global') = (scope_id IS NULL))
+        )
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS budget_events (
+            id TEXT PRIMARY KEY,
+            budget_id TEXT NOT NULL,
+            kind TEXT NOT NULL CHECK (kind IN ('warning', 'blocked')),
+            period_start DATE NOT NULL,
+            spent REAL NOT NULL,
+            limit_usd REAL NOT NULL,
+            source TEXT NOT NULL,
+            session_id TEXT,
+            chat_id TEXT,
+            project_id TEXT,
+            agent_id TEXT,
+            agent_run_id TEXT,
+            hook_id TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (budget_id) REFERENCES budgets(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let indexes = vec![
+        "CREATE INDEX IF NOT EXISTS idx_budgets_scope ON budgets(scope, scope_id)",
+        "CREATE INDEX IF NOT EXISTS idx_budget_events_budget_id ON budget_events(budget_id, period_start)",
+        "CREATE INDEX IF NOT EXISTS idx_budget_events_created_at ON budget_events(created_at)",
+    ];
+
+    for index_sql in indexes {
+        sqlx::query(index_sql).execute(&mut *tx).await?;
+    }
+
+    set_schema_version(&mut tx, 3).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// v4: projects carry metadata, used for generation settings their chats inherit
+async fn migrate_to_v4(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("ALTER TABLE projects ADD COLUMN metadata TEXT")
+        .execute(&mut *tx)
+        .await?;
+
+    set_schema_version(&mut tx, 4).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// v5: documents attached to projects as knowledge for their chats
+async fn migrate_to_v5(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS project_documents (
+            id TEXT PRIMARY KEY,
+            project_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            content_hash TEXT NOT NULL,
+            extracted_text TEXT NOT NULL,
+            token_count INTEGER NOT NULL,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (project_id, content_hash),
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_project_documents_project_id ON project_documents(project_id)")
+        .execute(&mut *tx)
+        .await?;
+
+    set_schema_version(&mut tx, 5).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// v6: full-text search over message content and chat titles. Index rows
+// share their source row's rowid and are kept in step by triggers
+async fn migrate_to_v6(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let statements = vec![
+        r#"
+        CREATE VIRTUAL TABLE IF NOT EXISTS message_search USING fts5(
+            content,
+            message_id UNINDEXED,
+            chat_id UNINDEXED,
+            tokenize = 'porter unicode61 remove_diacritics 2'
+        )
+        "#,
+        r#"
+        CREATE VIRTUAL TABLE IF NOT EXISTS chat_search USING fts5(
+            title,
+            chat_id UNINDEXED,
+            tokenize = 'porter unicode61 remove_diacritics 2'
+        )
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS messages_search_insert AFTER INSERT ON messages BEGIN
+            INSERT INTO message_search (rowid, content, message_id, chat_id)
+            VALUES (new.rowid, new.content, new.id, new.chat_id);
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS messages_search_delete AFTER DELETE ON messages BEGIN
+            DELETE FROM message_search WHERE rowid = old.rowid;
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS messages_search_update AFTER UPDATE OF content, chat_id ON messages BEGIN
+            DELETE FROM message_search WHERE rowid = old.rowid;
+            INSERT INTO message_search (rowid, content, message_id, chat_id)
+            VALUES (new.rowid, new.content, new.id, new.chat_id);
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS chats_search_insert AFTER INSERT ON chats BEGIN
+            INSERT INTO chat_search (rowid, title, chat_id) VALUES (new.rowid, new.title, new.id);
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS chats_search_delete AFTER DELETE ON chats BEGIN
+            DELETE FROM chat_search WHERE rowid = old.rowid;
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS chats_search_update AFTER UPDATE OF title ON chats BEGIN
+            DELETE FROM chat_search WHERE rowid = old.rowid;
+            INSERT INTO chat_search (rowid, title, chat_id) VALUES (new.rowid, new.title, new.id);
+        END
+        "#,
+    ];
+
+    for statement in statements {
+        sqlx::query(statement).execute(&mut *tx).await?;
+    }
+
+    // Index what the database already holds
+    crate::database::search::fill_index(&mut tx).await?;
+
+    set_schema_version(&mut tx, 6).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// v7: messages form a tree so edits and regenerated replies branch off
+// earlier turns, and each chat remembers the branch it shows. Existing chats
+// become a single branch in message order
+async fn migrate_to_v7(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("ALTER TABLE messages ADD COLUMN parent_id TEXT REFERENCES messages(id) ON DELETE CASCADE")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("ALTER TABLE chats ADD COLUMN active_message_id TEXT")
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE messages
+        SET parent_id = (
+            SELECT previous.parent_id
+            FROM (
+                SELECT id, LAG(id) OVER (PARTITION BY chat_id ORDER BY created_at, rowid) AS parent_id
+                FROM messages
+            ) AS previous
+            WHERE previous.id = messages.id
+        )
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        UPDATE chats
+        SET active_message_id = (
+            SELECT id FROM messages
+            WHERE messages.chat_id = chats.id
+            ORDER BY created_at DESC, rowid DESC
+            LIMIT 1
+        )
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_messages_parent_id ON messages(parent_id)")
+        .execute(&mut *tx)
+        .await?;
+
+    set_schema_version(&mut tx, 7).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// v8: generated titles and rolling summaries. Chats that already exist keep
+// the titles they have
+async fn migrate_to_v8(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let statements = vec![
+        "ALTER TABLE chats ADD COLUMN auto_title BOOLEAN NOT NULL DEFAULT TRUE",
+        "ALTER TABLE chats ADD COLUMN summary TEXT",
+        "ALTER TABLE chats ADD COLUMN summary_message_id TEXT",
+        "UPDATE chats SET auto_title = FALSE",
+    ];
+    for statement in statements {
+        sqlx::query(statement).execute(&mut *tx).await?;
+    }
+
+    set_schema_version(&mut tx, 8).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// v9: chats imported from other apps remember the conversation they came
+// from, so importing the same export again skips them
+async fn migrate_to_v9(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let statements = vec![
+        "ALTER TABLE chats ADD COLUMN source TEXT",
+        "ALTER TABLE chats ADD COLUMN source_id TEXT",
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_chats_source ON chats(source, source_id) WHERE source_id IS NOT NULL",
+    ];
+    for statement in statements {
+        sqlx::query(statement).execute(&mut *tx).await?;
+    }
+
+    set_schema_version(&mut tx, 9).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// v10: folders and tags become tables. Chats point at their folder, and
+// `folder_path` is kept as the folder's full path for display. Existing
+// folder paths become folders, and tags on a session are given to its chats
+async fn migrate_to_v10(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let statements = vec![
+        r#"
+        CREATE TABLE IF NOT EXISTS folders (
+            id TEXT PRIMARY KEY,
+            parent_id TEXT REFERENCES folders(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_folders_parent_name ON folders(COALESCE(parent_id, ''), name)",
+        "CREATE INDEX IF NOT EXISTS idx_folders_parent_id ON folders(parent_id)",
+        "ALTER TABLE chats ADD COLUMN folder_id TEXT REFERENCES folders(id) ON DELETE SET NULL",
+        "CREATE INDEX IF NOT EXISTS idx_chats_folder_id ON chats(folder_id)",
+        r#"
+        CREATE TABLE IF NOT EXISTS tags (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            color TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS chat_tags (
+            chat_id TEXT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+            tag_id TEXT NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (chat_id, tag_id)
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_chat_tags_tag_id ON chat_tags(tag_id)",
+        r#"
+        INSERT OR IGNORE INTO tags (id, name)
+        SELECT lower(hex(randomblob(16))), trim(tag.value)
+        FROM sessions, json_each(CASE WHEN json_valid(sessions.tags) THEN sessions.tags END) AS tag
+        WHERE tag.type = 'text' AND trim(tag.value) != ''
+        "#,
+        r#"
+        INSERT OR IGNORE INTO chat_tags (chat_id, tag_id)
+        SELECT c.id, t.id
+        FROM chats c
+        JOIN sessions s ON s.id = c.session_id
+        JOIN json_each(CASE WHEN json_valid(s.tags) THEN s.tags END) AS tag
+        JOIN tags t ON t.name = trim(tag.value)
+        WHERE tag.type = 'text'
+        "#,
+    ];
+    for statement in statements {
+        sqlx::query(statement).execute(&mut *tx).await?;
+    }
+
+    let paths: Vec<String> = sqlx::query_scalar("SELECT DISTINCT folder_path FROM chats WHERE folder_path IS NOT NULL")
+        .fetch_all(&mut *tx)
+        .await?;
+    for path in paths {
+        let folder_id = crate::database::folders::ensure_path(&mut tx, &path).await?;
+        let normalized = crate::database::folders::path_segments(&path).join("/");
+        sqlx::query("UPDATE chats SET folder_id = ?, folder_path = ? WHERE folder_path = ?")
+            .bind(&folder_id)
+            .bind(folder_id.as_ref().map(|_| normalized))
+            .bind(&path)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    set_schema_version(&mut tx, 10).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// v11: smart collections, saved queries listing the chats that match them
+async fn migrate_to_v11(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS collections (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            query TEXT NOT NULL,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    set_schema_version(&mut tx, 11).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// v12: sessions can be archived, and triggers keep their message count and
+// last activity in step with their chats. Chats and sessions left pointing
+// at rows that do not exist get them back, so foreign keys hold again
+async fn migrate_to_v12(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let statements = vec![
+        "ALTER TABLE sessions ADD COLUMN archived_at TIMESTAMP",
+        "INSERT OR IGNORE INTO user_profiles (id, auth_type) VALUES ('local', 'api_key')",
+        "UPDATE sessions SET user_id = 'local' WHERE user_id NOT IN (SELECT id FROM user_profiles)",
+        r#"
+        INSERT OR IGNORE INTO sessions (id, user_id, title)
+        SELECT DISTINCT session_id, 'local', 'Recovered session' FROM chats
+        WHERE session_id NOT IN (SELECT id FROM sessions)
+        "#,
+        r#"
+        UPDATE sessions SET
+            total_messages = (
+                SELECT COUNT(*) FROM messages m JOIN chats c ON c.id = m.chat_id WHERE c.session_id = sessions.id
+            ),
+            last_activity = COALESCE((
+                SELECT c.last_activity FROM chats c WHERE c.session_id = sessions.id
+                ORDER BY julianday(c.last_activity) DESC LIMIT 1
+            ), last_activity)
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS sessions_message_insert AFTER INSERT ON messages BEGIN
+            UPDATE sessions SET
+                total_messages = total_messages + 1,
+                last_activity = CASE WHEN julianday(new.created_at) > julianday(last_activity)
+                                     THEN new.created_at ELSE last_activity END
+            WHERE id = (SELECT session_id FROM chats WHERE id = new.chat_id);
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS sessions_message_delete AFTER DELETE ON messages BEGIN
+            UPDATE sessions SET total_messages = MAX(total_messages - 1, 0)
+            WHERE id = (SELECT session_id FROM chats WHERE id = old.chat_id);
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS sessions_chat_insert AFTER INSERT ON chats BEGIN
+            UPDATE sessions SET
+                last_activity = CASE WHEN julianday(new.last_activity) > julianday(last_activity)
+                                     THEN new.last_activity ELSE last_activity END
+            WHERE id = new.session_id;
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS sessions_chat_move AFTER UPDATE OF session_id ON chats
+        WHEN old.session_id IS NOT new.session_id BEGIN
+            UPDATE sessions SET total_messages = MAX(total_messages - (SELECT COUNT(*) FROM messages WHERE chat_id = new.id), 0)
+            WHERE id = old.session_id;
+            UPDATE sessions SET total_messages = total_messages + (SELECT COUNT(*) FROM messages WHERE chat_id = new.id)
+            WHERE id = new.session_id;
+        END
+        "#,
+    ];
+    for statement in statements {
+        sqlx::query(statement).execute(&mut *tx).await?;
+    }
+
+    set_schema_version(&mut tx, 12).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// Recreates `table` from its current definition with `replacements` applied
+// to it, keeping its rows with their rowids (the search index refers to
+// them), indexes and triggers. SQLite cannot change the constraints of an
+// existing table any other way. Foreign keys must be off
+async fn rebuild_table(conn: &mut SqliteConnection, table: &str, replacements: &[(&str, &str)]) -> Result<()> {
+    let definition: String = sqlx::query_scalar("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?")
+        .bind(table)
+        .fetch_one(&mut *conn)
+        .await?;
+    let dependents: Vec<String> = sqlx::query_scalar(
+        "SELECT sql FROM sqlite_master WHERE tbl_name = ? AND type IN ('index', 'trigger') AND sql IS NOT NULL",
+    )
+    .bind(table)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+        .bind(table)
+        .fetch_all(&mut *conn)
+        .await?;
+    let columns = columns.join(", ");
+
+    // Tables renamed into place are stored with their name quoted
+    let create = if definition.starts_with(&format!("CREATE TABLE \"{}\"", table)) {
+        format!("CREATE TABLE \"{}\" (", table)
+    } else {
+        format!("CREATE TABLE {} (", table)
+    };
+    let create_new = format!("CREATE TABLE {}_new (", table);
+    let mut rebuilt = definition;
+    for (from, to) in std::iter::once((create.as_str(), create_new.as_str())).chain(replacements.iter().copied()) {
+        if !rebuilt.contains(from) {
+            return Err(anyhow::anyhow!("Expected '{}' in the definition of {}", from, table));
+        }
+        rebuilt = rebuilt.replacen(from, to, 1);
+    }
+
+    sqlx::query(&rebuilt).execute(&mut *conn).await?;
+    sqlx::query(&format!("INSERT INTO {0}_new (rowid, {1}) SELECT rowid, {1} FROM {0}", table, columns))
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(&format!("DROP TABLE {}", table)).execute(&mut *conn).await?;
+    sqlx::query(&format!("ALTER TABLE {0}_new RENAME TO {0}", table)).execute(&mut *conn).await?;
+    for statement in dependents {
+        sqlx::query(&statement).execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+// v13: deleting a chat, project or agent takes what belongs to it along
+// (messages, runs) or lets go of it (a project's chats). Chats, projects and
+// agents are first moved to the trash, and a session's message count drops
+// when one of its chats is deleted for good
+async fn migrate_to_v13(pool: &SqlitePool) -> Result<()> {
+    // Foreign keys can only be turned off outside a transaction, and only
+    // for one connection
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await?;
+    let migrated = upgrade_to_v13(&mut conn).await;
+    sqlx::query("PRAGMA foreign_keys = ON").execute(&mut *conn).await?;
+    migrated
+}
+
+async fn upgrade_to_v13(conn: &mut SqliteConnection) -> Result<()> {
+    let mut tx = conn.begin().await?;
+    sqlx::query("PRAGMA legacy_alter_table = ON").execute(&mut *tx).await?;
+
+    // Rows pointing at parents that are gone would fail the check below
+    let cleanup = vec![
+        "UPDATE chats SET project_id = NULL WHERE project_id NOT IN (SELECT id FROM projects)",
+        "DELETE FROM messages WHERE chat_id NOT IN (SELECT id FROM chats)",
+        "DELETE FROM agent_runs WHERE agent_id NOT IN (SELECT id FROM agents)",
+        "DELETE FROM session_analytics WHERE session_id NOT IN (SELECT id FROM sessions)",
+    ];
+    for statement in cleanup {
+        sqlx::query(statement).execute(&mut *tx).await?;
+    }
+
+    rebuild_table(&mut tx, "chats", &[
+        ("REFERENCES sessions(id)", "REFERENCES sessions(id) ON DELETE CASCADE"),
+        ("REFERENCES projects(id)", "REFERENCES projects(id) ON DELETE SET NULL"),
+    ])
+    .await?;
+    rebuild_table(&mut tx, "messages", &[("REFERENCES chats(id)", "REFERENCES chats(id) ON DELETE CASCADE")]).await?;
+    rebuild_table(&mut tx, "agent_runs", &[("REFERENCES agents(id)", "REFERENCES agents(id) ON DELETE CASCADE")]).await?;
+    rebuild_table(&mut tx, "session_analytics", &[("REFERENCES sessions(id)", "REFERENCES sessions(id) ON DELETE CASCADE")])
+        .await?;
+
+    let violations = sqlx::query("PRAGMA foreign_key_check").fetch_all(&mut *tx).await?;
+    if !violations.is_empty() {
+        return Err(anyhow::anyhow!("{} rows break foreign keys after rebuilding tables", violations.len()));
+    }
+
+    sqlx::query("PRAGMA legacy_alter_table = OFF").execute(&mut *tx).await?;
+
+    let statements = vec![
+        "ALTER TABLE chats ADD COLUMN deleted_at TIMESTAMP",
+        "ALTER TABLE projects ADD COLUMN deleted_at TIMESTAMP",
+        "ALTER TABLE agents ADD COLUMN deleted_at TIMESTAMP",
+        "CREATE INDEX IF NOT EXISTS idx_chats_deleted_at ON chats(deleted_at)",
+        r#"
+        CREATE VIEW IF NOT EXISTS trash AS
+        SELECT 'chat' AS kind, id, title AS name, deleted_at FROM chats WHERE deleted_at IS NOT NULL
+        UNION ALL
+        SELECT 'project', id, name, deleted_at FROM projects WHERE deleted_at IS NOT NULL
+        UNION ALL
+        SELECT 'agent', id, name, deleted_at FROM agents WHERE deleted_at IS NOT NULL
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS sessions_chat_delete BEFORE DELETE ON chats BEGIN
+            UPDATE sessions SET total_messages = MAX(total_messages - (SELECT COUNT(*) FROM messages WHERE chat_id = old.id), 0)
+            WHERE id = old.session_id;
+        END
+        "#,
+    ];
+    for statement in statements {
+        sqlx::query(statement).execute(&mut *tx).await?;
+    }
+
+    set_schema_version(&mut tx, 13).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// Key under which v14 keeps JSON column values that were not objects
+const LEGACY_VALUE_KEY: &str = "legacy_value";
+
+// v14: JSON columns and hook triggers are read into typed values. JSON
+// columns holding something other than an object keep it under
+// `legacy_value`, empty ones are cleared, and hooks with a trigger the app
+// does not know become custom events that remember the old trigger in their
+// config. Every change is logged
+async fn migrate_to_v14(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    // (table, column, value for an empty one)
+    let columns = [
+        ("sessions", "metadata", "NULL"),
+        ("projects", "metadata", "NULL"),
+        ("chats", "metadata", "NULL"),
+        ("messages", "metadata", "NULL"),
+        ("agents", "model_config", "'{}'"),
+        ("agents", "schedule_config", "NULL"),
+    ];
+    for (table, column, empty) in columns {
+        let result = sqlx::query(&format!(
+            "UPDATE {table} SET {column} = CASE \
+               WHEN trim({column}) = '' THEN {empty} \
+               WHEN json_valid({column}) THEN json_object(?, json({column})) \
+               ELSE json_object(?, {column}) END \
+             WHERE {column} IS NOT NULL AND CASE WHEN json_valid({column}) THEN json_type({column}) END IS NOT 'object'"
+        ))
+        .bind(LEGACY_VALUE_KEY)
+        .bind(LEGACY_VALUE_KEY)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() > 0 {
+            log::warn!(
+                "{} rows of {}.{} were not JSON objects; kept their values under \"{}\"",
+                result.rows_affected(),
+                table,
+                column,
+                LEGACY_VALUE_KEY
+            );
+        }
+    }
+
+    let known = "('chat_message_sent', 'chat_message_received', 'chat_created', 'agent_run_completed', \
+                 'file_changed', 'schedule', 'api_call', 'custom_event')";
+    let unknown: Vec<(String, String)> = sqlx::query_as(&format!(
+        "SELECT id, trigger_type FROM hooks WHERE trigger_type NOT IN {known}"
+    ))
+    .fetch_all(&mut *tx)
+    .await?;
+    for (hook_id, trigger_type) in &unknown {
+        log::warn!(
+            "Hook {} has unknown trigger '{}'; it becomes a custom event with the trigger kept in its config",
+            hook_id,
+            trigger_type
+        );
+    }
+    sqlx::query(&format!(
+        "UPDATE hooks SET \
+           trigger_config = json_set(CASE WHEN json_valid(trigger_config) AND json_type(trigger_config) = 'object' \
+             THEN trigger_config ELSE '{{}}' END, '$.legacy_trigger_type', trigger_type), \
+           trigger_type = 'custom_event' \
+         WHERE trigger_type NOT IN {known}"
+    ))
+    .execute(&mut *tx)
+    .await?;
+
+    set_schema_version(&mut tx, 14).await?;
+    tx.commit().await?;
+    Ok(())
+}
@@ -1,45 +1,179 @@
+pub mod branches;
+pub mod budgets;
+pub mod collections;
 pub mod connection;
+pub mod export;
+pub mod folders;
+pub mod importers;
+pub mod knowledge;
+pub mod legacy;
 pub mod migrations;
 pub mod models;
+pub mod repositories;
+pub mod search;
+pub mod sessions;
+pub mod settings;
+pub mod summaries;
+pub mod tags;
+pub mod trash;
+pub mod usage;
 
-use sqlx::{SqlitePool, Row};
-use std::path::PathBuf;
+use sqlx::{Connection, SqlitePool, Row};
+use std::path::{Path, PathBuf};
+use connection::ConnectionOptions;
+use repositories::{
+    AgentRepository, ChatRepository, HookRepository, MessageRepository, ProjectRepository, Repositories,
+    RunRepository, SettingsRepository, SqliteRepository,
+};
+use std::sync::Arc;
 use anyhow::Result;
 
+// Connections that prepared statements before a migration altered a table
+// keep describing its old columns, so every open connection drops its cache.
+// One that sat idle while another migrated also still holds the old schema
+// and would prepare new statements against it, so each reads it again.
+// Connections are handed back to the pool in the background, so this waits
+// for all of them rather than taking the idle ones
+async fn clear_cached_statements(pool: &SqlitePool) -> Result<()> {
+    let mut connections = Vec::new();
+    while connections.len() < pool.size() as usize {
+        connections.push(pool.acquire().await?);
+    }
+    for connection in &mut connections {
+        connection.clear_cached_statements().await?;
+        sqlx::query("SELECT COUNT(*) FROM sqlite_master").execute(&mut **connection).await?;
+    }
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
+    repositories: Arc<dyn Repositories>,
 }
 
 impl Database {
     pub async fn new() -> Result<Self> {
-        let db_path = Self::get_database_path()?;
-        
+        Self::open(&Self::get_database_path()?).await
+    }
+
+    /// Opens (creating if needed) the database at `db_path` and migrates it.
+    pub async fn open(db_path: &Path) -> Result<Self> {
         // Ensure the directory exists
         if let Some(parent) = db_path.parent() {
             tokio::fs::create_dir_all(parent).await?;
         }
 
-        let database_url = format!("sqlite:{}", db_path.display());
-        
-        let pool = SqlitePool::connect(&database_url).await?;
+        let pool = connection::create_pool(ConnectionOptions {
+            database_url: format!("sqlite:{}", db_path.display()),
+            ..Default::default()
+        })
+        .await?;
         
         // Run migrations
         migrations::run_migrations(&pool).await?;
-        
-        Ok(Database { pool })
+        clear_cached_statements(&pool).await?;
+        sessions::ensure_defaults(&pool).await?;
+
+        Ok(Self::with_pool(pool))
+    }
+
+    /// Migrated database kept in memory until it is dropped, for tests.
+    pub async fn in_memory() -> Result<Self> {
+        // Each connection to `sqlite::memory:` opens a database of its own,
+        // so the pool holds on to a single one
+        let pool = connection::create_pool(ConnectionOptions {
+            database_url: "sqlite::memory:".to_string(),
+            max_connections: 1,
+            idle_timeout: None,
+            max_lifetime: None,
+            ..Default::default()
+        })
+        .await?;
+        migrations::run_migrations(&pool).await?;
+        clear_cached_statements(&pool).await?;
+        sessions::ensure_defaults(&pool).await?;
+
+        Ok(Self::with_pool(pool))
+    }
+
+    fn with_pool(pool: SqlitePool) -> Self {
+        let repositories = Arc::new(SqliteRepository::new(pool.clone()));
+        Database { pool, repositories }
+    }
+
+    /// Serves the repository accessors from `repositories` instead of the
+    /// SQLite ones. Queries made directly on the pool are unaffected.
+    pub fn with_repositories(mut self, repositories: Arc<dyn Repositories>) -> Self {
+        self.repositories = repositories;
+        self
     }
 
     pub fn pool(&self) -> &SqlitePool {
         &self.pool
     }
 
-    fn get_database_path() -> Result<PathBuf> {
+    pub fn chats(&self) -> &dyn ChatRepository {
+        self.repositories.chats()
+    }
+
+    pub fn messages(&self) -> &dyn MessageRepository {
+        self.repositories.messages()
+    }
+
+    pub fn projects(&self) -> &dyn ProjectRepository {
+        self.repositories.projects()
+    }
+
+    pub fn agents(&self) -> &dyn AgentRepository {
+        self.repositories.agents()
+    }
+
+    pub fn runs(&self) -> &dyn RunRepository {
+        self.repositories.runs()
+    }
+
+    pub fn hooks(&self) -> &dyn HookRepository {
+        self.repositories.hooks()
+    }
+
+    pub fn settings(&self) -> &dyn SettingsRepository {
+        self.repositories.settings()
+    }
+
+    pub fn get_database_path() -> Result<PathBuf> {
         // Use /tmp for database in development to avoid permission issues
         let db_path = std::path::Path::new("/tmp/cloddo.db");
         Ok(db_path.to_path_buf())
     }
 
+    /// Makes sure `session_id` exists, creating it under the local profile.
+    ///
+    /// Only for chats saved by earlier versions, which refer to sessions that
+    /// were never inserted. New chats need a session created beforehand.
+    pub async fn ensure_session(&self, session_id: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO user_profiles (id, auth_type)
+            VALUES ('local', 'api_key')
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO sessions (id, user_id, title)
+            VALUES (?, 'local', 'Default session')
+            "#,
+        )
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
     // Health check method
     pub async fn health_check(&self) -> Result<bool> {
         let row = sqlx::query("SELECT 1 as test")
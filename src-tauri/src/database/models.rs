@@ -2,13 +2,90 @@ use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use sqlx::FromRow;
+use sqlx::types::Json;
 use std::collections::HashMap;
+use crate::integrations::attachments::AttachmentRef;
+
+/// JSON object stored in a column, such as a row's `metadata`.
+pub type JsonMap = HashMap<String, serde_json::Value>;
+
+/// Column value for a JSON object holding `entries`.
+pub fn json_map<'a>(entries: impl IntoIterator<Item = (&'a str, serde_json::Value)>) -> Json<JsonMap> {
+    Json(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+// How a profile signs in to Anthropic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum AuthType {
+    AnthropicOauth,
+    ApiKey,
+}
+
+// Author of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+    System,
+}
+
+impl Role {
+    /// Name used by the database and the Anthropic API.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::System => "system",
+        }
+    }
+}
+
+// Progress of an agent run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum RunStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+}
+
+// Event that fires a hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum TriggerType {
+    ChatMessageSent,
+    ChatMessageReceived,
+    ChatCreated,
+    AgentRunCompleted,
+    FileChanged,
+    Schedule,
+    ApiCall,
+    CustomEvent,
+}
+
+// How a setting's value is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum SettingType {
+    String,
+    Number,
+    Boolean,
+    Json,
+}
 
 // User Profile
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct UserProfile {
     pub id: String,
-    pub auth_type: String, // 'anthropic_oauth' | 'api_key'
+    pub auth_type: AuthType,
     pub anthropic_user_id: Option<String>,
     pub subscription_tier: Option<String>,
     pub api_key_hash: Option<String>,
@@ -30,7 +107,9 @@ pub struct Session {
     pub token_usage: i32,
     pub last_activity: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
-    pub metadata: Option<String>, // JSON string
+    pub metadata: Option<Json<JsonMap>>,
+    #[serde(default)]
+    pub archived_at: Option<DateTime<Utc>>, // hidden from the session list when set
 }
 
 // Session Analytics
@@ -44,6 +123,61 @@ pub struct SessionAnalytics {
     pub date: String, // DATE string
 }
 
+// API Usage (one row per Anthropic API call)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ApiUsage {
+    pub id: String,
+    pub source: String, // 'chat' | 'agent' | 'hook'
+    pub session_id: Option<String>,
+    pub chat_id: Option<String>,
+    pub project_id: Option<String>,
+    pub agent_id: Option<String>,
+    pub agent_run_id: Option<String>,
+    pub hook_id: Option<String>,
+    pub model: String,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_creation_input_tokens: i64,
+    pub cache_read_input_tokens: i64,
+    pub cost: f64,
+    pub date: String, // DATE string
+    pub created_at: DateTime<Utc>,
+}
+
+// Spending budget
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Budget {
+    pub id: String,
+    pub name: String,
+    pub scope: String, // 'global' | 'project' | 'agent'
+    pub scope_id: Option<String>,
+    pub period: String, // 'daily' | 'monthly'
+    pub limit_usd: f64,
+    pub warn_threshold: f64,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// Budget warning or blocked request
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct BudgetEvent {
+    pub id: String,
+    pub budget_id: String,
+    pub kind: String, // 'warning' | 'blocked'
+    pub period_start: String, // DATE string
+    pub spent: f64,
+    pub limit_usd: f64,
+    pub source: String,
+    pub session_id: Option<String>,
+    pub chat_id: Option<String>,
+    pub project_id: Option<String>,
+    pub agent_id: Option<String>,
+    pub agent_run_id: Option<String>,
+    pub hook_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
 // Project
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Project {
@@ -53,6 +187,24 @@ pub struct Project {
     pub color: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub metadata: Option<Json<JsonMap>>,
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>, // in the trash since then
+}
+
+// Document attached to a project as knowledge
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ProjectDocument {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    pub mime_type: String,
+    pub size_bytes: i64,
+    pub content_hash: String, // SHA-256 of the original file
+    pub extracted_text: String,
+    pub token_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 // Chat
@@ -67,7 +219,64 @@ pub struct Chat {
     pub last_activity: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
-    pub metadata: Option<String>, // JSON string
+    pub metadata: Option<Json<JsonMap>>,
+    pub active_message_id: Option<String>, // leaf of the branch being shown
+    #[serde(default)]
+    pub auto_title: bool, // title may still be replaced by a generated one
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub summary_message_id: Option<String>, // last message the summary covers
+    #[serde(default)]
+    pub source: Option<String>, // app an imported chat came from
+    #[serde(default)]
+    pub source_id: Option<String>, // the conversation's id in that app
+    #[serde(default)]
+    pub folder_id: Option<String>, // folder_path is this folder's full path
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>, // in the trash since then
+}
+
+// Folder (chats are grouped in a tree of folders)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Folder {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub name: String,
+    pub path: String, // names from the top level down, joined with '/'
+    pub chat_count: i64, // chats directly in this folder
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// Tag
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    pub color: Option<String>,
+    pub chat_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// Smart collection (a saved query over chats)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Collection {
+    pub id: String,
+    pub name: String,
+    pub query: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// Chat, project or agent in the trash
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TrashItem {
+    pub kind: String, // 'chat' | 'project' | 'agent'
+    pub id: String,
+    pub name: String,
+    pub deleted_at: DateTime<Utc>,
 }
 
 // Message
@@ -75,9 +284,10 @@ pub struct Chat {
 pub struct Message {
     pub id: String,
     pub chat_id: String,
-    pub role: String, // 'user' | 'assistant' | 'system'
+    pub parent_id: Option<String>, // previous turn; siblings are alternative branches
+    pub role: Role,
     pub content: String,
-    pub metadata: Option<String>, // JSON string
+    pub metadata: Option<Json<JsonMap>>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -88,11 +298,13 @@ pub struct Agent {
     pub name: String,
     pub description: Option<String>,
     pub system_prompt: String,
-    pub model_config: String, // JSON string
-    pub schedule_config: Option<String>, // JSON string
+    pub model_config: Json<JsonMap>,
+    pub schedule_config: Option<Json<JsonMap>>,
     pub enabled: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>, // in the trash since then
 }
 
 // Agent Run
@@ -100,7 +312,7 @@ pub struct Agent {
 pub struct AgentRun {
     pub id: String,
     pub agent_id: String,
-    pub status: String, // 'pending' | 'running' | 'completed' | 'failed'
+    pub status: RunStatus,
     pub input_data: Option<String>, // JSON string
     pub output_data: Option<String>, // JSON string
     pub error_message: Option<String>,
@@ -114,7 +326,7 @@ pub struct Hook {
     pub id: String,
     pub name: String,
     pub description: Option<String>,
-    pub trigger_type: String,
+    pub trigger_type: TriggerType,
     pub trigger_config: String, // JSON string
     pub action_type: String,
     pub action_config: String, // JSON string
@@ -128,7 +340,8 @@ pub struct Hook {
 pub struct Setting {
     pub key: String,
     pub value: String,
-    pub setting_type: String, // 'string' | 'number' | 'boolean' | 'json'
+    #[sqlx(rename = "type")]
+    pub setting_type: SettingType,
     pub updated_at: DateTime<Utc>,
 }
 
@@ -152,9 +365,11 @@ pub struct UpdateChatRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateMessageRequest {
     pub chat_id: String,
-    pub role: String,
+    pub role: Role,
     pub content: String,
     pub metadata: Option<HashMap<String, serde_json::Value>>,
+    #[serde(default)]
+    pub attachments: Vec<AttachmentRef>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -170,7 +385,7 @@ pub struct CreateAgentRequest {
 pub struct CreateHookRequest {
     pub name: String,
     pub description: Option<String>,
-    pub trigger_type: String,
+    pub trigger_type: TriggerType,
     pub trigger_config: HashMap<String, serde_json::Value>,
     pub action_type: String,
     pub action_config: HashMap<String, serde_json::Value>,
@@ -183,9 +398,27 @@ pub struct CreateProjectRequest {
     pub color: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateBudgetRequest {
+    pub name: String,
+    pub scope: String,
+    pub scope_id: Option<String>,
+    pub period: String,
+    pub limit_usd: f64,
+    pub warn_threshold: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateBudgetRequest {
+    pub name: Option<String>,
+    pub limit_usd: Option<f64>,
+    pub warn_threshold: Option<f64>,
+    pub enabled: Option<bool>,
+}
+
 // Helper functions for ID generation
 impl UserProfile {
-    pub fn new(auth_type: String) -> Self {
+    pub fn new(auth_type: AuthType) -> Self {
         let now = Utc::now();
         Self {
             id: Uuid::new_v4().to_string(),
@@ -215,6 +448,7 @@ impl Session {
             last_activity: now,
             created_at: now,
             metadata: None,
+            archived_at: None,
         }
     }
 }
@@ -233,15 +467,24 @@ impl Chat {
             created_at: now,
             updated_at: now,
             metadata: None,
+            active_message_id: None,
+            auto_title: true,
+            summary: None,
+            summary_message_id: None,
+            source: None,
+            source_id: None,
+            folder_id: None,
+            deleted_at: None,
         }
     }
 }
 
 impl Message {
-    pub fn new(chat_id: String, role: String, content: String) -> Self {
+    pub fn new(chat_id: String, role: Role, content: String) -> Self {
         Self {
             id: Uuid::new_v4().to_string(),
             chat_id,
+            parent_id: None,
             role,
             content,
             metadata: None,
@@ -251,18 +494,19 @@ impl Message {
 }
 
 impl Agent {
-    pub fn new(name: String, system_prompt: String, model_config: HashMap<String, serde_json::Value>) -> Self {
+    pub fn new(name: String, system_prompt: String, model_config: JsonMap) -> Self {
         let now = Utc::now();
         Self {
             id: Uuid::new_v4().to_string(),
             name,
             description: None,
             system_prompt,
-            model_config: serde_json::to_string(&model_config).unwrap_or_default(),
+            model_config: Json(model_config),
             schedule_config: None,
             enabled: true,
             created_at: now,
             updated_at: now,
+            deleted_at: None,
         }
     }
 }
@@ -270,7 +514,7 @@ impl Agent {
 impl Hook {
     pub fn new(
         name: String,
-        trigger_type: String,
+        trigger_type: TriggerType,
         trigger_config: HashMap<String, serde_json::Value>,
         action_type: String,
         action_config: HashMap<String, serde_json::Value>,
@@ -301,6 +545,25 @@ impl Project {
             color: None,
             created_at: now,
             updated_at: now,
+            metadata: None,
+            deleted_at: None,
+        }
+    }
+}
+impl Budget {
+    pub fn new(name: String, scope: String, scope_id: Option<String>, period: String, limit_usd: f64) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name,
+            scope,
+            scope_id,
+            period,
+            limit_usd,
+            warn_threshold: 0.8,
+            enabled: true,
+            created_at: now,
+            updated_at: now,
         }
     }
-}
\ No newline at end of file
+}
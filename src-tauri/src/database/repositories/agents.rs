@@ -0,0 +1,88 @@
+use crate::database::models::{Agent, CreateAgentRequest};
+use crate::database::trash::{self, TrashKind};
+use super::SqliteRepository;
+use async_trait::async_trait;
+use sqlx::types::Json;
+use chrono::Utc;
+use anyhow::Result;
+
+/// Agents and their configuration.
+#[async_trait]
+pub trait AgentRepository: Send + Sync {
+    /// Agents outside the trash, newest first.
+    async fn list(&self) -> Result<Vec<Agent>>;
+    /// The agent, unless it is in the trash.
+    async fn get(&self, id: &str) -> Result<Option<Agent>>;
+    async fn create(&self, agent: &Agent) -> Result<()>;
+    /// Replaces the agent's configuration. Returns `None` if there is no
+    /// such agent or it is in the trash.
+    async fn update(&self, id: &str, request: &CreateAgentRequest) -> Result<Option<Agent>>;
+    /// Returns false if the agent does not exist or is already in the trash.
+    async fn move_to_trash(&self, id: &str) -> Result<bool>;
+}
+
+#[async_trait]
+impl AgentRepository for SqliteRepository {
+    async fn list(&self) -> Result<Vec<Agent>> {
+        let agents = sqlx::query_as::<_, Agent>(
+            "SELECT * FROM agents WHERE deleted_at IS NULL ORDER BY created_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(agents)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Agent>> {
+        let agent = sqlx::query_as::<_, Agent>("SELECT * FROM agents WHERE id = ? AND deleted_at IS NULL")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(agent)
+    }
+
+    async fn create(&self, agent: &Agent) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO agents (id, name, description, system_prompt, model_config, schedule_config, enabled, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&agent.id)
+        .bind(&agent.name)
+        .bind(&agent.description)
+        .bind(&agent.system_prompt)
+        .bind(&agent.model_config)
+        .bind(&agent.schedule_config)
+        .bind(agent.enabled)
+        .bind(agent.created_at)
+        .bind(agent.updated_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn update(&self, id: &str, request: &CreateAgentRequest) -> Result<Option<Agent>> {
+        sqlx::query(
+            r#"
+            UPDATE agents
+            SET name = ?, description = ?, system_prompt = ?, model_config = ?, schedule_config = ?, updated_at = ?
+            WHERE id = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(&request.name)
+        .bind(&request.description)
+        .bind(&request.system_prompt)
+        .bind(Json(&request.model_config))
+        .bind(request.schedule_config.as_ref().map(Json))
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        self.get(id).await
+    }
+
+    async fn move_to_trash(&self, id: &str) -> Result<bool> {
+        trash::move_to_trash(&self.pool, TrashKind::Agent, id).await
+    }
+}
@@ -0,0 +1,180 @@
+use crate::database::folders;
+use crate::database::models::{Chat, JsonMap, UpdateChatRequest};
+use crate::database::tags;
+use crate::database::trash::{self, TrashKind};
+use super::SqliteRepository;
+use async_trait::async_trait;
+use sqlx::types::Json;
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use chrono::Utc;
+use anyhow::Result;
+
+/// Narrows [`ChatRepository::list`]. `folder_path` matches the folder and
+/// all of its subfolders; `tag` matches chats carrying the tag of that name.
+#[derive(Debug, Clone, Default)]
+pub struct ChatFilter {
+    pub session_id: Option<String>,
+    pub project_id: Option<String>,
+    pub folder_path: Option<String>,
+    pub tag: Option<String>,
+    pub limit: Option<i32>,
+    pub offset: Option<i32>,
+}
+
+/// Chats, their place in folders and their metadata.
+#[async_trait]
+pub trait ChatRepository: Send + Sync {
+    /// Chats outside the trash, newest activity first.
+    async fn list(&self, filter: &ChatFilter) -> Result<Vec<Chat>>;
+    /// The chat, unless it is in the trash.
+    async fn get(&self, id: &str) -> Result<Option<Chat>>;
+    /// Stores `chat`, creating the folder at its `folder_path` if needed.
+    /// With `ignore_existing`, a chat already stored under its id is kept.
+    async fn insert(&self, chat: &Chat, ignore_existing: bool) -> Result<()>;
+    /// Applies the fields set in `request`; an empty folder path moves the
+    /// chat to the top level, and metadata keys are merged into the stored
+    /// ones, a null removing the key. Returns `None` if there is no such chat
+    /// or it is in the trash.
+    async fn update(&self, id: &str, request: &UpdateChatRequest) -> Result<Option<Chat>>;
+    async fn set_metadata(&self, id: &str, metadata: &JsonMap) -> Result<()>;
+    /// Returns false if the chat does not exist or is already in the trash.
+    async fn move_to_trash(&self, id: &str) -> Result<bool>;
+}
+
+// Folder at `path`, created if missing, and its normalized path. An empty
+// path is the top level
+async fn resolve_folder(pool: &SqlitePool, path: &str) -> Result<(Option<String>, Option<String>)> {
+    let mut conn = pool.acquire().await?;
+    let folder_id = folders::ensure_path(&mut conn, path).await?;
+    let path = folder_id.as_ref().map(|_| folders::path_segments(path).join("/"));
+    Ok((folder_id, path))
+}
+
+#[async_trait]
+impl ChatRepository for SqliteRepository {
+    async fn list(&self, filter: &ChatFilter) -> Result<Vec<Chat>> {
+        let mut query: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT * FROM chats WHERE deleted_at IS NULL");
+
+        if let Some(session_id) = &filter.session_id {
+            query.push(" AND session_id = ").push_bind(session_id.clone());
+        }
+        if let Some(project_id) = &filter.project_id {
+            query.push(" AND project_id = ").push_bind(project_id.clone());
+        }
+        if let Some(folder_path) = filter.folder_path.as_ref().filter(|path| !folders::path_segments(path).is_empty()) {
+            let mut conn = self.pool.acquire().await?;
+            let Some(folder_id) = folders::find_by_path(&mut conn, folder_path).await? else {
+                return Ok(Vec::new());
+            };
+            folders::push_subtree_filter(&mut query, "folder_id", folder_id);
+        }
+        if let Some(tag) = &filter.tag {
+            tags::push_tag_filter(&mut query, "chats.id", tag.clone());
+        }
+
+        query.push(" ORDER BY last_activity DESC");
+        query.push(" LIMIT ").push_bind(filter.limit.unwrap_or(-1));
+        query.push(" OFFSET ").push_bind(filter.offset.unwrap_or(0));
+
+        let chats = query.build_query_as::<Chat>().fetch_all(&self.pool).await?;
+        Ok(chats)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Chat>> {
+        let chat = sqlx::query_as::<_, Chat>("SELECT * FROM chats WHERE id = ? AND deleted_at IS NULL")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(chat)
+    }
+
+    async fn insert(&self, chat: &Chat, ignore_existing: bool) -> Result<()> {
+        let (folder_id, folder_path) = match &chat.folder_path {
+            Some(path) => resolve_folder(&self.pool, path).await?,
+            None => (None, None),
+        };
+
+        let verb = if ignore_existing { "INSERT OR IGNORE" } else { "INSERT" };
+        sqlx::query(&format!(
+            r#"
+            {} INTO chats (id, session_id, project_id, title, folder_id, folder_path, is_favorite, last_activity, created_at, updated_at, metadata, active_message_id, auto_title)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            verb
+        ))
+        .bind(&chat.id)
+        .bind(&chat.session_id)
+        .bind(&chat.project_id)
+        .bind(&chat.title)
+        .bind(&folder_id)
+        .bind(&folder_path)
+        .bind(chat.is_favorite)
+        .bind(chat.last_activity)
+        .bind(chat.created_at)
+        .bind(chat.updated_at)
+        .bind(&chat.metadata)
+        .bind(&chat.active_message_id)
+        .bind(chat.auto_title)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update(&self, id: &str, request: &UpdateChatRequest) -> Result<Option<Chat>> {
+        let folder = match &request.folder_path {
+            Some(path) => Some(resolve_folder(&self.pool, path).await?),
+            None => None,
+        };
+        let (folder_id, folder_path) = folder.clone().unwrap_or_default();
+        let now = Utc::now();
+
+        let result = sqlx::query(
+            r#"
+            UPDATE chats
+            SET title = COALESCE(?, title),
+                auto_title = auto_title AND ? IS NULL,
+                folder_id = CASE WHEN ? THEN ? ELSE folder_id END,
+                folder_path = CASE WHEN ? THEN ? ELSE folder_path END,
+                is_favorite = COALESCE(?, is_favorite),
+                metadata = CASE WHEN ? IS NULL THEN metadata ELSE json_patch(COALESCE(metadata, '{}'), ?) END,
+                updated_at = ?,
+                last_activity = ?
+            WHERE id = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(&request.title)
+        .bind(&request.title)
+        .bind(folder.is_some())
+        .bind(&folder_id)
+        .bind(folder.is_some())
+        .bind(&folder_path)
+        .bind(request.is_favorite)
+        .bind(request.metadata.as_ref().map(Json))
+        .bind(request.metadata.as_ref().map(Json))
+        .bind(now)
+        .bind(now)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.get(id).await
+    }
+
+    async fn set_metadata(&self, id: &str, metadata: &JsonMap) -> Result<()> {
+        sqlx::query("UPDATE chats SET metadata = ?, updated_at = ? WHERE id = ? AND deleted_at IS NULL")
+            .bind(Json(metadata))
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn move_to_trash(&self, id: &str) -> Result<bool> {
+        trash::move_to_trash(&self.pool, TrashKind::Chat, id).await
+    }
+}
@@ -0,0 +1,89 @@
+use crate::database::models::{CreateHookRequest, Hook};
+use super::SqliteRepository;
+use async_trait::async_trait;
+use chrono::Utc;
+use anyhow::Result;
+
+/// Hooks and the events that fire them.
+#[async_trait]
+pub trait HookRepository: Send + Sync {
+    /// All hooks, newest first.
+    async fn list(&self) -> Result<Vec<Hook>>;
+    async fn get(&self, id: &str) -> Result<Option<Hook>>;
+    async fn create(&self, hook: &Hook) -> Result<()>;
+    /// Replaces the hook's configuration. Returns `None` if there is no such
+    /// hook.
+    async fn update(&self, id: &str, request: &CreateHookRequest) -> Result<Option<Hook>>;
+    /// Returns false if there was no such hook.
+    async fn delete(&self, id: &str) -> Result<bool>;
+}
+
+#[async_trait]
+impl HookRepository for SqliteRepository {
+    async fn list(&self) -> Result<Vec<Hook>> {
+        let hooks = sqlx::query_as::<_, Hook>("SELECT * FROM hooks ORDER BY created_at DESC")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(hooks)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Hook>> {
+        let hook = sqlx::query_as::<_, Hook>("SELECT * FROM hooks WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(hook)
+    }
+
+    async fn create(&self, hook: &Hook) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO hooks (id, name, description, trigger_type, trigger_config, action_type, action_config, enabled, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&hook.id)
+        .bind(&hook.name)
+        .bind(&hook.description)
+        .bind(hook.trigger_type)
+        .bind(&hook.trigger_config)
+        .bind(&hook.action_type)
+        .bind(&hook.action_config)
+        .bind(hook.enabled)
+        .bind(hook.created_at)
+        .bind(hook.updated_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn update(&self, id: &str, request: &CreateHookRequest) -> Result<Option<Hook>> {
+        sqlx::query(
+            r#"
+            UPDATE hooks
+            SET name = ?, description = ?, trigger_type = ?, trigger_config = ?, action_type = ?, action_config = ?, updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&request.name)
+        .bind(&request.description)
+        .bind(request.trigger_type)
+        .bind(serde_json::to_string(&request.trigger_config)?)
+        .bind(&request.action_type)
+        .bind(serde_json::to_string(&request.action_config)?)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        self.get(id).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM hooks WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
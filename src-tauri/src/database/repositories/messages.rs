@@ -0,0 +1,78 @@
+use crate::database::branches;
+use crate::database::models::Message;
+use super::SqliteRepository;
+use async_trait::async_trait;
+use anyhow::Result;
+
+/// Messages and the branches they form within a chat.
+#[async_trait]
+pub trait MessageRepository: Send + Sync {
+    async fn get(&self, id: &str) -> Result<Option<Message>>;
+    /// Stores `message` and makes it the end of the branch its chat shows.
+    async fn insert(&self, message: &Message) -> Result<()>;
+    /// The branch the chat shows, oldest first.
+    async fn active_path(&self, chat_id: &str) -> Result<Vec<Message>>;
+    /// The message and everything before it on its branch, oldest first.
+    async fn path_to(&self, leaf_id: &str) -> Result<Vec<Message>>;
+    /// The message and the alternatives to it, oldest first.
+    async fn siblings(&self, id: &str) -> Result<Vec<Message>>;
+    /// Shows the branch through the message and returns it.
+    async fn switch_branch(&self, id: &str) -> Result<Vec<Message>>;
+}
+
+#[async_trait]
+impl MessageRepository for SqliteRepository {
+    async fn get(&self, id: &str) -> Result<Option<Message>> {
+        let message = sqlx::query_as::<_, Message>("SELECT * FROM messages WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(message)
+    }
+
+    async fn insert(&self, message: &Message) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO messages (id, chat_id, parent_id, role, content, metadata, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&message.id)
+        .bind(&message.chat_id)
+        .bind(&message.parent_id)
+        .bind(message.role)
+        .bind(&message.content)
+        .bind(&message.metadata)
+        .bind(message.created_at)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("UPDATE chats SET active_message_id = ?, last_activity = ?, updated_at = ? WHERE id = ?")
+            .bind(&message.id)
+            .bind(message.created_at)
+            .bind(message.created_at)
+            .bind(&message.chat_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn active_path(&self, chat_id: &str) -> Result<Vec<Message>> {
+        branches::active_path(&self.pool, chat_id).await
+    }
+
+    async fn path_to(&self, leaf_id: &str) -> Result<Vec<Message>> {
+        branches::path_to(&self.pool, leaf_id).await
+    }
+
+    async fn siblings(&self, id: &str) -> Result<Vec<Message>> {
+        branches::siblings(&self.pool, id).await
+    }
+
+    async fn switch_branch(&self, id: &str) -> Result<Vec<Message>> {
+        branches::switch_branch(&self.pool, id).await
+    }
+}
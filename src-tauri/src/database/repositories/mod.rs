@@ -0,0 +1,81 @@
+pub mod agents;
+pub mod chats;
+pub mod hooks;
+pub mod messages;
+pub mod projects;
+pub mod runs;
+pub mod settings;
+
+pub use agents::AgentRepository;
+pub use chats::{ChatFilter, ChatRepository};
+pub use hooks::HookRepository;
+pub use messages::MessageRepository;
+pub use projects::ProjectRepository;
+pub use runs::RunRepository;
+pub use settings::SettingsRepository;
+
+use sqlx::SqlitePool;
+
+/// Access to every repository, as held by [`Database`](crate::database::Database).
+/// Implemented for any type that implements all of them, so tests can hand
+/// the database a stand-in for some or all of its storage.
+pub trait Repositories: Send + Sync {
+    fn chats(&self) -> &dyn ChatRepository;
+    fn messages(&self) -> &dyn MessageRepository;
+    fn projects(&self) -> &dyn ProjectRepository;
+    fn agents(&self) -> &dyn AgentRepository;
+    fn runs(&self) -> &dyn RunRepository;
+    fn hooks(&self) -> &dyn HookRepository;
+    fn settings(&self) -> &dyn SettingsRepository;
+}
+
+impl<T> Repositories for T
+where
+    T: ChatRepository
+        + MessageRepository
+        + ProjectRepository
+        + AgentRepository
+        + RunRepository
+        + HookRepository
+        + SettingsRepository,
+{
+    fn chats(&self) -> &dyn ChatRepository {
+        self
+    }
+
+    fn messages(&self) -> &dyn MessageRepository {
+        self
+    }
+
+    fn projects(&self) -> &dyn ProjectRepository {
+        self
+    }
+
+    fn agents(&self) -> &dyn AgentRepository {
+        self
+    }
+
+    fn runs(&self) -> &dyn RunRepository {
+        self
+    }
+
+    fn hooks(&self) -> &dyn HookRepository {
+        self
+    }
+
+    fn settings(&self) -> &dyn SettingsRepository {
+        self
+    }
+}
+
+/// Every repository, backed by the app's SQLite database.
+#[derive(Clone)]
+pub struct SqliteRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::database::knowledge;
+use crate::database::models::{CreateProjectRequest, JsonMap, Project, ProjectDocument};
+use crate::database::trash::{self, TrashKind};
+use crate::integrations::filesystem::ExtractedDocument;
+use super::SqliteRepository;
+use async_trait::async_trait;
+use sqlx::types::Json;
+use chrono::Utc;
+use anyhow::Result;
+
+/// Projects and the documents attached to them as knowledge.
+#[async_trait]
+pub trait ProjectRepository: Send + Sync {
+    /// Projects outside the trash, newest first.
+    async fn list(&self) -> Result<Vec<Project>>;
+    /// The project, unless it is in the trash.
+    async fn get(&self, id: &str) -> Result<Option<Project>>;
+    async fn create(&self, project: &Project) -> Result<()>;
+    /// Replaces the project's name, description and color. Returns `None` if
+    /// there is no such project or it is in the trash.
+    async fn update(&self, id: &str, request: &CreateProjectRequest) -> Result<Option<Project>>;
+    async fn set_metadata(&self, id: &str, metadata: &JsonMap) -> Result<()>;
+    /// Returns false if the project does not exist or is already in the trash.
+    async fn move_to_trash(&self, id: &str) -> Result<bool>;
+
+    /// Attaches `document`, or returns the one already attached with the
+    /// same content.
+    async fn add_document(&self, project_id: &str, document: ExtractedDocument) -> Result<ProjectDocument>;
+    /// Documents of the project, oldest first.
+    async fn documents(&self, project_id: &str) -> Result<Vec<ProjectDocument>>;
+    /// Returns false if there was no such document.
+    async fn delete_document(&self, id: &str) -> Result<bool>;
+    /// Estimated tokens of all the project's documents.
+    async fn knowledge_tokens(&self, project_id: &str) -> Result<i64>;
+}
+
+#[async_trait]
+impl ProjectRepository for SqliteRepository {
+    async fn list(&self) -> Result<Vec<Project>> {
+        let projects = sqlx::query_as::<_, Project>(
+            "SELECT * FROM projects WHERE deleted_at IS NULL ORDER BY created_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(projects)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Project>> {
+        let project = sqlx::query_as::<_, Project>("SELECT * FROM projects WHERE id = ? AND deleted_at IS NULL")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(project)
+    }
+
+    async fn create(&self, project: &Project) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO projects (id, name, description, color, created_at, updated_at, metadata)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&project.id)
+        .bind(&project.name)
+        .bind(&project.description)
+        .bind(&project.color)
+        .bind(project.created_at)
+        .bind(project.updated_at)
+        .bind(&project.metadata)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn update(&self, id: &str, request: &CreateProjectRequest) -> Result<Option<Project>> {
+        sqlx::query(
+            r#"
+            UPDATE projects
+            SET name = ?, description = ?, color = ?, updated_at = ?
+            WHERE id = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(&request.name)
+        .bind(&request.description)
+        .bind(&request.color)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        self.get(id).await
+    }
+
+    async fn set_metadata(&self, id: &str, metadata: &JsonMap) -> Result<()> {
+        sqlx::query("UPDATE projects SET metadata = ?, updated_at = ? WHERE id = ? AND deleted_at IS NULL")
+            .bind(Json(metadata))
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn move_to_trash(&self, id: &str) -> Result<bool> {
+        trash::move_to_trash(&self.pool, TrashKind::Project, id).await
+    }
+
+    async fn add_document(&self, project_id: &str, document: ExtractedDocument) -> Result<ProjectDocument> {
+        knowledge::add_document(&self.pool, project_id, document).await
+    }
+
+    async fn documents(&self, project_id: &str) -> Result<Vec<ProjectDocument>> {
+        knowledge::list_documents(&self.pool, project_id).await
+    }
+
+    async fn delete_document(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM project_documents WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn knowledge_tokens(&self, project_id: &str) -> Result<i64> {
+        let total_tokens = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(token_count), 0) FROM project_documents WHERE project_id = ?",
+        )
+        .bind(project_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(total_tokens)
+    }
+}
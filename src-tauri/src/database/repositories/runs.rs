@@ -0,0 +1,92 @@
+use crate::database::models::{AgentRun, JsonMap, RunStatus};
+use super::SqliteRepository;
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+use anyhow::Result;
+
+/// Runs of agents, from pending to completed or failed.
+#[async_trait]
+pub trait RunRepository: Send + Sync {
+    /// Records a pending run of the agent.
+    async fn create(&self, agent_id: &str, input_data: Option<&JsonMap>) -> Result<AgentRun>;
+    async fn get(&self, id: &str) -> Result<Option<AgentRun>>;
+    /// Runs of the agent, most recently started first.
+    async fn list_for_agent(&self, agent_id: &str) -> Result<Vec<AgentRun>>;
+    /// Ends the run as completed with `output`.
+    async fn complete(&self, id: &str, output: &JsonMap) -> Result<()>;
+    /// Ends the run as failed with `error`.
+    async fn fail(&self, id: &str, error: &str) -> Result<()>;
+}
+
+#[async_trait]
+impl RunRepository for SqliteRepository {
+    async fn create(&self, agent_id: &str, input_data: Option<&JsonMap>) -> Result<AgentRun> {
+        let run = AgentRun {
+            id: Uuid::new_v4().to_string(),
+            agent_id: agent_id.to_string(),
+            status: RunStatus::Pending,
+            input_data: input_data.map(serde_json::to_string).transpose()?,
+            output_data: None,
+            error_message: None,
+            started_at: Some(Utc::now()),
+            completed_at: None,
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO agent_runs (id, agent_id, status, input_data, started_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&run.id)
+        .bind(&run.agent_id)
+        .bind(run.status)
+        .bind(&run.input_data)
+        .bind(run.started_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(run)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<AgentRun>> {
+        let run = sqlx::query_as::<_, AgentRun>("SELECT * FROM agent_runs WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(run)
+    }
+
+    async fn list_for_agent(&self, agent_id: &str) -> Result<Vec<AgentRun>> {
+        let runs = sqlx::query_as::<_, AgentRun>(
+            "SELECT * FROM agent_runs WHERE agent_id = ? ORDER BY started_at DESC",
+        )
+        .bind(agent_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(runs)
+    }
+
+    async fn complete(&self, id: &str, output: &JsonMap) -> Result<()> {
+        sqlx::query("UPDATE agent_runs SET status = ?, output_data = ?, completed_at = ? WHERE id = ?")
+            .bind(RunStatus::Completed)
+            .bind(serde_json::to_string(output)?)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn fail(&self, id: &str, error: &str) -> Result<()> {
+        sqlx::query("UPDATE agent_runs SET status = ?, error_message = ?, completed_at = ? WHERE id = ?")
+            .bind(RunStatus::Failed)
+            .bind(error)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use crate::database::models::Setting;
+use super::SqliteRepository;
+use async_trait::async_trait;
+use anyhow::Result;
+use sqlx::SqliteConnection;
+
+/// Rows of the `settings` table, one per key.
+#[async_trait]
+pub trait SettingsRepository: Send + Sync {
+    /// All stored settings by key.
+    async fn list(&self) -> Result<Vec<Setting>>;
+    async fn get(&self, key: &str) -> Result<Option<Setting>>;
+    /// Inserts the setting or replaces the stored value of its key.
+    async fn set(&self, setting: &Setting) -> Result<()>;
+    /// Returns false if the key was not stored.
+    async fn delete(&self, key: &str) -> Result<bool>;
+    /// Stores `set` and deletes the keys in `delete` together: if any write
+    /// fails, none of them is kept.
+    async fn apply(&self, set: &[Setting], delete: &[String]) -> Result<()>;
+}
+
+async fn upsert(conn: &mut SqliteConnection, setting: &Setting) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO settings (key, value, type, updated_at) VALUES (?, ?, ?, ?)
+        ON CONFLICT(key) DO UPDATE SET value = excluded.value, type = excluded.type, updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(&setting.key)
+    .bind(&setting.value)
+    .bind(setting.setting_type)
+    .bind(setting.updated_at)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn remove(conn: &mut SqliteConnection, key: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM settings WHERE key = ?")
+        .bind(key)
+        .execute(conn)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[async_trait]
+impl SettingsRepository for SqliteRepository {
+    async fn list(&self) -> Result<Vec<Setting>> {
+        let settings = sqlx::query_as::<_, Setting>("SELECT * FROM settings ORDER BY key")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(settings)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Setting>> {
+        let setting = sqlx::query_as::<_, Setting>("SELECT * FROM settings WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(setting)
+    }
+
+    async fn set(&self, setting: &Setting) -> Result<()> {
+        upsert(&mut *self.pool.acquire().await?, setting).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool> {
+        remove(&mut *self.pool.acquire().await?, key).await
+    }
+
+    async fn apply(&self, set: &[Setting], delete: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for setting in set {
+            upsert(&mut tx, setting).await?;
+        }
+        for key in delete {
+            remove(&mut tx, key).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
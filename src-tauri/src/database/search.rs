@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+use chrono::{DateTime, Utc};
+use anyhow::Result;
+use crate::database::folders;
+use crate::database::models::Role;
+use crate::database::tags;
+
+/// Markers around matched terms in result snippets.
+pub const HIGHLIGHT_START: &str = "<mark>";
+pub const HIGHLIGHT_END: &str = "</mark>";
+
+/// Results returned when the caller does not ask for a limit, and the most
+/// it may ask for.
+pub const DEFAULT_SEARCH_LIMIT: i64 = 50;
+pub const MAX_SEARCH_LIMIT: i64 = 200;
+
+// Tokens of context kept around the match in message snippets
+const SNIPPET_TOKENS: i64 = 24;
+
+/// Narrows a search to part of the history. Dates are `YYYY-MM-DD` and
+/// inclusive; `folder` matches the folder and everything below it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchFilter {
+    pub project_id: Option<String>,
+    pub folder: Option<String>,
+    pub tag: Option<String>,
+    pub role: Option<Role>,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// What a search result matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum SearchResultKind {
+    Message,
+    Title,
+}
+
+/// A message or chat title matching the query, best matches first.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SearchResult {
+    pub kind: SearchResultKind,
+    pub chat_id: String,
+    pub chat_title: String,
+    pub project_id: Option<String>,
+    pub folder_path: Option<String>,
+    pub message_id: Option<String>,
+    pub role: Option<Role>,
+    /// Matching text with terms wrapped in [`HIGHLIGHT_START`] and
+    /// [`HIGHLIGHT_END`].
+    pub snippet: String,
+    /// BM25 score; lower is a better match.
+    pub rank: f64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Rows in the search index after a rebuild.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchIndexStats {
+    pub messages_indexed: i64,
+    pub chats_indexed: i64,
+}
+
+/// Turns free text into an FTS5 query: every word must appear, the last one
+/// may be a prefix of a longer word, and FTS5 operators are matched
+/// literally. Returns `None` when there is nothing to search for.
+pub fn to_match_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+
+    let (last, rest) = terms.split_last()?;
+    let mut query = rest.to_vec();
+    query.push(format!("{}*", last));
+    Some(query.join(" "))
+}
+
+// Conditions on the chat (`c`) shared by message and title matches.
+// `folder_id` is the resolved `filter.folder`
+fn push_chat_filters(query: &mut QueryBuilder<Sqlite>, filter: &SearchFilter, folder_id: Option<&str>, date_column: &str) {
+    query.push(" AND c.deleted_at IS NULL");
+    if let Some(project_id) = &filter.project_id {
+        query.push(" AND c.project_id = ").push_bind(project_id.clone());
+    }
+    if let Some(folder_id) = folder_id {
+        folders::push_subtree_filter(query, "c.folder_id", folder_id.to_string());
+    }
+    if let Some(tag) = &filter.tag {
+        tags::push_tag_filter(query, "c.id", tag.clone());
+    }
+    if let Some(start_date) = &filter.start_date {
+        query
+            .push(format!(" AND date({}) >= ", date_column))
+            .push_bind(start_date.clone());
+    }
+    if let Some(end_date) = &filter.end_date {
+        query
+            .push(format!(" AND date({}) <= ", date_column))
+            .push_bind(end_date.clone());
+    }
+}
+
+/// Searches message content and chat titles for `input`.
+pub async fn search(pool: &SqlitePool, input: &str, filter: &SearchFilter) -> Result<Vec<SearchResult>> {
+    let Some(match_query) = to_match_query(input) else {
+        return Ok(Vec::new());
+    };
+    let limit = filter.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT);
+    let offset = filter.offset.unwrap_or(0).max(0);
+
+    // A folder that doesn't exist holds nothing
+    let folder_id = match filter.folder.as_deref().filter(|path| !folders::path_segments(path).is_empty()) {
+        Some(path) => {
+            let mut conn = pool.acquire().await?;
+            match folders::find_by_path(&mut conn, path).await? {
+                Some(folder_id) => Some(folder_id),
+                None => return Ok(Vec::new()),
+            }
+        }
+        None => None,
+    };
+
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+        r#"
+        SELECT 'message' AS kind, c.id AS chat_id, c.title AS chat_title, c.project_id, c.folder_path,
+               m.id AS message_id, m.role,
+               snippet(message_search, 0, '{start}', '{end}', '…', {tokens}) AS snippet,
+               bm25(message_search) AS rank, m.created_at AS created_at
+        FROM message_search
+        JOIN messages m ON m.rowid = message_search.rowid AND m.id = message_search.message_id
+        JOIN chats c ON c.id = m.chat_id
+        WHERE message_search MATCH "#,
+        start = HIGHLIGHT_START,
+        end = HIGHLIGHT_END,
+        tokens = SNIPPET_TOKENS,
+    ));
+    query.push_bind(match_query.clone());
+    if let Some(role) = &filter.role {
+        query.push(" AND m.role = ").push_bind(*role);
+    }
+    push_chat_filters(&mut query, filter, folder_id.as_deref(), "m.created_at");
+
+    // Titles have no role, so a role filter leaves only messages
+    if filter.role.is_none() {
+        query.push(format!(
+            r#"
+            UNION ALL
+            SELECT 'title', c.id, c.title, c.project_id, c.folder_path, NULL, NULL,
+                   highlight(chat_search, 0, '{start}', '{end}'),
+                   bm25(chat_search), c.created_at
+            FROM chat_search
+            JOIN chats c ON c.rowid = chat_search.rowid AND c.id = chat_search.chat_id
+            WHERE chat_search MATCH "#,
+            start = HIGHLIGHT_START,
+            end = HIGHLIGHT_END,
+        ));
+        query.push_bind(match_query);
+        push_chat_filters(&mut query, filter, folder_id.as_deref(), "c.created_at");
+    }
+
+    query
+        .push(" ORDER BY rank ASC, created_at DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    let results = query.build_query_as::<SearchResult>().fetch_all(pool).await?;
+    Ok(results)
+}
+
+/// Re-indexes every message and chat title, for databases whose index is
+/// missing rows or out of step with their tables.
+pub async fn rebuild_index(pool: &SqlitePool) -> Result<SearchIndexStats> {
+    let mut tx = pool.begin().await?;
+    let stats = fill_index(&mut tx).await?;
+    tx.commit().await?;
+
+    log::info!(
+        "Search index rebuilt with {} messages and {} chats",
+        stats.messages_indexed,
+        stats.chats_indexed
+    );
+    Ok(stats)
+}
+
+/// Replaces the index contents on `conn`, leaving the caller's transaction
+/// open.
+pub(crate) async fn fill_index(conn: &mut SqliteConnection) -> Result<SearchIndexStats> {
+    sqlx::query("DELETE FROM message_search").execute(&mut *conn).await?;
+    let messages = sqlx::query(
+        r#"
+        INSERT INTO message_search (rowid, content, message_id, chat_id)
+        SELECT rowid, content, id, chat_id FROM messages
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("DELETE FROM chat_search").execute(&mut *conn).await?;
+    let chats = sqlx::query("INSERT INTO chat_search (rowid, title, chat_id) SELECT rowid, title, id FROM chats")
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("INSERT INTO message_search (message_search) VALUES ('optimize')")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("INSERT INTO chat_search (chat_search) VALUES ('optimize')")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(SearchIndexStats {
+        messages_indexed: messages.rows_affected() as i64,
+        chats_indexed: chats.rows_affected() as i64,
+    })
+}
@@ -0,0 +1,202 @@
+use crate::database::models::{Session, UserProfile};
+use sqlx::SqlitePool;
+use chrono::Utc;
+use anyhow::Result;
+
+/// Profile everything belongs to until accounts are linked.
+pub const LOCAL_PROFILE_ID: &str = "local";
+
+/// Session created on first launch, which the frontend starts in.
+pub const DEFAULT_SESSION_ID: &str = "default-session";
+
+// Preference holding the session the user last switched to
+const ACTIVE_SESSION_KEY: &str = "$.activeSessionId";
+
+fn validate_title(title: &str) -> Result<String> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err(anyhow::anyhow!("Session title cannot be empty"));
+    }
+    Ok(title.to_string())
+}
+
+/// Creates the local profile and the default session when they are missing,
+/// as on first launch.
+pub async fn ensure_defaults(pool: &SqlitePool) -> Result<()> {
+    sqlx::query("INSERT OR IGNORE INTO user_profiles (id, auth_type) VALUES (?, 'api_key')")
+        .bind(LOCAL_PROFILE_ID)
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO sessions (id, user_id, title)
+        SELECT ?, ?, 'Default session'
+        WHERE NOT EXISTS (SELECT 1 FROM sessions)
+        "#,
+    )
+    .bind(DEFAULT_SESSION_ID)
+    .bind(LOCAL_PROFILE_ID)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn profile(pool: &SqlitePool) -> Result<UserProfile> {
+    let profile = sqlx::query_as::<_, UserProfile>("SELECT * FROM user_profiles WHERE id = ?")
+        .bind(LOCAL_PROFILE_ID)
+        .fetch_optional(pool)
+        .await?;
+    profile.ok_or_else(|| anyhow::anyhow!("Profile not found"))
+}
+
+/// Merges `preferences` into the profile's preferences. Keys set to `null`
+/// are removed.
+pub async fn update_preferences(pool: &SqlitePool, preferences: &serde_json::Value) -> Result<UserProfile> {
+    if !preferences.is_object() {
+        return Err(anyhow::anyhow!("Preferences must be a JSON object"));
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE user_profiles
+        SET preferences = json_patch(CASE WHEN json_valid(preferences) THEN preferences ELSE '{}' END, ?), updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(preferences.to_string())
+    .bind(Utc::now())
+    .bind(LOCAL_PROFILE_ID)
+    .execute(pool)
+    .await?;
+
+    profile(pool).await
+}
+
+/// Sessions by most recent activity, leaving out archived ones unless
+/// `include_archived`.
+pub async fn list(pool: &SqlitePool, include_archived: bool) -> Result<Vec<Session>> {
+    let sessions = sqlx::query_as::<_, Session>(
+        r#"
+        SELECT * FROM sessions
+        WHERE ? OR archived_at IS NULL
+        ORDER BY julianday(last_activity) DESC, created_at DESC
+        "#,
+    )
+    .bind(include_archived)
+    .fetch_all(pool)
+    .await?;
+    Ok(sessions)
+}
+
+pub async fn get(pool: &SqlitePool, session_id: &str) -> Result<Option<Session>> {
+    let session = sqlx::query_as::<_, Session>("SELECT * FROM sessions WHERE id = ?")
+        .bind(session_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(session)
+}
+
+async fn get_required(pool: &SqlitePool, session_id: &str) -> Result<Session> {
+    get(pool, session_id).await?.ok_or_else(|| anyhow::anyhow!("Session not found"))
+}
+
+/// Fails unless `session_id` exists and is not archived, so chats can be
+/// added to it.
+pub async fn require_open(pool: &SqlitePool, session_id: &str) -> Result<Session> {
+    let session = get_required(pool, session_id).await?;
+    if session.archived_at.is_some() {
+        return Err(anyhow::anyhow!("Session '{}' is archived", session.title));
+    }
+    Ok(session)
+}
+
+pub async fn create(pool: &SqlitePool, title: &str) -> Result<Session> {
+    let session = Session::new(LOCAL_PROFILE_ID.to_string(), validate_title(title)?);
+    sqlx::query("INSERT INTO sessions (id, user_id, title, last_activity, created_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(&session.id)
+        .bind(&session.user_id)
+        .bind(&session.title)
+        .bind(session.last_activity)
+        .bind(session.created_at)
+        .execute(pool)
+        .await?;
+    Ok(session)
+}
+
+pub async fn rename(pool: &SqlitePool, session_id: &str, title: &str) -> Result<Session> {
+    let title = validate_title(title)?;
+    let result = sqlx::query("UPDATE sessions SET title = ? WHERE id = ?")
+        .bind(&title)
+        .bind(session_id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(anyhow::anyhow!("Session not found"));
+    }
+    get_required(pool, session_id).await
+}
+
+/// Archives a session, or brings it back when `archived` is false. Its chats
+/// are kept.
+pub async fn set_archived(pool: &SqlitePool, session_id: &str, archived: bool) -> Result<Session> {
+    let result = sqlx::query("UPDATE sessions SET archived_at = CASE WHEN ? THEN COALESCE(archived_at, ?) END WHERE id = ?")
+        .bind(archived)
+        .bind(Utc::now())
+        .bind(session_id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(anyhow::anyhow!("Session not found"));
+    }
+    get_required(pool, session_id).await
+}
+
+/// Session the user works in: the one they last switched to, else the most
+/// recently active one. `None` when every session is archived.
+pub async fn active(pool: &SqlitePool) -> Result<Option<Session>> {
+    let chosen: Option<String> = sqlx::query_scalar(
+        "SELECT json_extract(preferences, ?) FROM user_profiles WHERE id = ? AND json_valid(preferences)",
+    )
+    .bind(ACTIVE_SESSION_KEY)
+    .bind(LOCAL_PROFILE_ID)
+    .fetch_optional(pool)
+    .await?
+    .flatten();
+
+    // A chosen session that was deleted or archived since falls back
+    if let Some(session_id) = chosen {
+        if let Some(session) = get(pool, &session_id).await?.filter(|session| session.archived_at.is_none()) {
+            return Ok(Some(session));
+        }
+    }
+    Ok(list(pool, false).await?.into_iter().next())
+}
+
+/// The [`active`] session, creating a new one when every session is
+/// archived.
+pub async fn ensure_active(pool: &SqlitePool) -> Result<Session> {
+    match active(pool).await? {
+        Some(session) => Ok(session),
+        None => create(pool, "Default session").await,
+    }
+}
+
+/// Makes `session_id` the active session.
+pub async fn switch(pool: &SqlitePool, session_id: &str) -> Result<Session> {
+    let session = require_open(pool, session_id).await?;
+    sqlx::query(
+        r#"
+        UPDATE user_profiles
+        SET preferences = json_set(CASE WHEN json_valid(preferences) THEN preferences ELSE '{}' END, ?, ?)
+        WHERE id = ?
+        "#,
+    )
+    .bind(ACTIVE_SESSION_KEY)
+    .bind(session_id)
+    .bind(LOCAL_PROFILE_ID)
+    .execute(pool)
+    .await?;
+    Ok(session)
+}
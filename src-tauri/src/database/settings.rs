@@ -0,0 +1,266 @@
+use crate::database::models::{Setting, SettingType};
+use crate::database::repositories::SettingsRepository;
+use crate::database::trash;
+use crate::integrations::generation;
+use crate::integrations::models;
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// What a setting's value must satisfy beyond its type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    Any,
+    /// A string with something other than whitespace in it.
+    NonEmpty,
+    /// One of the listed strings.
+    OneOf(&'static [&'static str]),
+    /// A whole number within the inclusive bounds.
+    Integer(i64, i64),
+    /// A number within the inclusive bounds.
+    Range(f64, f64),
+}
+
+/// A known setting. Keys outside the schema are rejected.
+#[derive(Debug, Clone)]
+pub struct SettingDefinition {
+    pub key: &'static str,
+    pub setting_type: SettingType,
+    /// Value until the setting is changed. `null` leaves the setting out
+    /// until then.
+    pub default: Value,
+    pub rule: Rule,
+    /// Kept in the credential store instead of the settings table.
+    pub secret: bool,
+}
+
+impl SettingDefinition {
+    fn new(key: &'static str, setting_type: SettingType, default: Value) -> Self {
+        Self { key, setting_type, default, rule: Rule::Any, secret: false }
+    }
+
+    fn rule(mut self, rule: Rule) -> Self {
+        self.rule = rule;
+        self
+    }
+
+    fn secret(mut self) -> Self {
+        self.secret = true;
+        self
+    }
+
+    /// Checks `value` against the setting's type and rule.
+    pub fn validate(&self, value: &Value) -> Result<()> {
+        let type_name = match self.setting_type {
+            SettingType::String if !value.is_string() => Some("a string"),
+            SettingType::Number if !value.is_number() => Some("a number"),
+            SettingType::Boolean if !value.is_boolean() => Some("true or false"),
+            SettingType::Json if !value.is_object() && !value.is_array() => Some("an object or a list"),
+            _ => None,
+        };
+        if let Some(type_name) = type_name {
+            bail!("Setting '{}' must be {}", self.key, type_name);
+        }
+
+        match &self.rule {
+            Rule::Any => {}
+            Rule::NonEmpty => {
+                if value.as_str().unwrap_or_default().trim().is_empty() {
+                    bail!("Setting '{}' cannot be empty", self.key);
+                }
+            }
+            Rule::OneOf(allowed) => {
+                if !value.as_str().is_some_and(|value| allowed.contains(&value)) {
+                    bail!("Setting '{}' must be one of: {}", self.key, allowed.join(", "));
+                }
+            }
+            Rule::Integer(min, max) => {
+                if !value.as_i64().is_some_and(|value| (*min..=*max).contains(&value)) {
+                    bail!("Setting '{}' must be a whole number from {} to {}", self.key, min, max);
+                }
+            }
+            Rule::Range(min, max) => {
+                if !value.as_f64().is_some_and(|value| (*min..=*max).contains(&value)) {
+                    bail!("Setting '{}' must be from {} to {}", self.key, min, max);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every setting the app and the settings screen know about.
+pub static SCHEMA: Lazy<Vec<SettingDefinition>> = Lazy::new(|| {
+    use SettingType::{Boolean, Json, Number, String};
+    let setting = SettingDefinition::new;
+    let flag = |key, default: bool| SettingDefinition::new(key, Boolean, json!(default));
+    let shortcut = |key, default: &str| SettingDefinition::new(key, String, json!(default)).rule(Rule::NonEmpty);
+
+    vec![
+        // API access and the defaults chats are generated with
+        setting("api.anthropicApiKey", String, json!("")).secret(),
+        setting("api.anthropicOAuthToken", String, json!("")).secret(),
+        setting("api.anthropicRefreshToken", String, json!("")).secret(),
+        setting("api.authMethod", String, json!("api_key")).rule(Rule::OneOf(&["api_key", "oauth"])),
+        setting("api.anthropicBaseUrl", String, json!("https://api.anthropic.com/v1")).rule(Rule::NonEmpty),
+        setting("api.defaultModel", String, json!(models::DEFAULT_MODEL)).rule(Rule::NonEmpty),
+        setting("api.defaultMaxTokens", Number, json!(generation::DEFAULT_MAX_TOKENS)).rule(Rule::Integer(1, 1_000_000)),
+        setting("api.defaultTemperature", Number, json!(generation::DEFAULT_TEMPERATURE)).rule(Rule::Range(0.0, 1.0)),
+        setting("api.defaultSystemPrompt", String, json!(generation::DEFAULT_SYSTEM_PROMPT)),
+        flag("api.promptCaching", true),
+        setting("api.requestTimeout", Number, json!(30)).rule(Rule::Integer(1, 600)),
+        setting("api.maxRetries", Number, json!(3)).rule(Rule::Integer(0, 10)),
+        setting("api.rateLimit", Json, json!({ "enabled": true, "requestsPerMinute": 50 })),
+        setting("api.proxy", Json, Value::Null),
+        // Titles and summaries generated in the background
+        flag("chat.autoTitle", true),
+        flag("chat.autoSummary", true),
+        setting("chat.summaryModel", String, json!(models::LIGHTWEIGHT_MODEL)).rule(Rule::NonEmpty),
+        // Scheduled backups and trash purges
+        flag("backup.autoBackup", true),
+        setting("backup.intervalHours", Number, json!(24)).rule(Rule::Integer(1, 24 * 30)),
+        setting("backup.retention", Number, json!(7)).rule(Rule::Integer(1, 365)),
+        flag("backup.encrypt", false),
+        setting("trash.retentionDays", Number, json!(trash::DEFAULT_RETENTION_DAYS)).rule(Rule::Integer(0, 3650)),
+        // Settings screen sections
+        setting("general.language", String, json!("en")).rule(Rule::NonEmpty),
+        flag("general.autoSave", true),
+        setting("general.autoSaveInterval", Number, json!(5)).rule(Rule::Integer(1, 1440)),
+        setting("general.defaultChatFolder", String, json!("")),
+        flag("general.showWelcomeMessage", true),
+        flag("general.checkForUpdates", true),
+        flag("general.sendAnalytics", false),
+        setting("appearance.theme", String, json!("system")).rule(Rule::OneOf(&["light", "dark", "system"])),
+        setting("appearance.customTheme", Json, Value::Null),
+        setting("appearance.fontSize", String, json!("medium")).rule(Rule::OneOf(&["small", "medium", "large"])),
+        setting("appearance.fontFamily", String, json!("Inter")).rule(Rule::NonEmpty),
+        setting("appearance.density", String, json!("comfortable")).rule(Rule::OneOf(&["compact", "comfortable", "spacious"])),
+        setting("appearance.sidebarPosition", String, json!("left")).rule(Rule::OneOf(&["left", "right"])),
+        flag("appearance.showStatusBar", true),
+        setting("appearance.customCSS", String, Value::Null),
+        flag("privacy.storeChatHistory", true),
+        flag("privacy.encryptLocalData", true),
+        flag("privacy.clearDataOnExit", false),
+        flag("privacy.shareErrorReports", false),
+        flag("privacy.allowTelemetry", false),
+        setting("privacy.dataRetentionDays", Number, json!(90)).rule(Rule::Integer(1, 36500)),
+        setting("performance.maxChatHistory", Number, json!(1000)).rule(Rule::Integer(1, 1_000_000)),
+        flag("performance.enableVirtualScrolling", true),
+        setting("performance.preloadMessages", Number, json!(50)).rule(Rule::Integer(0, 10_000)),
+        setting("performance.cacheSize", Number, json!(100)).rule(Rule::Integer(1, 100_000)),
+        flag("performance.enableBackgroundSync", true),
+        flag("performance.lowPowerMode", false),
+        shortcut("shortcuts.newChat", "CmdOrCtrl+N"),
+        shortcut("shortcuts.searchChats", "CmdOrCtrl+F"),
+        shortcut("shortcuts.toggleSidebar", "CmdOrCtrl+B"),
+        shortcut("shortcuts.toggleDarkMode", "CmdOrCtrl+D"),
+        shortcut("shortcuts.focusInput", "CmdOrCtrl+L"),
+        shortcut("shortcuts.sendMessage", "CmdOrCtrl+Enter"),
+        shortcut("shortcuts.clearChat", "CmdOrCtrl+Shift+C"),
+        shortcut("shortcuts.exportChat", "CmdOrCtrl+E"),
+        shortcut("shortcuts.settings", "CmdOrCtrl+,"),
+        shortcut("shortcuts.quit", "CmdOrCtrl+Q"),
+    ]
+});
+
+pub fn definition(key: &str) -> Option<&'static SettingDefinition> {
+    SCHEMA.iter().find(|definition| definition.key == key)
+}
+
+/// Keys of the settings kept in the credential store.
+pub fn secret_keys() -> impl Iterator<Item = &'static str> {
+    SCHEMA.iter().filter(|definition| definition.secret).map(|definition| definition.key)
+}
+
+/// Checks a set of changes before any of it is applied. `null` resets a
+/// setting to its default.
+pub fn validate_changes(changes: &HashMap<String, Value>) -> Result<()> {
+    for (key, value) in changes {
+        let definition = definition(key).ok_or_else(|| anyhow!("Unknown setting '{}'", key))?;
+        if !value.is_null() {
+            definition.validate(value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Every setting that has a value, stored or default, secrets left out.
+/// Stored values the schema no longer accepts fall back to the default.
+pub async fn load(repo: &dyn SettingsRepository) -> Result<HashMap<String, Value>> {
+    let mut values: HashMap<String, Value> = SCHEMA
+        .iter()
+        .filter(|definition| !definition.secret && !definition.default.is_null())
+        .map(|definition| (definition.key.to_string(), definition.default.clone()))
+        .collect();
+
+    for setting in repo.list().await? {
+        let Some(definition) = definition(&setting.key).filter(|definition| !definition.secret) else {
+            continue;
+        };
+        match serde_json::from_str::<Value>(&setting.value) {
+            Ok(value) if definition.validate(&value).is_ok() => {
+                values.insert(setting.key, value);
+            }
+            _ => log::warn!("Ignoring stored value of setting '{}'", setting.key),
+        }
+    }
+
+    Ok(values)
+}
+
+/// Applies `changes` on top of the stored settings and returns the settings
+/// whose value changed, with their new values. The changes are written
+/// together, and nothing is written if any of them is invalid; secrets must
+/// go to the credential store instead.
+pub async fn update(repo: &dyn SettingsRepository, changes: &HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+    validate_changes(changes)?;
+    if let Some(key) = changes.keys().find(|key| definition(key).is_some_and(|definition| definition.secret)) {
+        bail!("Setting '{}' is kept in the credential store", key);
+    }
+
+    let current = load(repo).await?;
+    let (mut set, mut delete) = (Vec::new(), Vec::new());
+    let mut changed = HashMap::new();
+    for (key, value) in changes {
+        let definition = definition(key).ok_or_else(|| anyhow!("Unknown setting '{}'", key))?;
+        if value.is_null() {
+            delete.push(key.clone());
+        } else {
+            set.push(Setting {
+                key: key.clone(),
+                value: serde_json::to_string(value)?,
+                setting_type: definition.setting_type,
+                updated_at: Utc::now(),
+            });
+        }
+
+        let value = if value.is_null() { &definition.default } else { value };
+        if current.get(key).unwrap_or(&Value::Null) != value {
+            changed.insert(key.clone(), value.clone());
+        }
+    }
+
+    repo.apply(&set, &delete).await?;
+    Ok(changed)
+}
+
+/// Stores the settings an earlier version kept in `settings.json`. Unknown
+/// keys, secrets and values the schema rejects are skipped. Returns how many
+/// settings were stored.
+pub async fn import(repo: &dyn SettingsRepository, values: &HashMap<String, Value>) -> Result<usize> {
+    let mut accepted = HashMap::new();
+    for (key, value) in values {
+        let known = definition(key).filter(|definition| !definition.secret);
+        match known.map(|definition| definition.validate(value)) {
+            Some(Ok(())) => {
+                accepted.insert(key.clone(), value.clone());
+            }
+            _ => log::warn!("Skipping setting '{}' from settings.json", key),
+        }
+    }
+
+    update(repo, &accepted).await?;
+    Ok(accepted.len())
+}
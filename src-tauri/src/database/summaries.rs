@@ -0,0 +1,195 @@
+use sqlx::SqlitePool;
+use chrono::Utc;
+use anyhow::Result;
+use crate::database::models::{Chat, Message, Role};
+use crate::integrations::filesystem::estimate_tokens;
+
+/// Title of a chat the user has not named. Only such chats are titled by
+/// the titling job.
+pub const DEFAULT_TITLE: &str = "New Chat";
+
+/// Longest title the titling job stores.
+pub const MAX_TITLE_CHARS: usize = 80;
+
+/// A chat's branch is summarized once it grows past this many messages.
+pub const SUMMARY_THRESHOLD: usize = 20;
+
+/// Newest messages that are always sent as they are, never summarized.
+pub const SUMMARY_KEEP_RECENT: usize = 10;
+
+/// Fewest new messages worth updating an existing summary for.
+pub const SUMMARY_MIN_BATCH: usize = 6;
+
+// Longest excerpt of a single message put into a titling or summary prompt
+const PROMPT_EXCERPT_CHARS: usize = 4000;
+
+fn excerpt(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Asks for a short title for a conversation that opened with `question`
+/// and `answer`.
+pub fn title_prompt(question: &str, answer: &str) -> String {
+    format!(
+        "Write a title of at most six words for the conversation below. Use the conversation's language, no quotes and no trailing punctuation. Reply with the title only.\n\n<user>\n{}\n</user>\n<assistant>\n{}\n</assistant>",
+        excerpt(question, PROMPT_EXCERPT_CHARS),
+        excerpt(answer, PROMPT_EXCERPT_CHARS)
+    )
+}
+
+/// Tidies a model-proposed title: first line only, without quotes, a
+/// "Title:" label or trailing punctuation. Returns `None` when nothing is left.
+pub fn clean_title(raw: &str) -> Option<String> {
+    let line = raw.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = line
+        .strip_prefix("Title:")
+        .or_else(|| line.strip_prefix("title:"))
+        .unwrap_or(line);
+    let title = line
+        .trim()
+        .trim_matches(|c: char| c == '"' || c == '\'' || c == '*' || c == '#')
+        .trim_end_matches(['.', '!', ':', ';'])
+        .trim();
+
+    if title.is_empty() {
+        return None;
+    }
+    Some(match title.char_indices().nth(MAX_TITLE_CHARS) {
+        Some((end, _)) => title[..end].trim_end().to_string(),
+        None => title.to_string(),
+    })
+}
+
+/// Asks for `previous` updated with `messages`, or a fresh summary when
+/// there is none.
+pub fn summary_prompt(previous: Option<&str>, messages: &[Message]) -> String {
+    let mut prompt = String::from(
+        "You keep a running summary of a conversation between a user and an assistant so it can continue after older messages are dropped. Update the summary with the new messages. Keep facts, decisions, constraints, open questions and names of files, functions or commands; drop pleasantries. Write in the third person. Reply with the summary only.\n\n",
+    );
+    if let Some(previous) = previous {
+        prompt.push_str(&format!("<summary>\n{}\n</summary>\n\n", previous));
+    }
+    prompt.push_str("<new_messages>\n");
+    for message in messages {
+        prompt.push_str(&format!(
+            "<{role}>\n{}\n</{role}>\n",
+            excerpt(&message.content, PROMPT_EXCERPT_CHARS),
+            role = message.role.as_str()
+        ));
+    }
+    prompt.push_str("</new_messages>");
+    prompt
+}
+
+/// Messages to fold into a chat's summary.
+#[derive(Debug, Clone)]
+pub struct SummaryPlan {
+    /// The summary to extend, if it still applies to this branch.
+    pub previous: Option<String>,
+    pub messages: Vec<Message>,
+    /// Last message the new summary covers.
+    pub through_message_id: String,
+}
+
+// Position of the chat's summarized prefix on `path`, if the summary
+// belongs to this branch
+fn summary_position(chat: &Chat, path: &[Message]) -> Option<usize> {
+    chat.summary.as_ref()?;
+    let through = chat.summary_message_id.as_deref()?;
+    path.iter().position(|message| message.id == through)
+}
+
+/// Decides whether `path`, the chat's active branch, needs its summary
+/// extended. Everything but the newest [`SUMMARY_KEEP_RECENT`] messages is
+/// summarized, always stopping before a user turn so the messages left
+/// after the summary start the way the API expects.
+pub fn plan_summary(chat: &Chat, path: &[Message]) -> Option<SummaryPlan> {
+    if path.len() <= SUMMARY_THRESHOLD {
+        return None;
+    }
+
+    let mut cut = path.len() - SUMMARY_KEEP_RECENT;
+    while cut > 0 && path[cut].role != Role::User {
+        cut -= 1;
+    }
+
+    // A summary from another branch no longer applies
+    let summarized = summary_position(chat, path);
+    let start = summarized.map_or(0, |position| position + 1);
+    if cut <= start || (summarized.is_some() && cut - start < SUMMARY_MIN_BATCH) {
+        return None;
+    }
+
+    Some(SummaryPlan {
+        previous: summarized.and(chat.summary.clone()),
+        messages: path[start..cut].to_vec(),
+        through_message_id: path[cut - 1].id.clone(),
+    })
+}
+
+/// Rough token count of `messages` as sent to the API.
+pub fn estimate_history_tokens(messages: &[Message]) -> i64 {
+    messages.iter().map(|message| estimate_tokens(&message.content)).sum()
+}
+
+/// Replaces the summarized start of `path` with the chat's summary when the
+/// branch would not fit in `budget` tokens. Returns the summary to send and
+/// the messages still sent as they are.
+///
+/// Before the first summary exists, or when the messages after it are still
+/// too long, the oldest of them are dropped until the rest fits.
+pub fn compress_history(chat: &Chat, path: Vec<Message>, budget: i64) -> (Option<String>, Vec<Message>) {
+    if estimate_history_tokens(&path) <= budget {
+        return (None, path);
+    }
+    let (summary, recent) = match summary_position(chat, &path) {
+        Some(position) => (chat.summary.clone(), path.into_iter().skip(position + 1).collect()),
+        None => (None, path),
+    };
+
+    let budget = budget - summary.as_deref().map_or(0, estimate_tokens);
+    (summary, trim_to_budget(recent, budget))
+}
+
+// Drops the oldest messages until the rest fits in `budget`, always keeping
+// the newest one and starting on a user turn
+fn trim_to_budget(messages: Vec<Message>, budget: i64) -> Vec<Message> {
+    let mut total = estimate_history_tokens(&messages);
+    let mut start = 0;
+    while total > budget && start + 1 < messages.len() {
+        total -= estimate_tokens(&messages[start].content);
+        start += 1;
+    }
+    while start + 1 < messages.len() && messages[start].role != Role::User {
+        start += 1;
+    }
+
+    messages.into_iter().skip(start).collect()
+}
+
+/// Stores a generated title unless the chat was renamed since. Returns
+/// whether the title was stored.
+pub async fn store_title(pool: &SqlitePool, chat_id: &str, title: &str) -> Result<bool> {
+    let result = sqlx::query("UPDATE chats SET title = ?, auto_title = FALSE, updated_at = ? WHERE id = ? AND auto_title")
+        .bind(title)
+        .bind(Utc::now())
+        .bind(chat_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn store_summary(pool: &SqlitePool, chat_id: &str, summary: &str, through_message_id: &str) -> Result<()> {
+    sqlx::query("UPDATE chats SET summary = ?, summary_message_id = ? WHERE id = ?")
+        .bind(summary)
+        .bind(through_message_id)
+        .bind(chat_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
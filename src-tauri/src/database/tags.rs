@@ -0,0 +1,156 @@
+use crate::database::models::Tag;
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use chrono::Utc;
+use uuid::Uuid;
+use anyhow::Result;
+
+// Tags with the number of chats carrying them
+const TAG_COLUMNS: &str = "SELECT t.id, t.name, t.color, \
+     (SELECT COUNT(*) FROM chat_tags ct JOIN chats c ON c.id = ct.chat_id WHERE ct.tag_id = t.id AND c.deleted_at IS NULL) AS chat_count, \
+     t.created_at, t.updated_at FROM tags t";
+
+fn validate_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("Tag name cannot be empty"));
+    }
+    Ok(name.to_string())
+}
+
+fn name_taken(e: sqlx::Error, name: &str) -> anyhow::Error {
+    match &e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            anyhow::anyhow!("A tag named '{}' already exists", name)
+        }
+        _ => e.into(),
+    }
+}
+
+/// Pushes a condition that the chat in `chat_column` carries the tag named
+/// `tag`, compared without case.
+pub fn push_tag_filter(query: &mut QueryBuilder<'_, Sqlite>, chat_column: &str, tag: String) {
+    query.push(" AND ");
+    push_has_tag(query, chat_column, tag);
+}
+
+/// The condition of [`push_tag_filter`] on its own, for queries that combine
+/// conditions themselves.
+pub fn push_has_tag(query: &mut QueryBuilder<'_, Sqlite>, chat_column: &str, tag: String) {
+    query
+        .push(format!(
+            "EXISTS (SELECT 1 FROM chat_tags ct JOIN tags t ON t.id = ct.tag_id WHERE ct.chat_id = {} AND t.name = ",
+            chat_column
+        ))
+        .push_bind(tag)
+        .push(")");
+}
+
+/// Every tag with its chat count, by name.
+pub async fn list(pool: &SqlitePool) -> Result<Vec<Tag>> {
+    let tags = sqlx::query_as::<_, Tag>(&format!("{} ORDER BY t.name", TAG_COLUMNS))
+        .fetch_all(pool)
+        .await?;
+    Ok(tags)
+}
+
+pub async fn get(pool: &SqlitePool, tag_id: &str) -> Result<Option<Tag>> {
+    let tag = sqlx::query_as::<_, Tag>(&format!("{} WHERE t.id = ?", TAG_COLUMNS))
+        .bind(tag_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(tag)
+}
+
+pub async fn create(pool: &SqlitePool, name: &str, color: Option<&str>) -> Result<Tag> {
+    let name = validate_name(name)?;
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    sqlx::query("INSERT INTO tags (id, name, color, created_at, updated_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(&id)
+        .bind(&name)
+        .bind(color)
+        .bind(now)
+        .bind(now)
+        .execute(pool)
+        .await
+        .map_err(|e| name_taken(e, &name))?;
+
+    get(pool, &id).await?.ok_or_else(|| anyhow::anyhow!("Tag not found"))
+}
+
+pub async fn update(pool: &SqlitePool, tag_id: &str, name: Option<&str>, color: Option<&str>) -> Result<Tag> {
+    let name = name.map(validate_name).transpose()?;
+    let result = sqlx::query("UPDATE tags SET name = COALESCE(?, name), color = COALESCE(?, color), updated_at = ? WHERE id = ?")
+        .bind(&name)
+        .bind(color)
+        .bind(Utc::now())
+        .bind(tag_id)
+        .execute(pool)
+        .await
+        .map_err(|e| name_taken(e, name.as_deref().unwrap_or_default()))?;
+    if result.rows_affected() == 0 {
+        return Err(anyhow::anyhow!("Tag not found"));
+    }
+
+    get(pool, tag_id).await?.ok_or_else(|| anyhow::anyhow!("Tag not found"))
+}
+
+/// Deletes a tag, removing it from every chat.
+pub async fn delete(pool: &SqlitePool, tag_id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM tags WHERE id = ?")
+        .bind(tag_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn for_chat(pool: &SqlitePool, chat_id: &str) -> Result<Vec<Tag>> {
+    let tags = sqlx::query_as::<_, Tag>(&format!(
+        "{} WHERE t.id IN (SELECT tag_id FROM chat_tags WHERE chat_id = ?) ORDER BY t.name",
+        TAG_COLUMNS
+    ))
+    .bind(chat_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(tags)
+}
+
+/// Replaces a chat's tags with the tags named `names`, creating the ones
+/// that do not exist yet. Chats in the trash are not found.
+pub async fn set_for_chat(pool: &SqlitePool, chat_id: &str, names: &[String]) -> Result<Vec<Tag>> {
+    let mut tx = pool.begin().await?;
+
+    let exists: Option<String> = sqlx::query_scalar("SELECT id FROM chats WHERE id = ? AND deleted_at IS NULL")
+        .bind(chat_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if exists.is_none() {
+        return Err(anyhow::anyhow!("Chat not found"));
+    }
+
+    sqlx::query("DELETE FROM chat_tags WHERE chat_id = ?")
+        .bind(chat_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let now = Utc::now();
+    for name in names {
+        let name = validate_name(name)?;
+        sqlx::query("INSERT OR IGNORE INTO tags (id, name, created_at, updated_at) VALUES (?, ?, ?, ?)")
+            .bind(Uuid::new_v4().to_string())
+            .bind(&name)
+            .bind(now)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT OR IGNORE INTO chat_tags (chat_id, tag_id, created_at) SELECT ?, id, ? FROM tags WHERE name = ?")
+            .bind(chat_id)
+            .bind(now)
+            .bind(&name)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    for_chat(pool, chat_id).await
+}
@@ -0,0 +1,94 @@
+use crate::database::models::TrashItem;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use chrono::{DateTime, Utc};
+use anyhow::Result;
+
+/// Days trashed items are kept before the background purge removes them.
+pub const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashKind {
+    Chat,
+    Project,
+    Agent,
+}
+
+impl TrashKind {
+    fn table(self) -> &'static str {
+        match self {
+            TrashKind::Chat => "chats",
+            TrashKind::Project => "projects",
+            TrashKind::Agent => "agents",
+        }
+    }
+}
+
+/// What a purge removed for good.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PurgeReport {
+    pub chats: u64,
+    pub projects: u64,
+    pub agents: u64,
+}
+
+/// Everything in the trash, most recently deleted first.
+pub async fn list(pool: &SqlitePool) -> Result<Vec<TrashItem>> {
+    let items = sqlx::query_as::<_, TrashItem>("SELECT * FROM trash ORDER BY julianday(deleted_at) DESC, deleted_at DESC")
+        .fetch_all(pool)
+        .await?;
+    Ok(items)
+}
+
+/// Moves an item to the trash. Returns false if it does not exist or is
+/// already there.
+pub async fn move_to_trash(pool: &SqlitePool, kind: TrashKind, id: &str) -> Result<bool> {
+    let result = sqlx::query(&format!("UPDATE {} SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL", kind.table()))
+        .bind(Utc::now())
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Takes an item out of the trash. Returns false if it is not in the trash.
+pub async fn restore(pool: &SqlitePool, kind: TrashKind, id: &str) -> Result<bool> {
+    let result = sqlx::query(&format!("UPDATE {} SET deleted_at = NULL WHERE id = ? AND deleted_at IS NOT NULL", kind.table()))
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Permanently deletes items trashed before `deleted_before`, or everything
+/// in the trash when `None`. Messages and agent runs go with their chat or
+/// agent; the chats of a deleted project are kept outside any project.
+pub async fn purge(pool: &SqlitePool, deleted_before: Option<DateTime<Utc>>) -> Result<PurgeReport> {
+    let mut tx = pool.begin().await?;
+
+    let mut removed = Vec::new();
+    for kind in [TrashKind::Chat, TrashKind::Project, TrashKind::Agent] {
+        let result = sqlx::query(&format!(
+            "DELETE FROM {} WHERE deleted_at IS NOT NULL AND (? IS NULL OR julianday(deleted_at) < julianday(?))",
+            kind.table()
+        ))
+        .bind(deleted_before)
+        .bind(deleted_before)
+        .execute(&mut *tx)
+        .await?;
+        removed.push(result.rows_affected());
+    }
+
+    tx.commit().await?;
+    let report = PurgeReport { chats: removed[0], projects: removed[1], agents: removed[2] };
+    if report != PurgeReport::default() {
+        log::info!(
+            "Purged {} chats, {} projects and {} agents from the trash",
+            report.chats,
+            report.projects,
+            report.agents
+        );
+    }
+    Ok(report)
+}
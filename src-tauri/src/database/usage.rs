@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
+use chrono::Utc;
+use uuid::Uuid;
+use anyhow::Result;
+use crate::database::models::{ApiUsage, Chat};
+use crate::integrations::anthropic::Usage;
+use crate::integrations::models::pricing_for;
+
+/// What made an API call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageSource {
+    #[default]
+    Chat,
+    Agent,
+    Hook,
+}
+
+impl UsageSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UsageSource::Chat => "chat",
+            UsageSource::Agent => "agent",
+            UsageSource::Hook => "hook",
+        }
+    }
+}
+
+/// Who an API call is attributed to.
+#[derive(Debug, Clone, Default)]
+pub struct UsageContext {
+    pub source: UsageSource,
+    pub session_id: Option<String>,
+    pub chat_id: Option<String>,
+    pub project_id: Option<String>,
+    pub agent_id: Option<String>,
+    pub agent_run_id: Option<String>,
+    pub hook_id: Option<String>,
+}
+
+impl UsageContext {
+    pub fn chat(chat: &Chat) -> Self {
+        Self {
+            source: UsageSource::Chat,
+            session_id: Some(chat.session_id.clone()),
+            chat_id: Some(chat.id.clone()),
+            project_id: chat.project_id.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn agent(agent_id: &str, agent_run_id: &str) -> Self {
+        Self {
+            source: UsageSource::Agent,
+            agent_id: Some(agent_id.to_string()),
+            agent_run_id: Some(agent_run_id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn hook(hook_id: &str) -> Self {
+        Self {
+            source: UsageSource::Hook,
+            hook_id: Some(hook_id.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Dimension usage totals are grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageGrouping {
+    Day,
+    Model,
+    Project,
+    Agent,
+}
+
+impl UsageGrouping {
+    fn column(&self) -> &'static str {
+        match self {
+            UsageGrouping::Day => "date",
+            UsageGrouping::Model => "model",
+            UsageGrouping::Project => "project_id",
+            UsageGrouping::Agent => "agent_id",
+        }
+    }
+}
+
+/// Optional restrictions applied before grouping. Dates are inclusive
+/// `YYYY-MM-DD` strings in UTC.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageFilter {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub session_id: Option<String>,
+    pub project_id: Option<String>,
+    pub agent_id: Option<String>,
+    pub source: Option<UsageSource>,
+}
+
+/// Token and cost totals for one group.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UsageSummary {
+    pub key: Option<String>,
+    pub api_calls: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_creation_input_tokens: i64,
+    pub cache_read_input_tokens: i64,
+    pub cost: f64,
+}
+
+/// Records one API call and rolls it up into the session's daily analytics.
+pub async fn record_usage(
+    pool: &SqlitePool,
+    context: &UsageContext,
+    model: &str,
+    usage: &Usage,
+) -> Result<ApiUsage> {
+    let cost = match pricing_for(model) {
+        Some(pricing) => pricing.cost(
+            usage.input_tokens,
+            usage.output_tokens,
+            usage.cache_creation_input_tokens,
+            usage.cache_read_input_tokens,
+        ),
+        None => {
+            log::warn!("No pricing known for model {}, recording zero cost", model);
+            0.0
+        }
+    };
+
+    let now = Utc::now();
+    let record = ApiUsage {
+        id: Uuid::new_v4().to_string(),
+        source: context.source.as_str().to_string(),
+        session_id: context.session_id.clone(),
+        chat_id: context.chat_id.clone(),
+        project_id: context.project_id.clone(),
+        agent_id: context.agent_id.clone(),
+        agent_run_id: context.agent_run_id.clone(),
+        hook_id: context.hook_id.clone(),
+        model: model.to_string(),
+        input_tokens: usage.input_tokens as i64,
+        output_tokens: usage.output_tokens as i64,
+        cache_creation_input_tokens: usage.cache_creation_input_tokens as i64,
+        cache_read_input_tokens: usage.cache_read_input_tokens as i64,
+        cost,
+        date: now.format("%Y-%m-%d").to_string(),
+        created_at: now,
+    };
+    let total_tokens = record.input_tokens
+        + record.output_tokens
+        + record.cache_creation_input_tokens
+        + record.cache_read_input_tokens;
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO api_usage (id, source, session_id, chat_id, project_id, agent_id, agent_run_id, hook_id,
+                               model, input_tokens, output_tokens, cache_creation_input_tokens,
+                               cache_read_input_tokens, cost, date, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&record.id)
+    .bind(&record.source)
+    .bind(&record.session_id)
+    .bind(&record.chat_id)
+    .bind(&record.project_id)
+    .bind(&record.agent_id)
+    .bind(&record.agent_run_id)
+    .bind(&record.hook_id)
+    .bind(&record.model)
+    .bind(record.input_tokens)
+    .bind(record.output_tokens)
+    .bind(record.cache_creation_input_tokens)
+    .bind(record.cache_read_input_tokens)
+    .bind(record.cost)
+    .bind(&record.date)
+    .bind(record.created_at)
+    .execute(&mut *tx)
+    .await?;
+
+    if let Some(session_id) = &record.session_id {
+        sqlx::query(
+            r#"
+            INSERT INTO session_analytics (id, session_id, tokens_used, api_calls, cost_estimate, date)
+            VALUES (?, ?, ?, 1, ?, ?)
+            ON CONFLICT(session_id, date) DO UPDATE SET
+                tokens_used = tokens_used + excluded.tokens_used,
+                api_calls = api_calls + 1,
+                cost_estimate = cost_estimate + excluded.cost_estimate
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(session_id)
+        .bind(total_tokens)
+        .bind(record.cost)
+        .bind(&record.date)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("UPDATE sessions SET token_usage = token_usage + ?, last_activity = ? WHERE id = ?")
+            .bind(total_tokens)
+            .bind(now)
+            .bind(session_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(record)
+}
+
+/// Sums recorded usage grouped by `grouping`, largest cost first except for
+/// daily totals, which are returned in date order.
+pub async fn summarize_usage(
+    pool: &SqlitePool,
+    grouping: UsageGrouping,
+    filter: &UsageFilter,
+) -> Result<Vec<UsageSummary>> {
+    let column = grouping.column();
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+        r#"
+        SELECT {column} AS key,
+               COUNT(*) AS api_calls,
+               COALESCE(SUM(input_tokens), 0) AS input_tokens,
+               COALESCE(SUM(output_tokens), 0) AS output_tokens,
+               COALESCE(SUM(cache_creation_input_tokens), 0) AS cache_creation_input_tokens,
+               COALESCE(SUM(cache_read_input_tokens), 0) AS cache_read_input_tokens,
+               COALESCE(SUM(cost), 0.0) AS cost
+        FROM api_usage
+        WHERE 1 = 1
+        "#
+    ));
+
+    if let Some(start_date) = &filter.start_date {
+        query.push(" AND date >= ").push_bind(start_date.clone());
+    }
+    if let Some(end_date) = &filter.end_date {
+        query.push(" AND date <= ").push_bind(end_date.clone());
+    }
+    if let Some(session_id) = &filter.session_id {
+        query.push(" AND session_id = ").push_bind(session_id.clone());
+    }
+    if let Some(project_id) = &filter.project_id {
+        query.push(" AND project_id = ").push_bind(project_id.clone());
+    }
+    if let Some(agent_id) = &filter.agent_id {
+        query.push(" AND agent_id = ").push_bind(agent_id.clone());
+    }
+    if let Some(source) = filter.source {
+        query.push(" AND source = ").push_bind(source.as_str());
+    }
+    if grouping == UsageGrouping::Agent {
+        query.push(" AND agent_id IS NOT NULL");
+    }
+
+    query.push(format!(" GROUP BY {column}"));
+    match grouping {
+        UsageGrouping::Day => query.push(" ORDER BY date ASC"),
+        _ => query.push(" ORDER BY cost DESC"),
+    };
+
+    let summaries = query
+        .build_query_as::<UsageSummary>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(summaries)
+}
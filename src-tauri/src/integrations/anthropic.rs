@@ -1,11 +1,103 @@
 use serde::{Deserialize, Serialize};
 use reqwest::Client;
 use anyhow::Result;
+use std::sync::Arc;
+use crate::database::Database;
+use crate::database::budgets;
+use crate::database::usage::{self, UsageContext};
+use crate::integrations::auth::{ApiKeyAuth, AuthProvider};
+use crate::integrations::models;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AnthropicMessage {
     pub role: String,
-    pub content: String,
+    pub content: MessageContent,
+}
+
+impl AnthropicMessage {
+    pub fn text(role: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            role: role.into(),
+            content: MessageContent::Text(text.into()),
+        }
+    }
+}
+
+/// Message content, either plain text or a list of blocks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Blocks(Vec<RequestBlock>),
+}
+
+/// Content block sent in a request message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RequestBlock {
+    Text {
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Image {
+        source: MediaSource,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Document {
+        source: MediaSource,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+}
+
+impl RequestBlock {
+    pub fn text(text: impl Into<String>) -> Self {
+        RequestBlock::Text {
+            text: text.into(),
+            cache_control: None,
+        }
+    }
+
+    pub fn cache_control(&self) -> Option<&CacheControl> {
+        match self {
+            RequestBlock::Text { cache_control, .. }
+            | RequestBlock::Image { cache_control, .. }
+            | RequestBlock::Document { cache_control, .. } => cache_control.as_ref(),
+        }
+    }
+
+    pub fn cache_control_mut(&mut self) -> &mut Option<CacheControl> {
+        match self {
+            RequestBlock::Text { cache_control, .. }
+            | RequestBlock::Image { cache_control, .. }
+            | RequestBlock::Document { cache_control, .. } => cache_control,
+        }
+    }
+}
+
+/// Inline data for an image or document block. `source_type` is `base64`
+/// for binary files and `text` for plain text documents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaSource {
+    #[serde(rename = "type")]
+    pub source_type: String,
+    pub media_type: String,
+    pub data: String,
+}
+
+/// A tool the model may call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tool {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub input_schema: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -14,7 +106,61 @@ pub struct AnthropicRequest {
     pub max_tokens: u32,
     pub messages: Vec<AnthropicMessage>,
     pub temperature: Option<f32>,
-    pub system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<SystemPrompt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+}
+
+/// Marks the end of a prefix the API should cache.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheControl {
+    #[serde(rename = "type")]
+    pub cache_type: String,
+}
+
+impl CacheControl {
+    pub fn ephemeral() -> Self {
+        Self {
+            cache_type: "ephemeral".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemBlock {
+    #[serde(rename = "type")]
+    pub block_type: String,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+impl SystemBlock {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            block_type: "text".to_string(),
+            text: text.into(),
+            cache_control: None,
+        }
+    }
+
+    /// A text block the API caches along with everything before it.
+    pub fn cached(text: impl Into<String>) -> Self {
+        Self {
+            cache_control: Some(CacheControl::ephemeral()),
+            ..Self::text(text)
+        }
+    }
+}
+
+/// The system prompt, either plain text or blocks that can carry cache
+/// breakpoints.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SystemPrompt {
+    Text(String),
+    Blocks(Vec<SystemBlock>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,61 +183,179 @@ pub struct ContentBlock {
 pub struct Usage {
     pub input_tokens: u32,
     pub output_tokens: u32,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u32,
+    #[serde(default)]
+    pub cache_read_input_tokens: u32,
+}
+
+/// One entry of the `/v1/models` listing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListedModel {
+    pub id: String,
+    pub display_name: String,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelsPage {
+    data: Vec<ListedModel>,
+    has_more: bool,
+    last_id: Option<String>,
 }
 
 pub struct AnthropicClient {
     client: Client,
-    api_key: String,
+    auth: Arc<dyn AuthProvider>,
     base_url: String,
+    usage_tracking: Option<(Arc<Database>, UsageContext)>,
 }
 
 impl AnthropicClient {
     pub fn new(api_key: String, base_url: Option<String>) -> Self {
+        Self::with_auth(Arc::new(ApiKeyAuth::new(api_key)), base_url)
+    }
+
+    pub fn with_auth(auth: Arc<dyn AuthProvider>, base_url: Option<String>) -> Self {
         Self {
             client: Client::new(),
-            api_key,
+            auth,
             base_url: base_url.unwrap_or_else(|| "https://api.anthropic.com/v1".to_string()),
+            usage_tracking: None,
         }
     }
 
+    /// Records the token usage and cost of every successful call, attributed
+    /// to `context`, and blocks calls once a budget covering it is spent.
+    pub fn with_usage_tracking(mut self, db: Arc<Database>, context: UsageContext) -> Self {
+        self.usage_tracking = Some((db, context));
+        self
+    }
+
     pub async fn send_message(&self, request: AnthropicRequest) -> Result<AnthropicResponse> {
         let url = format!("{}/messages", self.base_url);
+        let mut retried = false;
+
+        // Refuse the call up front if it would spend from an exhausted budget
+        if let Some((db, context)) = &self.usage_tracking {
+            budgets::enforce_budgets(db.pool(), context).await?;
+        }
         
-        let response = self
-            .client
-            .post(&url)
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("API request failed: {}", error_text));
+        loop {
+            let auth_headers = self.auth.auth_headers().await?;
+
+            let mut builder = self
+                .client
+                .post(&url)
+                .header("anthropic-version", "2023-06-01")
+                .header("content-type", "application/json");
+            for (name, value) in &auth_headers {
+                builder = builder.header(*name, value.expose());
+            }
+
+            let response = builder.json(&request).send().await?;
+
+            // Retry once with refreshed credentials if they were rejected
+            if response.status() == reqwest::StatusCode::UNAUTHORIZED
+                && !retried
+                && self.auth.on_unauthorized(&auth_headers).await?
+            {
+                retried = true;
+                continue;
+            }
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let error_text = response.text().await?;
+                return Err(anyhow::anyhow!("API request failed ({}): {}", status.as_u16(), error_text));
+            }
+
+            let anthropic_response: AnthropicResponse = response.json().await?;
+
+            if let Some((db, context)) = &self.usage_tracking {
+                // Accounting failures must not lose the response
+                if let Err(e) = usage::record_usage(
+                    db.pool(),
+                    context,
+                    &anthropic_response.model,
+                    &anthropic_response.usage,
+                )
+                .await
+                {
+                    log::error!("Failed to record API usage: {}", e);
+                }
+            }
+
+            return Ok(anthropic_response);
         }
+    }
+
+    /// Lists every model available to the current credentials.
+    pub async fn list_models(&self) -> Result<Vec<ListedModel>> {
+        let url = format!("{}/models", self.base_url);
+        let mut models = Vec::new();
+        let mut after_id: Option<String> = None;
+
+        loop {
+            let mut query = vec![("limit", "1000".to_string())];
+            if let Some(after_id) = &after_id {
+                query.push(("after_id", after_id.clone()));
+            }
+
+            let mut retried = false;
+            let response = loop {
+                let auth_headers = self.auth.auth_headers().await?;
 
-        let anthropic_response: AnthropicResponse = response.json().await?;
-        Ok(anthropic_response)
+                let mut builder = self
+                    .client
+                    .get(&url)
+                    .query(&query)
+                    .header("anthropic-version", "2023-06-01");
+                for (name, value) in &auth_headers {
+                    builder = builder.header(*name, value.expose());
+                }
+
+                let response = builder.send().await?;
+                if response.status() == reqwest::StatusCode::UNAUTHORIZED
+                    && !retried
+                    && self.auth.on_unauthorized(&auth_headers).await?
+                {
+                    retried = true;
+                    continue;
+                }
+                break response;
+            };
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let error_text = response.text().await?;
+                return Err(anyhow::anyhow!("API request failed ({}): {}", status.as_u16(), error_text));
+            }
+
+            let page: ModelsPage = response.json().await?;
+            models.extend(page.data);
+            match page.last_id {
+                Some(last_id) if page.has_more => after_id = Some(last_id),
+                _ => return Ok(models),
+            }
+        }
     }
 
     pub async fn validate_api_key(&self) -> Result<bool> {
-        log::info!("🔍 Validating API key: length={}, starts_with={}", 
-            self.api_key.len(), 
-            if self.api_key.len() >= 15 { &self.api_key[..15] } else { &self.api_key }
-        );
+        let Some(api_key) = self.auth.api_key().map(|key| key.expose()) else {
+            return Ok(false);
+        };
+        log::info!("🔍 Validating API key: length={}", api_key.len());
 
         // First check basic format - Anthropic API keys start with 'sk-ant-api03-' or 'sk-ant-api04-'
-        if !self.api_key.starts_with("sk-ant-api03-") && !self.api_key.starts_with("sk-ant-api04-") {
-            log::error!("❌ Invalid API key format: must start with 'sk-ant-api03-' or 'sk-ant-api04-'. Current format: {}...", 
-                if self.api_key.len() >= 15 { &self.api_key[..15] } else { &self.api_key });
+        if !api_key.starts_with("sk-ant-api03-") && !api_key.starts_with("sk-ant-api04-") {
+            log::error!("❌ Invalid API key format: must start with 'sk-ant-api03-' or 'sk-ant-api04-'");
             return Ok(false);
         }
 
         // Check minimum length (Anthropic keys are typically ~95+ characters)
-        if self.api_key.len() < 90 {
-            log::error!("❌ Invalid API key format: too short (length: {}, expected ~95+)", self.api_key.len());
+        if api_key.len() < 90 {
+            log::error!("❌ Invalid API key format: too short (length: {}, expected ~95+)", api_key.len());
             return Ok(false);
         }
 
@@ -99,14 +363,12 @@ impl AnthropicClient {
 
         // Test with actual API call using a minimal request
         let test_request = AnthropicRequest {
-            model: "claude-3-haiku-20240307".to_string(),
+            model: models::LIGHTWEIGHT_MODEL.to_string(),
             max_tokens: 10,
-            messages: vec![AnthropicMessage {
-                role: "user".to_string(),
-                content: "Test".to_string(),
-            }],
+            messages: vec![AnthropicMessage::text("user", "Test")],
             temperature: None,
             system: None,
+            tools: None,
         };
 
         match self.send_message(test_request).await {
@@ -0,0 +1,349 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use anyhow::Result;
+use base64::{engine::general_purpose, Engine as _};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use crate::database::models::JsonMap;
+use crate::integrations::anthropic::{MediaSource, RequestBlock};
+use crate::integrations::models::ModelInfo;
+use crate::utils::crypto;
+
+/// Largest image the API accepts.
+pub const MAX_IMAGE_BYTES: i64 = 5 * 1024 * 1024;
+
+/// Largest PDF or text document the API accepts.
+pub const MAX_DOCUMENT_BYTES: i64 = 32 * 1024 * 1024;
+
+/// Most attachments a single message may carry.
+pub const MAX_ATTACHMENTS_PER_MESSAGE: usize = 20;
+
+/// Key under which message metadata lists its attachments.
+pub const ATTACHMENTS_METADATA_KEY: &str = "attachments";
+
+/// How long a stored file may go unreferenced before garbage collection
+/// removes it, so files uploaded for a message being composed survive.
+pub const GC_GRACE_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+const IMAGE_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
+
+/// A stored file as referenced from message metadata.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttachmentRef {
+    pub hash: String,
+    pub name: String,
+    pub mime_type: String,
+    pub size_bytes: i64,
+}
+
+impl AttachmentRef {
+    pub fn is_image(&self) -> bool {
+        IMAGE_TYPES.contains(&self.mime_type.as_str())
+    }
+
+    /// Whether `model` can read the file: images and PDFs need vision.
+    pub fn supported_by(&self, model: &ModelInfo) -> bool {
+        self.mime_type == "text/plain" || model.capabilities.vision
+    }
+
+    /// Reads the attachments listed in a message's metadata.
+    pub fn from_metadata(metadata: Option<&JsonMap>) -> Vec<AttachmentRef> {
+        metadata
+            .and_then(|metadata| metadata.get(ATTACHMENTS_METADATA_KEY).cloned())
+            .and_then(|attachments| serde_json::from_value(attachments).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Result of a garbage collection pass.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GcReport {
+    pub files_removed: usize,
+    pub bytes_freed: u64,
+    pub files_kept: usize,
+}
+
+/// Determines the type of `bytes` from its content, falling back to plain
+/// text for UTF-8 files. Returns `None` for unsupported files.
+pub fn sniff_mime_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]) {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else if std::str::from_utf8(bytes).is_ok() {
+        Some("text/plain")
+    } else {
+        None
+    }
+}
+
+/// Checks `attachments` against the API's limits and what `model` accepts.
+pub fn validate_attachments(attachments: &[AttachmentRef], model: &ModelInfo) -> Result<(), String> {
+    if attachments.len() > MAX_ATTACHMENTS_PER_MESSAGE {
+        return Err(format!(
+            "A message can have at most {} attachments",
+            MAX_ATTACHMENTS_PER_MESSAGE
+        ));
+    }
+
+    for attachment in attachments {
+        let limit = if attachment.is_image() {
+            MAX_IMAGE_BYTES
+        } else if matches!(attachment.mime_type.as_str(), "application/pdf" | "text/plain") {
+            MAX_DOCUMENT_BYTES
+        } else {
+            return Err(format!(
+                "{} has unsupported type {}",
+                attachment.name, attachment.mime_type
+            ));
+        };
+
+        if attachment.size_bytes > limit {
+            return Err(format!(
+                "{} is larger than the {} MB limit for {} files",
+                attachment.name,
+                limit / 1024 / 1024,
+                attachment.mime_type
+            ));
+        }
+        if !attachment.supported_by(model) {
+            return Err(format!(
+                "{} does not accept images or PDFs. Choose a model with vision support to send {}.",
+                model.display_name, attachment.name
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Content-addressed files under `<data dir>/attachments`, stored by the
+/// SHA-256 of their contents.
+pub struct AttachmentStore {
+    root: PathBuf,
+}
+
+impl AttachmentStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Store in the app data directory.
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::new(crate::utils::config::get_data_dir()?.join("attachments")))
+    }
+
+    fn is_hash(hash: &str) -> bool {
+        hash.len() == 64 && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+    }
+
+    /// Where the file with `hash` lives, sharded by its first two characters.
+    pub fn path(&self, hash: &str) -> Result<PathBuf> {
+        if !Self::is_hash(hash) {
+            return Err(anyhow::anyhow!("Invalid attachment hash"));
+        }
+        Ok(self.root.join(&hash[..2]).join(hash))
+    }
+
+    /// Stores `bytes` and returns a reference to them. Storing the same
+    /// content twice keeps a single copy.
+    pub async fn put(&self, name: &str, bytes: &[u8]) -> Result<AttachmentRef> {
+        let mime_type = sniff_mime_type(bytes)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a supported image, PDF or text file", name))?;
+        let hash = crypto::sha256_hex(bytes);
+        let path = self.path(&hash)?;
+
+        if tokio::fs::try_exists(&path).await? {
+            // Refresh the timestamp so garbage collection treats it as new
+            let file = std::fs::File::options().append(true).open(&path)?;
+            file.set_modified(SystemTime::now())?;
+        } else {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            // Write under a temporary name so readers never see partial files
+            let partial = path.with_extension("partial");
+            tokio::fs::write(&partial, bytes).await?;
+            tokio::fs::rename(&partial, &path).await?;
+        }
+
+        Ok(AttachmentRef {
+            hash,
+            name: name.to_string(),
+            mime_type: mime_type.to_string(),
+            size_bytes: bytes.len() as i64,
+        })
+    }
+
+    /// Copies the file at `path` into the store.
+    pub async fn put_file(&self, path: &Path) -> Result<AttachmentRef> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow::anyhow!("{} is not a file", path.display()))?;
+        let size = tokio::fs::metadata(path).await?.len() as i64;
+        if size > MAX_DOCUMENT_BYTES {
+            return Err(anyhow::anyhow!(
+                "{} is larger than the {} MB attachment limit",
+                name,
+                MAX_DOCUMENT_BYTES / 1024 / 1024
+            ));
+        }
+
+        let bytes = tokio::fs::read(path).await?;
+        self.put(&name, &bytes).await
+    }
+
+    pub async fn contains(&self, hash: &str) -> bool {
+        match self.path(hash) {
+            Ok(path) => tokio::fs::try_exists(path).await.unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    /// The stored file `attachment` refers to, with its type and size taken
+    /// from the file rather than from the reference.
+    pub async fn resolve(&self, attachment: &AttachmentRef) -> Result<AttachmentRef> {
+        let bytes = self
+            .read(&attachment.hash)
+            .await
+            .map_err(|_| anyhow::anyhow!("{} is no longer available. Please attach it again.", attachment.name))?;
+        let mime_type = sniff_mime_type(&bytes)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a supported image, PDF or text file", attachment.name))?;
+
+        Ok(AttachmentRef {
+            hash: attachment.hash.clone(),
+            name: attachment.name.clone(),
+            mime_type: mime_type.to_string(),
+            size_bytes: bytes.len() as i64,
+        })
+    }
+
+    pub async fn read(&self, hash: &str) -> Result<Vec<u8>> {
+        let path = self.path(hash)?;
+        tokio::fs::read(&path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Attachment {} is missing: {}", hash, e))
+    }
+
+    /// Encodes attachments for a request to `model`. Files the model cannot
+    /// read are described in a text block instead, so earlier turns stay
+    /// usable after switching to a model without vision.
+    pub async fn to_blocks(&self, attachments: &[AttachmentRef], model: &ModelInfo) -> Result<Vec<RequestBlock>> {
+        let mut blocks = Vec::with_capacity(attachments.len());
+        for attachment in attachments {
+            if attachment.supported_by(model) {
+                blocks.push(self.to_block(attachment).await?);
+            } else {
+                blocks.push(RequestBlock::text(format!(
+                    "[{} ({}) was attached here but is not shown because {} does not accept images or PDFs.]",
+                    attachment.name, attachment.mime_type, model.display_name
+                )));
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Encodes `attachment` as an image or document content block.
+    pub async fn to_block(&self, attachment: &AttachmentRef) -> Result<RequestBlock> {
+        let bytes = self.read(&attachment.hash).await?;
+
+        if attachment.is_image() {
+            return Ok(RequestBlock::Image {
+                source: MediaSource {
+                    source_type: "base64".to_string(),
+                    media_type: attachment.mime_type.clone(),
+                    data: general_purpose::STANDARD.encode(&bytes),
+                },
+                cache_control: None,
+            });
+        }
+
+        let source = if attachment.mime_type == "text/plain" {
+            MediaSource {
+                source_type: "text".to_string(),
+                media_type: "text/plain".to_string(),
+                data: String::from_utf8(bytes)?,
+            }
+        } else {
+            MediaSource {
+                source_type: "base64".to_string(),
+                media_type: attachment.mime_type.clone(),
+                data: general_purpose::STANDARD.encode(&bytes),
+            }
+        };
+
+        Ok(RequestBlock::Document {
+            source,
+            title: Some(attachment.name.clone()),
+            cache_control: None,
+        })
+    }
+
+    /// Hashes of every attachment some message still refers to.
+    async fn referenced_hashes(pool: &SqlitePool) -> Result<HashSet<String>> {
+        let hashes: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT DISTINCT json_extract(attachment.value, '$.hash')
+            FROM messages,
+                 json_each(CASE WHEN json_valid(messages.metadata) THEN messages.metadata END, '$.attachments') AS attachment
+            WHERE json_extract(attachment.value, '$.hash') IS NOT NULL
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(hashes.into_iter().collect())
+    }
+
+    /// Removes stored files no message refers to, once they are older than
+    /// `grace_period`.
+    pub async fn collect_garbage(&self, pool: &SqlitePool, grace_period: Duration) -> Result<GcReport> {
+        let mut report = GcReport::default();
+        if !tokio::fs::try_exists(&self.root).await? {
+            return Ok(report);
+        }
+
+        let referenced = Self::referenced_hashes(pool).await?;
+        let cutoff = SystemTime::now() - grace_period;
+
+        let mut shards = tokio::fs::read_dir(&self.root).await?;
+        while let Some(shard) = shards.next_entry().await? {
+            if !shard.file_type().await?.is_dir() {
+                continue;
+            }
+
+            let mut files = tokio::fs::read_dir(shard.path()).await?;
+            while let Some(file) = files.next_entry().await? {
+                let name = file.file_name().to_string_lossy().to_string();
+                let metadata = file.metadata().await?;
+                let recent = metadata.modified().map(|modified| modified > cutoff).unwrap_or(true);
+
+                if referenced.contains(&name) || recent {
+                    report.files_kept += 1;
+                    continue;
+                }
+
+                tokio::fs::remove_file(file.path()).await?;
+                report.files_removed += 1;
+                report.bytes_freed += metadata.len();
+            }
+        }
+
+        if report.files_removed > 0 {
+            log::info!(
+                "Removed {} unreferenced attachments ({} bytes)",
+                report.files_removed,
+                report.bytes_freed
+            );
+        }
+        Ok(report)
+    }
+}
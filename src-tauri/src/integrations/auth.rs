@@ -0,0 +1,175 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
+use crate::integrations::oauth::OAuthManager;
+use crate::utils::credentials::CredentialStore;
+use crate::utils::crypto::Secret;
+
+/// How long before `expires_in` elapses an access token is refreshed.
+pub const REFRESH_MARGIN_SECS: i64 = 60;
+
+// Beta flag the Messages API requires for OAuth access tokens
+const OAUTH_BETA: &str = "oauth-2025-04-20";
+
+/// Supplies credentials for requests to the Anthropic API.
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Headers to attach to the next request. Implementations refresh
+    /// credentials that are about to expire before returning them.
+    async fn auth_headers(&self) -> Result<Vec<(&'static str, Secret)>>;
+
+    /// Called when the API rejected `headers` with a 401. Returns `true` when
+    /// fresh credentials are available and the request is worth retrying.
+    async fn on_unauthorized(&self, headers: &[(&'static str, Secret)]) -> Result<bool>;
+
+    /// The raw API key, for providers backed by one.
+    fn api_key(&self) -> Option<&Secret> {
+        None
+    }
+}
+
+/// Static `x-api-key` authentication.
+pub struct ApiKeyAuth {
+    api_key: Secret,
+}
+
+impl ApiKeyAuth {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: Secret::new(api_key),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for ApiKeyAuth {
+    async fn auth_headers(&self) -> Result<Vec<(&'static str, Secret)>> {
+        Ok(vec![("x-api-key", self.api_key.clone())])
+    }
+
+    async fn on_unauthorized(&self, _headers: &[(&'static str, Secret)]) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn api_key(&self) -> Option<&Secret> {
+        Some(&self.api_key)
+    }
+}
+
+/// OAuth tokens as persisted in the credential store.
+struct StoredTokens {
+    access_token: Secret,
+    refresh_token: Option<Secret>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl StoredTokens {
+    async fn load(store: &dyn CredentialStore) -> Result<Self> {
+        let access_token = store
+            .get("api.anthropicOAuthToken")
+            .await?
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("No OAuth access token stored. Please sign in again."))?;
+
+        let refresh_token = store
+            .get("api.anthropicRefreshToken")
+            .await?
+            .filter(|token| !token.is_empty())
+            .map(Secret::new);
+
+        let expires_at = store
+            .get("api.anthropicOAuthExpiresAt")
+            .await?
+            .and_then(|value| DateTime::parse_from_rfc3339(&value).ok())
+            .map(|value| value.with_timezone(&Utc));
+
+        Ok(Self {
+            access_token: Secret::new(access_token),
+            refresh_token,
+            expires_at,
+        })
+    }
+
+    fn expires_soon(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at - Utc::now() < Duration::seconds(REFRESH_MARGIN_SECS))
+    }
+}
+
+/// Bearer authentication with OAuth tokens from the credential store.
+///
+/// Tokens are re-read on every request so that all clients share whatever
+/// the most recent refresh produced. Refreshes take the manager's refresh
+/// lock, so concurrent requests trigger at most one token request.
+pub struct OAuthAuth {
+    manager: Arc<OAuthManager>,
+    store: Arc<dyn CredentialStore>,
+}
+
+impl OAuthAuth {
+    pub fn new(manager: Arc<OAuthManager>, store: Arc<dyn CredentialStore>) -> Self {
+        Self { manager, store }
+    }
+
+    fn headers(tokens: &StoredTokens) -> Vec<(&'static str, Secret)> {
+        vec![
+            (
+                "authorization",
+                Secret::new(format!("Bearer {}", tokens.access_token.expose())),
+            ),
+            ("anthropic-beta", Secret::new(OAUTH_BETA)),
+        ]
+    }
+
+    /// Refreshes the tokens unless another task already replaced `stale`.
+    async fn refresh(&self, stale: &Secret) -> Result<StoredTokens> {
+        let _guard = self.manager.refresh_lock().lock().await;
+
+        let current = StoredTokens::load(self.store.as_ref()).await?;
+        if current.access_token != *stale && !current.expires_soon() {
+            return Ok(current);
+        }
+
+        let refresh_token = current
+            .refresh_token
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No OAuth refresh token stored. Please sign in again."))?;
+
+        log::info!("Refreshing OAuth access token");
+        self.manager
+            .refresh(refresh_token.expose(), self.store.as_ref())
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        StoredTokens::load(self.store.as_ref()).await
+    }
+}
+
+#[async_trait]
+impl AuthProvider for OAuthAuth {
+    async fn auth_headers(&self) -> Result<Vec<(&'static str, Secret)>> {
+        let mut tokens = StoredTokens::load(self.store.as_ref()).await?;
+        if tokens.expires_soon() {
+            tokens = self.refresh(&tokens.access_token).await?;
+        }
+        Ok(Self::headers(&tokens))
+    }
+
+    async fn on_unauthorized(&self, headers: &[(&'static str, Secret)]) -> Result<bool> {
+        let Some((_, rejected)) = headers.iter().find(|(name, _)| *name == "authorization") else {
+            return Ok(false);
+        };
+        let Some(rejected) = rejected.expose().strip_prefix("Bearer ") else {
+            return Ok(false);
+        };
+
+        match self.refresh(&Secret::new(rejected)).await {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                log::error!("OAuth token refresh after 401 failed: {}", e);
+                Ok(false)
+            }
+        }
+    }
+}
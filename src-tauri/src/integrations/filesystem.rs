@@ -1 +1,101 @@
-// Filesystem operations integration - placeholder for Phase 3
\ No newline at end of file
+use anyhow::Result;
+use std::path::Path;
+use crate::utils::crypto;
+
+/// Largest file accepted as project knowledge.
+pub const MAX_DOCUMENT_BYTES: u64 = 10 * 1024 * 1024;
+
+/// A file read from disk with its text extracted.
+#[derive(Debug, Clone)]
+pub struct ExtractedDocument {
+    pub name: String,
+    pub mime_type: String,
+    pub size_bytes: i64,
+    pub content_hash: String,
+    pub text: String,
+}
+
+// Extensions read as plain text, with the MIME type recorded for them
+const TEXT_TYPES: &[(&str, &str)] = &[
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("markdown", "text/markdown"),
+    ("csv", "text/csv"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("html", "text/html"),
+    ("css", "text/css"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("toml", "application/toml"),
+];
+
+/// Rough token count for `text`, at about four characters per token.
+pub fn estimate_tokens(text: &str) -> i64 {
+    (text.chars().count() as i64 + 3) / 4
+}
+
+/// Extracts the text of a file named `name`. PDFs have their text layer
+/// extracted; anything else must be UTF-8 text, such as markdown or source
+/// code. CPU-bound for large PDFs, so async callers should run it on a
+/// blocking thread.
+pub fn extract_text(name: &str, bytes: &[u8]) -> Result<(String, String)> {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    if extension == "pdf" {
+        // The parser panics on some malformed files
+        let text = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes))
+            .map_err(|_| anyhow::anyhow!("Failed to extract text from {}: the PDF is malformed", name))?
+            .map_err(|e| anyhow::anyhow!("Failed to extract text from {}: {}", name, e))?;
+        return Ok(("application/pdf".to_string(), text));
+    }
+
+    let text = String::from_utf8(bytes.to_vec())
+        .map_err(|_| anyhow::anyhow!("{} is not a text file or PDF", name))?;
+    let mime_type = TEXT_TYPES
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, mime_type)| *mime_type)
+        .unwrap_or("text/plain");
+
+    Ok((mime_type.to_string(), text))
+}
+
+/// Reads and extracts the document at `path`.
+pub async fn read_document(path: &Path) -> Result<ExtractedDocument> {
+    let size = tokio::fs::metadata(path).await?.len();
+    if size > MAX_DOCUMENT_BYTES {
+        return Err(anyhow::anyhow!(
+            "{} is larger than the {} MB limit",
+            path.display(),
+            MAX_DOCUMENT_BYTES / 1024 / 1024
+        ));
+    }
+
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow::anyhow!("{} is not a file", path.display()))?;
+    let bytes = tokio::fs::read(path).await?;
+
+    tokio::task::spawn_blocking(move || {
+        let (mime_type, text) = extract_text(&name, &bytes)?;
+        if text.trim().is_empty() {
+            return Err(anyhow::anyhow!("No text could be extracted from {}", name));
+        }
+
+        Ok(ExtractedDocument {
+            name,
+            mime_type,
+            size_bytes: bytes.len() as i64,
+            content_hash: crypto::sha256_hex(&bytes),
+            text,
+        })
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?
+}
@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::database::models::JsonMap;
+use crate::integrations::models::{ModelRegistry, DEFAULT_MODEL};
+
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+pub const DEFAULT_TEMPERATURE: f64 = 0.7;
+pub const DEFAULT_SYSTEM_PROMPT: &str = "You are Claude, a helpful AI assistant created by Anthropic. You are running in Cloddo, a desktop application alternative to Claude Desktop.";
+
+/// Key under which chat and project metadata store their overrides.
+pub const GENERATION_METADATA_KEY: &str = "generation";
+
+/// Generation parameters set on a chat or project. Unset fields are
+/// inherited from the next level up.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+}
+
+impl GenerationSettings {
+    /// Reads the overrides from a chat's or project's metadata.
+    pub fn from_metadata(metadata: Option<&JsonMap>) -> Result<Self, String> {
+        match metadata.and_then(|metadata| metadata.get(GENERATION_METADATA_KEY)) {
+            None | Some(serde_json::Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid generation settings: {}", e)),
+        }
+    }
+
+    /// Writes the overrides into `metadata`, removing the key when `self`
+    /// is empty, and returns the updated metadata.
+    pub fn merge_into_metadata(&self, metadata: Option<&JsonMap>) -> Result<JsonMap, String> {
+        let mut map = metadata.cloned().unwrap_or_default();
+
+        if *self == Self::default() {
+            map.remove(GENERATION_METADATA_KEY);
+        } else {
+            let value = serde_json::to_value(self).map_err(|e| e.to_string())?;
+            map.insert(GENERATION_METADATA_KEY.to_string(), value);
+        }
+
+        Ok(map)
+    }
+
+    /// Reads the global defaults from the settings map.
+    pub fn from_global_settings(settings: &HashMap<String, serde_json::Value>) -> Self {
+        let string = |key: &str| {
+            settings
+                .get(key)
+                .and_then(|value| value.as_str())
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        Self {
+            model: string("api.defaultModel"),
+            max_tokens: settings
+                .get("api.defaultMaxTokens")
+                .and_then(|value| value.as_u64())
+                .and_then(|value| u32::try_from(value).ok()),
+            temperature: settings
+                .get("api.defaultTemperature")
+                .and_then(|value| value.as_f64()),
+            system_prompt: string("api.defaultSystemPrompt"),
+        }
+    }
+
+    /// Checks the fields that are set. `fallback_model` is the model the
+    /// overrides will be used with when they don't pick one.
+    pub fn validate(&self, models: &ModelRegistry, fallback_model: &str) -> Result<(), String> {
+        let model = self.model.as_deref().unwrap_or(fallback_model);
+        models
+            .validate_generation(model, self.max_tokens, self.temperature.map(|value| value as f32))
+            .map(|_| ())
+    }
+}
+
+/// Where an effective value came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SettingSource {
+    Chat,
+    Project,
+    Global,
+    Default,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationSources {
+    pub model: SettingSource,
+    pub max_tokens: SettingSource,
+    pub temperature: SettingSource,
+    pub system_prompt: SettingSource,
+}
+
+/// The settings a chat's next request will use.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EffectiveGenerationSettings {
+    pub model: String,
+    pub max_tokens: u32,
+    pub temperature: f64,
+    pub system_prompt: String,
+    pub sources: GenerationSources,
+}
+
+fn pick<T: Clone>(
+    chat: &Option<T>,
+    project: &Option<T>,
+    global: &Option<T>,
+    default: T,
+) -> (T, SettingSource) {
+    if let Some(value) = chat {
+        (value.clone(), SettingSource::Chat)
+    } else if let Some(value) = project {
+        (value.clone(), SettingSource::Project)
+    } else if let Some(value) = global {
+        (value.clone(), SettingSource::Global)
+    } else {
+        (default, SettingSource::Default)
+    }
+}
+
+/// Resolves each field from the chat, then the project, then the global
+/// settings, then the built-in defaults.
+///
+/// An inherited `max_tokens` above what the chosen model allows is lowered
+/// to the model's limit.
+pub fn resolve(
+    chat: &GenerationSettings,
+    project: &GenerationSettings,
+    global: &GenerationSettings,
+    models: &ModelRegistry,
+) -> Result<EffectiveGenerationSettings, String> {
+    let (model, model_source) = pick(&chat.model, &project.model, &global.model, DEFAULT_MODEL.to_string());
+    let (max_tokens, max_tokens_source) = pick(&chat.max_tokens, &project.max_tokens, &global.max_tokens, DEFAULT_MAX_TOKENS);
+    let (temperature, temperature_source) = pick(&chat.temperature, &project.temperature, &global.temperature, DEFAULT_TEMPERATURE);
+    let (system_prompt, system_prompt_source) = pick(
+        &chat.system_prompt,
+        &project.system_prompt,
+        &global.system_prompt,
+        DEFAULT_SYSTEM_PROMPT.to_string(),
+    );
+
+    let info = models.require(&model)?;
+    let max_tokens = max_tokens.clamp(1, info.max_output_tokens);
+    models.validate_generation(&model, Some(max_tokens), Some(temperature as f32))?;
+
+    Ok(EffectiveGenerationSettings {
+        model,
+        max_tokens,
+        temperature,
+        system_prompt,
+        sources: GenerationSources {
+            model: model_source,
+            max_tokens: max_tokens_source,
+            temperature: temperature_source,
+            system_prompt: system_prompt_source,
+        },
+    })
+}
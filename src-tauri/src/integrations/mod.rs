@@ -1,4 +1,10 @@
 pub mod anthropic;
+pub mod attachments;
+pub mod auth;
+pub mod generation;
+pub mod models;
+pub mod oauth;
+pub mod prompt_cache;
 pub mod mcp;
 pub mod filesystem;
 
@@ -0,0 +1,352 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use crate::integrations::anthropic::{AnthropicClient, ListedModel};
+
+/// Model used when neither the chat nor the settings pick one.
+pub const DEFAULT_MODEL: &str = "claude-3-5-sonnet-20241022";
+
+/// Small, cheap model for housekeeping requests such as key validation.
+pub const LIGHTWEIGHT_MODEL: &str = "claude-3-haiku-20240307";
+
+/// Prices in USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub input: f64,
+    pub output: f64,
+    pub cache_write: f64,
+    pub cache_read: f64,
+}
+
+impl ModelPricing {
+    const fn new(input: f64, output: f64, cache_write: f64, cache_read: f64) -> Self {
+        Self { input, output, cache_write, cache_read }
+    }
+
+    /// Cost in USD of a single call.
+    pub fn cost(
+        &self,
+        input_tokens: u32,
+        output_tokens: u32,
+        cache_creation_input_tokens: u32,
+        cache_read_input_tokens: u32,
+    ) -> f64 {
+        (input_tokens as f64 * self.input
+            + output_tokens as f64 * self.output
+            + cache_creation_input_tokens as f64 * self.cache_write
+            + cache_read_input_tokens as f64 * self.cache_read)
+            / 1_000_000.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelCapabilities {
+    pub vision: bool,
+    pub tools: bool,
+    pub extended_thinking: bool,
+}
+
+impl ModelCapabilities {
+    const fn new(vision: bool, tools: bool, extended_thinking: bool) -> Self {
+        Self { vision, tools, extended_thinking }
+    }
+}
+
+/// Everything the app needs to know about a model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: String,
+    pub context_window: u32,
+    pub max_output_tokens: u32,
+    pub pricing: Option<ModelPricing>,
+    pub capabilities: ModelCapabilities,
+}
+
+// Limits and prices shared by every snapshot of a model family
+struct ModelFamily {
+    prefix: &'static str,
+    context_window: u32,
+    max_output_tokens: u32,
+    pricing: ModelPricing,
+    capabilities: ModelCapabilities,
+}
+
+const fn family(
+    prefix: &'static str,
+    max_output_tokens: u32,
+    pricing: ModelPricing,
+    capabilities: ModelCapabilities,
+) -> ModelFamily {
+    ModelFamily {
+        prefix,
+        context_window: 200_000,
+        max_output_tokens,
+        pricing,
+        capabilities,
+    }
+}
+
+// Most specific prefixes first; dated model ids match their family prefix
+const FAMILIES: &[ModelFamily] = &[
+    family("claude-opus-4-5", 64_000, ModelPricing::new(5.0, 25.0, 6.25, 0.50), ModelCapabilities::new(true, true, true)),
+    family("claude-opus-4", 32_000, ModelPricing::new(15.0, 75.0, 18.75, 1.50), ModelCapabilities::new(true, true, true)),
+    family("claude-sonnet-4", 64_000, ModelPricing::new(3.0, 15.0, 3.75, 0.30), ModelCapabilities::new(true, true, true)),
+    family("claude-haiku-4-5", 64_000, ModelPricing::new(1.0, 5.0, 1.25, 0.10), ModelCapabilities::new(true, true, true)),
+    family("claude-3-7-sonnet", 64_000, ModelPricing::new(3.0, 15.0, 3.75, 0.30), ModelCapabilities::new(true, true, true)),
+    family("claude-3-5-sonnet", 8_192, ModelPricing::new(3.0, 15.0, 3.75, 0.30), ModelCapabilities::new(true, true, false)),
+    family("claude-3-5-haiku", 8_192, ModelPricing::new(0.80, 4.0, 1.0, 0.08), ModelCapabilities::new(true, true, false)),
+    family("claude-3-opus", 4_096, ModelPricing::new(15.0, 75.0, 18.75, 1.50), ModelCapabilities::new(true, true, false)),
+    family("claude-3-haiku", 4_096, ModelPricing::new(0.25, 1.25, 0.30, 0.03), ModelCapabilities::new(true, true, false)),
+];
+
+// Snapshots known at build time, used until the first refresh
+const BUILTIN_MODELS: &[(&str, &str)] = &[
+    ("claude-opus-4-5-20251101", "Claude Opus 4.5"),
+    ("claude-haiku-4-5-20251001", "Claude Haiku 4.5"),
+    ("claude-sonnet-4-5-20250929", "Claude Sonnet 4.5"),
+    ("claude-opus-4-1-20250805", "Claude Opus 4.1"),
+    ("claude-opus-4-20250514", "Claude Opus 4"),
+    ("claude-sonnet-4-20250514", "Claude Sonnet 4"),
+    ("claude-3-7-sonnet-20250219", "Claude Sonnet 3.7"),
+    ("claude-3-5-sonnet-20241022", "Claude Sonnet 3.5"),
+    ("claude-3-5-haiku-20241022", "Claude Haiku 3.5"),
+    ("claude-3-opus-20240229", "Claude Opus 3"),
+    ("claude-3-haiku-20240307", "Claude Haiku 3"),
+];
+
+fn family_for(model: &str) -> Option<&'static ModelFamily> {
+    FAMILIES.iter().find(|family| model.starts_with(family.prefix))
+}
+
+/// Looks up the price of `model`, or `None` for models of unknown families.
+pub fn pricing_for(model: &str) -> Option<ModelPricing> {
+    family_for(model).map(|family| family.pricing)
+}
+
+/// Describes `id` from its family's limits, or `None` for unknown families.
+fn describe(id: &str, display_name: &str) -> Option<ModelInfo> {
+    family_for(id).map(|family| ModelInfo {
+        id: id.to_string(),
+        display_name: display_name.to_string(),
+        context_window: family.context_window,
+        max_output_tokens: family.max_output_tokens,
+        pricing: Some(family.pricing),
+        capabilities: family.capabilities,
+    })
+}
+
+/// `id` without its `-latest` or `-YYYYMMDD` snapshot suffix.
+fn undated_id(id: &str) -> &str {
+    if let Some(base) = id.strip_suffix("-latest") {
+        return base;
+    }
+    match id.rsplit_once('-') {
+        Some((base, date)) if date.len() == 8 && date.bytes().all(|byte| byte.is_ascii_digit()) => base,
+        _ => id,
+    }
+}
+
+pub fn builtin_models() -> Vec<ModelInfo> {
+    BUILTIN_MODELS
+        .iter()
+        .filter_map(|(id, display_name)| describe(id, display_name))
+        .collect()
+}
+
+// models.json in the data directory
+#[derive(Debug, Serialize, Deserialize)]
+struct ModelCache {
+    refreshed_at: DateTime<Utc>,
+    models: Vec<ModelInfo>,
+}
+
+/// The models the app can use, seeded from the built-in list and refreshed
+/// from the `/v1/models` endpoint.
+pub struct ModelRegistry {
+    models: RwLock<Vec<ModelInfo>>,
+    refreshed_at: RwLock<Option<DateTime<Utc>>>,
+    cache_path: Option<PathBuf>,
+}
+
+impl ModelRegistry {
+    /// Loads the cached listing from `cache_path` when there is one, falling
+    /// back to the built-in models.
+    pub fn new(cache_path: Option<PathBuf>) -> Self {
+        let cache = cache_path.as_ref().and_then(|path| {
+            let content = std::fs::read_to_string(path).ok()?;
+            match serde_json::from_str::<ModelCache>(&content) {
+                Ok(cache) => Some(cache),
+                Err(e) => {
+                    log::warn!("Ignoring unreadable model cache: {}", e);
+                    None
+                }
+            }
+        });
+
+        let (models, refreshed_at) = match cache {
+            Some(cache) if !cache.models.is_empty() => (cache.models, Some(cache.refreshed_at)),
+            _ => (builtin_models(), None),
+        };
+
+        Self {
+            models: RwLock::new(models),
+            refreshed_at: RwLock::new(refreshed_at),
+            cache_path,
+        }
+    }
+
+    /// Registry cached in the app data directory.
+    pub fn load_default() -> Self {
+        match crate::utils::config::get_data_dir() {
+            Ok(data_dir) => Self::new(Some(data_dir.join("models.json"))),
+            Err(e) => {
+                log::warn!("Model cache unavailable, using built-in models: {}", e);
+                Self::new(None)
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<ModelInfo> {
+        self.models.read().unwrap().clone()
+    }
+
+    pub fn refreshed_at(&self) -> Option<DateTime<Utc>> {
+        *self.refreshed_at.read().unwrap()
+    }
+
+    /// Looks up `id`. Ids that aren't listed but name another snapshot or
+    /// the `-latest` alias of a listed model or known family are described
+    /// from it; anything else is unknown.
+    pub fn get(&self, id: &str) -> Option<ModelInfo> {
+        let models = self.models.read().unwrap();
+        if let Some(model) = models.iter().find(|model| model.id == id) {
+            return Some(model.clone());
+        }
+
+        let base = undated_id(id);
+        if let Some(model) = models.iter().find(|model| undated_id(&model.id) == base) {
+            return Some(ModelInfo { id: id.to_string(), display_name: id.to_string(), ..model.clone() });
+        }
+        FAMILIES
+            .iter()
+            .any(|family| family.prefix == base)
+            .then(|| describe(id, id))
+            .flatten()
+    }
+
+    /// Like `get`, but with an error message suitable for the user.
+    pub fn require(&self, id: &str) -> Result<ModelInfo, String> {
+        self.get(id).ok_or_else(|| {
+            format!(
+                "Unknown model '{}'. Refresh the model list or choose one of: {}",
+                id,
+                self.list()
+                    .iter()
+                    .map(|model| model.id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+
+    /// Replaces the listing with `listed`, keeping family limits and prices
+    /// for each, and writes it to the cache.
+    pub fn apply_listing(&self, listed: Vec<ListedModel>) -> Result<Vec<ModelInfo>> {
+        let models: Vec<ModelInfo> = listed
+            .into_iter()
+            .map(|model| {
+                describe(&model.id, &model.display_name).unwrap_or_else(|| ModelInfo {
+                    id: model.id,
+                    display_name: model.display_name,
+                    context_window: 200_000,
+                    max_output_tokens: 4_096,
+                    pricing: None,
+                    capabilities: ModelCapabilities::default(),
+                })
+            })
+            .collect();
+
+        if models.is_empty() {
+            return Err(anyhow::anyhow!("The models endpoint returned no models"));
+        }
+
+        let refreshed_at = Utc::now();
+        if let Some(path) = &self.cache_path {
+            let cache = ModelCache {
+                refreshed_at,
+                models: models.clone(),
+            };
+            std::fs::write(path, serde_json::to_string_pretty(&cache)?)?;
+        }
+
+        *self.models.write().unwrap() = models.clone();
+        *self.refreshed_at.write().unwrap() = Some(refreshed_at);
+        Ok(models)
+    }
+
+    /// Fetches the current listing from the API.
+    pub async fn refresh(&self, client: &AnthropicClient) -> Result<Vec<ModelInfo>> {
+        let listed = client.list_models().await?;
+        log::info!("Fetched {} models from the API", listed.len());
+        self.apply_listing(listed)
+    }
+
+    /// Checks generation parameters against what `model` supports.
+    pub fn validate_generation(
+        &self,
+        model: &str,
+        max_tokens: Option<u32>,
+        temperature: Option<f32>,
+    ) -> Result<ModelInfo, String> {
+        let info = self.require(model)?;
+
+        if let Some(max_tokens) = max_tokens {
+            if max_tokens == 0 || max_tokens > info.max_output_tokens {
+                return Err(format!(
+                    "max_tokens must be between 1 and {} for {}",
+                    info.max_output_tokens, info.id
+                ));
+            }
+        }
+        if let Some(temperature) = temperature {
+            if !(0.0..=1.0).contains(&temperature) {
+                return Err("temperature must be between 0 and 1".to_string());
+            }
+        }
+
+        Ok(info)
+    }
+
+    /// Validates the `model`, `max_tokens` and `temperature` keys of an
+    /// agent's `model_config` or a chat's overrides. Missing keys are fine.
+    pub fn validate_model_config(&self, config: &HashMap<String, serde_json::Value>) -> Result<(), String> {
+        let model = match config.get("model") {
+            None | Some(serde_json::Value::Null) => DEFAULT_MODEL,
+            Some(serde_json::Value::String(model)) => model.as_str(),
+            Some(_) => return Err("model must be a string".to_string()),
+        };
+        let max_tokens = match config.get("max_tokens") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(value) => Some(
+                value
+                    .as_u64()
+                    .and_then(|value| u32::try_from(value).ok())
+                    .ok_or_else(|| "max_tokens must be a positive integer".to_string())?,
+            ),
+        };
+        let temperature = match config.get("temperature") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(value) => Some(
+                value
+                    .as_f64()
+                    .ok_or_else(|| "temperature must be a number".to_string())? as f32,
+            ),
+        };
+
+        self.validate_generation(model, max_tokens, temperature).map(|_| ())
+    }
+}
@@ -0,0 +1,484 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use ring::digest;
+use base64::{Engine as _, engine::general_purpose};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use crate::utils::credentials::CredentialStore;
+use crate::utils::crypto::Secret;
+use crate::utils::logger::REDACTED;
+
+// Anthropic OAuth configuration
+const ANTHROPIC_AUTH_URL: &str = "https://console.anthropic.com/oauth/authorize";
+const ANTHROPIC_TOKEN_URL: &str = "https://api.anthropic.com/v1/oauth/token";
+// Claude Code's official client ID for desktop applications
+const DEFAULT_CLIENT_ID: &str = "9d1c250a-e61b-44d9-88ed-5944d1962f5e";
+
+const CALLBACK_PATH: &str = "/oauth/callback";
+const SESSION_TTL: Duration = Duration::from_secs(10 * 60);
+const MAX_REQUEST_BYTES: usize = 16 * 1024;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OAuthTokenResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub scope: String,
+}
+
+impl std::fmt::Debug for OAuthTokenResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OAuthTokenResponse")
+            .field("access_token", &REDACTED)
+            .field("refresh_token", &REDACTED)
+            .field("token_type", &self.token_type)
+            .field("expires_in", &self.expires_in)
+            .field("scope", &self.scope)
+            .finish()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OAuthError {
+    pub error: String,
+    pub error_description: String,
+}
+
+/// Endpoints and client credentials for the authorization server.
+#[derive(Debug, Clone)]
+pub struct OAuthConfig {
+    pub authorize_url: String,
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: Option<Secret>,
+}
+
+impl OAuthConfig {
+    /// Anthropic's endpoints, with the client overridable through
+    /// `ANTHROPIC_CLIENT_ID` / `ANTHROPIC_CLIENT_SECRET`.
+    pub fn from_env() -> Self {
+        Self {
+            authorize_url: ANTHROPIC_AUTH_URL.to_string(),
+            token_url: ANTHROPIC_TOKEN_URL.to_string(),
+            client_id: std::env::var("ANTHROPIC_CLIENT_ID")
+                .unwrap_or_else(|_| DEFAULT_CLIENT_ID.to_string()),
+            client_secret: std::env::var("ANTHROPIC_CLIENT_SECRET").ok().map(Secret::new),
+        }
+    }
+}
+
+/// An authorization request waiting for its redirect.
+pub struct PendingAuthorization {
+    pub code_verifier: Secret,
+    pub redirect_uri: String,
+    created_at: Instant,
+}
+
+/// In-flight authorization requests keyed by their `state` parameter.
+pub struct OAuthSessionStore {
+    sessions: Mutex<HashMap<String, PendingAuthorization>>,
+    ttl: Duration,
+}
+
+impl OAuthSessionStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Registers a new authorization request and returns its `(state, code_challenge)`.
+    pub fn create(&self, redirect_uri: String) -> (String, String) {
+        let state = generate_random_string(32);
+        let code_verifier = generate_code_verifier();
+        let code_challenge = generate_code_challenge(&code_verifier);
+
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.created_at.elapsed() < self.ttl);
+        sessions.insert(
+            state.clone(),
+            PendingAuthorization {
+                code_verifier: Secret::new(code_verifier),
+                redirect_uri,
+                created_at: Instant::now(),
+            },
+        );
+
+        (state, code_challenge)
+    }
+
+    /// Removes and returns the request for `state`, unless it is unknown or expired.
+    pub fn take(&self, state: &str) -> Option<PendingAuthorization> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .remove(state)
+            .filter(|session| session.created_at.elapsed() < self.ttl)
+    }
+
+    pub fn has_pending(&self) -> bool {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.values().any(|session| session.created_at.elapsed() < self.ttl)
+    }
+
+    /// When the most recently created request expires.
+    pub fn expires_at(&self) -> Option<Instant> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.values().map(|session| session.created_at + self.ttl).max()
+    }
+}
+
+/// A running loopback server and the result of the flow it is waiting for.
+pub struct CallbackServer {
+    pub port: u16,
+    pub completion: tokio::task::JoinHandle<Result<OAuthTokenResponse, String>>,
+}
+
+/// Drives the authorization code flow with PKCE against one authorization server.
+pub struct OAuthManager {
+    config: OAuthConfig,
+    sessions: OAuthSessionStore,
+    active_port: Mutex<Option<u16>>,
+    // Hands a pasted code's tokens to the running callback server so it stops
+    manual_exchange: Mutex<Option<oneshot::Sender<OAuthTokenResponse>>>,
+    refresh_lock: tokio::sync::Mutex<()>,
+    http: reqwest::Client,
+}
+
+impl OAuthManager {
+    pub fn new(config: OAuthConfig) -> Self {
+        Self::with_session_ttl(config, SESSION_TTL)
+    }
+
+    pub fn with_session_ttl(config: OAuthConfig, ttl: Duration) -> Self {
+        Self {
+            config,
+            sessions: OAuthSessionStore::new(ttl),
+            active_port: Mutex::new(None),
+            manual_exchange: Mutex::new(None),
+            refresh_lock: tokio::sync::Mutex::new(()),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Port of the callback server currently waiting for a redirect, if any.
+    pub fn active_port(&self) -> Option<u16> {
+        *self.active_port.lock().unwrap()
+    }
+
+    /// Held while refreshing tokens so concurrent requests refresh only once.
+    pub fn refresh_lock(&self) -> &tokio::sync::Mutex<()> {
+        &self.refresh_lock
+    }
+
+    /// Builds the authorization URL for a new request redirecting to `port`.
+    pub fn authorization_url(&self, port: u16) -> String {
+        let redirect_uri = format!("http://127.0.0.1:{}{}", port, CALLBACK_PATH);
+        let (state, code_challenge) = self.sessions.create(redirect_uri.clone());
+
+        format!(
+            "{}?response_type=code&client_id={}&redirect_uri={}&state={}&code_challenge={}&code_challenge_method=S256",
+            self.config.authorize_url,
+            urlencoding::encode(&self.config.client_id),
+            urlencoding::encode(&redirect_uri),
+            urlencoding::encode(&state),
+            urlencoding::encode(&code_challenge)
+        )
+    }
+
+    /// Binds a loopback listener and serves redirects until a flow completes,
+    /// through a redirect or a pasted code, or every pending request has
+    /// expired, then shuts down.
+    pub async fn start_callback_server(
+        self: &Arc<Self>,
+        store: Arc<dyn CredentialStore>,
+    ) -> Result<CallbackServer, String> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| format!("Failed to bind OAuth callback server: {}", e))?;
+
+        let port = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to get server port: {}", e))?
+            .port();
+
+        let (exchanged, manual_exchange) = oneshot::channel();
+        *self.active_port.lock().unwrap() = Some(port);
+        *self.manual_exchange.lock().unwrap() = Some(exchanged);
+        log::info!("OAuth callback server listening on 127.0.0.1:{}", port);
+
+        let manager = Arc::clone(self);
+        let completion = tokio::spawn(async move {
+            let result = manager.serve_callbacks(listener, manual_exchange, store.as_ref()).await;
+            *manager.active_port.lock().unwrap() = None;
+            manager.manual_exchange.lock().unwrap().take();
+            log::info!("OAuth callback server on port {} shut down", port);
+            result
+        });
+
+        Ok(CallbackServer { port, completion })
+    }
+
+    async fn serve_callbacks(
+        &self,
+        listener: TcpListener,
+        mut manual_exchange: oneshot::Receiver<OAuthTokenResponse>,
+        store: &dyn CredentialStore,
+    ) -> Result<OAuthTokenResponse, String> {
+        // Requests started after the server keep it alive for their own TTL
+        let started = Instant::now();
+        let deadline = || {
+            let idle = started + self.sessions.ttl;
+            self.sessions.expires_at().map_or(idle, |expires_at| expires_at.max(idle))
+        };
+
+        loop {
+            let accepted = tokio::select! {
+                accepted = tokio::time::timeout_at(deadline().into(), listener.accept()) => accepted,
+                tokens = &mut manual_exchange => {
+                    return tokens.map_err(|_| "OAuth callback server was stopped".to_string());
+                }
+            };
+            let (mut stream, _) = match accepted {
+                Ok(Ok(connection)) => connection,
+                Ok(Err(e)) => {
+                    log::warn!("OAuth callback server failed to accept connection: {}", e);
+                    continue;
+                }
+                // A request may have been started while waiting
+                Err(_) if Instant::now() < deadline() => continue,
+                Err(_) => return Err("OAuth authorization timed out".to_string()),
+            };
+
+            let Some(target) = read_request_target(&mut stream).await else {
+                respond(&mut stream, "400 Bad Request", "Malformed request.").await;
+                continue;
+            };
+
+            let Ok(url) = reqwest::Url::parse(&format!("http://127.0.0.1{}", target)) else {
+                respond(&mut stream, "400 Bad Request", "Malformed request.").await;
+                continue;
+            };
+
+            if url.path() != CALLBACK_PATH {
+                respond(&mut stream, "404 Not Found", "Not found.").await;
+                continue;
+            }
+
+            let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+            if let Some(error) = params.get("error") {
+                let description = params.get("error_description").unwrap_or(error);
+                respond(&mut stream, "200 OK", "Authorization was not completed. You can close this window.").await;
+                return Err(format!("OAuth authorization failed: {}", description));
+            }
+
+            let (Some(code), Some(state)) = (params.get("code"), params.get("state")) else {
+                respond(&mut stream, "400 Bad Request", "Missing code or state.").await;
+                continue;
+            };
+
+            // A forged or stale redirect must not end the flow for the real one
+            let Some(session) = self.sessions.take(state) else {
+                log::warn!("Rejected OAuth callback with unknown or expired state");
+                respond(&mut stream, "400 Bad Request", "Unknown or expired authorization request.").await;
+                if !self.sessions.has_pending() {
+                    return Err("OAuth authorization request expired".to_string());
+                }
+                continue;
+            };
+
+            let result = self.exchange_with_session(code, &session, store).await;
+            match &result {
+                Ok(_) => respond(&mut stream, "200 OK", "Signed in to Cloddo. You can close this window.").await,
+                Err(_) => respond(&mut stream, "200 OK", "Sign-in failed. Return to Cloddo for details.").await,
+            }
+            return result;
+        }
+    }
+
+    /// Exchanges a code pasted by the user, after validating its `state`,
+    /// and stops the callback server once the exchange succeeds.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        state: &str,
+        store: &dyn CredentialStore,
+    ) -> Result<OAuthTokenResponse, String> {
+        let session = self
+            .sessions
+            .take(state)
+            .ok_or_else(|| "Unknown or expired OAuth state. Please start sign-in again.".to_string())?;
+
+        let tokens = self.exchange_with_session(code, &session, store).await?;
+        if let Some(server) = self.manual_exchange.lock().unwrap().take() {
+            let _ = server.send(tokens.clone());
+        }
+        Ok(tokens)
+    }
+
+    async fn exchange_with_session(
+        &self,
+        code: &str,
+        session: &PendingAuthorization,
+        store: &dyn CredentialStore,
+    ) -> Result<OAuthTokenResponse, String> {
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", session.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", session.code_verifier.expose()),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            params.push(("client_secret", secret.expose()));
+        }
+
+        let tokens = self.request_tokens(&params, "Failed to exchange OAuth code").await?;
+        store_tokens(&tokens, store).await?;
+        Ok(tokens)
+    }
+
+    /// Trades a refresh token for new tokens and stores them.
+    pub async fn refresh(
+        &self,
+        refresh_token: &str,
+        store: &dyn CredentialStore,
+    ) -> Result<OAuthTokenResponse, String> {
+        let mut params = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", self.config.client_id.as_str()),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            params.push(("client_secret", secret.expose()));
+        }
+
+        let tokens = self.request_tokens(&params, "Failed to refresh OAuth token").await?;
+        store_tokens(&tokens, store).await?;
+        Ok(tokens)
+    }
+
+    async fn request_tokens(
+        &self,
+        params: &[(&str, &str)],
+        context: &str,
+    ) -> Result<OAuthTokenResponse, String> {
+        let response = self
+            .http
+            .post(&self.config.token_url)
+            .form(params)
+            .send()
+            .await
+            .map_err(|e| format!("{}: {}", context, e))?;
+
+        if response.status().is_success() {
+            response
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse token response: {}", e))
+        } else {
+            let error: OAuthError = response
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse error response: {}", e))?;
+            Err(format!("OAuth error: {}", error.error_description))
+        }
+    }
+}
+
+/// Persists a token response in the credential store, alongside the time
+/// the access token expires.
+pub async fn store_tokens(
+    tokens: &OAuthTokenResponse,
+    store: &dyn CredentialStore,
+) -> Result<(), String> {
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(tokens.expires_in);
+
+    let entries = [
+        ("api.anthropicOAuthToken", tokens.access_token.clone()),
+        ("api.anthropicRefreshToken", tokens.refresh_token.clone()),
+        ("api.anthropicOAuthExpiresAt", expires_at.to_rfc3339()),
+    ];
+    for (key, value) in entries {
+        store
+            .set(key, &value)
+            .await
+            .map_err(|e| format!("Failed to store OAuth tokens: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Reads the request head and returns the request target of a `GET`.
+async fn read_request_target(stream: &mut TcpStream) -> Option<String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+
+    while !buffer.windows(4).any(|window| window == b"\r\n\r\n") {
+        if buffer.len() > MAX_REQUEST_BYTES {
+            return None;
+        }
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut chunk))
+            .await
+            .ok()?
+            .ok()?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let head = String::from_utf8_lossy(&buffer);
+    let mut parts = head.lines().next()?.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => Some(target.to_string()),
+        _ => None,
+    }
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, message: &str) {
+    let body = format!(
+        "<!DOCTYPE html><html><head><title>Cloddo</title></head><body><p>{}</p></body></html>",
+        message
+    );
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        log::warn!("Failed to write OAuth callback response: {}", e);
+    }
+    let _ = stream.shutdown().await;
+}
+
+// PKCE helper functions
+fn generate_random_string(length: usize) -> String {
+    use rand::Rng;
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~";
+    let mut rng = rand::thread_rng();
+
+    (0..length)
+        .map(|_| {
+            let idx = rng.gen_range(0..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect()
+}
+
+fn generate_code_verifier() -> String {
+    generate_random_string(128)
+}
+
+pub fn generate_code_challenge(verifier: &str) -> String {
+    let digest = digest::digest(&digest::SHA256, verifier.as_bytes());
+    general_purpose::URL_SAFE_NO_PAD.encode(digest.as_ref())
+}
@@ -0,0 +1,114 @@
+use crate::integrations::anthropic::{
+    AnthropicMessage, AnthropicRequest, CacheControl, MessageContent, RequestBlock, SystemBlock, SystemPrompt,
+};
+
+/// The API accepts at most this many cache breakpoints per request.
+pub const MAX_CACHE_BREAKPOINTS: usize = 4;
+
+fn message_blocks(message: &AnthropicMessage) -> &[RequestBlock] {
+    match &message.content {
+        MessageContent::Blocks(blocks) => blocks,
+        MessageContent::Text(_) => &[],
+    }
+}
+
+/// Number of `cache_control` markers already in `request`.
+pub fn count_breakpoints(request: &AnthropicRequest) -> usize {
+    let tools = request
+        .tools
+        .iter()
+        .flatten()
+        .filter(|tool| tool.cache_control.is_some())
+        .count();
+    let system = match &request.system {
+        Some(SystemPrompt::Blocks(blocks)) => blocks.iter().filter(|block| block.cache_control.is_some()).count(),
+        _ => 0,
+    };
+    let messages = request
+        .messages
+        .iter()
+        .flat_map(message_blocks)
+        .filter(|block| block.cache_control().is_some())
+        .count();
+
+    tools + system + messages
+}
+
+/// Puts a breakpoint on the last block of `message`, converting plain text
+/// content into a block.
+fn mark_message(message: &mut AnthropicMessage) {
+    if let MessageContent::Text(text) = &mut message.content {
+        message.content = MessageContent::Blocks(vec![RequestBlock::text(std::mem::take(text))]);
+    }
+    if let MessageContent::Blocks(blocks) = &mut message.content {
+        if let Some(block) = blocks.last_mut() {
+            *block.cache_control_mut() = Some(CacheControl::ephemeral());
+        }
+    }
+}
+
+/// Places cache breakpoints on the parts of `request` that repeat from one
+/// call to the next, in order of how stable they are: the tool definitions,
+/// the system prompt (including any project knowledge after it), and the
+/// conversation so far.
+///
+/// The conversation gets a breakpoint on its newest message, so the next
+/// turn reads everything up to it from the cache, and one on the previous
+/// user message, which the current call can read. Sections the caller
+/// already marked are left alone, and no more than
+/// [`MAX_CACHE_BREAKPOINTS`] are used in total. Prefixes shorter than the
+/// model's minimum cacheable length are simply not cached by the API.
+///
+/// Returns the number of breakpoints added.
+pub fn apply_cache_breakpoints(request: &mut AnthropicRequest) -> usize {
+    let mut available = MAX_CACHE_BREAKPOINTS.saturating_sub(count_breakpoints(request));
+    let mut added = 0;
+
+    if let Some(tools) = request.tools.as_mut() {
+        if available > 0 && !tools.iter().any(|tool| tool.cache_control.is_some()) {
+            if let Some(tool) = tools.last_mut() {
+                tool.cache_control = Some(CacheControl::ephemeral());
+                available -= 1;
+                added += 1;
+            }
+        }
+    }
+
+    if let Some(SystemPrompt::Text(text)) = &mut request.system {
+        request.system = Some(SystemPrompt::Blocks(vec![SystemBlock::text(std::mem::take(text))]));
+    }
+    if let Some(SystemPrompt::Blocks(blocks)) = request.system.as_mut() {
+        if available > 0 && !blocks.iter().any(|block| block.cache_control.is_some()) {
+            if let Some(block) = blocks.last_mut() {
+                block.cache_control = Some(CacheControl::ephemeral());
+                available -= 1;
+                added += 1;
+            }
+        }
+    }
+
+    let history_marked = request
+        .messages
+        .iter()
+        .flat_map(message_blocks)
+        .any(|block| block.cache_control().is_some());
+    if history_marked || request.messages.is_empty() {
+        return added;
+    }
+
+    let last = request.messages.len() - 1;
+    let previous_user = request.messages[..last]
+        .iter()
+        .rposition(|message| message.role == "user");
+
+    for index in std::iter::once(last).chain(previous_user) {
+        if available == 0 {
+            break;
+        }
+        mark_message(&mut request.messages[index]);
+        available -= 1;
+        added += 1;
+    }
+
+    added
+}
@@ -6,10 +6,14 @@ pub mod workflows;
 pub mod integrations;
 pub mod utils;
 
-use commands::{chat, project, settings, agent, oauth};
-use database::Database;
+use commands::{session, chat, project, folder, tag, collection, settings, agent, oauth, usage, budget, models, attachments, search, export, importers, backup, trash};
+use database::{legacy, Database};
+use integrations::attachments::{AttachmentStore, GC_GRACE_PERIOD};
+use integrations::models::ModelRegistry;
+use integrations::oauth::{OAuthConfig, OAuthManager};
 use utils::config::AppConfig;
 use std::sync::Arc;
+use std::time::Duration;
 use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -21,6 +25,16 @@ pub fn run() {
         app.handle().plugin(
           tauri_plugin_log::Builder::default()
             .level(log::LevelFilter::Info)
+            // Scrub API keys, tokens and OAuth codes from every record
+            .format(|out, message, record| {
+              out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
+                record.level(),
+                record.target(),
+                utils::logger::redact(&message.to_string())
+              ))
+            })
             .build(),
         )?;
       }
@@ -28,9 +42,72 @@ pub fn run() {
       // Initialize configuration
       let config = AppConfig::load().expect("Failed to load configuration");
       app.manage(Arc::new(config));
+      app.manage(Arc::new(OAuthManager::new(OAuthConfig::from_env())));
+      app.manage(Arc::new(ModelRegistry::load_default()));
 
-      // Skip database initialization for now - will initialize lazily
-      // TODO: Initialize database properly
+      // Put a backup chosen with restore_backup in place before the data is opened
+      let restored = Database::get_database_path().and_then(|db_path| {
+        utils::backup::apply_pending_restore(&db_path, &utils::config::get_data_dir()?)
+      });
+      if let Err(e) = restored {
+        log::error!("Failed to restore backup: {}", e);
+      }
+
+      // Initialize database
+      let db = tauri::async_runtime::block_on(Database::new())
+        .expect("Failed to initialize database");
+      let imported = utils::config::get_data_dir()
+        .and_then(|data_dir| tauri::async_runtime::block_on(legacy::import_chats(&db, &data_dir)));
+      if let Err(e) = imported {
+        log::error!("Failed to import chats.json: {}", e);
+      }
+      if let Err(e) = tauri::async_runtime::block_on(settings::import_legacy_settings(&db)) {
+        log::error!("Failed to import settings.json: {}", e);
+      }
+      let db = Arc::new(db);
+      app.manage(db.clone());
+
+      // Scheduled backups; settings decide how often and how many are kept
+      let backup_db = db.clone();
+      tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+          interval.tick().await;
+          if let Err(e) = backup::run_scheduled_backup(&backup_db).await {
+            log::error!("Scheduled backup failed: {}", e);
+          }
+        }
+      });
+
+      // Attachments, with unreferenced files cleaned up every few hours
+      let attachment_store = Arc::new(
+        AttachmentStore::open_default().expect("Failed to open attachment store"),
+      );
+      app.manage(attachment_store.clone());
+
+      // Trashed chats, projects and agents are purged once they are older
+      // than the retention period
+      let purge_db = db.clone();
+      let purge_store = attachment_store.clone();
+      tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+        loop {
+          interval.tick().await;
+          if let Err(e) = trash::run_scheduled_purge(&purge_db, &purge_store).await {
+            log::error!("Trash purge failed: {}", e);
+          }
+        }
+      });
+
+      tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(6 * 60 * 60));
+        loop {
+          interval.tick().await;
+          if let Err(e) = attachment_store.collect_garbage(db.pool(), GC_GRACE_PERIOD).await {
+            log::error!("Attachment cleanup failed: {}", e);
+          }
+        }
+      });
 
       log::info!("Cloddo application initialized successfully");
       Ok(())
@@ -40,6 +117,18 @@ pub fn run() {
     .plugin(tauri_plugin_shell::init())
     .plugin(tauri_plugin_store::Builder::default().build())
     .invoke_handler(tauri::generate_handler![
+      // Session commands
+      session::get_user_profile,
+      session::update_user_profile,
+      session::get_sessions,
+      session::get_session,
+      session::create_session,
+      session::rename_session,
+      session::archive_session,
+      session::unarchive_session,
+      session::get_active_session,
+      session::switch_session,
+      
       // Chat commands
       chat::get_chats,
       chat::get_chat_by_id,
@@ -48,13 +137,48 @@ pub fn run() {
       chat::delete_chat,
       chat::get_messages,
       chat::send_claude_message,
+      chat::edit_message,
+      chat::regenerate_message,
+      chat::get_message_siblings,
+      chat::switch_branch,
+      chat::get_chat_generation_settings,
+      chat::set_chat_generation_settings,
       
       // Project commands
       project::get_projects,
       project::create_project,
       project::update_project,
       project::delete_project,
+      project::set_project_generation_settings,
+      project::add_project_document,
+      project::get_project_documents,
+      project::delete_project_document,
+      project::get_project_knowledge,
       
+      // Folder commands
+      folder::get_folders,
+      folder::create_folder,
+      folder::rename_folder,
+      folder::move_folder,
+      folder::delete_folder,
+      folder::move_chats_to_folder,
+
+      // Tag commands
+      tag::get_tags,
+      tag::create_tag,
+      tag::update_tag,
+      tag::delete_tag,
+      tag::get_chat_tags,
+      tag::set_chat_tags,
+
+      // Collection commands
+      collection::get_collections,
+      collection::create_collection,
+      collection::update_collection,
+      collection::delete_collection,
+      collection::get_collection_chats,
+      collection::preview_collection,
+
       // Settings commands
       settings::get_settings,
       settings::update_settings,
@@ -76,6 +200,51 @@ pub fn run() {
       oauth::validate_oauth_token,
       oauth::start_oauth_server,
       oauth::open_url,
+
+      // Usage commands
+      usage::get_usage_by_day,
+      usage::get_usage_by_model,
+      usage::get_usage_by_project,
+      usage::get_usage_by_agent,
+
+      // Budget commands
+      budget::get_budgets,
+      budget::create_budget,
+      budget::update_budget,
+      budget::delete_budget,
+      budget::get_budget_status,
+      budget::get_budget_events,
+
+      // Model commands
+      models::get_models,
+      models::get_model,
+      models::refresh_models,
+
+      // Attachment commands
+      attachments::add_attachment,
+      attachments::add_attachment_data,
+      attachments::collect_attachment_garbage,
+
+      // Search commands
+      search::search_messages,
+      search::rebuild_search_index,
+
+      // Export commands
+      export::export_chat,
+      export::export_project,
+      importers::import_conversations,
+
+      // Backup commands
+      backup::create_backup,
+      backup::list_backups,
+      backup::restore_backup,
+
+      // Trash commands
+      trash::get_trash,
+      trash::restore_chat,
+      trash::restore_project,
+      trash::restore_agent,
+      trash::empty_trash,
     ])
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
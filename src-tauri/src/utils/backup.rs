@@ -0,0 +1,400 @@
+use crate::database::migrations::{self, SCHEMA_VERSION};
+use crate::utils::crypto::{self, SecureStorage, SALT_LEN};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use sqlx::ConnectOptions;
+use sqlx::sqlite::SqliteConnectOptions;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const BACKUP_FORMAT_NAME: &str = "cloddo-backup";
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_NAME: &str = "manifest.json";
+const DATABASE_ENTRY: &str = "database/cloddo.db";
+const DATA_PREFIX: &str = "data";
+
+// Only attachments are backed up from the data directory. Credentials and the
+// encryption salt stay behind, as they are bound to this machine, and the
+// JSON files of earlier versions are in the database once imported
+const ATTACHMENTS_DIR: &str = "attachments";
+
+const FILE_PREFIX: &str = "cloddo-backup-";
+const FILE_TIMESTAMP: &str = "%Y%m%d-%H%M%S%.3f";
+const ARCHIVE_EXTENSION: &str = ".tar.gz";
+const ENCRYPTED_EXTENSION: &str = ".tar.gz.enc";
+
+// Encrypted archives start with this, then the salt the key was derived with
+const ENCRYPTED_MAGIC: &[u8] = b"CLODDO-BACKUP-ENC1";
+
+// Where `stage_restore` leaves a verified backup for the next launch
+const PENDING_RESTORE_DIR: &str = "restore-pending";
+
+/// A file in a backup, with what it must contain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupEntry {
+    pub path: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+}
+
+/// Describes a backup archive; stored in it as `manifest.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format: String,
+    pub version: u32,
+    pub app_version: String,
+    /// Database schema version the backup was taken at.
+    pub schema_version: i64,
+    pub created_at: DateTime<Utc>,
+    pub entries: Vec<BackupEntry>,
+}
+
+impl BackupManifest {
+    /// Checks that this app can restore the backup. Backups from newer
+    /// versions are refused since their schema cannot be migrated down.
+    pub fn validate(&self) -> Result<()> {
+        if self.format != BACKUP_FORMAT_NAME {
+            return Err(anyhow::anyhow!("Not a Cloddo backup"));
+        }
+        if self.version > BACKUP_FORMAT_VERSION {
+            return Err(anyhow::anyhow!("Backup format version {} is newer than this app supports", self.version));
+        }
+        if self.schema_version > SCHEMA_VERSION {
+            return Err(anyhow::anyhow!(
+                "Backup was made with database schema {}, newer than this app's {}; update the app first",
+                self.schema_version,
+                SCHEMA_VERSION
+            ));
+        }
+        if !self.entries.iter().any(|entry| entry.path == DATABASE_ENTRY) {
+            return Err(anyhow::anyhow!("Backup has no database"));
+        }
+        Ok(())
+    }
+}
+
+/// A backup archive on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub path: String,
+    pub file_name: String,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+    pub encrypted: bool,
+}
+
+/// Where backups are kept by default.
+pub fn backups_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("backups")
+}
+
+// Reads a backup's details from its file name
+fn backup_info(path: &Path) -> Option<BackupInfo> {
+    let file_name = path.file_name()?.to_str()?.to_string();
+    let (stem, encrypted) = match file_name.strip_suffix(ENCRYPTED_EXTENSION) {
+        Some(stem) => (stem, true),
+        None => (file_name.strip_suffix(ARCHIVE_EXTENSION)?, false),
+    };
+    let timestamp = stem.strip_prefix(FILE_PREFIX)?;
+    let created_at = NaiveDateTime::parse_from_str(timestamp, FILE_TIMESTAMP).ok()?.and_utc();
+
+    Some(BackupInfo {
+        path: path.to_string_lossy().to_string(),
+        size_bytes: fs::metadata(path).ok()?.len(),
+        file_name,
+        created_at,
+        encrypted,
+    })
+}
+
+/// Backups in `dir`, newest first.
+pub fn list_backups(dir: &Path) -> Result<Vec<BackupInfo>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups: Vec<BackupInfo> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| backup_info(&entry.path()))
+        .collect();
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+    Ok(backups)
+}
+
+/// Deletes all but the newest `keep` backups in `dir`. Returns how many were
+/// deleted.
+pub fn prune_backups(dir: &Path, keep: usize) -> Result<usize> {
+    let mut deleted = 0;
+    for backup in list_backups(dir)?.into_iter().skip(keep) {
+        fs::remove_file(&backup.path)?;
+        deleted += 1;
+    }
+    Ok(deleted)
+}
+
+// Relative paths of the files under `dir`, with `/` separators
+fn files_under(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let name = if prefix.is_empty() { file_name } else { format!("{}/{}", prefix, file_name) };
+        if entry.file_type()?.is_dir() {
+            files_under(&entry.path(), &name, files)?;
+        } else {
+            files.push((name, entry.path()));
+        }
+    }
+    Ok(())
+}
+
+fn write_archive(archive_path: &Path, snapshot: &Path, data_dir: &Path, schema_version: i64, created_at: DateTime<Utc>) -> Result<()> {
+    let mut files = vec![(DATABASE_ENTRY.to_string(), snapshot.to_path_buf())];
+    files_under(&data_dir.join(ATTACHMENTS_DIR), &format!("{}/{}", DATA_PREFIX, ATTACHMENTS_DIR), &mut files)?;
+
+    let mut entries = Vec::with_capacity(files.len());
+    for (name, path) in &files {
+        let (size_bytes, sha256) = crypto::sha256_reader(fs::File::open(path)?)?;
+        entries.push(BackupEntry {
+            path: name.clone(),
+            size_bytes,
+            sha256,
+        });
+    }
+    let manifest = BackupManifest {
+        format: BACKUP_FORMAT_NAME.to_string(),
+        version: BACKUP_FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version,
+        created_at,
+        entries,
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest)?;
+
+    let encoder = GzEncoder::new(fs::File::create(archive_path)?, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(created_at.timestamp().max(0) as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_NAME, manifest.as_slice())?;
+    for (name, path) in &files {
+        builder.append_path_with_name(path, name)?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn encrypt_archive(archive: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let salt = crypto::generate_salt();
+    let storage = SecureStorage::new(passphrase, &salt)?;
+
+    let mut encrypted = ENCRYPTED_MAGIC.to_vec();
+    encrypted.extend_from_slice(&salt);
+    encrypted.extend(storage.encrypt_bytes(archive)?);
+    Ok(encrypted)
+}
+
+fn decrypt_archive(encrypted: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let rest = &encrypted[ENCRYPTED_MAGIC.len()..];
+    if rest.len() < SALT_LEN {
+        return Err(anyhow::anyhow!("Backup is damaged"));
+    }
+    let (salt, sealed) = rest.split_at(SALT_LEN);
+    SecureStorage::new(passphrase, salt)?
+        .decrypt_bytes(sealed)
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase, or the backup is damaged"))
+}
+
+/// Backs up the database behind `pool` and the attachments in the data
+/// directory into a compressed archive in `dest_dir`, encrypted with
+/// `passphrase` if one is given.
+///
+/// The database is copied with `VACUUM INTO` rather than SQLite's online
+/// backup API, which sqlx does not expose. It likewise reads a consistent
+/// snapshot in a single read transaction while the app keeps using the
+/// database, and also leaves out free pages.
+pub async fn create_backup(pool: &SqlitePool, data_dir: &Path, dest_dir: &Path, passphrase: Option<&str>) -> Result<BackupInfo> {
+    tokio::fs::create_dir_all(dest_dir).await?;
+    let staging = dest_dir.join(format!(".staging-{}", Uuid::new_v4()));
+    tokio::fs::create_dir_all(&staging).await?;
+
+    let created_at = Utc::now();
+    let extension = if passphrase.is_some() { ENCRYPTED_EXTENSION } else { ARCHIVE_EXTENSION };
+    let path = dest_dir.join(format!("{}{}{}", FILE_PREFIX, created_at.format(FILE_TIMESTAMP), extension));
+
+    let result = async {
+        let snapshot = staging.join("cloddo.db");
+        sqlx::query("VACUUM INTO ?")
+            .bind(snapshot.to_string_lossy().to_string())
+            .execute(pool)
+            .await?;
+        let schema_version = migrations::schema_version(pool).await?;
+
+        let archive = staging.join("archive.tar.gz");
+        let (archive_path, data_dir) = (archive.clone(), data_dir.to_path_buf());
+        tokio::task::spawn_blocking(move || write_archive(&archive_path, &snapshot, &data_dir, schema_version, created_at))
+            .await??;
+
+        match passphrase {
+            Some(passphrase) => {
+                let encrypted = encrypt_archive(&tokio::fs::read(&archive).await?, passphrase)?;
+                tokio::fs::write(&path, encrypted).await?;
+            }
+            None => tokio::fs::rename(&archive, &path).await?,
+        }
+        anyhow::Ok(())
+    }
+    .await;
+
+    let _ = tokio::fs::remove_dir_all(&staging).await;
+    result?;
+
+    let info = backup_info(&path).ok_or_else(|| anyhow::anyhow!("Failed to read back {}", path.display()))?;
+    log::info!("Created backup {} ({} bytes)", info.file_name, info.size_bytes);
+    Ok(info)
+}
+
+// Unpacks and verifies an archive into `dir`. Every file but the manifest
+// must be listed in it with a matching hash
+fn unpack_archive(archive: impl std::io::Read, dir: &Path) -> Result<BackupManifest> {
+    tar::Archive::new(GzDecoder::new(archive)).unpack(dir)?;
+
+    let manifest: BackupManifest = serde_json::from_slice(
+        &fs::read(dir.join(MANIFEST_NAME)).map_err(|_| anyhow::anyhow!("Backup has no manifest"))?,
+    )?;
+    manifest.validate()?;
+
+    let mut unpacked = Vec::new();
+    files_under(dir, "", &mut unpacked)?;
+    for (name, _) in &unpacked {
+        if name != MANIFEST_NAME && !manifest.entries.iter().any(|entry| entry.path == *name) {
+            return Err(anyhow::anyhow!("Backup holds {}, which its manifest does not list", name));
+        }
+    }
+
+    for entry in &manifest.entries {
+        let file = fs::File::open(dir.join(&entry.path))
+            .map_err(|_| anyhow::anyhow!("Backup is missing {}", entry.path))?;
+        if crypto::sha256_reader(file)? != (entry.size_bytes, entry.sha256.clone()) {
+            return Err(anyhow::anyhow!("{} in the backup is damaged", entry.path));
+        }
+    }
+    Ok(manifest)
+}
+
+// Checks that the unpacked database opens, is intact and has the schema
+// version the manifest claims
+async fn verify_database(path: &Path, manifest: &BackupManifest) -> Result<()> {
+    let mut connection = SqliteConnectOptions::new().filename(path).read_only(true).connect().await?;
+
+    let integrity: String = sqlx::query_scalar("PRAGMA integrity_check").fetch_one(&mut connection).await?;
+    if integrity != "ok" {
+        return Err(anyhow::anyhow!("The backed up database is damaged: {}", integrity));
+    }
+    let version: i64 = sqlx::query_scalar("PRAGMA user_version").fetch_one(&mut connection).await?;
+    if version != manifest.schema_version {
+        return Err(anyhow::anyhow!(
+            "The backed up database is at schema {} but the manifest says {}",
+            version,
+            manifest.schema_version
+        ));
+    }
+    Ok(())
+}
+
+/// Reads `archive`, decrypting it with `passphrase` if needed, verifies it
+/// and leaves it in `data_dir` to replace the current data on the next
+/// launch, before the database is opened.
+pub async fn stage_restore(archive: &Path, passphrase: Option<&str>, data_dir: &Path) -> Result<BackupManifest> {
+    // Encrypted archives are sealed as a whole and must be read at once;
+    // plain ones are unpacked straight from the file
+    let mut magic = Vec::with_capacity(ENCRYPTED_MAGIC.len());
+    tokio::fs::File::open(archive)
+        .await?
+        .take(ENCRYPTED_MAGIC.len() as u64)
+        .read_to_end(&mut magic)
+        .await?;
+    let decrypted = if magic == ENCRYPTED_MAGIC {
+        let passphrase = passphrase.map(str::to_string).unwrap_or_else(crypto::local_passphrase);
+        Some(decrypt_archive(&tokio::fs::read(archive).await?, &passphrase)?)
+    } else {
+        None
+    };
+
+    let staging = data_dir.join(format!(".restore-{}", Uuid::new_v4()));
+    let result = async {
+        let (dir, archive) = (staging.clone(), archive.to_path_buf());
+        let manifest = tokio::task::spawn_blocking(move || match decrypted {
+            Some(bytes) => unpack_archive(bytes.as_slice(), &dir),
+            None => unpack_archive(fs::File::open(&archive)?, &dir),
+        })
+        .await??;
+        verify_database(&staging.join(DATABASE_ENTRY), &manifest).await?;
+
+        let pending = data_dir.join(PENDING_RESTORE_DIR);
+        if pending.exists() {
+            tokio::fs::remove_dir_all(&pending).await?;
+        }
+        tokio::fs::rename(&staging, &pending).await?;
+        anyhow::Ok(manifest)
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_dir_all(&staging).await;
+    }
+    result
+}
+
+/// Puts a backup left by [`stage_restore`] in place of the database at
+/// `db_path` and the files in `data_dir`. Must run before the database is
+/// opened. Returns the restored backup's manifest, if there was one.
+pub fn apply_pending_restore(db_path: &Path, data_dir: &Path) -> Result<Option<BackupManifest>> {
+    let pending = data_dir.join(PENDING_RESTORE_DIR);
+    let Ok(manifest) = fs::read(pending.join(MANIFEST_NAME)) else {
+        return Ok(None);
+    };
+    let manifest: BackupManifest = serde_json::from_slice(&manifest)?;
+
+    // A journal left by the current database would be replayed onto the
+    // restored one
+    for suffix in ["-wal", "-shm"] {
+        let journal = PathBuf::from(format!("{}{}", db_path.display(), suffix));
+        if journal.exists() {
+            fs::remove_file(journal)?;
+        }
+    }
+    fs::copy(pending.join(DATABASE_ENTRY), db_path)?;
+
+    let attachments = data_dir.join(ATTACHMENTS_DIR);
+    if attachments.exists() {
+        fs::remove_dir_all(&attachments)?;
+    }
+    let restored_attachments = pending.join(DATA_PREFIX).join(ATTACHMENTS_DIR);
+    if restored_attachments.exists() {
+        fs::rename(restored_attachments, &attachments)?;
+    }
+
+    fs::remove_dir_all(&pending)?;
+    log::info!("Restored backup from {}", manifest.created_at);
+    Ok(Some(manifest))
+}
+
+/// Drops a backup staged by [`stage_restore`] so it is not restored.
+pub fn discard_pending_restore(data_dir: &Path) -> Result<()> {
+    let pending = data_dir.join(PENDING_RESTORE_DIR);
+    if pending.exists() {
+        fs::remove_dir_all(pending)?;
+    }
+    Ok(())
+}
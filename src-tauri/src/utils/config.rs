@@ -105,4 +105,14 @@ impl AppConfig {
         
         Ok(config_dir.join("cloddo").join("config.json"))
     }
-}
\ No newline at end of file
+}
+
+/// Directory holding the app's data files (settings, chats, credentials).
+pub fn get_data_dir() -> Result<PathBuf> {
+    let data_dir = dirs::data_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("cloddo");
+
+    std::fs::create_dir_all(&data_dir)?;
+    Ok(data_dir)
+}
@@ -0,0 +1,241 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{Mutex, OnceCell};
+use crate::utils::crypto::{self, SecureStorage};
+
+/// Service name credentials are stored under in the OS keyring.
+pub const CREDENTIAL_SERVICE: &str = "cloddo";
+
+/// Storage for API keys, OAuth tokens and other secrets.
+#[async_trait]
+pub trait CredentialStore: Send + Sync {
+    /// Short backend identifier, used for logging.
+    fn backend_name(&self) -> &'static str;
+
+    async fn get(&self, key: &str) -> Result<Option<String>>;
+    async fn set(&self, key: &str, value: &str) -> Result<()>;
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Credentials kept in `credentials.json`, each value encrypted with `SecureStorage`.
+pub struct FileCredentialStore {
+    path: PathBuf,
+    storage: SecureStorage,
+    lock: Mutex<()>,
+}
+
+impl FileCredentialStore {
+    pub fn new(path: PathBuf, storage: SecureStorage) -> Self {
+        Self {
+            path,
+            storage,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn read_entries(&self) -> Result<HashMap<String, String>> {
+        if !self.path.exists() {
+            return Ok(HashMap::new());
+        }
+
+        let content = std::fs::read_to_string(&self.path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    fn write_entries(&self, entries: &HashMap<String, String>) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(&self.path, serde_json::to_string_pretty(entries)?)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CredentialStore for FileCredentialStore {
+    fn backend_name(&self) -> &'static str {
+        "encrypted-file"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        let _guard = self.lock.lock().await;
+        self.read_entries()?
+            .get(key)
+            .map(|ciphertext| self.storage.decrypt(ciphertext))
+            .transpose()
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut entries = self.read_entries()?;
+        entries.insert(key.to_string(), self.storage.encrypt(value)?);
+        self.write_entries(&entries)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut entries = self.read_entries()?;
+        if entries.remove(key).is_some() {
+            self.write_entries(&entries)?;
+        }
+        Ok(())
+    }
+}
+
+/// Process-local store with no persistence, for tests and headless runs.
+#[derive(Default)]
+pub struct MemoryCredentialStore {
+    entries: Mutex<HashMap<String, String>>,
+}
+
+impl MemoryCredentialStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl CredentialStore for MemoryCredentialStore {
+    fn backend_name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.entries.lock().await.get(key).cloned())
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.entries.lock().await.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.entries.lock().await.remove(key);
+        Ok(())
+    }
+}
+
+/// Credentials stored in the desktop keyring through the Secret Service D-Bus API.
+#[cfg(target_os = "linux")]
+pub struct SecretServiceCredentialStore {
+    service: secret_service::SecretService<'static>,
+}
+
+#[cfg(target_os = "linux")]
+impl SecretServiceCredentialStore {
+    /// Connects to the session bus and checks that a default collection exists.
+    pub async fn connect() -> Result<Self> {
+        let service = secret_service::SecretService::connect(secret_service::EncryptionType::Dh)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to connect to Secret Service: {}", e))?;
+
+        service
+            .get_default_collection()
+            .await
+            .map_err(|e| anyhow::anyhow!("Secret Service has no default collection: {}", e))?;
+
+        Ok(Self { service })
+    }
+
+    fn attributes(key: &str) -> HashMap<&str, &str> {
+        HashMap::from([("service", CREDENTIAL_SERVICE), ("key", key)])
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[async_trait]
+impl CredentialStore for SecretServiceCredentialStore {
+    fn backend_name(&self) -> &'static str {
+        "secret-service"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        let collection = self.service.get_default_collection().await?;
+        collection.ensure_unlocked().await?;
+
+        let items = collection.search_items(Self::attributes(key)).await?;
+        match items.first() {
+            Some(item) => Ok(Some(String::from_utf8(item.get_secret().await?)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        let collection = self.service.get_default_collection().await?;
+        collection.ensure_unlocked().await?;
+
+        collection
+            .create_item(
+                &format!("Cloddo: {}", key),
+                Self::attributes(key),
+                value.as_bytes(),
+                true,
+                "text/plain",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let collection = self.service.get_default_collection().await?;
+        collection.ensure_unlocked().await?;
+
+        for item in collection.search_items(Self::attributes(key)).await? {
+            item.delete().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Opens the encrypted-file store in `data_dir`, keyed by the per-install salt.
+pub fn open_file_store(data_dir: &Path) -> Result<FileCredentialStore> {
+    let salt = crypto::load_or_create_salt(&data_dir.join("secure.salt"))?;
+    let storage = SecureStorage::new(&crypto::local_passphrase(), &salt)?;
+    Ok(FileCredentialStore::new(data_dir.join("credentials.json"), storage))
+}
+
+/// Picks the best available backend: the OS keyring when reachable, otherwise
+/// the encrypted file in `data_dir`.
+///
+/// `CLODDO_CREDENTIAL_BACKEND` (`secret-service`, `file` or `memory`) forces a
+/// specific backend.
+pub async fn select_store(data_dir: &Path) -> Result<Arc<dyn CredentialStore>> {
+    let requested = std::env::var("CLODDO_CREDENTIAL_BACKEND").unwrap_or_default();
+
+    match requested.as_str() {
+        "memory" => return Ok(Arc::new(MemoryCredentialStore::new())),
+        "file" => return Ok(Arc::new(open_file_store(data_dir)?)),
+        _ => {}
+    }
+
+    #[cfg(target_os = "linux")]
+    match SecretServiceCredentialStore::connect().await {
+        Ok(store) => return Ok(Arc::new(store)),
+        Err(e) if requested == "secret-service" => return Err(e),
+        Err(e) => log::warn!("OS keyring unavailable, using encrypted file for credentials: {}", e),
+    }
+
+    Ok(Arc::new(open_file_store(data_dir)?))
+}
+
+/// The application-wide credential store, selected on first use.
+pub async fn default_store() -> Result<Arc<dyn CredentialStore>, String> {
+    static STORE: OnceCell<Arc<dyn CredentialStore>> = OnceCell::const_new();
+
+    STORE
+        .get_or_try_init(|| async {
+            let data_dir = crate::utils::config::get_data_dir()
+                .map_err(|e| format!("Failed to resolve data directory: {}", e))?;
+            let store = select_store(&data_dir)
+                .await
+                .map_err(|e| format!("Failed to open credential store: {}", e))?;
+            log::info!("Using {} credential store", store.backend_name());
+            Ok(store)
+        })
+        .await
+        .cloned()
+}
@@ -2,10 +2,52 @@ use ring::{aead, pbkdf2, rand};
 use ring::rand::SecureRandom;
 use anyhow::Result;
 use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use crate::utils::logger::REDACTED;
 
 const CREDENTIAL_LEN: usize = 32; // AES-256-GCM key length
 const NONCE_LEN: usize = 12; // AES-256-GCM nonce length
-const SALT_LEN: usize = 32;
+pub const SALT_LEN: usize = 32;
+
+/// A credential that must never reach logs: `Debug` and `Display` print
+/// `[REDACTED]`, and the value is only reachable through `expose`.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
 
 pub struct SecureStorage {
     key: aead::LessSafeKey,
@@ -30,6 +72,16 @@ impl SecureStorage {
     }
 
     pub fn encrypt(&self, data: &str) -> Result<String> {
+        Ok(general_purpose::STANDARD.encode(self.encrypt_bytes(data.as_bytes())?))
+    }
+
+    pub fn decrypt(&self, encrypted_data: &str) -> Result<String> {
+        let encrypted_bytes = general_purpose::STANDARD.decode(encrypted_data)?;
+        Ok(String::from_utf8(self.decrypt_bytes(&encrypted_bytes)?)?)
+    }
+
+    /// Encrypts `data`, returning the nonce followed by the ciphertext.
+    pub fn encrypt_bytes(&self, data: &[u8]) -> Result<Vec<u8>> {
         let rng = rand::SystemRandom::new();
         let mut nonce_bytes = [0u8; NONCE_LEN];
         rng.fill(&mut nonce_bytes)
@@ -37,19 +89,16 @@ impl SecureStorage {
         
         let nonce = aead::Nonce::assume_unique_for_key(nonce_bytes);
         
-        let mut in_out = data.as_bytes().to_vec();
+        let mut in_out = data.to_vec();
         self.key.seal_in_place_append_tag(nonce, aead::Aad::empty(), &mut in_out)
             .map_err(|e| anyhow::anyhow!("Failed to encrypt data: {:?}", e))?;
         
         let mut encrypted = nonce_bytes.to_vec();
         encrypted.extend_from_slice(&in_out);
-        
-        Ok(general_purpose::STANDARD.encode(&encrypted))
+        Ok(encrypted)
     }
 
-    pub fn decrypt(&self, encrypted_data: &str) -> Result<String> {
-        let encrypted_bytes = general_purpose::STANDARD.decode(encrypted_data)?;
-        
+    pub fn decrypt_bytes(&self, encrypted_bytes: &[u8]) -> Result<Vec<u8>> {
         if encrypted_bytes.len() < NONCE_LEN {
             return Err(anyhow::anyhow!("Invalid encrypted data length"));
         }
@@ -62,7 +111,7 @@ impl SecureStorage {
         let plaintext = self.key.open_in_place(nonce, aead::Aad::empty(), &mut in_out)
             .map_err(|e| anyhow::anyhow!("Failed to decrypt data: {:?}", e))?;
         
-        Ok(String::from_utf8(plaintext.to_vec())?)
+        Ok(plaintext.to_vec())
     }
 }
 
@@ -73,8 +122,84 @@ pub fn generate_salt() -> [u8; SALT_LEN] {
     salt
 }
 
+/// Loads the per-install salt from `path`, creating it on first use.
+pub fn load_or_create_salt(path: &Path) -> Result<Vec<u8>> {
+    if path.exists() {
+        let encoded = std::fs::read_to_string(path)?;
+        let salt = general_purpose::STANDARD.decode(encoded.trim())?;
+        if salt.len() != SALT_LEN {
+            return Err(anyhow::anyhow!("Invalid salt length in {}", path.display()));
+        }
+        return Ok(salt);
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let salt = generate_salt();
+    std::fs::write(path, general_purpose::STANDARD.encode(salt))?;
+    Ok(salt.to_vec())
+}
+
+/// Passphrase used to derive the local encryption key.
+///
+/// A user-supplied `CLODDO_PASSPHRASE` takes precedence; otherwise the key is
+/// bound to this machine and OS user so a copied data directory cannot be
+/// decrypted elsewhere.
+pub fn local_passphrase() -> String {
+    if let Ok(passphrase) = std::env::var("CLODDO_PASSPHRASE") {
+        if !passphrase.is_empty() {
+            return passphrase;
+        }
+    }
+
+    let machine_id = ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_default();
+
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default();
+
+    format!("cloddo:{}:{}", machine_id, user)
+}
+
 pub fn hash_api_key(api_key: &str) -> String {
     use ring::digest;
     let hash = digest::digest(&digest::SHA256, api_key.as_bytes());
     general_purpose::STANDARD.encode(hash.as_ref())
-}
\ No newline at end of file
+}
+
+/// Lowercase hex SHA-256 of `data`, used to content-address stored files.
+pub fn sha256_hex(data: &[u8]) -> String {
+    use ring::digest;
+    hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+/// Length and lowercase hex SHA-256 of everything `reader` yields, read in
+/// chunks so large files are never held in memory.
+pub fn sha256_reader(mut reader: impl std::io::Read) -> std::io::Result<(u64, String)> {
+    use ring::digest;
+    let mut context = digest::Context::new(&digest::SHA256);
+    let mut buffer = [0u8; 64 * 1024];
+    let mut length = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
+        length += read as u64;
+    }
+    Ok((length, hex(context.finish().as_ref())))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -1,8 +1,89 @@
-use log::LevelFilter;
+use log::{LevelFilter, Log, Metadata, Record};
+use once_cell::sync::Lazy;
+use regex::Regex;
 use std::path::PathBuf;
 use dirs::data_dir;
 use anyhow::Result;
 
+/// Replacement text for scrubbed secrets.
+pub const REDACTED: &str = "[REDACTED]";
+
+static SECRET_PATTERNS: Lazy<Vec<(Regex, String)>> = Lazy::new(|| {
+    vec![
+        // Anthropic API keys and OAuth tokens
+        (
+            Regex::new(r"sk-ant-[A-Za-z0-9_\-]+").unwrap(),
+            format!("sk-ant-{}", REDACTED),
+        ),
+        // Authorization headers
+        (
+            Regex::new(r"(?i)(bearer\s+)[A-Za-z0-9._~+/=\-]+").unwrap(),
+            format!("${{1}}{}", REDACTED),
+        ),
+        // OAuth verifiers and tokens in query strings, form bodies and JSON
+        (
+            Regex::new(
+                r#"(?i)\b(code_verifier|access_token|refresh_token|id_token|client_secret|api_key|x-api-key)("?\s*[:=]\s*"?)([^\s"&,;}]+)"#,
+            )
+            .unwrap(),
+            format!("${{1}}${{2}}{}", REDACTED),
+        ),
+        // OAuth codes, only as a query or form parameter or a JSON field so
+        // that text like "status code: 500" is left alone
+        (
+            Regex::new(r#"(?i)((?:^|[?&\s])code=|"code"\s*:\s*")([^\s"&,;}]+)"#).unwrap(),
+            format!("${{1}}{}", REDACTED),
+        ),
+    ]
+});
+
+/// Scrubs known secret patterns from a log message.
+pub fn redact(message: &str) -> String {
+    SECRET_PATTERNS
+        .iter()
+        .fold(message.to_string(), |text, (pattern, replacement)| {
+            pattern.replace_all(&text, replacement.as_str()).into_owned()
+        })
+}
+
+/// Wraps another logger and redacts every record before it is written.
+pub struct RedactingLogger<L: Log> {
+    inner: L,
+}
+
+impl<L: Log> RedactingLogger<L> {
+    pub fn new(inner: L) -> Self {
+        Self { inner }
+    }
+}
+
+impl<L: Log> Log for RedactingLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = redact(&record.args().to_string());
+        self.inner.log(
+            &Record::builder()
+                .metadata(record.metadata().clone())
+                .args(format_args!("{}", message))
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
 pub fn init_logger() -> Result<()> {
     let log_level = std::env::var("CLODDO_LOG_LEVEL")
         .unwrap_or_else(|_| "info".to_string());
@@ -16,9 +97,12 @@ pub fn init_logger() -> Result<()> {
         _ => LevelFilter::Info,
     };
 
-    env_logger::Builder::from_default_env()
+    let logger = env_logger::Builder::from_default_env()
         .filter_level(level)
-        .init();
+        .build();
+
+    log::set_max_level(logger.filter());
+    log::set_boxed_logger(Box::new(RedactingLogger::new(logger)))?;
 
     log::info!("Logger initialized with level: {}", log_level);
     Ok(())
@@ -32,4 +116,4 @@ pub fn get_log_dir() -> Result<PathBuf> {
     std::fs::create_dir_all(&log_dir)?;
     
     Ok(log_dir)
-}
\ No newline at end of file
+}
@@ -1,3 +1,6 @@
+pub mod backup;
+pub mod render;
 pub mod config;
 pub mod logger;
-pub mod crypto;
\ No newline at end of file
+pub mod crypto;
+pub mod credentials;
\ No newline at end of file
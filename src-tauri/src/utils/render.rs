@@ -0,0 +1,262 @@
+use chrono::{DateTime, Utc};
+use anyhow::Result;
+use crate::database::export::{ExportBundle, ExportFormat};
+use crate::database::models::Role;
+use crate::integrations::attachments::AttachmentRef;
+
+fn role_label(role: Role) -> &'static str {
+    match role {
+        Role::User => "User",
+        Role::Assistant => "Claude",
+        Role::System => "System",
+    }
+}
+
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+fn format_size(bytes: i64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{} KB", (bytes + 1023) / 1024)
+    }
+}
+
+pub fn render(format: ExportFormat, bundle: &ExportBundle) -> Result<String> {
+    match format {
+        ExportFormat::Markdown => Ok(render_markdown(bundle)),
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(bundle)?),
+        ExportFormat::Html => Ok(render_html(bundle)),
+    }
+}
+
+/// A code fence: a run of three or more backticks or tildes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fence {
+    marker: char,
+    length: usize,
+}
+
+impl Fence {
+    // The fence `line` opens or closes with, and its info string
+    fn parse(line: &str) -> Option<(Fence, &str)> {
+        let line = line.trim_start();
+        let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+        let length = line.chars().take_while(|c| *c == marker).count();
+        (length >= 3).then(|| (Fence { marker, length }, &line[length..]))
+    }
+
+    // Whether `line` ends the block this fence opened: the same marker, at
+    // least as long, and nothing after it
+    fn closed_by(&self, line: &str) -> bool {
+        Fence::parse(line).is_some_and(|(fence, info)| {
+            fence.marker == self.marker && fence.length >= self.length && info.trim().is_empty()
+        })
+    }
+
+    fn to_line(self) -> String {
+        self.marker.to_string().repeat(self.length)
+    }
+}
+
+// Closes a code fence the message left open so it does not swallow the
+// rest of the document
+fn balance_fences(content: &str) -> String {
+    let mut open: Option<Fence> = None;
+    for line in content.lines() {
+        open = match open {
+            Some(fence) if fence.closed_by(line) => None,
+            Some(fence) => Some(fence),
+            None => Fence::parse(line).map(|(fence, _)| fence),
+        };
+    }
+
+    let mut content = content.trim_end().to_string();
+    if let Some(fence) = open {
+        content.push('\n');
+        content.push_str(&fence.to_line());
+    }
+    content
+}
+
+/// The active branch of every chat, with a heading per turn. Code in
+/// messages stays in fenced blocks.
+pub fn render_markdown(bundle: &ExportBundle) -> String {
+    let mut output = String::new();
+    if let Some(project) = &bundle.project {
+        output.push_str(&format!("# {}\n\n", project.name));
+        if let Some(description) = project.description.as_deref().filter(|d| !d.is_empty()) {
+            output.push_str(&format!("{}\n\n", description));
+        }
+    }
+
+    let heading = if bundle.project.is_some() { "##" } else { "#" };
+    for (index, export) in bundle.chats.iter().enumerate() {
+        if index > 0 {
+            output.push_str("---\n\n");
+        }
+        output.push_str(&format!("{} {}\n\n", heading, export.chat.title));
+        output.push_str(&format!("*Created {}*\n\n", format_timestamp(&export.chat.created_at)));
+
+        for message in export.active_branch() {
+            output.push_str(&format!(
+                "{}# {}\n\n",
+                heading,
+                role_label(message.role)
+            ));
+            for attachment in AttachmentRef::from_metadata(message.metadata.as_deref()) {
+                output.push_str(&format!(
+                    "> Attachment: {} ({}, {})\n\n",
+                    attachment.name,
+                    attachment.mime_type,
+                    format_size(attachment.size_bytes)
+                ));
+            }
+            output.push_str(&balance_fences(&message.content));
+            output.push_str("\n\n");
+        }
+    }
+
+    output.trim_end().to_string() + "\n"
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Escapes a line of prose, turning `code` spans into <code> elements
+fn inline_html(line: &str) -> String {
+    let mut html = String::new();
+    for (index, part) in line.split('`').enumerate() {
+        if index % 2 == 1 {
+            html.push_str(&format!("<code>{}</code>", escape_html(part)));
+        } else {
+            html.push_str(&escape_html(part));
+        }
+    }
+    html
+}
+
+/// Message text as HTML: fenced code becomes `<pre>` blocks and the rest
+/// paragraphs, all of it escaped.
+pub fn content_html(content: &str) -> String {
+    let mut html = String::new();
+    let mut paragraph: Vec<String> = Vec::new();
+    let mut code: Option<(Fence, Vec<&str>)> = None;
+    let mut language = String::new();
+
+    let flush = |paragraph: &mut Vec<String>, html: &mut String| {
+        if !paragraph.is_empty() {
+            html.push_str(&format!("<p>{}</p>\n", paragraph.join("<br>\n")));
+            paragraph.clear();
+        }
+    };
+
+    for line in content.lines() {
+        match (&mut code, Fence::parse(line)) {
+            (Some((fence, _)), _) if fence.closed_by(line) => {
+                let class = if language.is_empty() {
+                    String::new()
+                } else {
+                    format!(" class=\"language-{}\"", escape_html(&language))
+                };
+                let lines = code.take().map(|(_, lines)| lines).unwrap_or_default();
+                html.push_str(&format!("<pre><code{}>{}</code></pre>\n", class, escape_html(&lines.join("\n"))));
+            }
+            (Some((_, lines)), _) => lines.push(line),
+            (None, Some((fence, info))) => {
+                flush(&mut paragraph, &mut html);
+                language = info.trim().to_string();
+                code = Some((fence, Vec::new()));
+            }
+            (None, None) if line.trim().is_empty() => flush(&mut paragraph, &mut html),
+            (None, None) => paragraph.push(inline_html(line)),
+        }
+    }
+
+    if let Some((_, lines)) = code {
+        html.push_str(&format!("<pre><code>{}</code></pre>\n", escape_html(&lines.join("\n"))));
+    }
+    flush(&mut paragraph, &mut html);
+    html
+}
+
+const HTML_STYLE: &str = r#"
+body { font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", sans-serif; max-width: 820px; margin: 2rem auto; padding: 0 1rem; color: #1f2328; line-height: 1.55; }
+header { border-bottom: 1px solid #d0d7de; margin-bottom: 1.5rem; }
+.meta { color: #656d76; font-size: 0.9rem; }
+.message { border-radius: 8px; padding: 0.75rem 1rem; margin: 1rem 0; }
+.message h3 { margin: 0 0 0.5rem; font-size: 0.85rem; text-transform: uppercase; letter-spacing: 0.04em; color: #656d76; }
+.user { background: #f6f8fa; }
+.assistant { background: #fff8f0; }
+pre { background: #1f2328; color: #f6f8fa; padding: 0.75rem; border-radius: 6px; overflow-x: auto; }
+code { font-family: ui-monospace, SFMono-Regular, Menlo, monospace; font-size: 0.9em; }
+p code { background: #eaeef2; padding: 0.1em 0.3em; border-radius: 4px; }
+img { max-width: 100%; border-radius: 6px; }
+.attachment { font-size: 0.9rem; }
+"#;
+
+/// A single HTML page with styles and attached images inlined, so it opens
+/// anywhere without the app.
+pub fn render_html(bundle: &ExportBundle) -> String {
+    let title = bundle
+        .project
+        .as_ref()
+        .map(|project| project.name.clone())
+        .or_else(|| bundle.chats.first().map(|export| export.chat.title.clone()))
+        .unwrap_or_else(|| "Cloddo export".to_string());
+
+    let mut body = String::new();
+    for export in &bundle.chats {
+        body.push_str(&format!(
+            "<article>\n<header><h2>{}</h2><p class=\"meta\">Created {}</p></header>\n",
+            escape_html(&export.chat.title),
+            format_timestamp(&export.chat.created_at)
+        ));
+
+        for message in export.active_branch() {
+            body.push_str(&format!(
+                "<section class=\"message {}\">\n<h3>{}</h3>\n",
+                message.role.as_str(),
+                escape_html(role_label(message.role))
+            ));
+            for attachment in AttachmentRef::from_metadata(message.metadata.as_deref()) {
+                let embedded = bundle.attachments.get(&attachment.hash);
+                match embedded {
+                    Some(file) if attachment.is_image() => body.push_str(&format!(
+                        "<img src=\"data:{};base64,{}\" alt=\"{}\">\n",
+                        escape_html(&file.mime_type),
+                        file.data,
+                        escape_html(&attachment.name)
+                    )),
+                    Some(file) => body.push_str(&format!(
+                        "<p class=\"attachment\"><a download=\"{name}\" href=\"data:{};base64,{}\">{name}</a></p>\n",
+                        escape_html(&file.mime_type),
+                        file.data,
+                        name = escape_html(&attachment.name)
+                    )),
+                    None => body.push_str(&format!(
+                        "<p class=\"attachment\">Attachment: {}</p>\n",
+                        escape_html(&attachment.name)
+                    )),
+                }
+            }
+            body.push_str(&content_html(&message.content));
+            body.push_str("</section>\n");
+        }
+        body.push_str("</article>\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{style}</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p class=\"meta\">Exported {exported}</p>\n{body}</body>\n</html>\n",
+        title = escape_html(&title),
+        style = HTML_STYLE,
+        exported = format_timestamp(&bundle.exported_at),
+        body = body
+    )
+}
@@ -0,0 +1,154 @@
+use app_lib::database::Database;
+use app_lib::integrations::attachments::{
+    sniff_mime_type, validate_attachments, AttachmentRef, AttachmentStore, MAX_ATTACHMENTS_PER_MESSAGE,
+};
+use app_lib::integrations::models::ModelRegistry;
+use serde_json::json;
+use std::time::Duration;
+
+const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0, 0, 0x0d];
+
+fn attachment(name: &str, mime_type: &str, size_bytes: i64) -> AttachmentRef {
+    AttachmentRef {
+        hash: "0".repeat(64),
+        name: name.to_string(),
+        mime_type: mime_type.to_string(),
+        size_bytes,
+    }
+}
+
+#[test]
+fn sniffs_supported_types_from_content() {
+    assert_eq!(sniff_mime_type(PNG), Some("image/png"));
+    assert_eq!(sniff_mime_type(&[0xff, 0xd8, 0xff, 0xe0]), Some("image/jpeg"));
+    assert_eq!(sniff_mime_type(b"GIF89a..."), Some("image/gif"));
+    assert_eq!(sniff_mime_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+    assert_eq!(sniff_mime_type(b"%PDF-1.7\n"), Some("application/pdf"));
+    assert_eq!(sniff_mime_type("plain notes".as_bytes()), Some("text/plain"));
+    assert_eq!(sniff_mime_type(&[0x00, 0xfe, 0xff, 0x80]), None);
+}
+
+#[test]
+fn validates_size_count_and_vision_support() {
+    let models = ModelRegistry::new(None);
+    let model = models.require("claude-3-5-sonnet-20241022").unwrap();
+
+    let photo = attachment("photo.png", "image/png", 1024);
+    let notes = attachment("notes.txt", "text/plain", 1024);
+    assert!(validate_attachments(&[photo.clone(), notes.clone()], &model).is_ok());
+
+    let huge = attachment("huge.png", "image/png", 6 * 1024 * 1024);
+    assert!(validate_attachments(&[huge], &model).unwrap_err().contains("5 MB"));
+
+    let too_many = vec![notes.clone(); MAX_ATTACHMENTS_PER_MESSAGE + 1];
+    assert!(validate_attachments(&too_many, &model).is_err());
+
+    let mut text_only = model.clone();
+    text_only.capabilities.vision = false;
+    assert!(validate_attachments(&[notes], &text_only).is_ok());
+    assert!(validate_attachments(&[photo], &text_only)
+        .unwrap_err()
+        .contains("vision support"));
+}
+
+#[tokio::test]
+async fn stores_content_once_and_encodes_blocks() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = AttachmentStore::new(dir.path().join("attachments"));
+
+    let first = store.put("screenshot.png", PNG).await.unwrap();
+    let second = store.put("copy.png", PNG).await.unwrap();
+    assert_eq!(first.hash, second.hash);
+    assert_eq!(first.mime_type, "image/png");
+    assert_eq!(first.size_bytes, PNG.len() as i64);
+
+    let path = store.path(&first.hash).unwrap();
+    assert!(path.ends_with(format!("{}/{}", &first.hash[..2], first.hash)));
+    assert!(store.contains(&first.hash).await);
+    assert!(store.path("../../etc/passwd").is_err());
+
+    let image = serde_json::to_value(store.to_block(&first).await.unwrap()).unwrap();
+    assert_eq!(image["type"], "image");
+    assert_eq!(image["source"]["type"], "base64");
+    assert_eq!(image["source"]["media_type"], "image/png");
+    assert!(image.get("cache_control").is_none());
+
+    let notes = store.put("notes.md", b"# Agenda").await.unwrap();
+    let document = serde_json::to_value(store.to_block(&notes).await.unwrap()).unwrap();
+    assert_eq!(
+        document,
+        json!({
+            "type": "document",
+            "source": {"type": "text", "media_type": "text/plain", "data": "# Agenda"},
+            "title": "notes.md"
+        })
+    );
+
+    assert!(store.put("blob.bin", &[0x00, 0xfe, 0xff, 0x80]).await.is_err());
+}
+
+#[tokio::test]
+async fn garbage_collection_keeps_referenced_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::open(&dir.path().join("cloddo.db")).await.unwrap();
+    let store = AttachmentStore::new(dir.path().join("attachments"));
+
+    let kept = store.put("kept.txt", b"still in a chat").await.unwrap();
+    let dropped = store.put("dropped.txt", b"never sent").await.unwrap();
+
+    db.ensure_session("session-1").await.unwrap();
+    sqlx::query("INSERT INTO chats (id, session_id, title) VALUES ('chat-1', 'session-1', 'Files')")
+        .execute(db.pool())
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO messages (id, chat_id, role, content, metadata) VALUES (?, 'chat-1', 'user', 'See file', ?)")
+        .bind("message-1")
+        .bind(json!({"attachments": [kept]}).to_string())
+        .execute(db.pool())
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO messages (id, chat_id, role, content, metadata) VALUES ('message-2', 'chat-1', 'assistant', 'Noted', 'not json')")
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+    // Recent uploads survive the default grace period
+    let report = store.collect_garbage(db.pool(), Duration::from_secs(3600)).await.unwrap();
+    assert_eq!(report.files_removed, 0);
+    assert_eq!(report.files_kept, 2);
+
+    let report = store.collect_garbage(db.pool(), Duration::ZERO).await.unwrap();
+    assert_eq!(report.files_removed, 1);
+    assert_eq!(report.bytes_freed, "never sent".len() as u64);
+    assert!(store.contains(&kept.hash).await);
+    assert!(!store.contains(&dropped.hash).await);
+}
+
+#[tokio::test]
+async fn trusts_stored_files_over_client_metadata_and_describes_unsupported_ones() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = AttachmentStore::new(dir.path().join("attachments"));
+    let models = ModelRegistry::new(None);
+    let mut text_only = models.require("claude-3-5-sonnet-20241022").unwrap();
+    text_only.capabilities.vision = false;
+
+    let photo = store.put("photo.png", PNG).await.unwrap();
+    let disguised = AttachmentRef {
+        mime_type: "text/plain".to_string(),
+        size_bytes: 1,
+        ..photo.clone()
+    };
+    let resolved = store.resolve(&disguised).await.unwrap();
+    assert_eq!((resolved.mime_type.as_str(), resolved.size_bytes), ("image/png", PNG.len() as i64));
+    assert!(validate_attachments(&[resolved], &text_only).is_err());
+
+    let missing = attachment("gone.txt", "text/plain", 10);
+    assert!(store.resolve(&missing).await.unwrap_err().to_string().contains("no longer available"));
+
+    // Earlier turns stay sendable after switching to a model without vision
+    let notes = store.put("notes.txt", b"agenda").await.unwrap();
+    let blocks = serde_json::to_value(store.to_blocks(&[photo, notes], &text_only).await.unwrap()).unwrap();
+    assert_eq!(blocks[0]["type"], "text");
+    assert!(blocks[0]["text"].as_str().unwrap().contains("photo.png"));
+    assert_eq!(blocks[1]["type"], "document");
+}
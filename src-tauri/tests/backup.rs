@@ -0,0 +1,147 @@
+use app_lib::database::migrations::SCHEMA_VERSION;
+use app_lib::database::Database;
+use app_lib::utils::backup::{
+    apply_pending_restore, create_backup, list_backups, prune_backups, stage_restore, BackupManifest,
+    BACKUP_FORMAT_NAME, BACKUP_FORMAT_VERSION,
+};
+use std::path::Path;
+
+// A data directory with imported settings and an attachment, and a database
+// holding one chat
+async fn populated(dir: &Path) -> Database {
+    let data_dir = dir.join("data");
+    std::fs::create_dir_all(data_dir.join("attachments/ab")).unwrap();
+    std::fs::write(data_dir.join("settings.json.imported"), r#"{"api.defaultModel": "saved"}"#).unwrap();
+    std::fs::write(data_dir.join("attachments/ab/abcdef"), b"attached").unwrap();
+
+    let db = Database::open(&dir.join("cloddo.db")).await.unwrap();
+    db.ensure_session("session-1").await.unwrap();
+    sqlx::query("INSERT INTO chats (id, session_id, title, last_activity) VALUES ('chat-1', 'session-1', 'Kept', CURRENT_TIMESTAMP)")
+        .execute(db.pool())
+        .await
+        .unwrap();
+    db
+}
+
+async fn chat_titles(db_path: &Path) -> Vec<String> {
+    let db = Database::open(db_path).await.unwrap();
+    sqlx::query_scalar("SELECT title FROM chats ORDER BY title").fetch_all(db.pool()).await.unwrap()
+}
+
+#[tokio::test]
+async fn restores_database_and_files_from_a_backup() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = populated(dir.path()).await;
+    let data_dir = dir.path().join("data");
+    let backups = dir.path().join("backups");
+
+    let backup = create_backup(db.pool(), &data_dir, &backups, None).await.unwrap();
+    assert!(!backup.encrypted);
+    assert!(backup.file_name.ends_with(".tar.gz"));
+
+    // Everything changes after the backup
+    sqlx::query("UPDATE chats SET title = 'Changed'").execute(db.pool()).await.unwrap();
+    std::fs::remove_dir_all(data_dir.join("attachments")).unwrap();
+    db.pool().close().await;
+
+    let manifest = stage_restore(Path::new(&backup.path), None, &data_dir).await.unwrap();
+    assert_eq!(manifest.schema_version, SCHEMA_VERSION);
+    let paths: Vec<_> = manifest.entries.iter().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(paths, ["database/cloddo.db", "data/attachments/ab/abcdef"]);
+
+    let db_path = dir.path().join("cloddo.db");
+    assert!(apply_pending_restore(&db_path, &data_dir).unwrap().is_some());
+    assert!(apply_pending_restore(&db_path, &data_dir).unwrap().is_none());
+
+    assert_eq!(chat_titles(&db_path).await, ["Kept"]);
+    assert_eq!(std::fs::read(data_dir.join("attachments/ab/abcdef")).unwrap(), b"attached");
+    assert!(data_dir.join("settings.json.imported").exists());
+}
+
+#[tokio::test]
+async fn encrypted_backups_need_the_passphrase() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = populated(dir.path()).await;
+    let data_dir = dir.path().join("data");
+
+    let backup = create_backup(db.pool(), &data_dir, &dir.path().join("backups"), Some("correct horse"))
+        .await
+        .unwrap();
+    assert!(backup.encrypted);
+    let raw = std::fs::read(&backup.path).unwrap();
+    assert!(!raw.windows(8).any(|window| window == b"attached"));
+
+    let wrong = stage_restore(Path::new(&backup.path), Some("battery staple"), &data_dir).await;
+    assert!(wrong.unwrap_err().to_string().contains("Wrong passphrase"));
+    assert!(!data_dir.join("restore-pending").exists());
+
+    stage_restore(Path::new(&backup.path), Some("correct horse"), &data_dir).await.unwrap();
+    assert!(data_dir.join("restore-pending").exists());
+}
+
+#[tokio::test]
+async fn keeps_only_the_newest_backups() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = populated(dir.path()).await;
+    let backups = dir.path().join("backups");
+
+    let mut created = Vec::new();
+    for _ in 0..3 {
+        created.push(create_backup(db.pool(), &dir.path().join("data"), &backups, None).await.unwrap());
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    }
+
+    assert_eq!(prune_backups(&backups, 2).unwrap(), 1);
+    let left: Vec<String> = list_backups(&backups).unwrap().into_iter().map(|backup| backup.file_name).collect();
+    assert_eq!(left, [created[2].file_name.clone(), created[1].file_name.clone()]);
+}
+
+#[tokio::test]
+async fn refuses_archives_with_files_the_manifest_does_not_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = populated(dir.path()).await;
+    let data_dir = dir.path().join("data");
+    let backup = create_backup(db.pool(), &data_dir, &dir.path().join("backups"), None).await.unwrap();
+
+    // Repack the archive with one extra file
+    let original = std::fs::File::open(&backup.path).unwrap();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(original));
+    let tampered_path = dir.path().join("tampered.tar.gz");
+    let encoder = flate2::write::GzEncoder::new(std::fs::File::create(&tampered_path).unwrap(), flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let header = entry.header().clone();
+        builder.append(&header, &mut entry).unwrap();
+    }
+    let mut header = tar::Header::new_gnu();
+    header.set_size(4);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, "data/attachments/zz/extra", b"evil".as_slice()).unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+
+    let error = stage_restore(&tampered_path, None, &data_dir).await.unwrap_err();
+    assert!(error.to_string().contains("does not list"));
+    assert!(stage_restore(Path::new(&backup.path), None, &data_dir).await.is_ok());
+}
+
+#[test]
+fn refuses_backups_from_newer_versions() {
+    let manifest = |format: &str, schema_version: i64| BackupManifest {
+        format: format.to_string(),
+        version: BACKUP_FORMAT_VERSION,
+        app_version: "1.0.0".to_string(),
+        schema_version,
+        created_at: chrono::Utc::now(),
+        entries: serde_json::from_value(serde_json::json!([
+            {"path": "database/cloddo.db", "size_bytes": 1, "sha256": "00"}
+        ]))
+        .unwrap(),
+    };
+
+    assert!(manifest(BACKUP_FORMAT_NAME, SCHEMA_VERSION).validate().is_ok());
+    assert!(manifest(BACKUP_FORMAT_NAME, 2).validate().is_ok());
+    assert!(manifest(BACKUP_FORMAT_NAME, SCHEMA_VERSION + 1).validate().is_err());
+    assert!(manifest("something-else", SCHEMA_VERSION).validate().is_err());
+}
@@ -0,0 +1,93 @@
+use app_lib::database::branches::{active_path, latest_leaf, path_to, siblings, switch_branch};
+use app_lib::database::Database;
+
+async fn open_database() -> Database {
+    let db = Database::in_memory().await.unwrap();
+    db.ensure_session("session-1").await.unwrap();
+    sqlx::query("INSERT INTO chats (id, session_id, title) VALUES ('chat-1', 'session-1', 'Branches')")
+        .execute(db.pool())
+        .await
+        .unwrap();
+    db
+}
+
+async fn add(db: &Database, id: &str, parent_id: Option<&str>, role: &str, created_at: &str) {
+    sqlx::query("INSERT INTO messages (id, chat_id, parent_id, role, content, created_at) VALUES (?, 'chat-1', ?, ?, ?, ?)")
+        .bind(id)
+        .bind(parent_id)
+        .bind(role)
+        .bind(format!("{} text", id))
+        .bind(created_at)
+        .execute(db.pool())
+        .await
+        .unwrap();
+    sqlx::query("UPDATE chats SET active_message_id = ? WHERE id = 'chat-1'")
+        .bind(id)
+        .execute(db.pool())
+        .await
+        .unwrap();
+}
+
+fn ids(messages: &[app_lib::database::models::Message]) -> Vec<&str> {
+    messages.iter().map(|message| message.id.as_str()).collect()
+}
+
+// q1 -> a1 -> q2 -> a2
+//          \-> q2b -> a2b, a2c (regenerated)
+async fn build_tree(db: &Database) {
+    add(db, "q1", None, "user", "2024-01-01 10:00:00").await;
+    add(db, "a1", Some("q1"), "assistant", "2024-01-01 10:00:01").await;
+    add(db, "q2", Some("a1"), "user", "2024-01-01 10:01:00").await;
+    add(db, "a2", Some("q2"), "assistant", "2024-01-01 10:01:01").await;
+    add(db, "q2b", Some("a1"), "user", "2024-01-01 10:02:00").await;
+    add(db, "a2b", Some("q2b"), "assistant", "2024-01-01 10:02:01").await;
+    add(db, "a2c", Some("q2b"), "assistant", "2024-01-01 10:03:00").await;
+}
+
+#[tokio::test]
+async fn history_follows_only_the_active_branch() {
+    let db = open_database().await;
+    build_tree(&db).await;
+
+    assert_eq!(ids(&active_path(db.pool(), "chat-1").await.unwrap()), ["q1", "a1", "q2b", "a2c"]);
+    assert_eq!(ids(&path_to(db.pool(), "a2").await.unwrap()), ["q1", "a1", "q2", "a2"]);
+
+    assert_eq!(ids(&siblings(db.pool(), "q2b").await.unwrap()), ["q2", "q2b"]);
+    assert_eq!(ids(&siblings(db.pool(), "a2c").await.unwrap()), ["a2b", "a2c"]);
+    assert_eq!(ids(&siblings(db.pool(), "q1").await.unwrap()), ["q1"]);
+}
+
+#[tokio::test]
+async fn switching_continues_with_the_newest_reply() {
+    let db = open_database().await;
+    build_tree(&db).await;
+
+    assert_eq!(latest_leaf(db.pool(), "a1").await.unwrap(), "a2c");
+    assert_eq!(latest_leaf(db.pool(), "a2").await.unwrap(), "a2");
+
+    let path = switch_branch(db.pool(), "q2").await.unwrap();
+    assert_eq!(ids(&path), ["q1", "a1", "q2", "a2"]);
+    assert_eq!(ids(&active_path(db.pool(), "chat-1").await.unwrap()), ["q1", "a1", "q2", "a2"]);
+
+    switch_branch(db.pool(), "a2b").await.unwrap();
+    assert_eq!(ids(&active_path(db.pool(), "chat-1").await.unwrap()), ["q1", "a1", "q2b", "a2b"]);
+
+    assert!(switch_branch(db.pool(), "missing").await.is_err());
+}
+
+#[tokio::test]
+async fn deleting_a_turn_removes_the_branches_below_it() {
+    let db = open_database().await;
+    build_tree(&db).await;
+
+    sqlx::query("DELETE FROM messages WHERE id = 'q2b'").execute(db.pool()).await.unwrap();
+
+    let remaining: Vec<String> = sqlx::query_scalar("SELECT id FROM messages ORDER BY created_at")
+        .fetch_all(db.pool())
+        .await
+        .unwrap();
+    assert_eq!(remaining, ["q1", "a1", "q2", "a2"]);
+
+    // The chat falls back to the branch that is left
+    assert_eq!(ids(&active_path(db.pool(), "chat-1").await.unwrap()), ["q1", "a1", "q2", "a2"]);
+}
@@ -0,0 +1,122 @@
+use app_lib::database::budgets::{enforce_budgets, period_start, BudgetExceeded, BudgetState, BUDGET_EXCEEDED};
+use app_lib::database::models::{Budget, BudgetEvent};
+use app_lib::database::usage::{record_usage, UsageContext};
+use app_lib::database::Database;
+use app_lib::integrations::anthropic::Usage;
+use chrono::NaiveDate;
+
+// One million input tokens of a Sonnet model cost $3.00
+const SONNET: &str = "claude-3-5-sonnet-20241022";
+
+async fn insert_budget(db: &Database, budget: &Budget) {
+    sqlx::query(
+        r#"
+        INSERT INTO budgets (id, name, scope, scope_id, period, limit_usd, warn_threshold, enabled)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&budget.id)
+    .bind(&budget.name)
+    .bind(&budget.scope)
+    .bind(&budget.scope_id)
+    .bind(&budget.period)
+    .bind(budget.limit_usd)
+    .bind(budget.warn_threshold)
+    .bind(budget.enabled)
+    .execute(db.pool())
+    .await
+    .unwrap();
+}
+
+async fn spend(db: &Database, context: &UsageContext, input_tokens: u32) {
+    let usage = Usage {
+        input_tokens,
+        output_tokens: 0,
+        cache_creation_input_tokens: 0,
+        cache_read_input_tokens: 0,
+    };
+    record_usage(db.pool(), context, SONNET, &usage).await.unwrap();
+}
+
+async fn events(db: &Database, kind: &str) -> Vec<BudgetEvent> {
+    sqlx::query_as::<_, BudgetEvent>("SELECT * FROM budget_events WHERE kind = ?")
+        .bind(kind)
+        .fetch_all(db.pool())
+        .await
+        .unwrap()
+}
+
+#[test]
+fn monthly_periods_start_on_the_first() {
+    let today = NaiveDate::from_ymd_opt(2024, 3, 17).unwrap();
+    assert_eq!(period_start("daily", today), today);
+    assert_eq!(period_start("monthly", today), NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+}
+
+#[tokio::test]
+async fn warns_once_then_blocks_and_records_the_attempt() {
+    let db = Database::in_memory().await.unwrap();
+    let budget = Budget::new("Agents".to_string(), "agent".to_string(), Some("agent-1".to_string()), "daily".to_string(), 4.0);
+    insert_budget(&db, &budget).await;
+
+    let context = UsageContext::agent("agent-1", "run-1");
+
+    // $3.00 of $4.00 is under the 80% threshold
+    spend(&db, &context, 1_000_000).await;
+    let statuses = enforce_budgets(db.pool(), &context).await.unwrap();
+    assert_eq!(statuses[0].state, BudgetState::Ok);
+
+    // $3.30 is past it; the warning is only recorded once per period
+    spend(&db, &context, 100_000).await;
+    enforce_budgets(db.pool(), &context).await.unwrap();
+    enforce_budgets(db.pool(), &context).await.unwrap();
+    assert_eq!(events(&db, "warning").await.len(), 1);
+
+    spend(&db, &context, 300_000).await;
+    let blocked = UsageContext::agent("agent-1", "run-2");
+    let error = enforce_budgets(db.pool(), &blocked).await.unwrap_err();
+    let exceeded = error.downcast_ref::<BudgetExceeded>().unwrap();
+    assert_eq!(exceeded.budget_id, budget.id);
+    assert!(error.to_string().starts_with(BUDGET_EXCEEDED));
+
+    let blocked_events = events(&db, "blocked").await;
+    assert_eq!(blocked_events.len(), 1);
+    assert_eq!(blocked_events[0].agent_run_id.as_deref(), Some("run-2"));
+    assert!((blocked_events[0].spent - 4.2).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn scoped_budgets_only_count_their_own_spending() {
+    let db = Database::in_memory().await.unwrap();
+    let budget = Budget::new("Agent 1".to_string(), "agent".to_string(), Some("agent-1".to_string()), "monthly".to_string(), 1.0);
+    insert_budget(&db, &budget).await;
+
+    spend(&db, &UsageContext::agent("agent-2", "run-1"), 1_000_000).await;
+
+    // Another agent's spending doesn't count against this budget
+    let statuses = enforce_budgets(db.pool(), &UsageContext::agent("agent-1", "run-2")).await.unwrap();
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[0].spent, 0.0);
+
+    // and the budget doesn't apply to other agents at all
+    let statuses = enforce_budgets(db.pool(), &UsageContext::agent("agent-2", "run-3")).await.unwrap();
+    assert!(statuses.is_empty());
+}
+
+#[tokio::test]
+async fn disabled_and_global_budgets() {
+    let db = Database::in_memory().await.unwrap();
+
+    let mut disabled = Budget::new("Off".to_string(), "global".to_string(), None, "daily".to_string(), 0.0);
+    disabled.enabled = false;
+    insert_budget(&db, &disabled).await;
+
+    let context = UsageContext::hook("hook-1");
+    enforce_budgets(db.pool(), &context).await.unwrap();
+
+    // A zero global budget blocks every source
+    let global = Budget::new("Everything".to_string(), "global".to_string(), None, "daily".to_string(), 0.0);
+    insert_budget(&db, &global).await;
+    let error = enforce_budgets(db.pool(), &context).await.unwrap_err();
+    assert!(error.downcast_ref::<BudgetExceeded>().is_some());
+}
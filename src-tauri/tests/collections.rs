@@ -0,0 +1,156 @@
+use app_lib::database::collections::{
+    self, Comparison, CountField, DateField, DateValue, Expr, Filter, Period,
+};
+use app_lib::database::{folders, tags, Database};
+use chrono::{TimeZone, Utc};
+
+fn filter(filter: Filter) -> Expr {
+    Expr::Filter(filter)
+}
+
+#[test]
+fn parses_fields_groups_and_negation() {
+    assert_eq!(collections::parse("  ").unwrap(), Expr::And(Vec::new()));
+
+    assert_eq!(
+        collections::parse(r#"is:favorite project:"Data Platform" updated:week migration"#).unwrap(),
+        Expr::And(vec![
+            filter(Filter::Favorite),
+            filter(Filter::Project("Data Platform".to_string())),
+            filter(Filter::Date(DateField::Updated, DateValue::Current(Period::Week))),
+            filter(Filter::Text { text: "migration".to_string(), phrase: false }),
+        ])
+    );
+
+    assert_eq!(
+        collections::parse(r#"(tag:work OR tag:urgent) -"draft plan" tokens:>=10k created:<2024-03-01"#).unwrap(),
+        Expr::And(vec![
+            Expr::Or(vec![
+                filter(Filter::Tag("work".to_string())),
+                filter(Filter::Tag("urgent".to_string())),
+            ]),
+            Expr::Not(Box::new(filter(Filter::Text { text: "draft plan".to_string(), phrase: true }))),
+            filter(Filter::Count(CountField::Tokens, Comparison::Ge, 10_000)),
+            filter(Filter::Date(
+                DateField::Created,
+                DateValue::Date(Comparison::Lt, chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap())
+            )),
+        ])
+    );
+
+    // Words that merely contain a colon are text
+    assert_eq!(
+        collections::parse("TODO:later https://example.com/a:b").unwrap(),
+        Expr::And(vec![
+            filter(Filter::Text { text: "TODO:later".to_string(), phrase: false }),
+            filter(Filter::Text { text: "https://example.com/a:b".to_string(), phrase: false }),
+        ])
+    );
+}
+
+#[test]
+fn reports_where_a_query_is_wrong() {
+    let error = |query: &str| collections::parse(query).unwrap_err().to_string();
+
+    assert_eq!(error(&format!("{}x{}", "(".repeat(40), ")".repeat(40))), "Query is nested too deeply at position 33");
+    assert_eq!(error(&format!("{}x", "-".repeat(100))), "Query is nested too deeply at position 33");
+    assert_eq!(error("tag:work (is:favorite"), "Expected ')' at position 22");
+    assert_eq!(error("tag:work )"), "Unexpected ')' at position 10");
+    assert_eq!(error("updated:>week"), "Expected a YYYY-MM-DD date after the comparison in '>week' at position 1");
+    assert_eq!(error("messages:lots"), "Cannot read 'lots' as a number at position 1");
+    assert_eq!(error("tokens:9999999999999999k"), "Cannot read '9999999999999999k' as a number at position 1");
+    assert_eq!(error("title:\"open"), "Unclosed quote at position 7");
+    assert_eq!(error("is:favorite OR"), "Expected a search term at position 15");
+}
+
+#[tokio::test]
+async fn evaluates_queries_against_chats() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::open(&dir.path().join("cloddo.db")).await.unwrap();
+    db.ensure_session("session-1").await.unwrap();
+    sqlx::query("INSERT INTO projects (id, name) VALUES ('project-1', 'Data Platform')")
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+    // (id, project, favorite, folder, last activity)
+    let chats = [
+        ("recent", Some("project-1"), true, Some("Work/Infra"), "2024-06-12 09:00:00"),
+        ("old", Some("project-1"), true, None, "2024-05-01 09:00:00"),
+        ("other", None, false, Some("Home"), "2024-06-11 09:00:00"),
+    ];
+    for (id, project_id, is_favorite, folder_path, last_activity) in chats {
+        let folder_id = match folder_path {
+            Some(path) => folders::ensure_path(&mut db.pool().acquire().await.unwrap(), path).await.unwrap(),
+            None => None,
+        };
+        sqlx::query(
+            "INSERT INTO chats (id, session_id, project_id, title, is_favorite, folder_id, folder_path, last_activity) \
+             VALUES (?, 'session-1', ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(id)
+        .bind(project_id)
+        .bind(format!("Chat {}", id))
+        .bind(is_favorite)
+        .bind(folder_id)
+        .bind(folder_path)
+        .bind(last_activity)
+        .execute(db.pool())
+        .await
+        .unwrap();
+    }
+    for (id, chat_id, content) in [
+        ("m1", "recent", "Plan the schema migration"),
+        ("m2", "old", "Migrations are done"),
+        ("m3", "other", "Groceries"),
+    ] {
+        sqlx::query("INSERT INTO messages (id, chat_id, role, content) VALUES (?, ?, 'user', ?)")
+            .bind(id)
+            .bind(chat_id)
+            .bind(content)
+            .execute(db.pool())
+            .await
+            .unwrap();
+    }
+    sqlx::query(
+        "INSERT INTO api_usage (id, source, chat_id, model, input_tokens, output_tokens, date) \
+         VALUES ('u1', 'chat', 'old', 'claude', 9000, 2000, '2024-05-01')",
+    )
+    .execute(db.pool())
+    .await
+    .unwrap();
+    tags::set_for_chat(db.pool(), "other", &["Personal".to_string()]).await.unwrap();
+
+    // A Wednesday
+    let now = Utc.with_ymd_and_hms(2024, 6, 12, 18, 0, 0).unwrap();
+    let matching = |query: &'static str| {
+        let pool = db.pool().clone();
+        async move {
+            let expr = collections::parse(query).unwrap();
+            let chats = collections::evaluate(&pool, &expr, now, None, None).await.unwrap();
+            chats.into_iter().map(|chat| chat.id).collect::<Vec<_>>()
+        }
+    };
+
+    assert_eq!(matching(r#"is:favorite project:"data platform" updated:week migration"#).await, ["recent"]);
+    assert_eq!(matching("is:favorite migration").await, ["recent", "old"]);
+    assert_eq!(matching("\"schema migration\"").await, ["recent"]);
+    assert_eq!(matching("folder:Work OR tag:personal").await, ["recent", "other"]);
+    // Folders are matched by what they are called now
+    let work = folders::find_by_path(&mut db.pool().acquire().await.unwrap(), "Work").await.unwrap().unwrap();
+    folders::rename(db.pool(), &work, "Office").await.unwrap();
+    assert_eq!(matching("folder:Office/Infra").await, ["recent"]);
+    assert!(matching("folder:Work").await.is_empty());
+    assert_eq!(matching("-is:favorite").await, ["other"]);
+    assert_eq!(matching("tokens:>10k").await, ["old"]);
+    assert_eq!(matching("updated:<2024-06-01").await, ["old"]);
+    assert_eq!(matching("updated:1d messages:1").await, ["recent"]);
+    assert_eq!(matching("").await.len(), 3);
+
+    let saved = collections::create(db.pool(), "Favorites", "is:favorite").await.unwrap();
+    assert!(collections::create(db.pool(), "Broken", "is:").await.is_err());
+    let renamed = collections::update(db.pool(), &saved.id, Some("Starred"), None).await.unwrap();
+    assert_eq!((renamed.name.as_str(), renamed.query.as_str()), ("Starred", "is:favorite"));
+    assert_eq!(collections::list(db.pool()).await.unwrap().len(), 1);
+    assert!(collections::delete(db.pool(), &saved.id).await.unwrap());
+}
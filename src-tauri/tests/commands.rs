@@ -0,0 +1,125 @@
+use app_lib::commands::{agent, chat, project, workflow, CommandError};
+use app_lib::database::models::*;
+use app_lib::database::repositories::ChatFilter;
+use app_lib::database::Database;
+use app_lib::integrations::models::ModelRegistry;
+use app_lib::integrations::oauth::{OAuthConfig, OAuthManager};
+use serde_json::json;
+use std::sync::Arc;
+
+fn config(value: serde_json::Value) -> JsonMap {
+    serde_json::from_value(value).unwrap()
+}
+
+#[tokio::test]
+async fn agent_and_hook_commands() {
+    let db = Arc::new(Database::in_memory().await.unwrap());
+    let models = ModelRegistry::new(None);
+    let request = |model: &str| CreateAgentRequest {
+        name: "Digest".to_string(),
+        description: None,
+        system_prompt: "Summarize".to_string(),
+        model_config: config(json!({ "model": model, "max_tokens": 512 })),
+        schedule_config: None,
+    };
+
+    assert!(agent::create(&db, &models, request("no-such-model")).await.is_err());
+    let created = agent::create(&db, &models, request("claude-3-5-haiku-20241022")).await.unwrap();
+    assert_eq!(agent::list(&db).await.unwrap().len(), 1);
+    assert!(agent::delete(&db, &created.id).await.unwrap());
+    let error = agent::update(&db, &models, &created.id, request("claude-3-5-haiku-20241022")).await.unwrap_err();
+    assert_eq!(error.message(), "Agent not found");
+
+    let oauth = Arc::new(OAuthManager::new(OAuthConfig::from_env()));
+    let hook = |action_type: &str| CreateHookRequest {
+        name: "Notify".to_string(),
+        description: None,
+        trigger_type: TriggerType::ChatCreated,
+        trigger_config: config(json!({})),
+        action_type: action_type.to_string(),
+        action_config: config(json!({})),
+    };
+    let notify = workflow::create(&db, hook("notify")).await.unwrap();
+    let message = workflow::trigger(&db, &oauth, &notify.id, None).await.unwrap();
+    assert_eq!(message, "Hook Notify triggered successfully");
+    // Agent hooks are checked before any credentials are needed
+    let start_agent = workflow::create(&db, hook("trigger_agent")).await.unwrap();
+    let error = workflow::trigger(&db, &oauth, &start_agent.id, None).await.unwrap_err();
+    assert_eq!(error.message(), "Hook does not name an agent to run");
+    sqlx::query("UPDATE hooks SET enabled = 0").execute(db.pool()).await.unwrap();
+    let error = workflow::trigger(&db, &oauth, &notify.id, None).await.unwrap_err();
+    assert_eq!(error.message(), "Hook is disabled");
+
+    assert!(workflow::delete(&db, &notify.id).await.unwrap());
+    assert_eq!(workflow::list(&db).await.unwrap().len(), 1);
+    assert!(workflow::update(&db, &notify.id, hook("notify")).await.is_err());
+}
+
+#[tokio::test]
+async fn project_commands() {
+    let db = Database::in_memory().await.unwrap();
+    let request = |name: &str| CreateProjectRequest {
+        name: name.to_string(),
+        description: None,
+        color: Some("blue".to_string()),
+    };
+
+    let created = project::create(&db, request("Launch")).await.unwrap();
+    let renamed = project::update(&db, &created.id, request("Relaunch")).await.unwrap();
+    assert_eq!((renamed.name.as_str(), renamed.color.as_deref()), ("Relaunch", Some("blue")));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.md");
+    std::fs::write(&path, "# Launch plan").unwrap();
+    let document = project::add_document(&db, &created.id, &path).await.unwrap();
+    assert_eq!(document.mime_type, "text/markdown");
+    assert_eq!(project::documents(&db, &created.id).await.unwrap().len(), 1);
+    assert!(project::delete_document(&db, &document.id).await.unwrap());
+    assert!(project::add_document(&db, &created.id, &dir.path().join("missing.md")).await.is_err());
+
+    assert!(project::delete(&db, &created.id).await.unwrap());
+    assert!(project::list(&db).await.unwrap().is_empty());
+    let error = project::update(&db, &created.id, request("Again")).await.unwrap_err();
+    assert_eq!(error.message(), "Project not found");
+}
+
+#[tokio::test]
+async fn chat_commands() {
+    let db = Database::in_memory().await.unwrap();
+    let request = CreateChatRequest {
+        session_id: "default-session".to_string(),
+        project_id: None,
+        title: " ".to_string(),
+        folder_path: Some("Work".to_string()),
+    };
+    let created = chat::create(&db, request).await.unwrap();
+    assert_eq!(created.title, "New Chat");
+    assert!(created.auto_title);
+
+    let filter = ChatFilter { folder_path: Some("Work".to_string()), ..Default::default() };
+    assert_eq!(chat::list(&db, &filter).await.unwrap().len(), 1);
+
+    let question = Message::new(created.id.clone(), Role::User, "Hi".to_string());
+    db.messages().insert(&question).await.unwrap();
+    let mut answer = Message::new(created.id.clone(), Role::Assistant, "Hello".to_string());
+    answer.parent_id = Some(question.id.clone());
+    db.messages().insert(&answer).await.unwrap();
+    let mut retry = Message::new(created.id.clone(), Role::Assistant, "Hey".to_string());
+    retry.parent_id = Some(question.id.clone());
+    db.messages().insert(&retry).await.unwrap();
+
+    let shown = chat::messages(&db, &created.id, None, Some(1)).await.unwrap();
+    assert_eq!(shown.iter().map(|message| message.content.as_str()).collect::<Vec<_>>(), ["Hey"]);
+    assert_eq!(chat::siblings(&db, &answer.id).await.unwrap().len(), 2);
+    let branch = chat::select_branch(&db, &answer.id).await.unwrap();
+    assert_eq!(branch.last().unwrap().content, "Hello");
+
+    assert!(chat::delete(&db, &created.id).await.unwrap());
+    assert!(chat::get(&db, &created.id).await.unwrap().is_none());
+    let error = chat::messages(&db, &created.id, None, None).await.unwrap_err();
+    assert!(error.message().contains("not found"));
+    // Errors reach the frontend as their message
+    let error = chat::messages(&db, "", None, None).await.unwrap_err();
+    assert_eq!(serde_json::to_value(&error).unwrap(), json!(error.message()));
+    assert_eq!(CommandError::from(anyhow::anyhow!("Disk full")).to_string(), "Disk full");
+}
@@ -0,0 +1,74 @@
+use app_lib::utils::credentials::{CredentialStore, FileCredentialStore, MemoryCredentialStore};
+use app_lib::utils::crypto::{generate_salt, SecureStorage};
+
+fn file_store(dir: &tempfile::TempDir, passphrase: &str, salt: &[u8]) -> FileCredentialStore {
+    let storage = SecureStorage::new(passphrase, salt).unwrap();
+    FileCredentialStore::new(dir.path().join("credentials.json"), storage)
+}
+
+async fn exercise_store(store: &dyn CredentialStore) {
+    assert_eq!(store.get("api.anthropicApiKey").await.unwrap(), None);
+
+    store.set("api.anthropicApiKey", "sk-ant-api03-first").await.unwrap();
+    assert_eq!(
+        store.get("api.anthropicApiKey").await.unwrap().as_deref(),
+        Some("sk-ant-api03-first")
+    );
+
+    store.set("api.anthropicApiKey", "sk-ant-api03-second").await.unwrap();
+    assert_eq!(
+        store.get("api.anthropicApiKey").await.unwrap().as_deref(),
+        Some("sk-ant-api03-second")
+    );
+
+    store.delete("api.anthropicApiKey").await.unwrap();
+    assert_eq!(store.get("api.anthropicApiKey").await.unwrap(), None);
+
+    // Deleting a missing key is not an error
+    store.delete("api.anthropicApiKey").await.unwrap();
+}
+
+#[tokio::test]
+async fn memory_store_round_trips() {
+    exercise_store(&MemoryCredentialStore::new()).await;
+}
+
+#[tokio::test]
+async fn file_store_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let salt = generate_salt();
+    exercise_store(&file_store(&dir, "passphrase", &salt)).await;
+}
+
+#[tokio::test]
+async fn file_store_never_writes_plaintext() {
+    let dir = tempfile::tempdir().unwrap();
+    let salt = generate_salt();
+    let store = file_store(&dir, "passphrase", &salt);
+
+    store.set("api.anthropicOAuthToken", "oauth-access-token").await.unwrap();
+
+    let on_disk = std::fs::read_to_string(dir.path().join("credentials.json")).unwrap();
+    assert!(on_disk.contains("api.anthropicOAuthToken"));
+    assert!(!on_disk.contains("oauth-access-token"));
+}
+
+#[tokio::test]
+async fn file_store_persists_across_instances() {
+    let dir = tempfile::tempdir().unwrap();
+    let salt = generate_salt();
+
+    file_store(&dir, "passphrase", &salt)
+        .set("api.anthropicRefreshToken", "refresh-token")
+        .await
+        .unwrap();
+
+    let reopened = file_store(&dir, "passphrase", &salt);
+    assert_eq!(
+        reopened.get("api.anthropicRefreshToken").await.unwrap().as_deref(),
+        Some("refresh-token")
+    );
+
+    let wrong_passphrase = file_store(&dir, "other", &salt);
+    assert!(wrong_passphrase.get("api.anthropicRefreshToken").await.is_err());
+}
@@ -0,0 +1,112 @@
+use app_lib::database::export::{file_name, load_chat, ChatExport, ExportBundle, ExportFormat, EXPORT_FORMAT_NAME};
+use app_lib::database::models::{json_map, Chat, Message, Role};
+use app_lib::database::Database;
+use app_lib::integrations::attachments::AttachmentStore;
+use app_lib::utils::render::{content_html, render};
+use serde_json::json;
+
+fn message(chat: &Chat, id: &str, parent_id: Option<&str>, role: Role, content: &str) -> Message {
+    let mut message = Message::new(chat.id.clone(), role, content.to_string());
+    message.id = id.to_string();
+    message.parent_id = parent_id.map(str::to_string);
+    message
+}
+
+// A question answered twice; the second answer is the one shown
+fn branched_chat() -> ChatExport {
+    let mut chat = Chat::new("session-1".to_string(), "Sorting <fast>".to_string());
+    chat.active_message_id = Some("a2".to_string());
+    let messages = vec![
+        message(&chat, "q1", None, Role::User, "How do I sort a `Vec`?"),
+        message(&chat, "a1", Some("q1"), Role::Assistant, "Old answer"),
+        message(&chat, "a2", Some("q1"), Role::Assistant, "Use sort:\n\n```rust\nv.sort();\n```"),
+    ];
+    ChatExport { chat, messages }
+}
+
+#[test]
+fn markdown_follows_the_active_branch() {
+    let bundle = ExportBundle::new(None, vec![branched_chat()]);
+    let markdown = render(ExportFormat::Markdown, &bundle).unwrap();
+
+    assert!(markdown.starts_with("# Sorting <fast>\n"));
+    assert!(markdown.contains("## User\n\nHow do I sort a `Vec`?"));
+    assert!(markdown.contains("## Claude\n\nUse sort:\n\n```rust\nv.sort();\n```"));
+    assert!(!markdown.contains("Old answer"));
+
+    // An unterminated fence is closed before the next turn
+    let mut open_fence = branched_chat();
+    open_fence.messages[2].content = "```python\nprint(1)".to_string();
+    let markdown = render(ExportFormat::Markdown, &ExportBundle::new(None, vec![open_fence.clone()])).unwrap();
+    assert!(markdown.ends_with("print(1)\n```\n"));
+
+    // Tilde fences count too, and backticks inside them open nothing
+    open_fence.messages[2].content = "~~~~\n```\nstill code".to_string();
+    let markdown = render(ExportFormat::Markdown, &ExportBundle::new(None, vec![open_fence.clone()])).unwrap();
+    assert!(markdown.ends_with("still code\n~~~~\n"));
+    open_fence.messages[2].content = "~~~\nx\n~~~".to_string();
+    let markdown = render(ExportFormat::Markdown, &ExportBundle::new(None, vec![open_fence])).unwrap();
+    assert!(markdown.ends_with("x\n~~~\n"));
+}
+
+#[test]
+fn html_is_escaped_and_self_contained() {
+    let html = render(ExportFormat::Html, &ExportBundle::new(None, vec![branched_chat()])).unwrap();
+
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<style>"));
+    assert!(html.contains("<title>Sorting &lt;fast&gt;</title>"));
+    assert!(html.contains("<pre><code class=\"language-rust\">v.sort();</code></pre>"));
+    assert!(!html.contains("<fast>"));
+    assert!(!html.contains("Old answer"));
+
+    assert_eq!(
+        content_html("Line one\nuses `<b>`\n\nSecond"),
+        "<p>Line one<br>\nuses <code>&lt;b&gt;</code></p>\n<p>Second</p>\n"
+    );
+    assert_eq!(content_html("~~~ sh\nls ```\n~~~"), "<pre><code class=\"language-sh\">ls ```</code></pre>\n");
+}
+
+#[tokio::test]
+async fn json_keeps_every_branch_and_embeds_attachments() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = AttachmentStore::new(dir.path().join("attachments"));
+    let attachment = store.put("notes.txt", b"remember this").await.unwrap();
+
+    let mut export = branched_chat();
+    export.messages[0].metadata = Some(json_map([("attachments", json!([attachment]))]));
+    let mut bundle = ExportBundle::new(None, vec![export]);
+    bundle.embed_attachments(&store).await.unwrap();
+
+    let json = render(ExportFormat::Json, &bundle).unwrap();
+    let parsed: ExportBundle = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.format, EXPORT_FORMAT_NAME);
+    assert_eq!(parsed.chats[0].messages.len(), 3);
+    assert_eq!(parsed.chats[0].messages[1].parent_id.as_deref(), Some("q1"));
+    assert_eq!(parsed.attachments[&attachment.hash].name, "notes.txt");
+    assert_eq!(parsed.attachments[&attachment.hash].data, "cmVtZW1iZXIgdGhpcw==");
+}
+
+#[tokio::test]
+async fn loads_chats_from_the_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::open(&dir.path().join("cloddo.db")).await.unwrap();
+    db.ensure_session("session-1").await.unwrap();
+    sqlx::query("INSERT INTO chats (id, session_id, title, last_activity) VALUES ('chat-1', 'session-1', 'Saved', CURRENT_TIMESTAMP)")
+        .execute(db.pool())
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO messages (id, chat_id, role, content) VALUES ('m-1', 'chat-1', 'user', 'Hi')")
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+    let export = load_chat(db.pool(), "chat-1").await.unwrap().unwrap();
+    assert_eq!(export.messages.len(), 1);
+    assert_eq!(export.active_branch()[0].content, "Hi");
+    assert!(load_chat(db.pool(), "missing").await.unwrap().is_none());
+
+    assert_eq!(file_name("Q3 plan: draft/v2?", ExportFormat::Markdown), "Q3 plan_ draft_v2_.md");
+    assert_eq!(file_name("???", ExportFormat::Html), "___.html");
+    assert_eq!(file_name("  ", ExportFormat::Json), "chat.json");
+}
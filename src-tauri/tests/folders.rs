@@ -0,0 +1,116 @@
+use app_lib::database::search::{search, SearchFilter};
+use app_lib::database::{folders, tags, Database};
+
+async fn open_db() -> Database {
+    let db = Database::in_memory().await.unwrap();
+    db.ensure_session("session-1").await.unwrap();
+    db
+}
+
+async fn insert_chat(db: &Database, id: &str, title: &str) {
+    sqlx::query("INSERT INTO chats (id, session_id, title, last_activity) VALUES (?, 'session-1', ?, CURRENT_TIMESTAMP)")
+        .bind(id)
+        .bind(title)
+        .execute(db.pool())
+        .await
+        .unwrap();
+}
+
+async fn chat_folder(db: &Database, id: &str) -> (Option<String>, Option<String>) {
+    sqlx::query_as("SELECT folder_id, folder_path FROM chats WHERE id = ?")
+        .bind(id)
+        .fetch_one(db.pool())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn renaming_and_moving_folders_updates_chat_paths() {
+    let db = open_db().await;
+    insert_chat(&db, "chat-1", "Invoice").await;
+    insert_chat(&db, "chat-2", "Plan").await;
+
+    let mut conn = db.pool().acquire().await.unwrap();
+    let clients = folders::ensure_path(&mut conn, " Work / Clients/ ").await.unwrap().unwrap();
+    assert_eq!(folders::find_by_path(&mut conn, "Work/Clients").await.unwrap().as_deref(), Some(clients.as_str()));
+    assert_eq!(folders::ensure_path(&mut conn, "/").await.unwrap(), None);
+    drop(conn);
+
+    let work = folders::list(db.pool()).await.unwrap()[0].clone();
+    assert_eq!(work.path, "Work");
+    folders::move_chats(db.pool(), &["chat-1".to_string()], Some(&clients)).await.unwrap();
+    folders::move_chats(db.pool(), &["chat-2".to_string()], Some(&work.id)).await.unwrap();
+    assert_eq!(chat_folder(&db, "chat-1").await.1.as_deref(), Some("Work/Clients"));
+
+    folders::rename(db.pool(), &work.id, "Job").await.unwrap();
+    assert_eq!(chat_folder(&db, "chat-1").await.1.as_deref(), Some("Job/Clients"));
+    assert_eq!(chat_folder(&db, "chat-2").await.1.as_deref(), Some("Job"));
+
+    let archive = folders::create(db.pool(), "Archive", None).await.unwrap();
+    let moved = folders::move_folder(db.pool(), &clients, Some(&archive.id)).await.unwrap();
+    assert_eq!(moved.path, "Archive/Clients");
+    assert_eq!(moved.chat_count, 1);
+    assert_eq!(chat_folder(&db, "chat-1").await.1.as_deref(), Some("Archive/Clients"));
+
+    // Folders cannot go inside themselves, and siblings need distinct names
+    let error = folders::move_folder(db.pool(), &archive.id, Some(&clients)).await.unwrap_err();
+    assert_eq!(error.to_string(), "Cannot move a folder into itself");
+    let error = folders::create(db.pool(), "Clients", Some(&archive.id)).await.unwrap_err();
+    assert!(error.to_string().contains("already exists"));
+}
+
+#[tokio::test]
+async fn deleting_a_folder_moves_its_chats_up() {
+    let db = open_db().await;
+    insert_chat(&db, "chat-1", "Deep").await;
+
+    let mut conn = db.pool().acquire().await.unwrap();
+    let leaf = folders::ensure_path(&mut conn, "A/B/C").await.unwrap().unwrap();
+    let b = folders::find_by_path(&mut conn, "A/B").await.unwrap().unwrap();
+    let a = folders::find_by_path(&mut conn, "A").await.unwrap().unwrap();
+    drop(conn);
+    folders::move_chats(db.pool(), &["chat-1".to_string()], Some(&leaf)).await.unwrap();
+
+    assert!(folders::delete(db.pool(), &b).await.unwrap());
+    assert_eq!(chat_folder(&db, "chat-1").await, (Some(a.clone()), Some("A".to_string())));
+    let paths: Vec<String> = folders::list(db.pool()).await.unwrap().into_iter().map(|folder| folder.path).collect();
+    assert_eq!(paths, ["A"]);
+
+    assert!(folders::delete(db.pool(), &a).await.unwrap());
+    assert_eq!(chat_folder(&db, "chat-1").await, (None, None));
+    assert!(!folders::delete(db.pool(), &a).await.unwrap());
+}
+
+#[tokio::test]
+async fn tags_count_chats_and_filter_search() {
+    let db = open_db().await;
+    insert_chat(&db, "chat-1", "Release notes").await;
+    insert_chat(&db, "chat-2", "Release party").await;
+
+    let work = tags::create(db.pool(), "Work", Some("#ff0000")).await.unwrap();
+    assert!(tags::create(db.pool(), "work", None).await.unwrap_err().to_string().contains("already exists"));
+
+    let assigned = tags::set_for_chat(db.pool(), "chat-1", &["work".to_string(), "Urgent".to_string()]).await.unwrap();
+    let names: Vec<&str> = assigned.iter().map(|tag| tag.name.as_str()).collect();
+    assert_eq!(names, ["Urgent", "Work"]);
+    tags::set_for_chat(db.pool(), "chat-2", &["Urgent".to_string()]).await.unwrap();
+
+    let counts: Vec<(String, i64)> =
+        tags::list(db.pool()).await.unwrap().into_iter().map(|tag| (tag.name, tag.chat_count)).collect();
+    assert_eq!(counts, [("Urgent".to_string(), 2), ("Work".to_string(), 1)]);
+
+    let filter = SearchFilter { tag: Some("work".to_string()), ..Default::default() };
+    let results = search(db.pool(), "release", &filter).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].chat_id, "chat-1");
+
+    let renamed = tags::update(db.pool(), &work.id, Some("Job"), None).await.unwrap();
+    assert_eq!((renamed.name.as_str(), renamed.color.as_deref()), ("Job", Some("#ff0000")));
+
+    // Deleting a tag or a chat removes the assignments
+    assert!(tags::delete(db.pool(), &work.id).await.unwrap());
+    sqlx::query("DELETE FROM chats WHERE id = 'chat-2'").execute(db.pool()).await.unwrap();
+    let counts: Vec<(String, i64)> =
+        tags::list(db.pool()).await.unwrap().into_iter().map(|tag| (tag.name, tag.chat_count)).collect();
+    assert_eq!(counts, [("Urgent".to_string(), 1)]);
+}
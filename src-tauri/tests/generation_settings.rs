@@ -0,0 +1,111 @@
+use app_lib::integrations::generation::{
+    resolve, GenerationSettings, SettingSource, DEFAULT_MAX_TOKENS, DEFAULT_SYSTEM_PROMPT,
+};
+use app_lib::database::models::{json_map, Chat, UpdateChatRequest};
+use app_lib::database::Database;
+use app_lib::integrations::models::{ModelRegistry, DEFAULT_MODEL};
+use serde_json::json;
+use std::collections::HashMap;
+
+#[test]
+fn chat_overrides_project_overrides_global() {
+    let models = ModelRegistry::new(None);
+    let chat = GenerationSettings {
+        temperature: Some(0.2),
+        ..Default::default()
+    };
+    let project = GenerationSettings {
+        model: Some("claude-3-5-haiku-20241022".to_string()),
+        temperature: Some(0.9),
+        system_prompt: Some("You review Rust code.".to_string()),
+        ..Default::default()
+    };
+    let global = GenerationSettings::from_global_settings(&HashMap::from([
+        ("api.defaultModel".to_string(), serde_json::json!(DEFAULT_MODEL)),
+        ("api.defaultMaxTokens".to_string(), serde_json::json!(2048)),
+        ("api.defaultSystemPrompt".to_string(), serde_json::json!("")),
+    ]));
+
+    let effective = resolve(&chat, &project, &global, &models).unwrap();
+    assert_eq!(effective.model, "claude-3-5-haiku-20241022");
+    assert_eq!(effective.sources.model, SettingSource::Project);
+    assert_eq!(effective.temperature, 0.2);
+    assert_eq!(effective.sources.temperature, SettingSource::Chat);
+    assert_eq!(effective.max_tokens, 2048);
+    assert_eq!(effective.sources.max_tokens, SettingSource::Global);
+    assert_eq!(effective.system_prompt, "You review Rust code.");
+
+    let defaults = GenerationSettings::default();
+    let effective = resolve(&defaults, &defaults, &defaults, &models).unwrap();
+    assert_eq!(effective.model, DEFAULT_MODEL);
+    assert_eq!(effective.max_tokens, DEFAULT_MAX_TOKENS);
+    assert_eq!(effective.system_prompt, DEFAULT_SYSTEM_PROMPT);
+    assert_eq!(effective.sources.system_prompt, SettingSource::Default);
+}
+
+#[test]
+fn inherited_max_tokens_is_capped_by_the_model() {
+    let models = ModelRegistry::new(None);
+    let chat = GenerationSettings {
+        model: Some("claude-3-haiku-20240307".to_string()),
+        ..Default::default()
+    };
+    let project = GenerationSettings {
+        max_tokens: Some(32_000),
+        ..Default::default()
+    };
+
+    let effective = resolve(&chat, &project, &GenerationSettings::default(), &models).unwrap();
+    assert_eq!(effective.max_tokens, 4_096);
+
+    // Set directly on the chat, the same value is rejected
+    let chat = GenerationSettings {
+        max_tokens: Some(32_000),
+        ..chat
+    };
+    assert!(chat.validate(&models, DEFAULT_MODEL).is_err());
+}
+
+#[test]
+fn overrides_round_trip_through_metadata() {
+    let settings = GenerationSettings {
+        model: Some("claude-3-5-haiku-20241022".to_string()),
+        max_tokens: Some(1024),
+        ..Default::default()
+    };
+
+    let pinned = json_map([("pinned", json!(true))]);
+    let metadata = settings.merge_into_metadata(Some(&pinned)).unwrap();
+    assert_eq!(metadata["pinned"], true);
+    assert_eq!(metadata["generation"]["max_tokens"], 1024);
+    assert!(metadata["generation"].get("temperature").is_none());
+
+    assert_eq!(GenerationSettings::from_metadata(Some(&metadata)).unwrap(), settings);
+
+    // Clearing the overrides leaves the rest of the metadata alone
+    let cleared = GenerationSettings::default().merge_into_metadata(Some(&metadata)).unwrap();
+    assert_eq!(cleared, *pinned);
+    assert_eq!(GenerationSettings::from_metadata(None).unwrap(), GenerationSettings::default());
+}
+
+#[tokio::test]
+async fn metadata_updates_keep_overrides() {
+    let db = Database::in_memory().await.unwrap();
+    let chat = Chat::new("default-session".to_string(), "Review".to_string());
+    db.chats().insert(&chat, false).await.unwrap();
+    let overrides = GenerationSettings { temperature: Some(0.1), ..Default::default() };
+    db.chats().set_metadata(&chat.id, &overrides.merge_into_metadata(None).unwrap()).await.unwrap();
+
+    let update = |metadata: serde_json::Value| UpdateChatRequest {
+        title: None,
+        folder_path: None,
+        is_favorite: None,
+        metadata: Some(serde_json::from_value(metadata).unwrap()),
+    };
+    db.chats().update(&chat.id, &update(json!({ "pinned": true }))).await.unwrap();
+    let updated = db.chats().update(&chat.id, &update(json!({ "color": "red", "pinned": null }))).await.unwrap().unwrap();
+
+    let metadata = updated.metadata.unwrap();
+    assert_eq!(GenerationSettings::from_metadata(Some(&metadata)).unwrap(), overrides);
+    assert_eq!((metadata.get("color"), metadata.get("pinned")), (Some(&json!("red")), None));
+}
@@ -0,0 +1,175 @@
+use app_lib::database::branches::active_path;
+use app_lib::database::importers::{import_export, parse_export, ImportSource};
+use app_lib::database::Database;
+use serde_json::json;
+
+async fn open_db() -> Database {
+    let db = Database::in_memory().await.unwrap();
+    db.ensure_session("session-1").await.unwrap();
+    db
+}
+
+// A question answered twice; the second answer was showing
+fn claude_export() -> String {
+    json!([
+        {
+            "uuid": "conv-1",
+            "name": "Sorting",
+            "created_at": "2024-03-01T10:00:00.000000Z",
+            "updated_at": "2024-03-01T10:05:00.000000Z",
+            "current_leaf_message_uuid": "a2",
+            "chat_messages": [
+                {"uuid": "q1", "sender": "human", "text": "How do I sort?", "created_at": "2024-03-01T10:00:00Z",
+                 "parent_message_uuid": "00000000-0000-4000-8000-000000000000"},
+                {"uuid": "a1", "sender": "assistant", "text": "", "created_at": "2024-03-01T10:01:00Z",
+                 "content": [{"type": "text", "text": "Use sort()"}], "parent_message_uuid": "q1"},
+                {"uuid": "a2", "sender": "assistant", "text": "Use sort_by()", "created_at": "2024-03-01T10:02:00Z",
+                 "parent_message_uuid": "q1"}
+            ]
+        },
+        {
+            "uuid": "conv-2",
+            "name": "",
+            "created_at": "2024-03-02T09:00:00Z",
+            "chat_messages": [
+                {"uuid": "m1", "sender": "human", "text": "Read this", "created_at": "2024-03-02T09:00:00Z",
+                 "attachments": [{"file_name": "notes.txt", "file_type": "text/plain", "file_size": 5, "extracted_content": "hello"}]},
+                {"uuid": "m2", "sender": "assistant", "text": "Done", "created_at": "2024-03-02T09:01:00Z"}
+            ]
+        },
+        {"name": "Broken"}
+    ])
+    .to_string()
+}
+
+#[test]
+fn parses_claude_exports() {
+    let (source, conversations, issues) = parse_export(&claude_export(), None).unwrap();
+    assert_eq!(source, ImportSource::ClaudeAi);
+    assert_eq!(conversations.len(), 2);
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].title.as_deref(), Some("Broken"));
+
+    let sorting = &conversations[0];
+    assert_eq!(sorting.messages[1].content, "Use sort()");
+    assert_eq!(sorting.messages[2].parent_source_id.as_deref(), Some("q1"));
+    assert_eq!(sorting.current_source_id.as_deref(), Some("a2"));
+
+    // Without parent links the messages form one branch
+    let untitled = &conversations[1];
+    assert_eq!(untitled.title, "Untitled");
+    assert_eq!(untitled.messages[1].parent_source_id.as_deref(), Some("m1"));
+    assert!(untitled.messages[0].metadata.as_ref().unwrap()["imported_attachments"][0]["name"] == "notes.txt");
+}
+
+#[test]
+fn replies_to_skipped_claude_messages_attach_to_the_nearest_kept_one() {
+    let export = json!([{
+        "uuid": "conv-3",
+        "name": "Tools",
+        "created_at": "2024-03-03T08:00:00Z",
+        "current_leaf_message_uuid": "a2",
+        "chat_messages": [
+            {"uuid": "q1", "sender": "human", "text": "Look it up", "parent_message_uuid": "00000000-0000-4000-8000-000000000000"},
+            {"uuid": "t1", "sender": "assistant", "text": "", "content": [{"type": "tool_use", "name": "search"}],
+             "parent_message_uuid": "q1"},
+            {"uuid": "t2", "sender": "human", "text": "  ", "parent_message_uuid": "t1"},
+            {"uuid": "a2", "sender": "assistant", "text": "Found it", "parent_message_uuid": "t2"}
+        ]
+    }])
+    .to_string();
+
+    let (_, conversations, _) = parse_export(&export, None).unwrap();
+    let messages = &conversations[0].messages;
+    assert_eq!(messages.iter().map(|message| message.source_id.as_str()).collect::<Vec<_>>(), ["q1", "a2"]);
+    assert_eq!(messages[1].parent_source_id.as_deref(), Some("q1"));
+}
+
+#[test]
+fn a_skipped_claude_leaf_shows_its_nearest_kept_ancestor() {
+    let export = json!([{
+        "uuid": "conv-4",
+        "name": "Unfinished",
+        "created_at": "2024-03-04T08:00:00Z",
+        "current_leaf_message_uuid": "t2",
+        "chat_messages": [
+            {"uuid": "q1", "sender": "human", "text": "Look it up", "parent_message_uuid": "00000000-0000-4000-8000-000000000000"},
+            {"uuid": "a1", "sender": "assistant", "text": "On it", "parent_message_uuid": "q1"},
+            {"uuid": "t2", "sender": "assistant", "text": "", "content": [{"type": "tool_use", "name": "search"}],
+             "parent_message_uuid": "a1"}
+        ]
+    }])
+    .to_string();
+
+    let (_, conversations, _) = parse_export(&export, None).unwrap();
+    assert_eq!(conversations[0].messages.len(), 2);
+    assert_eq!(conversations[0].current_source_id.as_deref(), Some("a1"));
+}
+
+#[test]
+fn parses_chatgpt_trees_and_skips_hidden_nodes() {
+    let export = json!([{
+        "conversation_id": "gpt-1",
+        "title": "Trip",
+        "create_time": 1700000000.5,
+        "update_time": 1700000100.0,
+        "current_node": "tool",
+        "mapping": {
+            "root": {"id": "root", "message": null, "parent": null, "children": ["system"]},
+            "system": {"id": "system", "parent": "root", "children": ["u1"],
+                       "message": {"author": {"role": "system"}, "content": {"content_type": "text", "parts": [""]}}},
+            "u1": {"id": "u1", "parent": "system", "children": ["a1", "a2"],
+                   "message": {"author": {"role": "user"}, "create_time": 1700000001.0, "content": {"parts": ["Plan a trip"]}}},
+            "a1": {"id": "a1", "parent": "u1", "children": [],
+                   "message": {"author": {"role": "assistant"}, "create_time": 1700000002.0, "content": {"parts": ["Paris"]}}},
+            "a2": {"id": "a2", "parent": "u1", "children": ["tool"],
+                   "message": {"author": {"role": "assistant"}, "create_time": 1700000003.0, "content": {"parts": ["Rome"]}}},
+            "tool": {"id": "tool", "parent": "a2", "children": [],
+                     "message": {"author": {"role": "tool"}, "create_time": 1700000004.0, "content": {"parts": ["{}"]}}}
+        }
+    }])
+    .to_string();
+
+    let (source, conversations, issues) = parse_export(&export, None).unwrap();
+    assert_eq!(source, ImportSource::Chatgpt);
+    assert!(issues.is_empty());
+
+    let trip = &conversations[0];
+    let ids: Vec<&str> = trip.messages.iter().map(|message| message.source_id.as_str()).collect();
+    assert_eq!(ids, ["u1", "a1", "a2"]);
+    assert_eq!(trip.messages[0].parent_source_id, None);
+    assert_eq!(trip.messages[2].parent_source_id.as_deref(), Some("u1"));
+    assert_eq!(trip.current_source_id.as_deref(), Some("a2"));
+
+    assert!(parse_export(r#"[{"something": "else"}]"#, None).is_err());
+}
+
+#[tokio::test]
+async fn imports_once_and_keeps_the_shown_branch() {
+    let db = open_db().await;
+
+    let summary = import_export(db.pool(), &claude_export(), None, "session-1", None).await.unwrap();
+    assert_eq!(summary.conversations_found, 3);
+    assert_eq!(summary.chats_imported, 2);
+    assert_eq!(summary.messages_imported, 5);
+    assert_eq!(summary.issues.len(), 1);
+
+    let (chat_id, title, created_at): (String, String, String) =
+        sqlx::query_as("SELECT id, title, created_at FROM chats WHERE source = 'claude_ai' AND source_id = 'conv-1'")
+            .fetch_one(db.pool())
+            .await
+            .unwrap();
+    assert_eq!(title, "Sorting");
+    assert!(created_at.starts_with("2024-03-01"));
+
+    let path = active_path(db.pool(), &chat_id).await.unwrap();
+    let contents: Vec<&str> = path.iter().map(|message| message.content.as_str()).collect();
+    assert_eq!(contents, ["How do I sort?", "Use sort_by()"]);
+
+    // Importing the same file again adds nothing
+    let again = import_export(db.pool(), &claude_export(), None, "session-1", None).await.unwrap();
+    assert_eq!(again.chats_imported, 0);
+    assert_eq!(again.chats_skipped, 2);
+    let chats: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM chats").fetch_one(db.pool()).await.unwrap();
+    assert_eq!(chats, 2);
+}
@@ -0,0 +1,39 @@
+use app_lib::database::legacy::import_chats;
+use app_lib::database::models::Chat;
+use app_lib::database::repositories::ChatFilter;
+use app_lib::database::Database;
+
+#[tokio::test]
+async fn imports_chats_file_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::in_memory().await.unwrap();
+    assert_eq!(import_chats(&db, dir.path()).await.unwrap(), 0);
+
+    let chats = vec![
+        Chat::new("old-session".to_string(), "Trip planning".to_string()),
+        Chat::new("old-session".to_string(), "Recipes".to_string()),
+    ];
+    std::fs::write(dir.path().join("chats.json"), serde_json::to_string(&chats).unwrap()).unwrap();
+    // A chat imported by an interrupted earlier run
+    db.ensure_session("old-session").await.unwrap();
+    db.chats().insert(&chats[0], false).await.unwrap();
+
+    assert_eq!(import_chats(&db, dir.path()).await.unwrap(), 2);
+    let mut titles: Vec<String> = db.chats().list(&ChatFilter::default()).await.unwrap().into_iter().map(|chat| chat.title).collect();
+    titles.sort();
+    assert_eq!(titles, ["Recipes", "Trip planning"]);
+
+    assert!(!dir.path().join("chats.json").exists());
+    assert!(dir.path().join("chats.json.imported").exists());
+    assert_eq!(import_chats(&db, dir.path()).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn unreadable_chats_file_is_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::in_memory().await.unwrap();
+    std::fs::write(dir.path().join("chats.json"), "not json").unwrap();
+
+    assert!(import_chats(&db, dir.path()).await.is_err());
+    assert!(dir.path().join("chats.json").exists());
+}
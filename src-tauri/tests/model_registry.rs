@@ -0,0 +1,132 @@
+use app_lib::integrations::anthropic::{AnthropicClient, ListedModel};
+use app_lib::integrations::models::{ModelRegistry, DEFAULT_MODEL, LIGHTWEIGHT_MODEL};
+use std::collections::HashMap;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Serves a two-page `/v1/models` listing.
+async fn start_models_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = [0u8; 4096];
+            let read = stream.read(&mut buffer).await.unwrap();
+            let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+
+            let body = if request.starts_with("GET /models?limit=1000&after_id=claude-opus-4-5-20251101 ") {
+                r#"{"data":[{"type":"model","id":"claude-experimental-1","display_name":"Experimental","created_at":"2025-01-01T00:00:00Z"}],"has_more":false,"first_id":"claude-experimental-1","last_id":"claude-experimental-1"}"#
+            } else if request.starts_with("GET /models?limit=1000 ") && request.contains("x-api-key: test-key") {
+                r#"{"data":[{"type":"model","id":"claude-opus-4-5-20251101","display_name":"Claude Opus 4.5","created_at":"2025-11-01T00:00:00Z"}],"has_more":true,"first_id":"claude-opus-4-5-20251101","last_id":"claude-opus-4-5-20251101"}"#
+            } else {
+                r#"{"type":"error","error":{"type":"not_found_error","message":"unexpected request"}}"#
+            };
+
+            let reply = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(reply.as_bytes()).await.unwrap();
+        }
+    });
+
+    base_url
+}
+
+fn config(pairs: &[(&str, serde_json::Value)]) -> HashMap<String, serde_json::Value> {
+    pairs
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.clone()))
+        .collect()
+}
+
+#[test]
+fn builtin_models_cover_the_defaults() {
+    let registry = ModelRegistry::new(None);
+    assert!(registry.refreshed_at().is_none());
+
+    let default = registry.get(DEFAULT_MODEL).unwrap();
+    assert_eq!(default.max_output_tokens, 8_192);
+    assert!(default.capabilities.vision);
+    assert!(!default.capabilities.extended_thinking);
+
+    assert!(registry.get(LIGHTWEIGHT_MODEL).is_some());
+
+    // Aliases and newer snapshots of a known family are described from it
+    let alias = registry.get("claude-sonnet-4-5").unwrap();
+    assert_eq!(alias.max_output_tokens, 64_000);
+    assert!(alias.capabilities.extended_thinking);
+
+    assert_eq!(registry.get("claude-3-5-sonnet-latest").unwrap().max_output_tokens, 8_192);
+    assert!(registry.get("claude-3-5-haiku-20250601").is_some());
+    assert!(registry.get("claude-3-opus-latest").is_some());
+
+    // Typos within a family are not guessed at
+    assert!(registry.get("claude-3-5-sonnet-garbage").is_none());
+    assert!(registry.get("claude-sonnet-4-5-2025").is_none());
+    assert!(registry.require("gpt-4o").unwrap_err().contains("Unknown model 'gpt-4o'"));
+}
+
+#[test]
+fn validates_model_configs() {
+    let registry = ModelRegistry::new(None);
+
+    registry.validate_model_config(&HashMap::new()).unwrap();
+    registry
+        .validate_model_config(&config(&[
+            ("model", "claude-3-5-haiku-20241022".into()),
+            ("max_tokens", 8192.into()),
+            ("temperature", 0.2.into()),
+        ]))
+        .unwrap();
+
+    let errors = [
+        config(&[("model", "not-a-model".into())]),
+        config(&[("model", 42.into())]),
+        config(&[("model", "claude-3-haiku-20240307".into()), ("max_tokens", 8192.into())]),
+        config(&[("max_tokens", 0.into())]),
+        config(&[("max_tokens", (-5).into())]),
+        config(&[("temperature", 1.5.into())]),
+        config(&[("temperature", "hot".into())]),
+    ];
+    for invalid in errors {
+        assert!(registry.validate_model_config(&invalid).is_err(), "{:?} was accepted", invalid);
+    }
+}
+
+#[tokio::test]
+async fn refresh_pages_through_listing_and_caches_it() {
+    let base_url = start_models_server().await;
+    let dir = tempfile::tempdir().unwrap();
+    let cache_path = dir.path().join("models.json");
+
+    let registry = ModelRegistry::new(Some(cache_path.clone()));
+    let client = AnthropicClient::new("test-key".to_string(), Some(base_url));
+    let models = registry.refresh(&client).await.unwrap();
+
+    let ids: Vec<&str> = models.iter().map(|model| model.id.as_str()).collect();
+    assert_eq!(ids, ["claude-opus-4-5-20251101", "claude-experimental-1"]);
+    assert!(models[0].pricing.is_some());
+    // Unknown families get conservative limits and no price
+    assert!(models[1].pricing.is_none());
+    assert_eq!(models[1].max_output_tokens, 4_096);
+
+    // Models missing from the listing are no longer listed
+    assert!(registry.list().iter().all(|model| model.id != LIGHTWEIGHT_MODEL));
+
+    let reloaded = ModelRegistry::new(Some(cache_path));
+    assert_eq!(reloaded.list(), models);
+    assert!(reloaded.refreshed_at().is_some());
+}
+
+#[test]
+fn empty_listing_keeps_current_models() {
+    let registry = ModelRegistry::new(None);
+    let before = registry.list();
+
+    assert!(registry.apply_listing(Vec::<ListedModel>::new()).is_err());
+    assert_eq!(registry.list(), before);
+}
@@ -0,0 +1,112 @@
+use app_lib::database::models::{json_map, AgentRun, Hook, Message, Project, Role, RunStatus, TriggerType};
+use app_lib::database::search::SearchFilter;
+use app_lib::database::migrations::{schema_version, SCHEMA_VERSION};
+use app_lib::database::Database;
+use serde_json::json;
+
+#[tokio::test]
+async fn enums_and_json_columns_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::open(&dir.path().join("cloddo.db")).await.unwrap();
+    let statements = [
+        "INSERT INTO chats (id, session_id, title) VALUES ('chat-1', 'default-session', 'Chat')",
+        r#"INSERT INTO messages (id, chat_id, role, content, metadata) VALUES ('m1', 'chat-1', 'assistant', 'Hi', '{"pinned":true}')"#,
+        r#"INSERT INTO agents (id, name, system_prompt, model_config) VALUES ('agent-1', 'Digest', 'Summarize', '{"model":"claude"}')"#,
+        "INSERT INTO agent_runs (id, agent_id, status) VALUES ('run-1', 'agent-1', 'running')",
+    ];
+    for statement in statements {
+        sqlx::query(statement).execute(db.pool()).await.unwrap();
+    }
+
+    let message: Message = sqlx::query_as("SELECT * FROM messages").fetch_one(db.pool()).await.unwrap();
+    assert_eq!(message.role, Role::Assistant);
+    assert_eq!(message.metadata.as_deref().unwrap()["pinned"], true);
+    let run: AgentRun = sqlx::query_as("SELECT * FROM agent_runs").fetch_one(db.pool()).await.unwrap();
+    assert_eq!(run.status, RunStatus::Running);
+
+    let mut project = Project::new("Launch".to_string());
+    project.metadata = Some(json_map([("color", json!("blue"))]));
+    sqlx::query("INSERT INTO projects (id, name, metadata) VALUES (?, ?, ?)")
+        .bind(&project.id)
+        .bind(&project.name)
+        .bind(&project.metadata)
+        .execute(db.pool())
+        .await
+        .unwrap();
+    let stored: String = sqlx::query_scalar("SELECT metadata FROM projects").fetch_one(db.pool()).await.unwrap();
+    assert_eq!(stored, r#"{"color":"blue"}"#);
+
+    // Values outside the CHECK constraints never reach the database
+    let serialized = serde_json::to_value(&message).unwrap();
+    assert_eq!(serialized["role"], "assistant");
+    assert_eq!(serialized["metadata"], json!({ "pinned": true }));
+    let filter = serde_json::from_value::<SearchFilter>(json!({ "role": "robot" }));
+    assert!(filter.unwrap_err().to_string().contains("unknown variant `robot`"));
+}
+
+#[tokio::test]
+async fn unreadable_values_are_kept_aside_on_upgrade() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cloddo.db");
+    let db = Database::open(&path).await.unwrap();
+    let statements = [
+        "INSERT INTO chats (id, session_id, title, metadata) VALUES ('chat-1', 'default-session', 'Chat', 'not json')",
+        "INSERT INTO messages (id, chat_id, role, content, metadata) VALUES ('m1', 'chat-1', 'user', 'Hi', '[1, 2]')",
+        "INSERT INTO agents (id, name, system_prompt, model_config, schedule_config) VALUES ('agent-1', 'Digest', 'Summarize', '', '\"daily\"')",
+        "INSERT INTO hooks (id, name, trigger_type, trigger_config, action_type, action_config) \
+         VALUES ('hook-1', 'Old', 'message_sent', '{}', 'notify', '{}')",
+        "PRAGMA user_version = 13",
+    ];
+    for statement in statements {
+        sqlx::query(statement).execute(db.pool()).await.unwrap();
+    }
+    db.pool().close().await;
+
+    let db = Database::open(&path).await.unwrap();
+    // Values that were not objects are kept rather than dropped
+    let message: Message = sqlx::query_as("SELECT * FROM messages").fetch_one(db.pool()).await.unwrap();
+    assert_eq!(message.metadata.unwrap()["legacy_value"], json!([1, 2]));
+    let chat_metadata: Option<String> = sqlx::query_scalar("SELECT metadata FROM chats").fetch_one(db.pool()).await.unwrap();
+    assert_eq!(chat_metadata.as_deref(), Some(r#"{"legacy_value":"not json"}"#));
+    let agent: (String, Option<String>) = sqlx::query_as("SELECT model_config, schedule_config FROM agents")
+        .fetch_one(db.pool())
+        .await
+        .unwrap();
+    assert_eq!(agent, ("{}".to_string(), Some(r#"{"legacy_value":"daily"}"#.to_string())));
+    let hook: Hook = sqlx::query_as("SELECT * FROM hooks").fetch_one(db.pool()).await.unwrap();
+    assert_eq!(hook.trigger_type, TriggerType::CustomEvent);
+    let trigger_config: serde_json::Value = serde_json::from_str(&hook.trigger_config).unwrap();
+    assert_eq!(trigger_config["legacy_trigger_type"], "message_sent");
+}
+
+#[tokio::test]
+async fn an_interrupted_upgrade_resumes_where_it_stopped() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cloddo.db");
+    let url = format!("sqlite:{}?mode=rwc", path.display());
+
+    // A row v13's foreign key check refuses makes the upgrade stop there
+    let mut conn = <sqlx::SqliteConnection as sqlx::Connection>::connect(&url).await.unwrap();
+    for statement in [
+        "PRAGMA foreign_keys = OFF",
+        "CREATE TABLE notes (chat_id TEXT REFERENCES chats(id))",
+        "INSERT INTO notes (chat_id) VALUES ('gone')",
+    ] {
+        sqlx::query(statement).execute(&mut conn).await.unwrap();
+    }
+    let error = Database::open(&path).await.err().unwrap();
+    assert!(error.to_string().contains("break foreign keys"), "{}", error);
+
+    let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+    assert_eq!(schema_version(&pool).await.unwrap(), 12);
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('chats')")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert!(!columns.iter().any(|column| column == "deleted_at"));
+    pool.close().await;
+
+    sqlx::query("DELETE FROM notes").execute(&mut conn).await.unwrap();
+    let db = Database::open(&path).await.unwrap();
+    assert_eq!(schema_version(db.pool()).await.unwrap(), SCHEMA_VERSION);
+}
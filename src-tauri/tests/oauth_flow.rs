@@ -0,0 +1,339 @@
+use app_lib::integrations::anthropic::{AnthropicClient, AnthropicMessage, AnthropicRequest};
+use app_lib::integrations::auth::{AuthProvider, OAuthAuth};
+use app_lib::integrations::oauth::{generate_code_challenge, OAuthConfig, OAuthManager};
+use app_lib::utils::credentials::{CredentialStore, MemoryCredentialStore};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Minimal authorization server that answers every token request and keeps
+/// the submitted form parameters.
+struct MockAuthServer {
+    base_url: String,
+    token_requests: Arc<Mutex<Vec<HashMap<String, String>>>>,
+}
+
+impl MockAuthServer {
+    async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let token_requests = Arc::new(Mutex::new(Vec::new()));
+
+        let requests = token_requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let body = read_body(&mut stream).await;
+                let params = parse_form(&body);
+
+                let (status, response) = if params.get("code").map(String::as_str) == Some("bad-code") {
+                    ("400 Bad Request", r#"{"error":"invalid_grant","error_description":"Invalid code"}"#)
+                } else {
+                    (
+                        "200 OK",
+                        r#"{"access_token":"mock-access","refresh_token":"mock-refresh","token_type":"Bearer","expires_in":3600,"scope":"user:inference"}"#,
+                    )
+                };
+                requests.lock().unwrap().push(params);
+
+                let reply = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    response.len(),
+                    response
+                );
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        Self { base_url, token_requests }
+    }
+
+    fn config(&self) -> OAuthConfig {
+        OAuthConfig {
+            authorize_url: format!("{}/authorize", self.base_url),
+            token_url: format!("{}/token", self.base_url),
+            client_id: "test-client".to_string(),
+            client_secret: None,
+        }
+    }
+}
+
+async fn read_body(stream: &mut tokio::net::TcpStream) -> String {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    loop {
+        let read = stream.read(&mut chunk).await.unwrap();
+        buffer.extend_from_slice(&chunk[..read]);
+
+        let text = String::from_utf8_lossy(&buffer).to_string();
+        if let Some(split) = text.find("\r\n\r\n") {
+            let content_length = text[..split]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().unwrap())
+                })
+                .unwrap_or(0);
+            if buffer.len() >= split + 4 + content_length || read == 0 {
+                return text[split + 4..].to_string();
+            }
+        }
+    }
+}
+
+fn parse_form(body: &str) -> HashMap<String, String> {
+    reqwest::Url::parse(&format!("http://localhost/?{}", body))
+        .unwrap()
+        .query_pairs()
+        .into_owned()
+        .collect()
+}
+
+fn query_params(url: &str) -> HashMap<String, String> {
+    reqwest::Url::parse(url).unwrap().query_pairs().into_owned().collect()
+}
+
+#[tokio::test]
+async fn completes_flow_through_loopback_server() {
+    let auth = MockAuthServer::start().await;
+    let manager = Arc::new(OAuthManager::new(auth.config()));
+    let store = Arc::new(MemoryCredentialStore::new());
+
+    let server = manager.start_callback_server(store.clone()).await.unwrap();
+    assert_eq!(manager.active_port(), Some(server.port));
+
+    let authorize = query_params(&manager.authorization_url(server.port));
+    assert_eq!(authorize["client_id"], "test-client");
+    assert_eq!(authorize["code_challenge_method"], "S256");
+    let redirect_uri = authorize["redirect_uri"].clone();
+    assert_eq!(redirect_uri, format!("http://127.0.0.1:{}/oauth/callback", server.port));
+
+    // Browser follows the redirect back to the loopback server
+    let response = reqwest::get(format!("{}?code=auth-code&state={}", redirect_uri, authorize["state"]))
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let tokens = server.completion.await.unwrap().unwrap();
+    assert_eq!(tokens.access_token, "mock-access");
+    assert_eq!(manager.active_port(), None);
+
+    let request = {
+        let requests = auth.token_requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        requests[0].clone()
+    };
+    assert_eq!(request["grant_type"], "authorization_code");
+    assert_eq!(request["code"], "auth-code");
+    assert_eq!(request["redirect_uri"], redirect_uri);
+    assert_eq!(generate_code_challenge(&request["code_verifier"]), authorize["code_challenge"]);
+
+    assert_eq!(store.get("api.anthropicOAuthToken").await.unwrap().as_deref(), Some("mock-access"));
+    assert_eq!(store.get("api.anthropicRefreshToken").await.unwrap().as_deref(), Some("mock-refresh"));
+    assert!(store.get("api.anthropicOAuthExpiresAt").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn rejects_callback_with_wrong_state() {
+    let auth = MockAuthServer::start().await;
+    let manager = Arc::new(OAuthManager::new(auth.config()));
+    let store = Arc::new(MemoryCredentialStore::new());
+
+    let server = manager.start_callback_server(store.clone()).await.unwrap();
+    let authorize = query_params(&manager.authorization_url(server.port));
+    let redirect_uri = authorize["redirect_uri"].clone();
+
+    let forged = reqwest::get(format!("{}?code=auth-code&state=forged", redirect_uri))
+        .await
+        .unwrap();
+    assert_eq!(forged.status(), reqwest::StatusCode::BAD_REQUEST);
+    assert!(auth.token_requests.lock().unwrap().is_empty());
+
+    // The genuine redirect still completes afterwards
+    reqwest::get(format!("{}?code=auth-code&state={}", redirect_uri, authorize["state"]))
+        .await
+        .unwrap();
+    assert!(server.completion.await.unwrap().is_ok());
+    assert_eq!(auth.token_requests.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn reports_denied_authorization() {
+    let auth = MockAuthServer::start().await;
+    let manager = Arc::new(OAuthManager::new(auth.config()));
+    let store = Arc::new(MemoryCredentialStore::new());
+
+    let server = manager.start_callback_server(store.clone()).await.unwrap();
+    let authorize = query_params(&manager.authorization_url(server.port));
+
+    reqwest::get(format!(
+        "{}?error=access_denied&error_description=User+denied&state={}",
+        authorize["redirect_uri"], authorize["state"]
+    ))
+    .await
+    .unwrap();
+
+    let error = server.completion.await.unwrap().unwrap_err();
+    assert!(error.contains("User denied"));
+    assert_eq!(store.get("api.anthropicOAuthToken").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn manual_exchange_validates_state_and_expiry() {
+    let auth = MockAuthServer::start().await;
+    let manager = OAuthManager::with_session_ttl(auth.config(), Duration::from_millis(50));
+    let store = MemoryCredentialStore::new();
+
+    let authorize = query_params(&manager.authorization_url(1234));
+    assert!(manager.exchange_code("auth-code", "unknown", &store).await.is_err());
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(manager.exchange_code("auth-code", &authorize["state"], &store).await.is_err());
+    assert!(auth.token_requests.lock().unwrap().is_empty());
+
+    // A state can only be used once
+    let manager = OAuthManager::new(auth.config());
+    let authorize = query_params(&manager.authorization_url(1234));
+    let error = manager.exchange_code("bad-code", &authorize["state"], &store).await.unwrap_err();
+    assert!(error.contains("Invalid code"));
+    assert!(manager.exchange_code("auth-code", &authorize["state"], &store).await.is_err());
+}
+
+#[tokio::test]
+async fn refresh_stores_new_tokens() {
+    let auth = MockAuthServer::start().await;
+    let manager = OAuthManager::new(auth.config());
+    let store = MemoryCredentialStore::new();
+
+    manager.refresh("old-refresh", &store).await.unwrap();
+
+    let request = auth.token_requests.lock().unwrap()[0].clone();
+    assert_eq!(request["grant_type"], "refresh_token");
+    assert_eq!(request["refresh_token"], "old-refresh");
+    assert_eq!(store.get("api.anthropicOAuthToken").await.unwrap().as_deref(), Some("mock-access"));
+}
+
+#[tokio::test]
+async fn concurrent_requests_refresh_expiring_token_once() {
+    let auth = MockAuthServer::start().await;
+    let manager = Arc::new(OAuthManager::new(auth.config()));
+    let store = Arc::new(MemoryCredentialStore::new());
+
+    store.set("api.anthropicOAuthToken", "expiring-access").await.unwrap();
+    store.set("api.anthropicRefreshToken", "old-refresh").await.unwrap();
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(10);
+    store.set("api.anthropicOAuthExpiresAt", &expires_at.to_rfc3339()).await.unwrap();
+
+    let provider = Arc::new(OAuthAuth::new(manager, store.clone()));
+    let requests = (0..5).map(|_| {
+        let provider = provider.clone();
+        tokio::spawn(async move { provider.auth_headers().await.unwrap() })
+    });
+
+    for headers in futures::future::join_all(requests).await {
+        let headers = headers.unwrap();
+        let (_, authorization) = headers.iter().find(|(name, _)| *name == "authorization").unwrap();
+        assert_eq!(authorization.expose(), "Bearer mock-access");
+    }
+    assert_eq!(auth.token_requests.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn pasted_code_stops_callback_server() {
+    let auth = MockAuthServer::start().await;
+    let manager = Arc::new(OAuthManager::new(auth.config()));
+    let store = Arc::new(MemoryCredentialStore::new());
+
+    let server = manager.start_callback_server(store.clone()).await.unwrap();
+    let authorize = query_params(&manager.authorization_url(server.port));
+    manager.exchange_code("auth-code", &authorize["state"], store.as_ref()).await.unwrap();
+
+    let completion = tokio::time::timeout(Duration::from_secs(1), server.completion).await.unwrap();
+    assert_eq!(completion.unwrap().unwrap().access_token, "mock-access");
+    assert_eq!(manager.active_port(), None);
+}
+
+#[tokio::test]
+async fn reused_server_waits_for_latest_request() {
+    let auth = MockAuthServer::start().await;
+    let manager = Arc::new(OAuthManager::with_session_ttl(auth.config(), Duration::from_secs(1)));
+    let store = Arc::new(MemoryCredentialStore::new());
+
+    let server = manager.start_callback_server(store.clone()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(700)).await;
+    // Started late, so still valid after the server's own TTL has passed
+    let authorize = query_params(&manager.authorization_url(server.port));
+    tokio::time::sleep(Duration::from_millis(700)).await;
+
+    reqwest::get(format!("{}?code=auth-code&state={}", authorize["redirect_uri"], authorize["state"]))
+        .await
+        .unwrap();
+    server.completion.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn rejected_token_is_refreshed_and_request_retried() {
+    let auth = MockAuthServer::start().await;
+    let manager = Arc::new(OAuthManager::new(auth.config()));
+    let store = Arc::new(MemoryCredentialStore::new());
+    store.set("api.anthropicOAuthToken", "revoked-access").await.unwrap();
+    store.set("api.anthropicRefreshToken", "old-refresh").await.unwrap();
+
+    // Messages API that only accepts the refreshed token
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let api_url = format!("http://{}", listener.local_addr().unwrap());
+    let authorizations = Arc::new(Mutex::new(Vec::new()));
+    let seen = authorizations.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            let mut chunk = [0u8; 4096];
+            while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+                let read = stream.read(&mut chunk).await.unwrap();
+                head.extend_from_slice(&chunk[..read]);
+            }
+            let head = String::from_utf8_lossy(&head).to_lowercase();
+            let authorization = head.lines().find_map(|line| line.strip_prefix("authorization: ")).unwrap().to_string();
+            let (status, body) = if authorization == "bearer mock-access" {
+                (
+                    "200 OK",
+                    r#"{"id":"msg_1","content":[{"type":"text","text":"Hi"}],"model":"claude-3-5-haiku-20241022","stop_reason":"end_turn","usage":{"input_tokens":3,"output_tokens":1}}"#,
+                )
+            } else {
+                ("401 Unauthorized", r#"{"type":"error","error":{"type":"authentication_error"}}"#)
+            };
+            seen.lock().unwrap().push(authorization);
+            let reply = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(reply.as_bytes()).await.unwrap();
+        }
+    });
+
+    let client = AnthropicClient::with_auth(Arc::new(OAuthAuth::new(manager, store.clone())), Some(api_url));
+    let response = client
+        .send_message(AnthropicRequest {
+            model: "claude-3-5-haiku-20241022".to_string(),
+            max_tokens: 10,
+            messages: vec![AnthropicMessage::text("user", "Hello")],
+            temperature: None,
+            system: None,
+            tools: None,
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(response.content[0].text, "Hi");
+    assert_eq!(*authorizations.lock().unwrap(), ["bearer revoked-access", "bearer mock-access"]);
+    assert_eq!(auth.token_requests.lock().unwrap()[0]["refresh_token"], "old-refresh");
+    assert_eq!(store.get("api.anthropicRefreshToken").await.unwrap().as_deref(), Some("mock-refresh"));
+}
@@ -0,0 +1,91 @@
+use app_lib::database::knowledge::{add_document, check_budget, list_documents, load_knowledge};
+use app_lib::database::Database;
+use app_lib::integrations::filesystem::{estimate_tokens, read_document};
+use app_lib::integrations::models::ModelRegistry;
+
+async fn create_project(db: &Database, id: &str) {
+    sqlx::query("INSERT INTO projects (id, name) VALUES (?, ?)")
+        .bind(id)
+        .bind("Knowledge")
+        .execute(db.pool())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn reads_text_files_and_rejects_binaries() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let notes = dir.path().join("notes.md");
+    std::fs::write(&notes, "# Release process\n\nTag, then publish.").unwrap();
+    let document = read_document(&notes).await.unwrap();
+    assert_eq!(document.name, "notes.md");
+    assert_eq!(document.mime_type, "text/markdown");
+    assert_eq!(document.content_hash.len(), 64);
+
+    let source = dir.path().join("main.rs");
+    std::fs::write(&source, "fn main() {}\n").unwrap();
+    assert_eq!(read_document(&source).await.unwrap().mime_type, "text/plain");
+
+    let binary = dir.path().join("image.png");
+    std::fs::write(&binary, [0x89, 0x50, 0x4e, 0x47, 0xff, 0xfe]).unwrap();
+    assert!(read_document(&binary).await.is_err());
+
+    // Malformed PDFs are reported instead of taking the command down
+    let pdf = dir.path().join("broken.pdf");
+    std::fs::write(&pdf, "%PDF-1.4\n1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\ntrailer\n<< /Root 1 0 R >>\n%%EOF").unwrap();
+    assert!(read_document(&pdf).await.unwrap_err().to_string().contains("broken.pdf"));
+
+    assert_eq!(estimate_tokens("abcdefgh"), 2);
+    assert_eq!(estimate_tokens("abcdefghi"), 3);
+}
+
+#[tokio::test]
+async fn documents_are_deduplicated_and_formatted_as_context() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::in_memory().await.unwrap();
+    create_project(&db, "project-1").await;
+
+    let path = dir.path().join("style.md");
+    std::fs::write(&path, "Use tabs.").unwrap();
+
+    let first = add_document(db.pool(), "project-1", read_document(&path).await.unwrap())
+        .await
+        .unwrap();
+    let second = add_document(db.pool(), "project-1", read_document(&path).await.unwrap())
+        .await
+        .unwrap();
+    assert_eq!(first.id, second.id);
+    assert_eq!(list_documents(db.pool(), "project-1").await.unwrap().len(), 1);
+
+    let knowledge = load_knowledge(db.pool(), "project-1").await.unwrap().unwrap();
+    assert_eq!(knowledge.document_count, 1);
+    assert_eq!(knowledge.total_tokens, first.token_count);
+    assert!(knowledge.text.contains("<source>style.md</source>"));
+    assert!(knowledge.text.contains("Use tabs."));
+
+    assert!(load_knowledge(db.pool(), "other").await.unwrap().is_none());
+
+    // Documents go with their project
+    sqlx::query("DELETE FROM projects WHERE id = ?")
+        .bind("project-1")
+        .execute(db.pool())
+        .await
+        .unwrap();
+    assert!(list_documents(db.pool(), "project-1").await.unwrap().is_empty());
+}
+
+#[test]
+fn warns_when_knowledge_exceeds_the_context_budget() {
+    let models = ModelRegistry::new(None);
+    let model = models.require("claude-3-5-sonnet-20241022").unwrap();
+
+    let fits = check_budget(150_000, &model, 4096);
+    assert_eq!(fits.context_budget, 195_904);
+    assert!(!fits.over_budget);
+    assert!(fits.warning.is_none());
+
+    let too_big = check_budget(199_000, &model, 4096);
+    assert!(too_big.over_budget);
+    assert!(too_big.warning.unwrap().contains("199000 tokens"));
+}
@@ -0,0 +1,101 @@
+use app_lib::integrations::anthropic::{
+    AnthropicMessage, AnthropicRequest, RequestBlock, SystemBlock, SystemPrompt, Tool,
+};
+use app_lib::integrations::prompt_cache::{apply_cache_breakpoints, count_breakpoints, MAX_CACHE_BREAKPOINTS};
+use serde_json::json;
+
+fn request(messages: Vec<AnthropicMessage>, system: Option<SystemPrompt>, tools: Option<Vec<Tool>>) -> AnthropicRequest {
+    AnthropicRequest {
+        model: "claude-3-5-sonnet-20241022".to_string(),
+        max_tokens: 1024,
+        messages,
+        temperature: None,
+        system,
+        tools,
+    }
+}
+
+fn tool(name: &str) -> Tool {
+    Tool {
+        name: name.to_string(),
+        description: None,
+        input_schema: json!({"type": "object"}),
+        cache_control: None,
+    }
+}
+
+fn conversation() -> Vec<AnthropicMessage> {
+    vec![
+        AnthropicMessage::text("user", "First question"),
+        AnthropicMessage::text("assistant", "First answer"),
+        AnthropicMessage::text("user", "Second question"),
+        AnthropicMessage::text("assistant", "Second answer"),
+        AnthropicMessage::text("user", "Third question"),
+    ]
+}
+
+#[test]
+fn marks_tools_system_and_recent_history() {
+    let mut request = request(
+        conversation(),
+        Some(SystemPrompt::Text("You are helpful.".to_string())),
+        Some(vec![tool("search"), tool("fetch")]),
+    );
+
+    assert_eq!(apply_cache_breakpoints(&mut request), MAX_CACHE_BREAKPOINTS);
+    assert_eq!(count_breakpoints(&request), MAX_CACHE_BREAKPOINTS);
+
+    let body = serde_json::to_value(&request).unwrap();
+    let ephemeral = json!({"type": "ephemeral"});
+    assert!(body["tools"][0].get("cache_control").is_none());
+    assert_eq!(body["tools"][1]["cache_control"], ephemeral);
+    assert_eq!(body["system"][0]["type"], "text");
+    assert_eq!(body["system"][0]["cache_control"], ephemeral);
+
+    // The newest message and the user turn before it
+    assert_eq!(body["messages"][4]["content"][0]["text"], "Third question");
+    assert_eq!(body["messages"][4]["content"][0]["cache_control"], ephemeral);
+    assert_eq!(body["messages"][2]["content"][0]["cache_control"], ephemeral);
+    assert_eq!(body["messages"][0]["content"], "First question");
+    assert_eq!(body["messages"][3]["content"], "Second answer");
+}
+
+#[test]
+fn keeps_existing_breakpoints_within_the_limit() {
+    let mut request = request(
+        conversation(),
+        Some(SystemPrompt::Blocks(vec![
+            SystemBlock::cached("Base prompt"),
+            SystemBlock::cached("Style guide"),
+            SystemBlock::text("Project documents"),
+        ])),
+        None,
+    );
+
+    // Only two breakpoints are left after the system prompt's own
+    assert_eq!(apply_cache_breakpoints(&mut request), 2);
+    assert_eq!(count_breakpoints(&request), MAX_CACHE_BREAKPOINTS);
+
+    let Some(SystemPrompt::Blocks(blocks)) = &request.system else {
+        panic!("system prompt should stay in blocks");
+    };
+    assert!(blocks[2].cache_control.is_none());
+
+    // A second pass adds nothing
+    assert_eq!(apply_cache_breakpoints(&mut request), 0);
+}
+
+#[test]
+fn single_message_without_system_prompt() {
+    let mut request = request(vec![AnthropicMessage::text("user", "Hello")], None, None);
+
+    assert_eq!(apply_cache_breakpoints(&mut request), 1);
+    let body = serde_json::to_value(&request).unwrap();
+    assert!(body.get("system").is_none());
+    assert!(body.get("tools").is_none());
+    assert!(matches!(
+        &request.messages[0].content,
+        app_lib::integrations::anthropic::MessageContent::Blocks(blocks)
+            if matches!(&blocks[0], RequestBlock::Text { cache_control: Some(_), .. })
+    ));
+}
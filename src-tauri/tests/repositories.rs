@@ -0,0 +1,162 @@
+use app_lib::database::models::*;
+use app_lib::database::repositories::ChatFilter;
+use app_lib::database::Database;
+use chrono::Utc;
+use serde_json::json;
+
+fn config(value: serde_json::Value) -> JsonMap {
+    serde_json::from_value(value).unwrap()
+}
+
+#[tokio::test]
+async fn agents_hooks_and_runs() {
+    let db = Database::in_memory().await.unwrap();
+
+    let mut agent = Agent::new("Digest".to_string(), "Summarize".to_string(), config(json!({ "model": "claude" })));
+    agent.schedule_config = Some(sqlx::types::Json(config(json!({ "cron": "0 9 * * *" }))));
+    db.agents().create(&agent).await.unwrap();
+    let request = CreateAgentRequest {
+        name: "Daily digest".to_string(),
+        description: Some("Mornings".to_string()),
+        system_prompt: "Summarize briefly".to_string(),
+        model_config: config(json!({ "model": "claude", "max_tokens": 512 })),
+        schedule_config: None,
+    };
+    let updated = db.agents().update(&agent.id, &request).await.unwrap().unwrap();
+    assert_eq!(updated.name, "Daily digest");
+    assert_eq!(updated.model_config["max_tokens"], 512);
+    assert!(updated.schedule_config.is_none());
+    assert!(db.agents().update("missing", &request).await.unwrap().is_none());
+
+    let run = db.runs().create(&agent.id, Some(&config(json!({ "topic": "news" })))).await.unwrap();
+    db.runs().fail(&run.id, "Budget reached").await.unwrap();
+    let stored = db.runs().get(&run.id).await.unwrap().unwrap();
+    assert_eq!((stored.status, stored.error_message.as_deref()), (RunStatus::Failed, Some("Budget reached")));
+    assert_eq!(db.runs().list_for_agent(&agent.id).await.unwrap().len(), 1);
+
+    assert!(db.agents().move_to_trash(&agent.id).await.unwrap());
+    assert!(db.agents().list().await.unwrap().is_empty());
+    assert!(db.agents().get(&agent.id).await.unwrap().is_none());
+    assert!(db.agents().update(&agent.id, &request).await.unwrap().is_none());
+
+    let hook = Hook::new(
+        "Notify".to_string(),
+        TriggerType::ChatCreated,
+        config(json!({})),
+        "notify".to_string(),
+        config(json!({ "sound": true })),
+    );
+    db.hooks().create(&hook).await.unwrap();
+    let request = CreateHookRequest {
+        name: "Notify quietly".to_string(),
+        description: None,
+        trigger_type: TriggerType::ChatMessageReceived,
+        trigger_config: config(json!({})),
+        action_type: "notify".to_string(),
+        action_config: config(json!({ "sound": false })),
+    };
+    let updated = db.hooks().update(&hook.id, &request).await.unwrap().unwrap();
+    assert_eq!(updated.trigger_type, TriggerType::ChatMessageReceived);
+    assert_eq!(updated.action_config, r#"{"sound":false}"#);
+    assert_eq!(db.hooks().list().await.unwrap().len(), 1);
+    assert!(db.hooks().delete(&hook.id).await.unwrap());
+    assert!(!db.hooks().delete(&hook.id).await.unwrap());
+}
+
+#[tokio::test]
+async fn projects_chats_and_messages() {
+    let db = Database::in_memory().await.unwrap();
+
+    let project = Project::new("Launch".to_string());
+    db.projects().create(&project).await.unwrap();
+    let request = CreateProjectRequest { name: "Launch v2".to_string(), description: None, color: Some("red".to_string()) };
+    assert_eq!(db.projects().update(&project.id, &request).await.unwrap().unwrap().name, "Launch v2");
+    db.projects().set_metadata(&project.id, &config(json!({ "pinned": true }))).await.unwrap();
+    let stored = db.projects().get(&project.id).await.unwrap().unwrap();
+    assert_eq!(stored.metadata.unwrap()["pinned"], true);
+    assert_eq!(db.projects().knowledge_tokens(&project.id).await.unwrap(), 0);
+
+    let mut chat = Chat::new("default-session".to_string(), "Plan".to_string());
+    chat.project_id = Some(project.id.clone());
+    chat.folder_path = Some("Work/Q3".to_string());
+    db.chats().insert(&chat, false).await.unwrap();
+    let other = Chat::new("default-session".to_string(), "Notes".to_string());
+    db.chats().insert(&other, false).await.unwrap();
+    // Importing the same chat again leaves it alone
+    db.chats().insert(&other, true).await.unwrap();
+
+    let in_folder = ChatFilter { folder_path: Some("Work".to_string()), ..Default::default() };
+    let listed: Vec<String> = db.chats().list(&in_folder).await.unwrap().into_iter().map(|chat| chat.id).collect();
+    assert_eq!(listed, [chat.id.clone()]);
+    assert_eq!(db.chats().list(&ChatFilter::default()).await.unwrap().len(), 2);
+
+    let update = UpdateChatRequest { title: Some("Launch plan".to_string()), folder_path: Some(String::new()), is_favorite: Some(true), metadata: None };
+    let updated = db.chats().update(&chat.id, &update).await.unwrap().unwrap();
+    assert_eq!((updated.title.as_str(), updated.folder_path, updated.is_favorite, updated.auto_title), ("Launch plan", None, true, false));
+    assert!(db.chats().update("missing", &update).await.unwrap().is_none());
+
+    let question = Message::new(chat.id.clone(), Role::User, "When do we ship?".to_string());
+    db.messages().insert(&question).await.unwrap();
+    let mut first = Message::new(chat.id.clone(), Role::Assistant, "Friday".to_string());
+    first.parent_id = Some(question.id.clone());
+    db.messages().insert(&first).await.unwrap();
+    let mut second = Message::new(chat.id.clone(), Role::Assistant, "Monday".to_string());
+    second.parent_id = Some(question.id.clone());
+    second.created_at = Utc::now() + chrono::Duration::seconds(1);
+    db.messages().insert(&second).await.unwrap();
+
+    let shown = db.chats().get(&chat.id).await.unwrap().unwrap();
+    assert_eq!(shown.active_message_id.as_deref(), Some(second.id.as_str()));
+    assert_eq!(db.messages().siblings(&first.id).await.unwrap().len(), 2);
+    let path = db.messages().switch_branch(&first.id).await.unwrap();
+    assert_eq!(path.iter().map(|message| message.content.as_str()).collect::<Vec<_>>(), ["When do we ship?", "Friday"]);
+    assert_eq!(db.messages().active_path(&chat.id).await.unwrap().len(), 2);
+    assert_eq!(db.messages().path_to(&second.id).await.unwrap().len(), 2);
+
+    assert!(db.chats().move_to_trash(&chat.id).await.unwrap());
+    assert!(db.projects().move_to_trash(&project.id).await.unwrap());
+    assert_eq!(db.chats().list(&ChatFilter::default()).await.unwrap().len(), 1);
+    assert!(db.projects().list().await.unwrap().is_empty());
+
+    // Trashed rows are not found by id either, so nothing posts into them
+    assert!(db.chats().get(&chat.id).await.unwrap().is_none());
+    assert!(db.chats().update(&chat.id, &update).await.unwrap().is_none());
+    assert!(db.projects().get(&project.id).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn settings_rows_are_replaced_by_key() {
+    let db = Database::in_memory().await.unwrap();
+    let setting = |value: &str| Setting {
+        key: "ui.theme".to_string(),
+        value: value.to_string(),
+        setting_type: SettingType::String,
+        updated_at: Utc::now(),
+    };
+
+    db.settings().set(&setting("\"light\"")).await.unwrap();
+    db.settings().set(&setting("\"dark\"")).await.unwrap();
+    let stored = db.settings().list().await.unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].value, "\"dark\"");
+    assert_eq!(db.settings().get("ui.theme").await.unwrap().unwrap().setting_type, SettingType::String);
+
+    assert!(db.settings().delete("ui.theme").await.unwrap());
+    assert!(db.settings().get("ui.theme").await.unwrap().is_none());
+
+    // A batch that fails part way leaves nothing behind
+    sqlx::query(
+        "CREATE TRIGGER refuse BEFORE INSERT ON settings WHEN NEW.key = 'ui.fontSize' \
+         BEGIN SELECT RAISE(ABORT, 'refused'); END",
+    )
+    .execute(db.pool())
+    .await
+    .unwrap();
+    let font_size = Setting { key: "ui.fontSize".to_string(), ..setting("14") };
+    assert!(db.settings().apply(&[setting("\"light\""), font_size], &[]).await.is_err());
+    assert!(db.settings().list().await.unwrap().is_empty());
+
+    // Each in-memory database starts empty
+    let other = Database::in_memory().await.unwrap();
+    assert!(other.chats().list(&ChatFilter::default()).await.unwrap().is_empty());
+}
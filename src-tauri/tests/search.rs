@@ -0,0 +1,130 @@
+use app_lib::database::search::{rebuild_index, search, to_match_query, SearchFilter, SearchResultKind};
+use app_lib::database::models::Role;
+use app_lib::database::{folders, Database};
+
+async fn insert_chat(db: &Database, id: &str, title: &str, project_id: Option<&str>, folder: Option<&str>) {
+    db.ensure_session("session-1").await.unwrap();
+    let folder_id = match folder {
+        Some(path) => folders::ensure_path(&mut db.pool().acquire().await.unwrap(), path).await.unwrap(),
+        None => None,
+    };
+    sqlx::query("INSERT INTO chats (id, session_id, project_id, title, folder_id, folder_path) VALUES (?, 'session-1', ?, ?, ?, ?)")
+        .bind(id)
+        .bind(project_id)
+        .bind(title)
+        .bind(folder_id)
+        .bind(folder)
+        .execute(db.pool())
+        .await
+        .unwrap();
+}
+
+async fn insert_message(db: &Database, id: &str, chat_id: &str, role: &str, content: &str, created_at: &str) {
+    sqlx::query("INSERT INTO messages (id, chat_id, role, content, created_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(id)
+        .bind(chat_id)
+        .bind(role)
+        .bind(content)
+        .bind(created_at)
+        .execute(db.pool())
+        .await
+        .unwrap();
+}
+
+async fn find_release(db: &Database, filter: SearchFilter) -> Vec<app_lib::database::search::SearchResult> {
+    search(db.pool(), "release", &filter).await.unwrap()
+}
+
+#[test]
+fn builds_literal_prefix_queries() {
+    assert_eq!(to_match_query("  "), None);
+    assert_eq!(to_match_query("borrow checker").unwrap(), "\"borrow\" \"checker\"*");
+    assert_eq!(to_match_query("say \"hi\" OR -x").unwrap(), "\"say\" \"\"\"hi\"\"\" \"OR\" \"-x\"*");
+}
+
+#[tokio::test]
+async fn finds_messages_and_titles_kept_in_sync() {
+    let db = Database::in_memory().await.unwrap();
+    insert_chat(&db, "chat-1", "Lifetimes in Rust", None, None).await;
+    insert_message(&db, "m-1", "chat-1", "user", "Why does the borrow checker reject this?", "2024-03-01 10:00:00").await;
+    insert_message(&db, "m-2", "chat-1", "assistant", "The borrowed value does not live long enough.", "2024-03-01 10:00:05").await;
+
+    let results = search(db.pool(), "borrow", &SearchFilter::default()).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|result| result.kind == SearchResultKind::Message));
+    assert!(results.iter().any(|result| result.snippet.contains("<mark>borrow</mark>")));
+
+    let titles = search(db.pool(), "lifetime", &SearchFilter::default()).await.unwrap();
+    assert_eq!(titles.len(), 1);
+    assert_eq!(titles[0].kind, SearchResultKind::Title);
+    assert_eq!(titles[0].snippet, "<mark>Lifetimes</mark> in Rust");
+
+    // Edits, renames and deletes reach the index
+    sqlx::query("UPDATE messages SET content = 'Ownership question' WHERE id = 'm-1'")
+        .execute(db.pool())
+        .await
+        .unwrap();
+    sqlx::query("UPDATE chats SET title = 'Ownership' WHERE id = 'chat-1'")
+        .execute(db.pool())
+        .await
+        .unwrap();
+    let results = search(db.pool(), "ownership", &SearchFilter::default()).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(search(db.pool(), "lifetimes", &SearchFilter::default()).await.unwrap().is_empty());
+
+    sqlx::query("DELETE FROM messages WHERE id = 'm-2'").execute(db.pool()).await.unwrap();
+    assert!(search(db.pool(), "borrowed", &SearchFilter::default()).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn filters_by_project_folder_role_and_date() {
+    let db = Database::in_memory().await.unwrap();
+    sqlx::query("INSERT INTO projects (id, name) VALUES ('project-1', 'Backend')")
+        .execute(db.pool())
+        .await
+        .unwrap();
+    insert_chat(&db, "chat-1", "Deploys", Some("project-1"), Some("work/ops")).await;
+    insert_chat(&db, "chat-2", "Recipes", None, Some("workshop")).await;
+    insert_message(&db, "m-1", "chat-1", "user", "deploy the release", "2024-01-10 09:00:00").await;
+    insert_message(&db, "m-2", "chat-1", "assistant", "release deployed", "2024-02-10 09:00:00").await;
+    insert_message(&db, "m-3", "chat-2", "user", "release the dough", "2024-02-11 09:00:00").await;
+
+    let project = find_release(&db, SearchFilter { project_id: Some("project-1".into()), ..Default::default() }).await;
+    assert_eq!(project.len(), 2);
+
+    // A folder includes its subfolders but not siblings sharing a prefix
+    let folder = find_release(&db, SearchFilter { folder: Some("work".into()), ..Default::default() }).await;
+    assert_eq!(folder.len(), 2);
+    assert!(folder.iter().all(|result| result.chat_id == "chat-1"));
+
+    let role = find_release(&db, SearchFilter { role: Some(Role::Assistant), ..Default::default() }).await;
+    assert_eq!(role.len(), 1);
+    assert_eq!(role[0].message_id.as_deref(), Some("m-2"));
+
+    let dates = find_release(&db, SearchFilter {
+        start_date: Some("2024-02-01".into()),
+        end_date: Some("2024-02-10".into()),
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(dates.len(), 1);
+    assert_eq!(dates[0].message_id.as_deref(), Some("m-2"));
+
+    let page = find_release(&db, SearchFilter { limit: Some(1), offset: Some(1), ..Default::default() }).await;
+    assert_eq!(page.len(), 1);
+}
+
+#[tokio::test]
+async fn rebuild_restores_a_cleared_index() {
+    let db = Database::in_memory().await.unwrap();
+    insert_chat(&db, "chat-1", "Indexing", None, None).await;
+    insert_message(&db, "m-1", "chat-1", "user", "searchable text", "2024-01-01 00:00:00").await;
+
+    sqlx::query("DELETE FROM message_search").execute(db.pool()).await.unwrap();
+    assert!(search(db.pool(), "searchable", &SearchFilter::default()).await.unwrap().is_empty());
+
+    let stats = rebuild_index(db.pool()).await.unwrap();
+    assert_eq!(stats.messages_indexed, 1);
+    assert_eq!(stats.chats_indexed, 1);
+    assert_eq!(search(db.pool(), "searchable", &SearchFilter::default()).await.unwrap().len(), 1);
+}
@@ -0,0 +1,108 @@
+use app_lib::database::{sessions, Database};
+
+async fn session_counts(db: &Database, session_id: &str) -> (i32, String) {
+    let session = sessions::get(db.pool(), session_id).await.unwrap().unwrap();
+    (session.total_messages, session.last_activity.format("%Y-%m-%d").to_string())
+}
+
+#[tokio::test]
+async fn first_launch_creates_the_profile_and_default_session() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::open(&dir.path().join("cloddo.db")).await.unwrap();
+
+    let profile = sessions::profile(db.pool()).await.unwrap();
+    assert_eq!(profile.id, sessions::LOCAL_PROFILE_ID);
+    let listed = sessions::list(db.pool(), false).await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, sessions::DEFAULT_SESSION_ID);
+    assert_eq!(sessions::active(db.pool()).await.unwrap().unwrap().id, sessions::DEFAULT_SESSION_ID);
+
+    // Opening again does not add another session
+    drop(db);
+    let db = Database::open(&dir.path().join("cloddo.db")).await.unwrap();
+    assert_eq!(sessions::list(db.pool(), true).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn sessions_can_be_renamed_archived_and_switched() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::open(&dir.path().join("cloddo.db")).await.unwrap();
+
+    let work = sessions::create(db.pool(), " Work ").await.unwrap();
+    assert_eq!(work.title, "Work");
+    assert!(sessions::create(db.pool(), "  ").await.is_err());
+    assert_eq!(sessions::rename(db.pool(), &work.id, "Job").await.unwrap().title, "Job");
+
+    sessions::switch(db.pool(), &work.id).await.unwrap();
+    assert_eq!(sessions::active(db.pool()).await.unwrap().unwrap().id, work.id);
+    sessions::update_preferences(db.pool(), &serde_json::json!({ "theme": "dark" })).await.unwrap();
+    assert_eq!(sessions::active(db.pool()).await.unwrap().unwrap().id, work.id);
+
+    let archived = sessions::set_archived(db.pool(), &work.id, true).await.unwrap();
+    assert!(archived.archived_at.is_some());
+    let ids: Vec<String> = sessions::list(db.pool(), false).await.unwrap().into_iter().map(|s| s.id).collect();
+    assert_eq!(ids, [sessions::DEFAULT_SESSION_ID]);
+    assert_eq!(sessions::active(db.pool()).await.unwrap().unwrap().id, sessions::DEFAULT_SESSION_ID);
+    let error = sessions::switch(db.pool(), &work.id).await.unwrap_err();
+    assert_eq!(error.to_string(), "Session 'Job' is archived");
+
+    sessions::set_archived(db.pool(), &work.id, false).await.unwrap();
+    assert_eq!(sessions::list(db.pool(), false).await.unwrap().len(), 2);
+    assert_eq!(sessions::require_open(db.pool(), "missing").await.unwrap_err().to_string(), "Session not found");
+
+    let profile = sessions::profile(db.pool()).await.unwrap();
+    let preferences: serde_json::Value = serde_json::from_str(profile.preferences.as_deref().unwrap()).unwrap();
+    assert_eq!(preferences["theme"], "dark");
+    assert_eq!(preferences["activeSessionId"], work.id.as_str());
+
+    // Looking up the active session never creates one
+    for session_id in [sessions::DEFAULT_SESSION_ID, work.id.as_str()] {
+        sessions::set_archived(db.pool(), session_id, true).await.unwrap();
+    }
+    assert!(sessions::active(db.pool()).await.unwrap().is_none());
+    assert!(sessions::list(db.pool(), false).await.unwrap().is_empty());
+    let created = sessions::ensure_active(db.pool()).await.unwrap();
+    assert_eq!(sessions::active(db.pool()).await.unwrap().unwrap().id, created.id);
+}
+
+#[tokio::test]
+async fn triggers_keep_session_totals_and_orphan_chats_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::open(&dir.path().join("cloddo.db")).await.unwrap();
+    let other = sessions::create(db.pool(), "Other").await.unwrap();
+    let session = sessions::DEFAULT_SESSION_ID;
+
+    sqlx::query("INSERT INTO chats (id, session_id, title, last_activity) VALUES ('chat-1', ?, 'Chat', '2030-01-01 10:00:00')")
+        .bind(session)
+        .execute(db.pool())
+        .await
+        .unwrap();
+    assert_eq!(session_counts(&db, session).await.1, "2030-01-01");
+
+    for (id, created_at) in [("m1", "2031-02-03 10:00:00"), ("m2", "2020-01-01 10:00:00")] {
+        sqlx::query("INSERT INTO messages (id, chat_id, role, content, created_at) VALUES (?, 'chat-1', 'user', 'Hi', ?)")
+            .bind(id)
+            .bind(created_at)
+            .execute(db.pool())
+            .await
+            .unwrap();
+    }
+    // An older message does not move last activity back
+    assert_eq!(session_counts(&db, session).await, (2, "2031-02-03".to_string()));
+
+    sqlx::query("DELETE FROM messages WHERE id = 'm2'").execute(db.pool()).await.unwrap();
+    assert_eq!(session_counts(&db, session).await.0, 1);
+
+    sqlx::query("UPDATE chats SET session_id = ? WHERE id = 'chat-1'")
+        .bind(&other.id)
+        .execute(db.pool())
+        .await
+        .unwrap();
+    assert_eq!(session_counts(&db, session).await.0, 0);
+    assert_eq!(session_counts(&db, &other.id).await.0, 1);
+
+    let orphan = sqlx::query("INSERT INTO chats (id, session_id, title, last_activity) VALUES ('chat-2', 'nowhere', 'Lost', CURRENT_TIMESTAMP)")
+        .execute(db.pool())
+        .await;
+    assert!(orphan.unwrap_err().to_string().contains("FOREIGN KEY"));
+}
@@ -0,0 +1,95 @@
+use app_lib::database::models::{Setting, SettingType};
+use app_lib::database::settings::{self, SCHEMA};
+use app_lib::database::Database;
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+fn changes(value: Value) -> HashMap<String, Value> {
+    serde_json::from_value(value).unwrap()
+}
+
+#[tokio::test]
+async fn updates_merge_into_stored_settings() {
+    let db = Database::in_memory().await.unwrap();
+
+    let defaults = settings::load(db.settings()).await.unwrap();
+    assert_eq!(defaults["appearance.theme"], "system");
+    assert_eq!(defaults["trash.retentionDays"], 30);
+    assert!(!defaults.contains_key("api.anthropicApiKey"));
+    assert!(!defaults.contains_key("api.proxy"));
+
+    let changed = settings::update(db.settings(), &changes(json!({ "appearance.theme": "dark", "backup.retention": 7 })))
+        .await
+        .unwrap();
+    // Setting a value to what it already is is not a change
+    assert_eq!(changed, changes(json!({ "appearance.theme": "dark" })));
+
+    settings::update(db.settings(), &changes(json!({ "chat.autoTitle": false }))).await.unwrap();
+    let stored = settings::load(db.settings()).await.unwrap();
+    assert_eq!((&stored["appearance.theme"], &stored["chat.autoTitle"]), (&json!("dark"), &json!(false)));
+    let row = db.settings().get("chat.autoTitle").await.unwrap().unwrap();
+    assert_eq!((row.value.as_str(), row.setting_type), ("false", SettingType::Boolean));
+
+    // Null goes back to the default
+    let changed = settings::update(db.settings(), &changes(json!({ "appearance.theme": null }))).await.unwrap();
+    assert_eq!(changed, changes(json!({ "appearance.theme": "system" })));
+    assert!(db.settings().get("appearance.theme").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn rejects_unknown_keys_and_bad_values_without_writing() {
+    let db = Database::in_memory().await.unwrap();
+    let error = |value: Value| {
+        let db = db.clone();
+        async move { settings::update(db.settings(), &changes(value)).await.unwrap_err().to_string() }
+    };
+
+    assert_eq!(error(json!({ "ui.sparkles": true })).await, "Unknown setting 'ui.sparkles'");
+    assert_eq!(error(json!({ "chat.autoTitle": "yes" })).await, "Setting 'chat.autoTitle' must be true or false");
+    assert_eq!(
+        error(json!({ "appearance.theme": "neon" })).await,
+        "Setting 'appearance.theme' must be one of: light, dark, system"
+    );
+    assert_eq!(
+        error(json!({ "backup.retention": 2.5 })).await,
+        "Setting 'backup.retention' must be a whole number from 1 to 365"
+    );
+    assert_eq!(
+        error(json!({ "api.anthropicApiKey": "sk-ant" })).await,
+        "Setting 'api.anthropicApiKey' is kept in the credential store"
+    );
+    // One bad value keeps the valid ones from being stored too
+    error(json!({ "appearance.theme": "dark", "api.defaultTemperature": 3 })).await;
+    assert!(db.settings().list().await.unwrap().is_empty());
+
+    for definition in SCHEMA.iter().filter(|definition| !definition.default.is_null()) {
+        definition.validate(&definition.default).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn imports_known_settings_and_ignores_unreadable_rows() {
+    let db = Database::in_memory().await.unwrap();
+    let legacy = changes(json!({
+        "api.defaultModel": "claude-3-5-haiku-20241022",
+        "appearance.theme": "neon",
+        "api.anthropicApiKey": "sk-ant",
+        "old.setting": 1,
+    }));
+    assert_eq!(settings::import(db.settings(), &legacy).await.unwrap(), 1);
+    let stored = settings::load(db.settings()).await.unwrap();
+    assert_eq!(stored["api.defaultModel"], "claude-3-5-haiku-20241022");
+    assert_eq!(stored["appearance.theme"], "system");
+
+    db.settings()
+        .set(&Setting {
+            key: "backup.retention".to_string(),
+            value: "\"forever\"".to_string(),
+            setting_type: SettingType::String,
+            updated_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(settings::load(db.settings()).await.unwrap()["backup.retention"], 7);
+}
@@ -0,0 +1,119 @@
+use app_lib::database::models::{Chat, Message, Role};
+use app_lib::database::summaries::{
+    clean_title, compress_history, plan_summary, store_summary, store_title, summary_prompt, SUMMARY_KEEP_RECENT,
+    SUMMARY_THRESHOLD,
+};
+use app_lib::database::Database;
+
+// Alternating user and assistant turns, each about 25 tokens long
+fn conversation(length: usize) -> Vec<Message> {
+    (0..length)
+        .map(|index| {
+            let role = if index % 2 == 0 { Role::User } else { Role::Assistant };
+            Message::new("chat-1".to_string(), role, format!("{:0>100}", index))
+        })
+        .collect()
+}
+
+#[test]
+fn cleans_model_proposed_titles() {
+    assert_eq!(clean_title("\"Fixing the borrow checker.\"").unwrap(), "Fixing the borrow checker");
+    assert_eq!(clean_title("\nTitle: Weekly meal plan\nExtra line").unwrap(), "Weekly meal plan");
+    assert_eq!(clean_title("**Rust lifetimes**").unwrap(), "Rust lifetimes");
+    assert_eq!(clean_title(&"x".repeat(200)).unwrap().chars().count(), 80);
+    assert_eq!(clean_title("  \n \"\" "), None);
+}
+
+#[test]
+fn summarizes_all_but_recent_turns_in_batches() {
+    let mut chat = Chat::new("session-1".to_string(), "Long".to_string());
+
+    assert!(plan_summary(&chat, &conversation(SUMMARY_THRESHOLD)).is_none());
+
+    // The kept messages start with a user turn
+    let path = conversation(SUMMARY_THRESHOLD + 3);
+    let plan = plan_summary(&chat, &path).unwrap();
+    assert!(plan.previous.is_none());
+    assert_eq!(plan.messages.len(), 12);
+    assert_eq!(plan.through_message_id, path[11].id);
+    assert_eq!(path[12].role, Role::User);
+    assert!(path.len() - 12 >= SUMMARY_KEEP_RECENT);
+
+    chat.summary = Some("Earlier turns".to_string());
+    chat.summary_message_id = Some(path[11].id.clone());
+    assert!(plan_summary(&chat, &path).is_none());
+
+    // Enough new turns extend the existing summary
+    let mut longer = path.clone();
+    longer.extend(conversation(8).into_iter().skip(1));
+    let plan = plan_summary(&chat, &longer).unwrap();
+    assert_eq!(plan.previous.as_deref(), Some("Earlier turns"));
+    assert_eq!(plan.messages.first().unwrap().id, longer[12].id);
+    assert!(summary_prompt(plan.previous.as_deref(), &plan.messages).contains("<summary>\nEarlier turns\n</summary>"));
+
+    // A summary of another branch starts over
+    chat.summary_message_id = Some("elsewhere".to_string());
+    assert!(plan_summary(&chat, &longer).unwrap().previous.is_none());
+}
+
+#[test]
+fn compresses_history_only_when_over_budget() {
+    let path = conversation(30);
+    let mut chat = Chat::new("session-1".to_string(), "Long".to_string());
+    chat.summary = Some("What was said".to_string());
+    chat.summary_message_id = Some(path[17].id.clone());
+
+    let (summary, messages) = compress_history(&chat, path.clone(), 1_000_000);
+    assert!(summary.is_none());
+    assert_eq!(messages.len(), 30);
+
+    let (summary, messages) = compress_history(&chat, path.clone(), 400);
+    assert_eq!(summary.as_deref(), Some("What was said"));
+    assert_eq!(messages.len(), 12);
+    assert_eq!(messages[0].id, path[18].id);
+
+    // What follows the summary is trimmed too when it still doesn't fit
+    let (summary, messages) = compress_history(&chat, path.clone(), 100);
+    assert_eq!(summary.as_deref(), Some("What was said"));
+    assert_eq!(messages.iter().map(|message| &message.id).collect::<Vec<_>>(), [&path[28].id, &path[29].id]);
+
+    // Before the first summary, the oldest turns are dropped to fit
+    let unsummarized = Chat::new("session-1".to_string(), "Long".to_string());
+    let (summary, messages) = compress_history(&unsummarized, path.clone(), 100);
+    assert!(summary.is_none());
+    assert_eq!(messages.len(), 4);
+    assert_eq!(messages[0].id, path[26].id);
+}
+
+#[tokio::test]
+async fn generated_titles_never_replace_a_rename() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::open(&dir.path().join("cloddo.db")).await.unwrap();
+    db.ensure_session("session-1").await.unwrap();
+    for id in ["chat-1", "chat-2"] {
+        sqlx::query("INSERT INTO chats (id, session_id, title) VALUES (?, 'session-1', 'New Chat')")
+            .bind(id)
+            .execute(db.pool())
+            .await
+            .unwrap();
+    }
+    sqlx::query("UPDATE chats SET title = 'Mine', auto_title = FALSE WHERE id = 'chat-2'")
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+    assert!(store_title(db.pool(), "chat-1", "Generated").await.unwrap());
+    assert!(!store_title(db.pool(), "chat-1", "Generated again").await.unwrap());
+    assert!(!store_title(db.pool(), "chat-2", "Generated").await.unwrap());
+
+    store_summary(db.pool(), "chat-1", "A summary", "message-9").await.unwrap();
+    let (title, auto_title, summary, through): (String, bool, Option<String>, Option<String>) =
+        sqlx::query_as("SELECT title, auto_title, summary, summary_message_id FROM chats WHERE id = 'chat-1'")
+            .fetch_one(db.pool())
+            .await
+            .unwrap();
+    assert_eq!(title, "Generated");
+    assert!(!auto_title);
+    assert_eq!(summary.as_deref(), Some("A summary"));
+    assert_eq!(through.as_deref(), Some("message-9"));
+}
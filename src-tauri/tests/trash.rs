@@ -0,0 +1,94 @@
+use app_lib::database::search::{search, SearchFilter};
+use app_lib::database::trash::{self, PurgeReport, TrashKind};
+use app_lib::database::{collections, sessions, tags, Database};
+use chrono::{Duration, Utc};
+
+async fn open_db() -> Database {
+    let db = Database::in_memory().await.unwrap();
+    let statements = [
+        "INSERT INTO projects (id, name) VALUES ('project-1', 'Launch')",
+        "INSERT INTO chats (id, session_id, project_id, title, last_activity) \
+         VALUES ('chat-1', 'default-session', 'project-1', 'Launch plan', CURRENT_TIMESTAMP)",
+        "INSERT INTO chats (id, session_id, title, last_activity) VALUES ('chat-2', 'default-session', 'Notes', CURRENT_TIMESTAMP)",
+        "INSERT INTO messages (id, chat_id, role, content) VALUES ('m1', 'chat-1', 'user', 'Rocket checklist')",
+        "INSERT INTO messages (id, chat_id, role, content) VALUES ('m2', 'chat-2', 'user', 'Rocket fuel')",
+        "INSERT INTO agents (id, name, system_prompt, model_config) VALUES ('agent-1', 'Digest', 'Summarize', '{}')",
+        "INSERT INTO agent_runs (id, agent_id, status) VALUES ('run-1', 'agent-1', 'completed')",
+    ];
+    for statement in statements {
+        sqlx::query(statement).execute(db.pool()).await.unwrap();
+    }
+    db
+}
+
+async fn count(db: &Database, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+        .fetch_one(db.pool())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn trashed_chats_are_hidden_until_restored() {
+    let db = open_db().await;
+    let matching = |results: Vec<app_lib::database::search::SearchResult>| {
+        results.into_iter().map(|result| result.chat_id).collect::<Vec<_>>()
+    };
+
+    assert!(trash::move_to_trash(db.pool(), TrashKind::Chat, "chat-2").await.unwrap());
+    assert!(!trash::move_to_trash(db.pool(), TrashKind::Chat, "chat-2").await.unwrap());
+    assert!(trash::move_to_trash(db.pool(), TrashKind::Agent, "agent-1").await.unwrap());
+
+    let items: Vec<(String, String)> =
+        trash::list(db.pool()).await.unwrap().into_iter().map(|item| (item.kind, item.name)).collect();
+    assert_eq!(items, [("agent".to_string(), "Digest".to_string()), ("chat".to_string(), "Notes".to_string())]);
+
+    assert_eq!(matching(search(db.pool(), "rocket", &SearchFilter::default()).await.unwrap()), ["chat-1"]);
+    let everything = collections::parse("").unwrap();
+    assert_eq!(collections::evaluate(db.pool(), &everything, Utc::now(), None, None).await.unwrap().len(), 1);
+    assert!(tags::set_for_chat(db.pool(), "chat-2", &["Later".to_string()]).await.is_err());
+
+    assert!(trash::restore(db.pool(), TrashKind::Chat, "chat-2").await.unwrap());
+    assert!(!trash::restore(db.pool(), TrashKind::Chat, "chat-2").await.unwrap());
+    assert_eq!(search(db.pool(), "rocket", &SearchFilter::default()).await.unwrap().len(), 2);
+    assert_eq!(trash::list(db.pool()).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn purging_respects_retention_and_cascades() {
+    let db = open_db().await;
+    for kind in [TrashKind::Chat, TrashKind::Project, TrashKind::Agent] {
+        let id = match kind {
+            TrashKind::Chat => "chat-2",
+            TrashKind::Project => "project-1",
+            TrashKind::Agent => "agent-1",
+        };
+        trash::move_to_trash(db.pool(), kind, id).await.unwrap();
+    }
+    sqlx::query("UPDATE chats SET deleted_at = ? WHERE id = 'chat-2'")
+        .bind(Utc::now() - Duration::days(40))
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+    // Only the chat has been in the trash for longer than 30 days
+    let report = trash::purge(db.pool(), Some(Utc::now() - Duration::days(30))).await.unwrap();
+    assert_eq!(report, PurgeReport { chats: 1, projects: 0, agents: 0 });
+    assert_eq!(count(&db, "messages").await, 1);
+    let session = sessions::get(db.pool(), sessions::DEFAULT_SESSION_ID).await.unwrap().unwrap();
+    assert_eq!(session.total_messages, 1);
+
+    let report = trash::purge(db.pool(), None).await.unwrap();
+    assert_eq!(report, PurgeReport { chats: 0, projects: 1, agents: 1 });
+    assert_eq!(count(&db, "agent_runs").await, 0);
+    let project_id: Option<String> = sqlx::query_scalar("SELECT project_id FROM chats WHERE id = 'chat-1'")
+        .fetch_one(db.pool())
+        .await
+        .unwrap();
+    assert_eq!(project_id, None);
+    assert!(trash::list(db.pool()).await.unwrap().is_empty());
+
+    // Deleting a chat for good takes its messages along
+    sqlx::query("DELETE FROM chats WHERE id = 'chat-1'").execute(db.pool()).await.unwrap();
+    assert_eq!(count(&db, "messages").await, 0);
+}
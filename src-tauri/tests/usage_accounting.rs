@@ -0,0 +1,183 @@
+use app_lib::agents::executor;
+use app_lib::database::models::{Agent, ApiUsage, Chat, RunStatus, SessionAnalytics};
+use app_lib::database::usage::{record_usage, summarize_usage, UsageContext, UsageFilter, UsageGrouping};
+use app_lib::database::Database;
+use app_lib::integrations::anthropic::{AnthropicClient, Usage};
+use app_lib::integrations::models::pricing_for;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+async fn create_chat(db: &Database, session_id: &str) -> Chat {
+    db.ensure_session(session_id).await.unwrap();
+    let chat = Chat::new(session_id.to_string(), "Usage".to_string());
+    sqlx::query("INSERT INTO chats (id, session_id, title) VALUES (?, ?, ?)")
+        .bind(&chat.id)
+        .bind(&chat.session_id)
+        .bind(&chat.title)
+        .execute(db.pool())
+        .await
+        .unwrap();
+    chat
+}
+
+fn usage(input_tokens: u32, output_tokens: u32) -> Usage {
+    Usage {
+        input_tokens,
+        output_tokens,
+        cache_creation_input_tokens: 0,
+        cache_read_input_tokens: 0,
+    }
+}
+
+#[test]
+fn dated_models_use_family_pricing() {
+    let pricing = pricing_for("claude-3-5-sonnet-20241022").unwrap();
+    let cost = pricing.cost(1_000_000, 1_000_000, 0, 0);
+    assert!((cost - 18.0).abs() < 1e-9);
+
+    let cached = pricing.cost(0, 0, 1_000_000, 1_000_000);
+    assert!((cached - 4.05).abs() < 1e-9);
+
+    assert!(pricing_for("gpt-4o").is_none());
+}
+
+#[tokio::test]
+async fn calls_roll_up_into_session_analytics() {
+    let db = Database::in_memory().await.unwrap();
+    let chat = create_chat(&db, "session-1").await;
+    let context = UsageContext::chat(&chat);
+
+    record_usage(db.pool(), &context, "claude-3-5-sonnet-20241022", &usage(1000, 500))
+        .await
+        .unwrap();
+    record_usage(db.pool(), &context, "claude-3-5-haiku-20241022", &usage(2000, 100))
+        .await
+        .unwrap();
+
+    let rows = sqlx::query_as::<_, SessionAnalytics>("SELECT * FROM session_analytics WHERE session_id = ?")
+        .bind("session-1")
+        .fetch_all(db.pool())
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].api_calls, 2);
+    assert_eq!(rows[0].tokens_used, 3600);
+
+    let expected_cost = (1000.0 * 3.0 + 500.0 * 15.0 + 2000.0 * 0.8 + 100.0 * 4.0) / 1_000_000.0;
+    assert!((rows[0].cost_estimate - expected_cost).abs() < 1e-9);
+
+    let token_usage: i64 = sqlx::query_scalar("SELECT token_usage FROM sessions WHERE id = ?")
+        .bind("session-1")
+        .fetch_one(db.pool())
+        .await
+        .unwrap();
+    assert_eq!(token_usage, 3600);
+}
+
+#[tokio::test]
+async fn usage_is_summarized_by_model_and_agent() {
+    let db = Database::in_memory().await.unwrap();
+    let chat = create_chat(&db, "session-1").await;
+
+    record_usage(db.pool(), &UsageContext::chat(&chat), "claude-3-5-sonnet-20241022", &usage(100, 10))
+        .await
+        .unwrap();
+    record_usage(db.pool(), &UsageContext::agent("agent-1", "run-1"), "claude-3-5-sonnet-20241022", &usage(200, 20))
+        .await
+        .unwrap();
+    record_usage(db.pool(), &UsageContext::hook("hook-1"), "claude-3-haiku-20240307", &usage(300, 30))
+        .await
+        .unwrap();
+
+    let by_model = summarize_usage(db.pool(), UsageGrouping::Model, &UsageFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(by_model.len(), 2);
+    assert_eq!(by_model[0].key.as_deref(), Some("claude-3-5-sonnet-20241022"));
+    assert_eq!(by_model[0].api_calls, 2);
+    assert_eq!(by_model[0].input_tokens, 300);
+
+    let by_agent = summarize_usage(db.pool(), UsageGrouping::Agent, &UsageFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(by_agent.len(), 1);
+    assert_eq!(by_agent[0].key.as_deref(), Some("agent-1"));
+    assert_eq!(by_agent[0].output_tokens, 20);
+
+    let by_day = summarize_usage(
+        db.pool(),
+        UsageGrouping::Day,
+        &UsageFilter {
+            session_id: Some("session-1".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(by_day.len(), 1);
+    assert_eq!(by_day[0].api_calls, 1);
+}
+
+// Messages API that answers one request with a fixed reply and returns the
+// request body
+async fn mock_messages_api(reply: &'static str) -> (String, tokio::task::JoinHandle<serde_json::Value>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let request = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let body = loop {
+            let read = stream.read(&mut chunk).await.unwrap();
+            buffer.extend_from_slice(&chunk[..read]);
+            let text = String::from_utf8_lossy(&buffer).to_string();
+            if let Some(split) = text.find("\r\n\r\n") {
+                let length: usize = text[..split]
+                    .lines()
+                    .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|value| value.trim().parse().unwrap()))
+                    .unwrap_or(0);
+                if buffer.len() >= split + 4 + length {
+                    break text[split + 4..].to_string();
+                }
+            }
+        };
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            reply.len(),
+            reply
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+        serde_json::from_str(&body).unwrap()
+    });
+    (base_url, request)
+}
+
+#[tokio::test]
+async fn agent_runs_record_their_usage() {
+    let db = Arc::new(Database::in_memory().await.unwrap());
+    let config = serde_json::from_value(json!({ "model": "claude-3-5-haiku-20241022", "max_tokens": 256 })).unwrap();
+    let agent = Agent::new("Digest".to_string(), "Summarize the news".to_string(), config);
+    db.agents().create(&agent).await.unwrap();
+    let input = serde_json::from_value(json!({ "topic": "rust" })).unwrap();
+    let run = db.runs().create(&agent.id, Some(&input)).await.unwrap();
+
+    let (base_url, request) = mock_messages_api(
+        r#"{"id":"msg_1","content":[{"type":"text","text":"All quiet"}],"model":"claude-3-5-haiku-20241022","stop_reason":"end_turn","usage":{"input_tokens":40,"output_tokens":5}}"#,
+    )
+    .await;
+    let client = AnthropicClient::new("sk-ant-test".to_string(), Some(base_url))
+        .with_usage_tracking(db.clone(), UsageContext::agent(&agent.id, &run.id));
+
+    assert_eq!(executor::execute(&db, &client, &agent, &run).await.unwrap(), "All quiet");
+    let sent = request.await.unwrap();
+    assert_eq!((sent["system"].as_str(), sent["max_tokens"].as_u64()), (Some("Summarize the news"), Some(256)));
+    assert_eq!(sent["messages"][0]["content"], r#"{"topic":"rust"}"#);
+
+    let finished = db.runs().get(&run.id).await.unwrap().unwrap();
+    assert_eq!((finished.status, finished.output_data.as_deref()), (RunStatus::Completed, Some(r#"{"text":"All quiet"}"#)));
+    let recorded: Vec<ApiUsage> = sqlx::query_as("SELECT * FROM api_usage").fetch_all(db.pool()).await.unwrap();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!((recorded[0].agent_id.as_deref(), recorded[0].agent_run_id.as_deref()), (Some(agent.id.as_str()), Some(run.id.as_str())));
+    assert_eq!((recorded[0].source.as_str(), recorded[0].output_tokens), ("agent", 5));
+}